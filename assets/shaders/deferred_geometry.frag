@@ -0,0 +1,14 @@
+#version 460
+
+// Fragment outputs, one per geometry buffer target.
+layout (location = 0) out vec4 outAlbedo;
+layout (location = 1) out vec4 outNormal;
+
+// The surface's color.
+const vec3 ALBEDO = vec3(1.0, 0.5, 0.25);
+
+// Fills the geometry buffer with a flat surface facing the camera.
+void main() {
+    outAlbedo = vec4(ALBEDO, 1.0);
+    outNormal = vec4(0.0, 0.0, 1.0, 0.0);
+}
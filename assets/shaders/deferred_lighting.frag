@@ -0,0 +1,24 @@
+#version 460
+
+// Fragment inputs.
+layout (location = 0) in vec2 fragTexCoord;
+
+// Fragment outputs.
+layout (location = 0) out vec4 outColor;
+
+// Bindings. The geometry buffer targets share a sampler.
+layout (binding = 0) uniform texture2D albedoImage;
+layout (binding = 1) uniform texture2D normalImage;
+layout (binding = 2) uniform sampler gbufferSampler;
+
+// The direction towards the light.
+const vec3 LIGHT_DIRECTION = vec3(0.0, 0.6, 0.8);
+
+// Lights the geometry buffer with a single directional light.
+void main() {
+    vec3 albedo = texture(sampler2D(albedoImage, gbufferSampler), fragTexCoord).rgb;
+    vec3 normal = texture(sampler2D(normalImage, gbufferSampler), fragTexCoord).xyz;
+    float diffuse = max(dot(normalize(normal), LIGHT_DIRECTION), 0.0);
+
+    outColor = vec4(albedo * diffuse, 1.0);
+}
@@ -0,0 +1,12 @@
+#version 460
+
+// Fragment outputs.
+layout (location = 0) out vec2 fragTexCoord;
+
+// Draws a single triangle that covers the screen, with no vertex buffer.
+// Vertices 0, 1, and 2 get the texture coordinates (0, 0), (2, 0), and
+// (0, 2), so the part of the triangle on screen spans (0, 0) to (1, 1).
+void main() {
+    fragTexCoord = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(fragTexCoord * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,25 @@
+// Gamma helpers for UNORM swapchains. sRGB swapchains encode
+// on write, so shaders rendering to them output linear colors.
+// With a UNORM swapchain, encode the final color yourself:
+//
+//     #include "gamma.glsl"
+//
+//     outColor = vec4(linearToSrgb(color.rgb), color.a);
+//
+// Includes need the GL_GOOGLE_include_directive extension, which glslc enables.
+
+// Encode a linear color to sRGB.
+vec3 linearToSrgb(vec3 color) {
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+
+    return mix(low, high, step(vec3(0.0031308), color));
+}
+
+// Decode an sRGB color to linear.
+vec3 srgbToLinear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+
+    return mix(low, high, step(vec3(0.04045), color));
+}
@@ -0,0 +1,11 @@
+#version 460
+
+// Fragment inputs.
+layout (location = 0) in vec3 fragColor;
+
+// Fragment outputs.
+layout (location = 0) out vec4 outColor;
+
+void main() {
+    outColor = vec4(fragColor, 1.0);
+}
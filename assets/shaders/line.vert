@@ -0,0 +1,18 @@
+#version 460
+
+// Vertex inputs.
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inColor;
+
+// Vertex outputs.
+layout(location = 0) out vec3 fragColor;
+
+// Push constants.
+layout(push_constant) uniform PushConstants {
+    mat4 viewProj;
+} pc;
+
+void main() {
+    gl_Position = pc.viewProj * vec4(inPosition, 1.0);
+    fragColor = inColor;
+}
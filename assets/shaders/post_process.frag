@@ -0,0 +1,17 @@
+#version 460
+
+// Fragment inputs.
+layout (location = 0) in vec2 fragTexCoord;
+
+// Fragment outputs.
+layout (location = 0) out vec4 outColor;
+
+// Bindings. The input is bound as a separate image and sampler.
+layout (binding = 0) uniform texture2D inputImage;
+layout (binding = 1) uniform sampler inputSampler;
+
+// Copies the input. Use this as a starting point for post-processing
+// passes like tonemapping or color grading.
+void main() {
+    outColor = texture(sampler2D(inputImage, inputSampler), fragTexCoord);
+}
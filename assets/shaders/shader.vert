@@ -5,19 +5,35 @@ layout(location = 0) in vec2 inPosition;
 layout(location = 1) in vec3 inColor;
 layout(location = 2) in vec2 inTexCoord;
 
+// Instance inputs. The model matrix is passed one column at a time.
+layout(location = 3) in vec4 inInstanceModel0;
+layout(location = 4) in vec4 inInstanceModel1;
+layout(location = 5) in vec4 inInstanceModel2;
+layout(location = 6) in vec4 inInstanceModel3;
+
 // Vertex outputs.
 layout(location = 0) out vec3 fragColor;
 layout(location = 1) out vec2 fragTexCoord;
 
 // Bindings.
 layout(binding = 0) uniform UniformData {
-    mat4 model;
     mat4 view;
     mat4 proj;
 } ubo;
 
+layout(binding = 2) uniform ObjectData {
+    mat4 model;
+} object;
+
+// Push constants.
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+} pc;
+
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPosition, 0.0, 1.0);
+    mat4 instanceModel = mat4(inInstanceModel0, inInstanceModel1, inInstanceModel2, inInstanceModel3);
+
+    gl_Position = ubo.proj * ubo.view * object.model * instanceModel * pc.model * vec4(inPosition, 0.0, 1.0);
     fragColor = inColor;
     fragTexCoord = inTexCoord;
 }
\ No newline at end of file
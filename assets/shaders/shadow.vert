@@ -0,0 +1,15 @@
+#version 460
+
+// Push constants.
+layout (push_constant) uniform PushConstants {
+    mat4 lightModelViewProjection;
+} pushConstants;
+
+// Vertex inputs. This matches the position of a model vertex.
+layout (location = 0) in vec3 inPosition;
+
+// Renders depth from the light's point of view into a shadow map. There's
+// no fragment shader, so only the position matters.
+void main() {
+    gl_Position = pushConstants.lightModelViewProjection * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 460
+#extension GL_EXT_multiview : require
+
+// Fragment outputs.
+layout(location = 0) out vec3 fragColor;
+
+// How far each eye's triangle is shifted along x, in clip space.
+const float EYE_OFFSET = 0.25;
+
+// Draws a single triangle with no vertex buffer, once per view. The left
+// eye (view 0) sees it shifted right and the right eye (view 1) sees it
+// shifted left, like a close object seen by a pair of eyes.
+void main() {
+    vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 0.5 - 0.5;
+    float offset = gl_ViewIndex == 0 ? EYE_OFFSET : -EYE_OFFSET;
+
+    fragColor = vec3(1.0 - float(gl_ViewIndex), 0.0, float(gl_ViewIndex));
+    gl_Position = vec4(position.x + offset, position.y, 0.5, 1.0);
+}
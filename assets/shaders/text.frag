@@ -0,0 +1,19 @@
+#version 460
+
+// Fragment inputs.
+layout (location = 0) in vec3 fragColor;
+layout (location = 1) in vec2 fragTexCoord;
+
+// Fragment outputs.
+layout (location = 0) out vec4 outColor;
+
+// Bindings. The font atlas is bound as a separate image and sampler.
+layout (binding = 0) uniform texture2D fontImage;
+layout (binding = 1) uniform sampler fontSampler;
+
+void main() {
+    // The font atlas holds the glyph coverage in its alpha.
+    float coverage = texture(sampler2D(fontImage, fontSampler), fragTexCoord).a;
+
+    outColor = vec4(fragColor, coverage);
+}
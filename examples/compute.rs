@@ -0,0 +1,207 @@
+use anyhow::{anyhow, Result};
+use ash::{vk, Entry};
+use bytemuck::{Pod, Zeroable};
+use std::{mem::size_of, path::Path};
+use tracing::{error, info, level_filters::LevelFilter, subscriber::set_global_default, Level};
+use tracing_log::LogTracer;
+use tracing_subscriber::FmtSubscriber;
+use vulkan::{
+    assets_path, ComputePipeline, ComputePipelineSettings, DescriptorBinding, DescriptorResource,
+    DescriptorSetLayout, Device, DeviceRequirements, DeviceSelector, Instance, MappedBuffer,
+    MemoryCoherence, Pipeline, PipelineCache, ShaderSource, ValidationSettings
+};
+
+/// The number of values to square.
+const COUNT: u32 = 1000;
+
+/// The compute shader workgroup size.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Our push constants.
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+struct PushConstants {
+    count: u32
+}
+
+unsafe impl Zeroable for PushConstants {}
+unsafe impl Pod for PushConstants {}
+
+/// Square the values in a storage buffer on the GPU and read them back.
+unsafe fn run(device: &Device, pipeline_cache: &PipelineCache, assets_path: &Path) -> Result<()> {
+    // The compute work is recorded on the graphics queue.
+    let compute_support = device
+        .queue_family_properties()
+        .queue_flags
+        .contains(vk::QueueFlags::COMPUTE);
+
+    if !compute_support {
+        info!("Skipping, the device's queue doesn't support compute");
+
+        return Ok(());
+    }
+
+    // The values to square.
+    let values = (0..COUNT)
+        .map(|value| value as f32)
+        .collect::<Vec<_>>();
+
+    // Create the storage buffer. It's host-visible so we can read it
+    // back. It's dropped rather than destroyed, so the device destroys
+    // it along with itself, even if we return early.
+    let buffer = MappedBuffer::new(
+        device,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        MemoryCoherence::Coherent,
+        &values
+    )?;
+
+    // Create the descriptor set layout.
+    let descriptor_set_layout = DescriptorSetLayout::new(
+        device,
+        &[DescriptorBinding {
+            binding:         0,
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            stage_flags:     vk::ShaderStageFlags::COMPUTE,
+            count:           1
+        }]
+    )?;
+
+    // Create the pipeline.
+    let pipeline = ComputePipeline::new(
+        device,
+        pipeline_cache,
+        &ComputePipelineSettings {
+            comp_shader:            ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/square.comp")
+            )),
+            comp_entry:             None,
+            comp_specialization:    vec![],
+            descriptor_set_layouts: Some(vec![*descriptor_set_layout]),
+            push_constant_ranges:   vec![vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset:      0,
+                size:        size_of::<PushConstants>() as u32
+            }]
+        }
+    )?;
+
+    // Create the descriptor pool.
+    let descriptor_pool = descriptor_set_layout.new_pool(device, 1)?;
+
+    // Create the descriptor set, pointing at the buffer.
+    let descriptor_set = descriptor_set_layout.allocate(
+        device,
+        &descriptor_pool,
+        &[(0, DescriptorResource::buffer(*buffer, 0, vk::WHOLE_SIZE))]
+    )?;
+
+    // Square the values.
+    device.one_time_graphics_command(|command_buffer| {
+        // Bind the pipeline.
+        pipeline.bind(device, &command_buffer);
+
+        // Bind the descriptor set.
+        pipeline.bind_descriptor_sets(device, &command_buffer, 0, &[descriptor_set]);
+
+        // Push the constants.
+        pipeline.push_constants(device, &command_buffer, 0, &PushConstants { count: COUNT });
+
+        // Dispatch enough workgroups to cover the buffer.
+        pipeline.dispatch(
+            device,
+            &command_buffer,
+            COUNT.div_ceil(WORKGROUP_SIZE),
+            1,
+            1
+        );
+
+        // Make the shader writes visible to the host.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)],
+            &[],
+            &[]
+        );
+
+        Ok(())
+    })?;
+
+    // Read the results back and check them.
+    let results = buffer.read(device)?;
+
+    for (value, result) in values.iter().zip(&results) {
+        if value * value != *result {
+            return Err(anyhow!(
+                "Expected {} squared to be {} but got {}.",
+                value,
+                value * value,
+                result
+            ));
+        }
+    }
+
+    info!(
+        "Squared {} values, the last is {}",
+        COUNT,
+        results[results.len() - 1]
+    );
+
+    // Destroy the descriptor pool. The buffer, layout, and pipeline
+    // are dropped, so the device destroys them along with itself.
+    device.destroy_descriptor_pool(descriptor_pool, None);
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    // Catch panics and emit them as errors.
+    std::panic::set_hook(Box::new(|panic_info| {
+        error!("{}", panic_info);
+    }));
+
+    // This routes log macros through tracing.
+    LogTracer::init()?;
+
+    // Setup the tracing subscriber globally.
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(LevelFilter::from_level(Level::INFO))
+        .finish();
+
+    set_global_default(subscriber)?;
+
+    let assets_path = assets_path()?;
+
+    unsafe {
+        // Load the Vulkan library.
+        let entry = Entry::linked();
+
+        // Create the instance wrapper without any surface extensions.
+        let instance = Instance::new_headless(&entry, &ValidationSettings::default())?;
+
+        // Create the device wrapper. We never present.
+        let device = Device::new(
+            &instance,
+            None,
+            &DeviceSelector::Auto,
+            &DeviceRequirements::default()
+        )?;
+
+        // Create the pipeline cache.
+        let mut pipeline_cache = PipelineCache::new(&device, &PipelineCache::default_path()?)?;
+
+        // Run the compute work.
+        let result = run(&device, &pipeline_cache, &assets_path);
+
+        // Save and destroy the pipeline cache. The device and then
+        // the instance are destroyed as they're dropped.
+        pipeline_cache.destroy(&device);
+
+        result
+    }
+}
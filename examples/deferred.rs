@@ -0,0 +1,484 @@
+use anyhow::{anyhow, Result};
+use ash::{vk, Entry};
+use std::path::Path;
+use tracing::{error, info, level_filters::LevelFilter, subscriber::set_global_default, Level};
+use tracing_log::LogTracer;
+use tracing_subscriber::FmtSubscriber;
+use vulkan::{
+    assets_path, AttachmentImage, BlendMode, ClipSpace, DepthStencilSettings, DescriptorBinding,
+    DescriptorResource, DescriptorSetLayout, Device, DeviceRequirements, DeviceSelector,
+    FrameBuffers, ImageKind, ImageSettings, Instance, MappedBuffer, MemoryCoherence, Pipeline,
+    PipelineCache, PipelineSettings, RenderPass, Sampler, SamplerSettings, ShaderSource,
+    ValidationSettings
+};
+
+/// The size of the rendered image.
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width:  64,
+    height: 64
+};
+
+/// The formats of the geometry buffer targets, the albedo and the normal.
+const GBUFFER_FORMATS: [vk::Format; 2] =
+    [vk::Format::R8G8B8A8_UNORM, vk::Format::R16G16B16A16_SFLOAT];
+
+/// The format of the lit image.
+const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// The number of bytes per pixel of the lit image.
+const PIXEL_SIZE: usize = 4;
+
+/// The lit color the shaders should produce, the surface's
+/// albedo scaled by how directly it faces the light.
+const EXPECTED_COLOR: [f32; 3] = [0.8, 0.4, 0.2];
+
+/// How far each channel of the lit color may be from the expected one.
+const TOLERANCE: f32 = 2.0 / 255.0;
+
+/// Fill a geometry buffer with several render targets at once, light it
+/// in a second pass, read the result back, and check it's lit correctly.
+unsafe fn run(device: &Device, pipeline_cache: &PipelineCache, assets_path: &Path) -> Result<()> {
+    // The geometry buffer targets are sampled by the lighting pass.
+    let depth_format = device.sampled_depth_format()?;
+
+    // Create the render passes. The geometry pass leaves its targets ready
+    // to sample, and the lighting pass leaves the lit image ready to be
+    // copied. Everything but the frame buffers and the descriptor pool is
+    // dropped rather than destroyed, so the device destroys it later.
+    let geometry_pass = RenderPass::new_with_formats(
+        device,
+        &GBUFFER_FORMATS,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        Some(depth_format)
+    )?;
+
+    let lighting_pass = RenderPass::new_with_format(
+        device,
+        COLOR_FORMAT,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::SampleCountFlags::TYPE_1
+    )?;
+
+    // Create the geometry buffer targets.
+    let gbuffer_images = GBUFFER_FORMATS
+        .iter()
+        .map(|format| {
+            AttachmentImage::new(
+                device,
+                &image_settings(
+                    *format,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
+                ),
+                &EXTENT,
+                vk::ImageAspectFlags::COLOR
+            )
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Create the depth image.
+    let depth_image = AttachmentImage::new(
+        device,
+        &image_settings(depth_format, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT),
+        &EXTENT,
+        vk::ImageAspectFlags::DEPTH
+    )?;
+
+    // Create the lit image.
+    let color_image = AttachmentImage::new(
+        device,
+        &image_settings(
+            COLOR_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC
+        ),
+        &EXTENT,
+        vk::ImageAspectFlags::COLOR
+    )?;
+
+    // Create the geometry pipeline. It writes every target
+    // of the geometry buffer and tests against the depth.
+    let geometry_pipeline = Pipeline::new(
+        device,
+        pipeline_cache,
+        &geometry_pass,
+        &pipeline_settings(
+            assets_path,
+            "shaders/deferred_geometry.frag",
+            Some(DepthStencilSettings::default()),
+            None
+        )
+    )?;
+
+    // Create the descriptor set layout for the lighting pass. The
+    // geometry buffer targets are bound as separate images that
+    // share a sampler.
+    let descriptor_set_layout = DescriptorSetLayout::new(
+        device,
+        &[
+            DescriptorBinding {
+                binding:         0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                count:           1
+            },
+            DescriptorBinding {
+                binding:         1,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                count:           1
+            },
+            DescriptorBinding {
+                binding:         2,
+                descriptor_type: vk::DescriptorType::SAMPLER,
+                stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                count:           1
+            }
+        ]
+    )?;
+
+    // Create the lighting pipeline.
+    let lighting_pipeline = Pipeline::new(
+        device,
+        pipeline_cache,
+        &lighting_pass,
+        &pipeline_settings(
+            assets_path,
+            "shaders/deferred_lighting.frag",
+            None,
+            Some(vec![*descriptor_set_layout])
+        )
+    )?;
+
+    // Create the sampler. Each pixel of the lit image
+    // reads the same pixel of the geometry buffer.
+    let sampler = Sampler::new(
+        device,
+        &SamplerSettings {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            max_anisotropy: None,
+            ..Default::default()
+        }
+    )?;
+
+    // Create the descriptor pool and set, pointing at the geometry buffer.
+    let descriptor_pool = descriptor_set_layout.new_pool(device, 1)?;
+
+    let descriptor_set = descriptor_set_layout.allocate(
+        device,
+        &descriptor_pool,
+        &[
+            (
+                0,
+                DescriptorResource::image(
+                    *gbuffer_images[0].view(),
+                    vk::Sampler::null(),
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                )
+            ),
+            (
+                1,
+                DescriptorResource::image(
+                    *gbuffer_images[1].view(),
+                    vk::Sampler::null(),
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                )
+            ),
+            (
+                2,
+                DescriptorResource::image(
+                    vk::ImageView::null(),
+                    *sampler,
+                    vk::ImageLayout::UNDEFINED
+                )
+            )
+        ]
+    )?;
+
+    // Create a buffer the host can read the lit image back into.
+    let buffer = MappedBuffer::new(
+        device,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryCoherence::Coherent,
+        &vec![0u8; (EXTENT.width * EXTENT.height) as usize * PIXEL_SIZE]
+    )?;
+
+    // Create the frame buffers wrappers last, as they're destroyed by
+    // hand. The geometry frame buffer has every target, in the render
+    // pass's order, followed by the depth.
+    let mut geometry_frame_buffers = FrameBuffers::new_from_views(
+        device,
+        &[*gbuffer_images[0].view()],
+        &EXTENT,
+        &geometry_pass,
+        |_, view| vec![view, *gbuffer_images[1].view(), *depth_image.view()]
+    )?;
+
+    let mut lighting_frame_buffers = FrameBuffers::new_from_views(
+        device,
+        &[*color_image.view()],
+        &EXTENT,
+        &lighting_pass,
+        |_, view| vec![view]
+    )?;
+
+    // Render both passes and copy the lit image to the buffer.
+    let result = device.one_time_graphics_command(|command_buffer| {
+        // Fill the geometry buffer. The render pass makes the
+        // writes visible to the lighting pass's fragment shader.
+        geometry_pass.begin(
+            device,
+            &EXTENT,
+            &geometry_frame_buffers,
+            &command_buffer,
+            0,
+            vk::SubpassContents::INLINE
+        );
+
+        set_dynamic_state(device, &command_buffer);
+
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *geometry_pipeline
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        geometry_pass.end(device, &command_buffer);
+
+        // Light the geometry buffer.
+        lighting_pass.begin(
+            device,
+            &EXTENT,
+            &lighting_frame_buffers,
+            &command_buffer,
+            0,
+            vk::SubpassContents::INLINE
+        );
+
+        set_dynamic_state(device, &command_buffer);
+
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *lighting_pipeline
+        );
+        device.cmd_bind_descriptor_sets(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *lighting_pipeline.pipeline_layout(),
+            0,
+            &[descriptor_set],
+            &[]
+        );
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        lighting_pass.end(device, &command_buffer);
+
+        // Wait for the lighting pass to finish writing the image.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(*color_image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask:      vk::ImageAspectFlags::COLOR,
+                    base_mip_level:   0,
+                    level_count:      1,
+                    base_array_layer: 0,
+                    layer_count:      1
+                })]
+        );
+
+        // Copy the image.
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            *color_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            *buffer,
+            &[vk::BufferImageCopy::default()
+                .image_subresource(vk::ImageSubresourceLayers {
+                    aspect_mask:      vk::ImageAspectFlags::COLOR,
+                    mip_level:        0,
+                    base_array_layer: 0,
+                    layer_count:      1
+                })
+                .image_extent(vk::Extent3D {
+                    width:  EXTENT.width,
+                    height: EXTENT.height,
+                    depth:  1
+                })]
+        );
+
+        // Make the copy visible to the host.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)],
+            &[],
+            &[]
+        );
+
+        Ok(())
+    });
+
+    // Destroy the frame buffers and the descriptor pool. The rest is dropped.
+    lighting_frame_buffers.destroy(device);
+    geometry_frame_buffers.destroy(device);
+    device.destroy_descriptor_pool(descriptor_pool, None);
+
+    result?;
+
+    // Read the lit image back and check the pixel in its center.
+    let pixels = buffer.read(device)?;
+    let center = ((EXTENT.height / 2 * EXTENT.width + EXTENT.width / 2) as usize) * PIXEL_SIZE;
+
+    let color = pixels[center..center + 3]
+        .iter()
+        .map(|channel| *channel as f32 / 255.0)
+        .collect::<Vec<_>>();
+
+    let lit = color
+        .iter()
+        .zip(EXPECTED_COLOR)
+        .all(|(channel, expected)| (channel - expected).abs() <= TOLERANCE);
+
+    if !lit {
+        return Err(anyhow!(
+            "Expected the lit color to be {:?} but got {:?}.",
+            EXPECTED_COLOR,
+            color
+        ));
+    }
+
+    info!(
+        "Lit a geometry buffer with {} targets, the color is {:?}",
+        GBUFFER_FORMATS.len(),
+        color
+    );
+
+    Ok(())
+}
+
+/// The settings for a single-sampled 2D image of the given format and usage.
+fn image_settings(format: vk::Format, usage: vk::ImageUsageFlags) -> ImageSettings {
+    ImageSettings {
+        format,
+        usage,
+        tiling: vk::ImageTiling::OPTIMAL,
+        samples: vk::SampleCountFlags::TYPE_1,
+        mip_levels: 1,
+        kind: ImageKind::Flat,
+        memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+    }
+}
+
+/// The settings for a pipeline that draws a fullscreen triangle with the
+/// given frag shader. There's no vertex buffer, the vertex shader derives
+/// the corners from the vertex index.
+fn pipeline_settings(
+    assets_path: &Path,
+    frag_shader: &str,
+    depth_stencil: Option<DepthStencilSettings>,
+    descriptor_set_layouts: Option<Vec<vk::DescriptorSetLayout>>
+) -> PipelineSettings {
+    PipelineSettings {
+        subpass: 0,
+        vert_shader: ShaderSource::File(Pipeline::shader_path(
+            &assets_path.join("shaders/fullscreen.vert")
+        )),
+        frag_shader: Some(ShaderSource::File(Pipeline::shader_path(
+            &assets_path.join(frag_shader)
+        ))),
+        vert_entry: None,
+        frag_entry: None,
+        vert_specialization: vec![],
+        frag_specialization: vec![],
+        vertex_descriptions: None,
+        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        primitive_restart: false,
+        polygon_mode: vk::PolygonMode::FILL,
+        cull_mode: vk::CullModeFlags::NONE,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        blend: BlendMode::None,
+        samples: vk::SampleCountFlags::TYPE_1,
+        depth_stencil,
+        depth_bias: None,
+        dynamic_depth_bias: false,
+        descriptor_set_layouts,
+        push_constant_ranges: vec![]
+    }
+}
+
+/// Cover the image with the viewport and scissor. The fullscreen
+/// triangle's corners are in Vulkan's clip space.
+unsafe fn set_dynamic_state(device: &Device, command_buffer: &vk::CommandBuffer) {
+    device.cmd_set_viewport(*command_buffer, 0, &[ClipSpace::YDown.viewport(&EXTENT)]);
+    device.reset_scissor(command_buffer, &EXTENT);
+
+    // The line width is dynamic state, even though we only fill.
+    device.set_line_width(command_buffer, 1.0);
+}
+
+fn main() -> Result<()> {
+    // Catch panics and emit them as errors.
+    std::panic::set_hook(Box::new(|panic_info| {
+        error!("{}", panic_info);
+    }));
+
+    // This routes log macros through tracing.
+    LogTracer::init()?;
+
+    // Setup the tracing subscriber globally.
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(LevelFilter::from_level(Level::INFO))
+        .finish();
+
+    set_global_default(subscriber)?;
+
+    let assets_path = assets_path()?;
+
+    unsafe {
+        // Load the Vulkan library.
+        let entry = Entry::linked();
+
+        // Create the instance wrapper without any surface extensions.
+        let instance = Instance::new_headless(&entry, &ValidationSettings::default())?;
+
+        // Create the device wrapper. We never present.
+        let device = Device::new(
+            &instance,
+            None,
+            &DeviceSelector::Auto,
+            &DeviceRequirements::default()
+        )?;
+
+        // Create the pipeline cache.
+        let mut pipeline_cache = PipelineCache::new(&device, &PipelineCache::default_path()?)?;
+
+        // Render and light the geometry buffer.
+        let result = run(&device, &pipeline_cache, &assets_path);
+
+        // Save and destroy the pipeline cache. The device and then
+        // the instance are destroyed as they're dropped.
+        pipeline_cache.destroy(&device);
+
+        result
+    }
+}
@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+use tracing::{error, info, level_filters::LevelFilter, subscriber::set_global_default, Level};
+use tracing_log::LogTracer;
+use tracing_subscriber::FmtSubscriber;
+use vulkan::{
+    assets_path, DeviceRequirements, DeviceSelector, HeadlessRenderer, ValidationSettings
+};
+
+/// The size of the rendered image.
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width:  1024,
+    height: 768
+};
+
+fn main() -> Result<()> {
+    // Catch panics and emit them as errors.
+    std::panic::set_hook(Box::new(|panic_info| {
+        error!("{}", panic_info);
+    }));
+
+    // This routes log macros through tracing.
+    LogTracer::init()?;
+
+    // Setup the tracing subscriber globally.
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(LevelFilter::from_level(Level::INFO))
+        .finish();
+
+    set_global_default(subscriber)?;
+
+    // Create the headless renderer.
+    let mut renderer = unsafe {
+        HeadlessRenderer::new(
+            assets_path()?,
+            EXTENT,
+            &DeviceSelector::Auto,
+            &DeviceRequirements::default(),
+            &ValidationSettings {
+                collect_messages: true,
+                ..Default::default()
+            }
+        )?
+    };
+
+    // The live resources before rendering, in debug builds.
+    let live_before = renderer
+        .device()
+        .live_resource_counts();
+
+    // Render a frame and read it back.
+    let image = unsafe {
+        renderer.draw()?;
+        renderer.screenshot()?
+    };
+
+    // Fail if rendering leaked a buffer, image, or allocation.
+    let live_after = renderer
+        .device()
+        .live_resource_counts();
+
+    if live_after != live_before {
+        return Err(anyhow!(
+            "Rendering leaked resources, {:?} were live before and {:?} after.",
+            live_before,
+            live_after
+        ));
+    }
+
+    // Fail if the validation layer reported any errors.
+    if let Some(debugging) = renderer.debugging() {
+        let errors = debugging.errors_since();
+
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "The validation layer reported {} errors, the first is: {}",
+                errors.len(),
+                errors[0].text
+            ));
+        }
+    }
+
+    // Save the frame to disk.
+    image.save("headless.png")?;
+
+    info!("Saved headless.png");
+
+    Ok(())
+}
@@ -1,18 +1,51 @@
-use anyhow::{anyhow, Result};
-use std::{env::current_exe, fs::canonicalize, path::PathBuf, sync::Arc, time::Instant};
-use tracing::{debug, error, level_filters::LevelFilter, subscriber::set_global_default, Level};
+use anyhow::Result;
+use ash::vk;
+use glam::{Vec2, Vec3};
+use std::{
+    env::var,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+use tracing::{
+    debug, error, info, level_filters::LevelFilter, subscriber::set_global_default, Level
+};
 use tracing_log::LogTracer;
 use tracing_subscriber::FmtSubscriber;
-use vulkan::Renderer;
+use vulkan::{
+    assets_path, ColorSpacePreference, DebugLevel, DeviceSelector, PresentModePreference,
+    RenderMode, Renderer, RendererBuilder, SwapchainConfig, ValidationMode, ValidationSettings,
+    VulkanError
+};
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
     event::{StartCause, WindowEvent},
     event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
     keyboard::{Key, NamedKey},
-    window::{Window, WindowId}
+    window::{Fullscreen, Window, WindowId}
 };
 
+/// The number of threads to record the main pass on when threading is on.
+const RECORDING_THREADS: usize = 2;
+
+/// How much the width of wireframe lines changes per key press.
+const LINE_WIDTH_STEP: f32 = 1.0;
+
+/// The internal resolution to render at when it's toggled on.
+const INTERNAL_RESOLUTION: vk::Extent2D = vk::Extent2D {
+    width:  640,
+    height: 360
+};
+
+/// The render scale to render at when it's toggled on.
+const RENDER_SCALE: f32 = 0.75;
+
+/// Where the stats overlay is drawn, in logical pixels.
+const OVERLAY_POSITION: Vec2 = Vec2::new(8.0, 8.0);
+
+/// The height of a line of the stats overlay, in logical pixels.
+const OVERLAY_TEXT_SIZE: f32 = 16.0;
+
 /// The app.
 struct App {
     /// Whether we are setup.
@@ -27,8 +60,11 @@ struct App {
     /// The fps timer.
     fps_timer: Instant,
 
-    /// The fps count.
-    fps_count: u32
+    /// The frame rate cap, or none to draw as fast as possible.
+    max_fps: Option<u32>,
+
+    /// When the last frame started.
+    last_frame: Instant
 }
 
 impl Default for App {
@@ -38,7 +74,8 @@ impl Default for App {
             window:      None,
             renderer:    None,
             fps_timer:   Instant::now(),
-            fps_count:   0
+            max_fps:     Self::max_fps(),
+            last_frame:  Instant::now()
         }
     }
 }
@@ -60,10 +97,16 @@ impl App {
         let window = Arc::new(window);
 
         // Get the assets path.
-        let assets_path = Self::assets_path()?;
+        let assets_path = assets_path()?;
 
         // Create the vulkan renderer.
-        let renderer = unsafe { Renderer::new(window.clone(), assets_path)? };
+        let renderer = unsafe {
+            RendererBuilder::new(assets_path)
+                .device_selector(Self::device_selector())
+                .validation(Self::validation_settings())
+                .text(true)
+                .build(window.clone())?
+        };
 
         self.initialized = true;
         self.window = Some(window);
@@ -73,23 +116,129 @@ impl App {
         Ok(())
     }
 
-    // TODO: This sucks, make it better!
+    /// Get the validation settings from the environment. VULKAN_VALIDATION
+    /// can be "on" or "off", otherwise it's automatic. VULKAN_VALIDATION_FEATURES
+    /// is a comma separated list of "gpu-assisted" and "best-practices".
+    /// VULKAN_DEBUG_LEVEL can be "info", "warning", or "error" to hide
+    /// less severe messages, otherwise everything is reported.
+    fn validation_settings() -> ValidationSettings {
+        let mode = match var("VULKAN_VALIDATION").as_deref() {
+            Ok("on") => ValidationMode::On,
+            Ok("off") => ValidationMode::Off,
+            _ => ValidationMode::Auto
+        };
+
+        let features = var("VULKAN_VALIDATION_FEATURES").unwrap_or_default();
+        let features = features
+            .split(',')
+            .map(str::trim)
+            .collect::<Vec<_>>();
+
+        let debug_level = match var("VULKAN_DEBUG_LEVEL").as_deref() {
+            Ok("info") => DebugLevel::Info,
+            Ok("warning") => DebugLevel::Warning,
+            Ok("error") => DebugLevel::Error,
+            _ => DebugLevel::Verbose
+        };
+
+        ValidationSettings {
+            mode,
+            gpu_assisted: features.contains(&"gpu-assisted"),
+            best_practices: features.contains(&"best-practices"),
+            debug_level,
+            ..Default::default()
+        }
+    }
+
+    /// Get the frame rate cap from the MAX_FPS environment variable.
+    /// Zero or anything that isn't a number means there's no cap.
+    fn max_fps() -> Option<u32> {
+        var("MAX_FPS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|max_fps| *max_fps > 0)
+    }
+
+    /// Schedule the next frame. Without a cap we redraw right away,
+    /// otherwise we sleep until a frame time after the last frame.
+    fn schedule_frame(&self, event_loop: &ActiveEventLoop) {
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        match self.max_fps {
+            Some(max_fps) => {
+                let next_frame = self.last_frame + Duration::from_secs_f64(1.0 / max_fps as f64);
+
+                // We're already late, draw now.
+                match next_frame <= Instant::now() {
+                    true => window.request_redraw(),
+                    false => event_loop.set_control_flow(ControlFlow::WaitUntil(next_frame))
+                }
+            },
+
+            None => window.request_redraw()
+        }
+    }
+
+    /// Get the device selector from the VULKAN_DEVICE environment variable. It
+    /// can be "integrated", a device index, or part of a device name.
+    fn device_selector() -> DeviceSelector {
+        match var("VULKAN_DEVICE") {
+            Ok(value) if value == "integrated" => DeviceSelector::PreferIntegrated,
+            Ok(value) => match value.parse() {
+                Ok(index) => DeviceSelector::Index(index),
+                Err(_) => DeviceSelector::Name(value)
+            },
+            Err(_) => DeviceSelector::Auto
+        }
+    }
 
-    /// Get the path to the assets directory.
-    fn assets_path() -> Result<PathBuf> {
-        let path = current_exe()?
-            .parent()
-            .map(PathBuf::from)
-            .ok_or_else(|| anyhow!("Could not get parent directory"))?;
-        let path = path.join("../../../assets");
-        let path = canonicalize(path)?;
+    /// Toggle borderless fullscreen on the window's current monitor.
+    /// Borderless fullscreen keeps the monitor's current video mode, so
+    /// the window is resized to the monitor's size and the renderer
+    /// recreates its swapchain at the next draw after the resize event.
+    fn toggle_fullscreen(&self) {
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        match window.fullscreen() {
+            Some(_) => window.set_fullscreen(None),
+            None => {
+                let monitor = window.current_monitor();
+
+                // Log the mode we expect to end up in.
+                if let Some(monitor) = &monitor {
+                    let size = monitor.size();
+
+                    info!(
+                        "Fullscreen on {}: {}x{} @ {:.2} Hz",
+                        monitor.name().unwrap_or_default(),
+                        size.width,
+                        size.height,
+                        monitor
+                            .refresh_rate_millihertz()
+                            .unwrap_or_default() as f32
+                            / 1000.0
+                    );
+                }
 
-        Ok(path)
+                window.set_fullscreen(Some(Fullscreen::Borderless(monitor)));
+            }
+        }
     }
 }
 
 impl ApplicationHandler for App {
-    fn new_events(&mut self, _event_loop: &ActiveEventLoop, _cause: StartCause) {
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        // It's time for the next capped frame.
+        if let StartCause::ResumeTimeReached { .. } = cause {
+            if let Some(window) = &self.window {
+                window.request_redraw();
+            }
+        }
+
         // Print the fps every second.
         if self
             .fps_timer
@@ -97,11 +246,24 @@ impl ApplicationHandler for App {
             .as_secs_f32()
             >= 1.0
         {
-            debug!("fps: {}", self.fps_count);
+            if let Some(renderer) = &self.renderer {
+                let stats = renderer.frame_stats();
+
+                // Include the GPU time if we have it.
+                match renderer.gpu_time() {
+                    Some(gpu_time) => debug!(
+                        "fps: {:.0}, cpu: {:.3?} (min {:.3?}, max {:.3?}), gpu: {:.3} ms",
+                        stats.fps, stats.average, stats.min, stats.max, gpu_time
+                    ),
+                    None => debug!(
+                        "fps: {:.0}, cpu: {:.3?} (min {:.3?}, max {:.3?})",
+                        stats.fps, stats.average, stats.min, stats.max
+                    )
+                }
+            }
 
-            // Reset the timer / counter.
+            // Reset the timer.
             self.fps_timer = Instant::now();
-            self.fps_count = 0;
         }
     }
 
@@ -135,34 +297,61 @@ impl ApplicationHandler for App {
             },
 
             WindowEvent::RedrawRequested => {
+                // The next capped frame is timed from this one.
+                self.last_frame = Instant::now();
+
                 // Render the frame.
                 if let Some(renderer) = &mut self.renderer {
+                    // Overlay the frame stats.
+                    let stats = renderer.frame_stats();
+                    let overlay = match renderer.gpu_time() {
+                        Some(gpu_time) => {
+                            format!("fps: {:.0}\ngpu: {:.3} ms", stats.fps, gpu_time)
+                        },
+                        None => format!("fps: {:.0}", stats.fps)
+                    };
+
+                    renderer.draw_text(&overlay, OVERLAY_POSITION, OVERLAY_TEXT_SIZE, Vec3::ONE);
+
                     if let Err(e) = unsafe { renderer.draw() } {
                         error!("{}", e);
 
-                        event_loop.exit();
-                    }
-                }
+                        // Rebuilding everything after losing the device
+                        // isn't supported yet, so give up.
+                        if VulkanError::is_device_lost(&e) {
+                            event_loop.exit();
+
+                            return;
+                        }
 
-                // Increment the fps count.
-                self.fps_count += 1;
+                        // Try to continue, giving up if we can't.
+                        if let Err(e) = unsafe { renderer.recover() } {
+                            error!("{}", e);
 
-                // Request a redraw.
-                if let Some(window) = &mut self.window {
-                    window.request_redraw();
+                            event_loop.exit();
+                        }
+                    }
                 }
+
+                // Schedule the next frame.
+                self.schedule_frame(event_loop);
             },
 
             WindowEvent::Resized(size) => {
                 debug!("resized: {:?}", size);
 
-                // A resize occurred.
+                // A resize occurred. The renderer applies it at the next draw.
                 if let Some(renderer) = &mut self.renderer {
-                    if let Err(e) = unsafe { renderer.resize(&size) } {
-                        error!("{}", e);
+                    renderer.resize(&size);
+                }
+            },
 
-                        event_loop.exit();
-                    }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                debug!("scale factor changed: {}", scale_factor);
+
+                // The window moved to a display with a different DPI.
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.set_scale_factor(scale_factor);
                 }
             },
 
@@ -175,6 +364,128 @@ impl ApplicationHandler for App {
                     event_loop.exit();
                 },
 
+                // Toggle borderless fullscreen.
+                Key::Named(key) if key == NamedKey::F11 && event.state.is_pressed() => {
+                    self.toggle_fullscreen();
+                },
+
+                // Toggle between the triangle and compute rendering.
+                Key::Character(key) if key == "c" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let render_mode = match renderer.render_mode() {
+                            RenderMode::Triangle => RenderMode::ComputePresent,
+                            RenderMode::ComputePresent => RenderMode::Triangle
+                        };
+
+                        if let Err(e) = renderer.set_render_mode(render_mode) {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
+                // Toggle drawing wireframes.
+                Key::Character(key) if key == "w" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let wireframe = !renderer.wireframe();
+
+                        if let Err(e) = unsafe { renderer.set_wireframe(wireframe) } {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
+                // Change the width of wireframe lines.
+                Key::Character(key) if (key == "[" || key == "]") && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let line_width = match key.as_str() {
+                            "[" => renderer.line_width() - LINE_WIDTH_STEP,
+                            _ => renderer.line_width() + LINE_WIDTH_STEP
+                        };
+
+                        renderer.set_line_width(line_width.max(1.0));
+                    }
+                },
+
+                // Toggle recording the main pass on several threads.
+                Key::Character(key) if key == "t" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let threads = match renderer.recording_threads() {
+                            0 => RECORDING_THREADS,
+                            _ => 0
+                        };
+
+                        if let Err(e) = unsafe { renderer.set_recording_threads(threads) } {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
+                // Toggle rendering at a low internal resolution.
+                Key::Character(key) if key == "r" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let extent = match renderer.internal_resolution() {
+                            Some(_) => None,
+                            None => Some(INTERNAL_RESOLUTION)
+                        };
+
+                        if let Err(e) = unsafe { renderer.set_internal_resolution(extent) } {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
+                // Toggle rendering at a fraction of the window's resolution.
+                Key::Character(key) if key == "s" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let render_scale = match renderer.render_scale() {
+                            Some(_) => None,
+                            None => Some(RENDER_SCALE)
+                        };
+
+                        if let Err(e) = unsafe { renderer.set_render_scale(render_scale) } {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
+                // Toggle HDR, if the display supports it.
+                Key::Character(key) if key == "h" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let color_space_preference = match renderer.color_space_preference() {
+                            ColorSpacePreference::HdrLinear => ColorSpacePreference::Srgb,
+                            _ => ColorSpacePreference::HdrLinear
+                        };
+
+                        let config = SwapchainConfig {
+                            color_space_preference,
+                            ..renderer.swapchain_config()
+                        };
+
+                        if let Err(e) = unsafe { renderer.set_swapchain_config(config) } {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
+                // Toggle vsync.
+                Key::Character(key) if key == "v" && event.state.is_pressed() => {
+                    if let Some(renderer) = &mut self.renderer {
+                        let present_mode_preference = match renderer.present_mode_preference() {
+                            PresentModePreference::Fifo => PresentModePreference::Immediate,
+                            _ => PresentModePreference::Fifo
+                        };
+
+                        let config = SwapchainConfig {
+                            present_mode_preference,
+                            ..renderer.swapchain_config()
+                        };
+
+                        if let Err(e) = unsafe { renderer.set_swapchain_config(config) } {
+                            error!("{}", e);
+                        }
+                    }
+                },
+
                 _ => {}
             },
 
@@ -202,7 +513,8 @@ fn main() -> Result<()> {
     // Create the event loop.
     let event_loop = EventLoop::new()?;
 
-    // Poll continuously.
+    // Poll continuously. With a frame rate cap this is replaced by
+    // waiting until the next frame is due.
     event_loop.set_control_flow(ControlFlow::Poll);
 
     let mut app = App::default();
@@ -0,0 +1,319 @@
+use anyhow::{anyhow, Result};
+use ash::{vk, Entry};
+use std::path::Path;
+use tracing::{error, info, level_filters::LevelFilter, subscriber::set_global_default, Level};
+use tracing_log::LogTracer;
+use tracing_subscriber::FmtSubscriber;
+use vulkan::{
+    assets_path, AttachmentImage, BlendMode, ClipSpace, DepthStencilSettings, Device,
+    DeviceRequirements, DeviceSelector, FrameBuffers, ImageKind, ImageSettings, Instance,
+    MappedBuffer, MemoryCoherence, Pipeline, PipelineCache, PipelineSettings, RenderPass,
+    ShaderSource, ValidationSettings
+};
+
+/// The size of each eye's image.
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width:  64,
+    height: 64
+};
+
+/// The number of views, one per eye.
+const VIEW_COUNT: u32 = 2;
+
+/// The format of the color image.
+const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+/// The number of bytes per pixel of the color image.
+const PIXEL_SIZE: usize = 4;
+
+/// Render a triangle to both eyes of a layered image in a single
+/// multiview pass, read the eyes back, and check they differ.
+unsafe fn run(device: &Device, pipeline_cache: &PipelineCache, assets_path: &Path) -> Result<()> {
+    // Multiview is only enabled if the device supports it.
+    if !device.multiview_enabled() {
+        info!("Skipping, the device doesn't support multiview");
+
+        return Ok(());
+    }
+
+    // Create the render pass. It renders both views at once and leaves
+    // the color image ready to be copied. Everything but the frame buffers
+    // is dropped rather than destroyed, so the device destroys it later.
+    let depth_format = device.sampled_depth_format()?;
+
+    let render_pass = RenderPass::new_multiview(
+        device,
+        COLOR_FORMAT,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::SampleCountFlags::TYPE_1,
+        Some(depth_format),
+        VIEW_COUNT
+    )?;
+
+    // Create the color image, with a layer per view.
+    let color_image = AttachmentImage::new(
+        device,
+        &ImageSettings {
+            format:            COLOR_FORMAT,
+            usage:             vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+            tiling:            vk::ImageTiling::OPTIMAL,
+            samples:           vk::SampleCountFlags::TYPE_1,
+            mip_levels:        1,
+            kind:              ImageKind::Array(VIEW_COUNT),
+            memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+        },
+        &EXTENT,
+        vk::ImageAspectFlags::COLOR
+    )?;
+
+    // Create the depth image, also with a layer per view.
+    let depth_image = AttachmentImage::new(
+        device,
+        &ImageSettings {
+            format:            depth_format,
+            usage:             vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            tiling:            vk::ImageTiling::OPTIMAL,
+            samples:           vk::SampleCountFlags::TYPE_1,
+            mip_levels:        1,
+            kind:              ImageKind::Array(VIEW_COUNT),
+            memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+        },
+        &EXTENT,
+        vk::ImageAspectFlags::DEPTH
+    )?;
+
+    // Create the pipeline. The vert shader offsets the
+    // triangle by gl_ViewIndex, so each eye sees it elsewhere.
+    let pipeline = Pipeline::new(
+        device,
+        pipeline_cache,
+        &render_pass,
+        &PipelineSettings {
+            subpass:                0,
+            vert_shader:            ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/stereo.vert")
+            )),
+            frag_shader:            Some(ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/stereo.frag")
+            ))),
+            vert_entry:             None,
+            frag_entry:             None,
+            vert_specialization:    vec![],
+            frag_specialization:    vec![],
+            vertex_descriptions:    None,
+            topology:               vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart:      false,
+            polygon_mode:           vk::PolygonMode::FILL,
+            cull_mode:              vk::CullModeFlags::NONE,
+            front_face:             vk::FrontFace::COUNTER_CLOCKWISE,
+            blend:                  BlendMode::None,
+            samples:                vk::SampleCountFlags::TYPE_1,
+            depth_stencil:          Some(DepthStencilSettings::default()),
+            depth_bias:             None,
+            dynamic_depth_bias:     false,
+            descriptor_set_layouts: None,
+            push_constant_ranges:   vec![]
+        }
+    )?;
+
+    // Create a buffer the host can read both eyes back into.
+    let layer_size = (EXTENT.width * EXTENT.height) as usize * PIXEL_SIZE;
+
+    let buffer = MappedBuffer::new(
+        device,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        MemoryCoherence::Coherent,
+        &vec![0u8; layer_size * VIEW_COUNT as usize]
+    )?;
+
+    // Create the frame buffers wrapper last, as it's destroyed by hand. The
+    // frame buffer has one layer, multiview renders to the attachments'
+    // layers itself.
+    let mut frame_buffers = FrameBuffers::new_from_views(
+        device,
+        &[*color_image.view()],
+        &EXTENT,
+        &render_pass,
+        |_, view| vec![view, *depth_image.view()]
+    )?;
+
+    // Render both eyes and copy them to the buffer.
+    let result = device.one_time_graphics_command(|command_buffer| {
+        // Begin the render pass.
+        render_pass.begin(
+            device,
+            &EXTENT,
+            &frame_buffers,
+            &command_buffer,
+            0,
+            vk::SubpassContents::INLINE
+        );
+
+        // Set the viewport, scissor, and line width.
+        device.cmd_set_viewport(command_buffer, 0, &[ClipSpace::YDown.viewport(&EXTENT)]);
+        device.reset_scissor(&command_buffer, &EXTENT);
+        device.set_line_width(&command_buffer, 1.0);
+
+        // Draw the triangle once, which draws it to every view.
+        device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, *pipeline);
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+
+        // End the render pass.
+        render_pass.end(device, &command_buffer);
+
+        // Both layers are covered from here on.
+        let layers = vk::ImageSubresourceLayers {
+            aspect_mask:      vk::ImageAspectFlags::COLOR,
+            mip_level:        0,
+            base_array_layer: 0,
+            layer_count:      VIEW_COUNT
+        };
+
+        // Wait for the render pass to finish writing the image.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(*color_image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask:      layers.aspect_mask,
+                    base_mip_level:   layers.mip_level,
+                    level_count:      1,
+                    base_array_layer: layers.base_array_layer,
+                    layer_count:      layers.layer_count
+                })]
+        );
+
+        // Copy both layers, one after the other.
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            *color_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            *buffer,
+            &[vk::BufferImageCopy::default()
+                .image_subresource(layers)
+                .image_extent(vk::Extent3D {
+                    width:  EXTENT.width,
+                    height: EXTENT.height,
+                    depth:  1
+                })]
+        );
+
+        // Make the copy visible to the host.
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST,
+            vk::DependencyFlags::empty(),
+            &[vk::MemoryBarrier::default()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ)],
+            &[],
+            &[]
+        );
+
+        Ok(())
+    });
+
+    // Destroy the frame buffers. The rest is dropped.
+    frame_buffers.destroy(device);
+
+    result?;
+
+    // Read the eyes back. The left eye sees the triangle
+    // further right than the right eye does.
+    let pixels = buffer.read(device)?;
+    let (left, right) = pixels.split_at(layer_size);
+
+    let left_center = center_x(left).ok_or_else(|| anyhow!("The left eye is empty."))?;
+    let right_center = center_x(right).ok_or_else(|| anyhow!("The right eye is empty."))?;
+
+    if left_center <= right_center {
+        return Err(anyhow!(
+            "Expected the left eye's triangle at {} to be right of the right eye's at {}.",
+            left_center,
+            right_center
+        ));
+    }
+
+    info!(
+        "Rendered {} views, the triangle is at x = {} and x = {}",
+        VIEW_COUNT, left_center, right_center
+    );
+
+    Ok(())
+}
+
+/// The average x of the pixels the triangle covers, or none if it
+/// covers nothing. The background is cleared to black.
+fn center_x(pixels: &[u8]) -> Option<f32> {
+    let covered = pixels
+        .chunks_exact(PIXEL_SIZE)
+        .enumerate()
+        .filter(|(_, pixel)| pixel[..3] != [0, 0, 0])
+        .map(|(index, _)| (index as u32 % EXTENT.width) as f32)
+        .collect::<Vec<_>>();
+
+    match covered.is_empty() {
+        true => None,
+        false => Some(covered.iter().sum::<f32>() / covered.len() as f32)
+    }
+}
+
+fn main() -> Result<()> {
+    // Catch panics and emit them as errors.
+    std::panic::set_hook(Box::new(|panic_info| {
+        error!("{}", panic_info);
+    }));
+
+    // This routes log macros through tracing.
+    LogTracer::init()?;
+
+    // Setup the tracing subscriber globally.
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(LevelFilter::from_level(Level::INFO))
+        .finish();
+
+    set_global_default(subscriber)?;
+
+    let assets_path = assets_path()?;
+
+    unsafe {
+        // Load the Vulkan library.
+        let entry = Entry::linked();
+
+        // Create the instance wrapper without any surface extensions.
+        let instance = Instance::new_headless(&entry, &ValidationSettings::default())?;
+
+        // Create the device wrapper. We never present.
+        let device = Device::new(
+            &instance,
+            None,
+            &DeviceSelector::Auto,
+            &DeviceRequirements::default()
+        )?;
+
+        // Create the pipeline cache.
+        let mut pipeline_cache = PipelineCache::new(&device, &PipelineCache::default_path()?)?;
+
+        // Render both eyes.
+        let result = run(&device, &pipeline_cache, &assets_path);
+
+        // Save and destroy the pipeline cache. The device and then
+        // the instance are destroyed as they're dropped.
+        pipeline_cache.destroy(&device);
+
+        result
+    }
+}
@@ -6,7 +6,7 @@ SHADER_DIR="../assets/shaders"
 echo "Building shaders .."
 
 # Compile all shader files.
-find $SHADER_DIR -type f \( -name "*.frag" -o -name "*.vert" \) -print0 | \
+find $SHADER_DIR -type f \( -name "*.frag" -o -name "*.vert" -o -name "*.comp" \) -print0 | \
     xargs -0 -I {} glslc {} -o {}.spv
 
 echo "Done!"
@@ -0,0 +1,282 @@
+use crate::{find_memory_type, Device};
+use anyhow::Result;
+use ash::vk;
+use std::{ffi::c_void, ptr::NonNull};
+
+/// The memory backing a buffer or image. It's either a dedicated
+/// allocation or a range of a larger block owned by the allocator,
+/// so always bind and map it at its offset. Free it with the
+/// allocator that made it.
+#[derive(Default)]
+pub struct Allocation {
+    /// The memory the allocation lives in.
+    memory: vk::DeviceMemory,
+
+    /// The offset of the allocation in the memory.
+    offset: vk::DeviceSize,
+
+    /// The size of the allocation in bytes.
+    size: vk::DeviceSize,
+
+    /// The sub-allocation, or none if the memory is dedicated.
+    #[cfg(feature = "gpu-allocator")]
+    sub_allocation: Option<gpu_allocator::vulkan::Allocation>
+}
+
+impl Allocation {
+    /// Returns the memory the allocation lives in.
+    pub fn memory(&self) -> vk::DeviceMemory {
+        self.memory
+    }
+
+    /// Returns the offset of the allocation in the memory.
+    pub fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+
+    /// Returns the size of the allocation in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Returns whether the allocation has its own memory.
+    pub fn is_dedicated(&self) -> bool {
+        #[cfg(feature = "gpu-allocator")]
+        if self.sub_allocation.is_some() {
+            return false;
+        }
+
+        true
+    }
+
+    /// Map the allocation and return a pointer to its start. The
+    /// allocator's blocks stay mapped, so only dedicated memory is
+    /// actually mapped here.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be host-visible and not already mapped by the caller.
+    /// The pointer is only valid until unmap is called or the allocation is
+    /// freed.
+    pub unsafe fn map(&self, device: &Device) -> Result<NonNull<c_void>> {
+        #[cfg(feature = "gpu-allocator")]
+        if let Some(sub_allocation) = &self.sub_allocation {
+            return sub_allocation
+                .mapped_ptr()
+                .ok_or_else(|| anyhow::anyhow!("Cannot map memory that isn't host-visible."));
+        }
+
+        let ptr = device.map_memory(
+            self.memory,
+            self.offset,
+            self.size,
+            vk::MemoryMapFlags::empty()
+        )?;
+
+        Ok(NonNull::new_unchecked(ptr))
+    }
+
+    /// Unmap the allocation. This does nothing for sub-allocations.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must have been mapped with map, and the pointer it
+    /// returned must not be used afterwards.
+    pub unsafe fn unmap(&self, device: &Device) {
+        if self.is_dedicated() {
+            device.unmap_memory(self.memory);
+        }
+    }
+}
+
+/// Allocates the memory of buffers and images. With the gpu-allocator
+/// feature, device-local and host-visible coherent memory is carved out
+/// of larger blocks, which keeps the number of allocations far below
+/// the device's maxMemoryAllocationCount. Everything else, and all
+/// memory without the feature, gets a dedicated allocation.
+pub struct Allocator {
+    /// The sub-allocator, or none if every allocation is dedicated.
+    #[cfg(feature = "gpu-allocator")]
+    allocator: Option<gpu_allocator::vulkan::Allocator>
+}
+
+impl Allocator {
+    /// Create a new allocator for the device. Without the
+    /// gpu-allocator feature, every allocation is dedicated.
+    ///
+    /// # Safety
+    ///
+    /// The instance, device, and physical device must belong together, and the
+    /// allocator must be destroyed before the device is.
+    #[cfg(feature = "gpu-allocator")]
+    pub unsafe fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        physical_device: vk::PhysicalDevice
+    ) -> Result<Self> {
+        use gpu_allocator::{vulkan::AllocatorCreateDesc, AllocationSizes, AllocatorDebugSettings};
+
+        // Create the sub-allocator.
+        let allocator = gpu_allocator::vulkan::Allocator::new(&AllocatorCreateDesc {
+            instance: instance.clone(),
+            device: device.clone(),
+            physical_device,
+            debug_settings: AllocatorDebugSettings::default(),
+            buffer_device_address: false,
+            allocation_sizes: AllocationSizes::default()
+        })?;
+
+        Ok(Self {
+            allocator: Some(allocator)
+        })
+    }
+
+    /// Create a new allocator for the device. Without the
+    /// gpu-allocator feature, every allocation is dedicated.
+    ///
+    /// # Safety
+    ///
+    /// The instance, device, and physical device must belong together, and the
+    /// allocator must be destroyed before the device is.
+    #[cfg(not(feature = "gpu-allocator"))]
+    pub unsafe fn new(
+        _instance: &ash::Instance,
+        _device: &ash::Device,
+        _physical_device: vk::PhysicalDevice
+    ) -> Result<Self> {
+        Ok(Self::dedicated())
+    }
+
+    /// Create an allocator that gives every allocation its own memory.
+    pub fn dedicated() -> Self {
+        Self {
+            #[cfg(feature = "gpu-allocator")]
+            allocator:                                   None
+        }
+    }
+
+    /// Allocate memory for a resource with the given requirements. Linear
+    /// resources are buffers and linearly tiled images, which the allocator
+    /// keeps apart from optimally tiled images. The name is for debugging.
+    ///
+    /// # Safety
+    ///
+    /// The device must be the one the allocator was created for. The memory
+    /// must be freed with this allocator, after whatever is bound to it is
+    /// destroyed.
+    #[cfg_attr(not(feature = "gpu-allocator"), allow(unused_variables))]
+    pub unsafe fn allocate(
+        &mut self,
+        device: &Device,
+        memory_requirements: &vk::MemoryRequirements,
+        memory_properties: vk::MemoryPropertyFlags,
+        linear: bool,
+        name: &str
+    ) -> Result<Allocation> {
+        #[cfg(feature = "gpu-allocator")]
+        if let Some(allocator) = &mut self.allocator {
+            use gpu_allocator::{
+                vulkan::{AllocationCreateDesc, AllocationScheme},
+                MemoryLocation
+            };
+
+            // Only sub-allocate memory the allocator has a location for.
+            // Non-coherent memory would need its flushes rounded to the
+            // atom size within a block, so it stays dedicated.
+            let host_coherent =
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+            let location = match memory_properties {
+                vk::MemoryPropertyFlags::DEVICE_LOCAL => Some(MemoryLocation::GpuOnly),
+                properties if properties == host_coherent => Some(MemoryLocation::CpuToGpu),
+                _ => None
+            };
+
+            if let Some(location) = location {
+                let sub_allocation = allocator.allocate(&AllocationCreateDesc {
+                    name,
+                    requirements: *memory_requirements,
+                    location,
+                    linear,
+                    allocation_scheme: AllocationScheme::GpuAllocatorManaged
+                })?;
+
+                return Ok(Allocation {
+                    memory:         sub_allocation.memory(),
+                    offset:         sub_allocation.offset(),
+                    size:           sub_allocation.size(),
+                    sub_allocation: Some(sub_allocation)
+                });
+            }
+        }
+
+        // Find a suitable memory type.
+        let memory_index = find_memory_type(device, memory_requirements, memory_properties)?;
+
+        // Create the memory allocation info.
+        let memory_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(memory_requirements.size)
+            .memory_type_index(memory_index);
+
+        // Count the allocation, which fails at the device's limit.
+        device.add_allocation()?;
+
+        // Allocate the memory.
+        let memory = match device.allocate_memory(&memory_info, None) {
+            Ok(memory) => memory,
+            Err(e) => {
+                device.remove_allocation();
+
+                return Err(e.into());
+            }
+        };
+
+        Ok(Allocation {
+            memory,
+            offset: 0,
+            size: memory_requirements.size,
+            #[cfg(feature = "gpu-allocator")]
+            sub_allocation: None
+        })
+    }
+
+    /// Free an allocation made by this allocator. The
+    /// resources bound to it must be destroyed first.
+    ///
+    /// # Safety
+    ///
+    /// The allocation must have been made by this allocator, and the GPU must
+    /// be done with anything bound to it.
+    pub unsafe fn free(&mut self, device: &Device, allocation: Allocation) {
+        #[cfg(feature = "gpu-allocator")]
+        if let Some(sub_allocation) = allocation.sub_allocation {
+            if let Some(allocator) = &mut self.allocator {
+                if let Err(e) = allocator.free(sub_allocation) {
+                    tracing::error!("Failed to free a sub-allocation: {}", e);
+                }
+            }
+
+            return;
+        }
+
+        // An empty allocation has nothing to free.
+        if allocation.memory == vk::DeviceMemory::null() {
+            return;
+        }
+
+        device.free_memory(allocation.memory, None);
+        device.remove_allocation();
+    }
+
+    /// Destroy the allocator, which frees its blocks. Every
+    /// allocation must have been freed before this.
+    ///
+    /// # Safety
+    ///
+    /// Every allocation must have been freed, and the device must not have been
+    /// destroyed yet.
+    pub unsafe fn destroy(&mut self) {
+        #[cfg(feature = "gpu-allocator")]
+        self.allocator.take();
+    }
+}
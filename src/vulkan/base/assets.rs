@@ -0,0 +1,55 @@
+use anyhow::{anyhow, Result};
+use std::{
+    env::{current_exe, var_os},
+    fs::canonicalize,
+    path::PathBuf
+};
+use tracing::debug;
+
+/// The environment variable that overrides where the assets are.
+pub const ASSETS_DIR_VAR: &str = "VULKAN_ASSETS_DIR";
+
+/// The name of the assets directory.
+const ASSETS_DIR_NAME: &str = "assets";
+
+/// Find the assets directory. This is the first that exists of the
+/// directory in VULKAN_ASSETS_DIR, the assets directory of this crate's
+/// source, and an assets directory next to the executable or in one
+/// of its parents. The last covers both a shipped binary with its
+/// assets beside it and the examples in target/<profile>/examples.
+pub fn assets_path() -> Result<PathBuf> {
+    let mut candidates = vec![];
+
+    // The directory from the environment.
+    if let Some(path) = var_os(ASSETS_DIR_VAR) {
+        candidates.push(PathBuf::from(path));
+    }
+
+    // The assets directory of this crate's source.
+    candidates.push(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(ASSETS_DIR_NAME));
+
+    // The assets directory next to the executable or in one of its parents.
+    if let Ok(exe) = current_exe() {
+        candidates.extend(
+            exe.ancestors()
+                .skip(1)
+                .map(|dir| dir.join(ASSETS_DIR_NAME))
+        );
+    }
+
+    // Use the first one that exists.
+    let path = candidates
+        .iter()
+        .find(|path| path.is_dir())
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not find the assets directory, set {} to its path. Tried {:?}.",
+                ASSETS_DIR_VAR,
+                candidates
+            )
+        })?;
+
+    debug!("Assets path: {:?}", path);
+
+    Ok(canonicalize(path)?)
+}
@@ -0,0 +1,179 @@
+use crate::{DescriptorBinding, DescriptorResource, DescriptorSetLayout, Device};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use tracing::{info, warn};
+
+/// A single descriptor set holding an array of textures at binding 0,
+/// which shaders index by e.g. a push constant or an instance index. This
+/// needs dynamic indexing of sampled image arrays. With descriptor
+/// indexing, the array is partially bound and slots can be updated while
+/// the set is bound, as long as the frames in flight don't use them, and
+/// indices that vary within a draw must be wrapped in nonuniformEXT.
+/// Without it, the array is a regular fixed-size binding, slots may only
+/// be updated while no frame in flight uses the set, and indices must be
+/// the same across a draw. Either way, every slot starts out as the
+/// placeholder texture, so shaders should declare the array with
+/// capacity() elements, e.g. through a specialization constant.
+pub struct BindlessTextures {
+    /// The descriptor set layout.
+    layout: DescriptorSetLayout,
+
+    /// The descriptor pool the set is allocated from.
+    descriptor_pool: vk::DescriptorPool,
+
+    /// The descriptor set.
+    descriptor_set: vk::DescriptorSet,
+
+    /// The number of slots in the array.
+    capacity: u32
+}
+
+impl BindlessTextures {
+    /// Create a new texture array with room for the given number of
+    /// textures, clamped to what the device supports. Every slot is
+    /// filled with the placeholder, e.g. a checkerboard.
+    ///
+    /// # Safety
+    ///
+    /// The placeholder's view and sampler must outlive the texture array, or be
+    /// replaced in every slot before they're destroyed.
+    pub unsafe fn new(
+        device: &Device,
+        stage_flags: vk::ShaderStageFlags,
+        capacity: u32,
+        placeholder: (vk::ImageView, vk::Sampler)
+    ) -> Result<Self> {
+        // Without dynamic indexing, the array could only be indexed by constants.
+        if !device.sampled_image_dynamic_indexing_enabled() {
+            return Err(anyhow!(
+                "Bindless textures need dynamic indexing of sampled image arrays."
+            ));
+        }
+
+        let limits = &device.properties().limits;
+
+        // Clamp the capacity to the limits of the path we take.
+        let max_capacity = match device.descriptor_indexing_enabled() {
+            true => device.max_update_after_bind_textures(),
+            false => limits
+                .max_per_stage_descriptor_samplers
+                .min(limits.max_per_stage_descriptor_sampled_images)
+        };
+
+        if capacity == 0 {
+            return Err(anyhow!(
+                "A texture array needs room for at least one texture."
+            ));
+        }
+
+        if capacity > max_capacity {
+            warn!(
+                "Requested {} bindless textures, but the device supports at most {}.",
+                capacity, max_capacity
+            );
+        }
+
+        let capacity = capacity.min(max_capacity);
+
+        info!(
+            "Bindless textures: {} (descriptor indexing: {})",
+            capacity,
+            device.descriptor_indexing_enabled()
+        );
+
+        // The array binding.
+        let bindings = [DescriptorBinding {
+            binding: 0,
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            stage_flags,
+            count: capacity
+        }];
+
+        // Create the descriptor set layout.
+        let layout = match device.descriptor_indexing_enabled() {
+            true => DescriptorSetLayout::new_update_after_bind(device, &bindings)?,
+            false => DescriptorSetLayout::new(device, &bindings)?
+        };
+
+        // Create the descriptor pool.
+        let descriptor_pool = layout.new_pool(device, 1)?;
+
+        // Allocate the descriptor set, with the placeholder in every slot.
+        let (view, sampler) = placeholder;
+
+        let descriptor_set = layout.allocate(
+            device,
+            &descriptor_pool,
+            &[(
+                0,
+                DescriptorResource::Images(vec![
+                    vk::DescriptorImageInfo {
+                        sampler,
+                        image_view: view,
+                        image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    };
+                    capacity as usize
+                ])
+            )]
+        )?;
+
+        Ok(Self {
+            layout,
+            descriptor_pool,
+            descriptor_set,
+            capacity
+        })
+    }
+
+    /// Returns the descriptor set layout, for building pipeline layouts.
+    pub fn layout(&self) -> &DescriptorSetLayout {
+        &self.layout
+    }
+
+    /// Returns the descriptor set.
+    pub fn descriptor_set(&self) -> &vk::DescriptorSet {
+        &self.descriptor_set
+    }
+
+    /// Returns the number of slots in the array.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Put a texture in the given slot. The image must be in the shader
+    /// read-only layout whenever the slot is sampled.
+    ///
+    /// # Safety
+    ///
+    /// The slot must not be in use by the GPU unless the layout allows updates
+    /// after binding. The view and sampler must outlive every frame that
+    /// samples the slot.
+    pub unsafe fn set(
+        &self,
+        device: &Device,
+        slot: u32,
+        view: vk::ImageView,
+        sampler: vk::Sampler
+    ) -> Result<()> {
+        self.layout.write_elements(
+            device,
+            &self.descriptor_set,
+            0,
+            slot,
+            &DescriptorResource::image(view, sampler, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        )
+    }
+
+    /// Destroy the texture array. The textures themselves are left alone.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the texture array's descriptor set.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the descriptor pool, which frees the set.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        // Destroy the descriptor set layout.
+        self.layout.destroy(device);
+    }
+}
@@ -1,24 +1,33 @@
-use crate::{new_buffer, Device};
-use anyhow::Result;
+use crate::{new_buffer, Allocation, DeferredDestroyQueue, DeferredResource, Device, ResourceKind};
+use anyhow::{anyhow, Result};
 use ash::{
     util::Align,
     vk::{self}
 };
 use std::{
-    mem::{align_of, size_of_val},
-    ops::Deref
+    mem::{align_of, size_of_val, take},
+    ops::Deref,
+    sync::Arc
 };
 
 /// Wraps a Vulkan buffer. This version uses a staging buffer to
-/// directly upload data to the GPU exactly once. No CPU-side
-/// buffer is kept around for copying. Use this for data like
-/// meshes that never change and should be uploaded once.
+/// upload data to device-local memory. No CPU-side buffer is kept
+/// around for copying, so every update stages the data again. Use
+/// this for data like meshes that never or rarely change. Dropping
+/// the buffer without destroying it defers its destruction to the
+/// device's next wait for idle.
 pub struct ImmutableBuffer {
     /// The buffer.
     buffer: vk::Buffer,
 
     /// The memory.
-    memory: vk::DeviceMemory
+    allocation: Allocation,
+
+    /// The size of the buffer in bytes.
+    size: vk::DeviceSize,
+
+    /// Where the buffer goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
 }
 
 impl ImmutableBuffer {
@@ -31,36 +40,12 @@ impl ImmutableBuffer {
         let size = size_of_val(data) as vk::DeviceSize;
 
         // Create the src buffer.
-        let (src_buffer, src_memory, src_memory_size) = new_buffer(
-            device,
-            size,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
-        )?;
-
-        // Copy data to the src buffer.
-        {
-            // Map the memory so we can write to it.
-            let ptr =
-                device.map_memory(src_memory, 0, src_memory_size, vk::MemoryMapFlags::empty())?;
-
-            // Get an aligned view into the memory.
-            let mut aligned = Align::new(
-                ptr,
-                align_of::<T>() as vk::DeviceSize,
-                src_memory_size as vk::DeviceSize
-            );
-
-            // Copy the data to the memory.
-            aligned.copy_from_slice(data);
-
-            // Unmap the memory.
-            device.unmap_memory(src_memory);
-        }
+        let (src_buffer, src_allocation) = Self::new_staging_buffer(device, data)?;
 
         // Create the dst buffer.
-        let (dst_buffer, dst_memory, _dst_memory_size) = new_buffer(
+        let (dst_buffer, dst_allocation) = new_buffer(
             device,
+            &mut device.allocator(),
             size,
             usage | vk::BufferUsageFlags::TRANSFER_DST,
             vk::MemoryPropertyFlags::DEVICE_LOCAL
@@ -80,28 +65,229 @@ impl ImmutableBuffer {
                 }]
             );
 
+            // Release ownership to the graphics queue family.
+            if device.has_dedicated_transfer_queue() {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[Self::ownership_barrier(device, dst_buffer, size)
+                        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::empty())],
+                    &[]
+                );
+            }
+
             Ok(())
         })?;
 
+        // Acquire ownership on the graphics queue family.
+        if device.has_dedicated_transfer_queue() {
+            device.one_time_graphics_command(|command_buffer| {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::ALL_COMMANDS,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[Self::ownership_barrier(device, dst_buffer, size)
+                        .src_access_mask(vk::AccessFlags::empty())
+                        .dst_access_mask(vk::AccessFlags::MEMORY_READ)],
+                    &[]
+                );
+
+                Ok(())
+            })?;
+        }
+
         // Destroy the src buffer.
         device.destroy_buffer(src_buffer, None);
+        device.untrack_resource(ResourceKind::Buffer);
 
         // Free the src memory.
-        device.free_memory(src_memory, None);
+        device
+            .allocator()
+            .free(device, src_allocation);
 
         Ok(Self {
             buffer: dst_buffer,
-            memory: dst_memory
+            allocation: dst_allocation,
+            size,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
         })
     }
 
-    /// Destroy the buffer.
-    pub unsafe fn destroy(&self, device: &Device) {
-        // Destroy the buffer.
-        device.destroy_buffer(self.buffer, None);
+    /// Overwrite the start of the buffer with new data. The data is copied
+    /// on the graphics queue and this waits for the copy to finish. The
+    /// caller must make sure no frame in flight is still using the buffer,
+    /// e.g. by waiting for the device to be idle or keeping a buffer per
+    /// frame in flight.
+    ///
+    /// # Safety
+    ///
+    /// No frame in flight may still be using the buffer, and the data must fit
+    /// in it.
+    pub unsafe fn update<T: Copy>(&self, device: &Device, data: &[T]) -> Result<()> {
+        // Compute the size of the data in bytes.
+        let size = size_of_val(data) as vk::DeviceSize;
+
+        // The data must fit in the buffer.
+        if size > self.size {
+            return Err(anyhow!(
+                "Cannot update a buffer of {} bytes with {} bytes.",
+                self.size,
+                size
+            ));
+        }
+
+        // There's nothing to copy.
+        if size == 0 {
+            return Ok(());
+        }
+
+        // Create the src buffer.
+        let (src_buffer, src_allocation) = Self::new_staging_buffer(device, data)?;
+
+        // Copy the staging buffer to the gpu. The graphics queue already
+        // owns the buffer, so there's no ownership to transfer.
+        let result = device.one_time_graphics_command(|command_buffer| {
+            device.cmd_copy_buffer(
+                command_buffer,
+                src_buffer,
+                self.buffer,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size
+                }]
+            );
+
+            // Make the copy visible to whatever reads the buffer next.
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                &[vk::MemoryBarrier::default()
+                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .dst_access_mask(vk::AccessFlags::MEMORY_READ)],
+                &[],
+                &[]
+            );
+
+            Ok(())
+        });
 
-        // Free the memory.
-        device.free_memory(self.memory, None);
+        // Destroy the src buffer.
+        device.destroy_buffer(src_buffer, None);
+        device.untrack_resource(ResourceKind::Buffer);
+
+        // Free the src memory.
+        device
+            .allocator()
+            .free(device, src_allocation);
+
+        result
+    }
+
+    /// Returns the size of the buffer in bytes.
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// Create a host-visible staging buffer holding the data.
+    unsafe fn new_staging_buffer<T: Copy>(
+        device: &Device,
+        data: &[T]
+    ) -> Result<(vk::Buffer, Allocation)> {
+        // Create the buffer.
+        let (buffer, allocation) = new_buffer(
+            device,
+            &mut device.allocator(),
+            size_of_val(data) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+        )?;
+
+        // Map the memory so we can write to it.
+        let ptr = allocation.map(device)?;
+
+        // Get an aligned view into the memory.
+        let mut aligned = Align::new(
+            ptr.as_ptr(),
+            align_of::<T>() as vk::DeviceSize,
+            allocation.size()
+        );
+
+        // Copy the data to the memory.
+        aligned.copy_from_slice(data);
+
+        // Unmap the memory.
+        allocation.unmap(device);
+
+        Ok((buffer, allocation))
+    }
+
+    /// Create a new buffer of indirect draw commands, for use
+    /// with cmd_draw_indexed_indirect.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must be dropped or destroyed before the device is, and the
+    /// GPU must be done with it by then.
+    pub unsafe fn new_indirect(
+        device: &Device,
+        commands: &[vk::DrawIndexedIndirectCommand]
+    ) -> Result<Self> {
+        Self::new(device, vk::BufferUsageFlags::INDIRECT_BUFFER, commands)
+    }
+
+    /// The barrier that moves the buffer from the transfer queue family to the
+    /// graphics queue family. The same barrier is recorded on both queues.
+    fn ownership_barrier<'a>(
+        device: &Device,
+        buffer: vk::Buffer,
+        size: vk::DeviceSize
+    ) -> vk::BufferMemoryBarrier<'a> {
+        vk::BufferMemoryBarrier::default()
+            .src_queue_family_index(device.transfer_queue_family_index())
+            .dst_queue_family_index(device.queue_family_index())
+            .buffer(buffer)
+            .offset(0)
+            .size(size)
+    }
+
+    /// Destroy the buffer right away.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handles out of the buffer, leaving it destroyed.
+    /// Returns None if the buffer was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.buffer == vk::Buffer::null() {
+            return None;
+        }
+
+        Some(DeferredResource::Buffer(
+            take(&mut self.buffer),
+            take(&mut self.allocation)
+        ))
+    }
+}
+
+impl Drop for ImmutableBuffer {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
     }
 }
 
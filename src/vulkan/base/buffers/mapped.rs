@@ -1,63 +1,104 @@
-use crate::{new_buffer, Device};
+use crate::{new_buffer, Allocation, DeferredDestroyQueue, DeferredResource, Device};
 use anyhow::{anyhow, Result};
 use ash::{
     util::Align,
     vk::{self}
 };
 use std::{
-    mem::{align_of, size_of_val},
+    mem::{align_of, size_of, size_of_val, take},
     ops::Deref,
-    ptr::NonNull
+    ptr::{copy_nonoverlapping, NonNull},
+    sync::Arc
 };
 
+/// Whether a mapped buffer uses coherent memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryCoherence {
+    /// Writes are visible to the GPU without flushing.
+    #[default]
+    Coherent,
+
+    /// Writes must be flushed before the GPU can see them. On some
+    /// platforms this memory is more plentiful or faster to write.
+    NonCoherent
+}
+
 /// Wraps a Vulkan buffer. This version does not use a staging buffer
 /// but instead directly maps host-visible coherent memory. Use this
-/// for things like uniform buffers that are small.
+/// for things like uniform buffers that are small. If the memory
+/// is non-coherent, call flush after writing to it. Dropping the
+/// buffer without destroying it defers its destruction to the
+/// device's next wait for idle.
 pub struct MappedBuffer<T> {
     /// The buffer.
     buffer: vk::Buffer,
 
     /// The memory.
-    memory: vk::DeviceMemory,
-
-    /// The memory size.
-    memory_size: vk::DeviceSize,
+    allocation: Allocation,
 
     /// The raw memory.
     ptr: NonNull<T>,
 
     /// The size of the data in bytes.
-    size: vk::DeviceSize
+    size: vk::DeviceSize,
+
+    /// Whether the memory is coherent.
+    coherence: MemoryCoherence,
+
+    /// Where the buffer goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
 }
 
+// The mapped pointer is only written through &mut self, so sharing
+// the buffer is as safe as sharing the data it holds.
+unsafe impl<T: Send> Send for MappedBuffer<T> {}
+unsafe impl<T: Sync> Sync for MappedBuffer<T> {}
+
 impl<T: Copy> MappedBuffer<T> {
-    pub unsafe fn new(device: &Device, usage: vk::BufferUsageFlags, data: &[T]) -> Result<Self> {
+    pub unsafe fn new(
+        device: &Device,
+        usage: vk::BufferUsageFlags,
+        coherence: MemoryCoherence,
+        data: &[T]
+    ) -> Result<Self> {
         // Compute the size of the buffer in bytes.
         let size = size_of_val(data) as vk::DeviceSize;
 
+        // Non-coherent memory only needs to be host-visible.
+        let memory_properties = match coherence {
+            MemoryCoherence::Coherent => {
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+            },
+            MemoryCoherence::NonCoherent => vk::MemoryPropertyFlags::HOST_VISIBLE
+        };
+
         // Create the buffer.
-        let (buffer, memory, memory_size) = new_buffer(
+        let (buffer, allocation) = new_buffer(
             device,
+            &mut device.allocator(),
             size,
             usage,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+            memory_properties
         )?;
 
         // Map the memory and grab a raw pointer.
-        let ptr = device.map_memory(memory, 0, memory_size, vk::MemoryMapFlags::empty())?;
-        let ptr = NonNull::new_unchecked(ptr.cast());
+        let ptr = allocation.map(device)?.cast();
 
         // Create the host buffer.
         let mut this = Self {
             buffer,
-            memory,
-            memory_size,
+            allocation,
             ptr,
-            size
+            size,
+            coherence,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
         };
 
         // Write the data to the memory.
         this.overwrite(data)?;
+        this.flush(device)?;
 
         Ok(this)
     }
@@ -72,7 +113,7 @@ impl<T: Copy> MappedBuffer<T> {
         let mut aligned = Align::new(
             self.ptr.as_ptr().cast(),
             align_of::<T>() as vk::DeviceSize,
-            self.memory_size
+            self.allocation.size()
         );
 
         // Copy the data to the memory.
@@ -81,16 +122,140 @@ impl<T: Copy> MappedBuffer<T> {
         Ok(())
     }
 
-    /// Destroy the buffer.
-    pub unsafe fn destroy(&self, device: &Device) {
-        // Unmap the memory.
-        device.unmap_memory(self.memory);
+    /// Overwrite part of the buffer with new data, starting at
+    /// the given element. The rest of the buffer is left as is.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must not be reading the overwritten elements, e.g. the buffer
+    /// belongs to a frame whose fence has been waited on.
+    pub unsafe fn overwrite_range(&mut self, offset_elements: usize, data: &[T]) -> Result<()> {
+        // The number of elements the buffer holds.
+        let capacity = self.size as usize / size_of::<T>();
+
+        // The data must fit in the buffer.
+        let end = offset_elements.checked_add(data.len());
+
+        if end.is_none_or(|end| end > capacity) {
+            return Err(anyhow!(
+                "Cannot write {} elements at offset {} to a buffer of {} elements.",
+                data.len(),
+                offset_elements,
+                capacity
+            ));
+        }
+
+        // Copy the data to the memory.
+        copy_nonoverlapping(
+            data.as_ptr(),
+            self.ptr
+                .as_ptr()
+                .add(offset_elements),
+            data.len()
+        );
+
+        Ok(())
+    }
+
+    /// Make writes to non-coherent memory visible to the GPU.
+    /// This does nothing if the memory is coherent.
+    ///
+    /// # Safety
+    ///
+    /// The device must be the one the buffer was created on.
+    pub unsafe fn flush(&self, device: &Device) -> Result<()> {
+        if self.coherence == MemoryCoherence::Coherent {
+            return Ok(());
+        }
+
+        // The flushed range must be a multiple of the atom size,
+        // unless it runs to the end of the allocation.
+        let atom_size = device
+            .properties()
+            .limits
+            .non_coherent_atom_size;
+        let size = self
+            .size
+            .next_multiple_of(atom_size)
+            .min(self.allocation.size());
+
+        // Flush the memory.
+        device.flush_mapped_memory_ranges(&[vk::MappedMemoryRange::default()
+            .memory(self.allocation.memory())
+            .offset(self.allocation.offset())
+            .size(size)])?;
+
+        Ok(())
+    }
+
+    /// Read the buffer back, e.g. after the GPU has written to it. The
+    /// caller must make sure the GPU's writes are finished and visible
+    /// to the host. Non-coherent memory is invalidated before reading.
+    ///
+    /// # Safety
+    ///
+    /// The GPU's writes to the buffer must be finished and made visible to the
+    /// host, e.g. with a barrier to the host stage followed by a fence wait.
+    pub unsafe fn read(&self, device: &Device) -> Result<Vec<T>> {
+        if self.coherence == MemoryCoherence::NonCoherent {
+            // Invalidate the whole allocation, which sidesteps
+            // rounding the range to the atom size.
+            device.invalidate_mapped_memory_ranges(&[vk::MappedMemoryRange::default()
+                .memory(self.allocation.memory())
+                .offset(self.allocation.offset())
+                .size(vk::WHOLE_SIZE)])?;
+        }
+
+        // The number of elements the buffer holds.
+        let count = self.size as usize / size_of::<T>();
+
+        // Copy the data out of the memory.
+        let mut data = Vec::with_capacity(count);
+
+        copy_nonoverlapping(self.ptr.as_ptr(), data.as_mut_ptr(), count);
+        data.set_len(count);
+
+        Ok(data)
+    }
+
+    /// Returns whether the memory is coherent.
+    pub fn coherence(&self) -> MemoryCoherence {
+        self.coherence
+    }
+}
 
-        // Destroy the buffer.
-        device.destroy_buffer(self.buffer, None);
+impl<T> MappedBuffer<T> {
+    /// Destroy the buffer right away.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the buffer.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
 
-        // Free the memory.
-        device.free_memory(self.memory, None);
+    /// Take the handles out of the buffer, leaving it destroyed.
+    /// Returns None if the buffer was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.buffer == vk::Buffer::null() {
+            return None;
+        }
+
+        Some(DeferredResource::MappedBuffer(
+            take(&mut self.buffer),
+            take(&mut self.allocation)
+        ))
+    }
+}
+
+impl<T> Drop for MappedBuffer<T> {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
     }
 }
 
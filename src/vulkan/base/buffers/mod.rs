@@ -1,7 +1,9 @@
 mod immutable;
 mod mapped;
+mod region;
 mod util;
 
 pub use immutable::*;
 pub use mapped::*;
+pub use region::*;
 pub use util::*;
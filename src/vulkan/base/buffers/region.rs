@@ -0,0 +1,131 @@
+use crate::{Device, MappedBuffer, MemoryCoherence};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use bytemuck::{bytes_of, Pod};
+use std::ops::Deref;
+
+/// A mapped buffer split into equally sized regions, e.g. one per frame
+/// in flight. This keeps small per-frame uniforms in a single allocation
+/// and mapping instead of one buffer each. Every region starts at an
+/// offset aligned for uniform buffer bindings and dynamic offsets, so
+/// a region can be bound on its own or reached with a dynamic offset.
+pub struct RegionBuffer {
+    /// The buffer holding every region.
+    buffer: MappedBuffer<u8>,
+
+    /// The size of a region in bytes, rounded up to the alignment.
+    region_size: vk::DeviceSize,
+
+    /// The number of regions.
+    region_count: usize
+}
+
+impl RegionBuffer {
+    /// Create a new buffer of zeroed regions, each at least the given size.
+    ///
+    /// # Safety
+    ///
+    /// The buffer must be destroyed before the device is.
+    pub unsafe fn new(
+        device: &Device,
+        usage: vk::BufferUsageFlags,
+        coherence: MemoryCoherence,
+        region_size: vk::DeviceSize,
+        region_count: usize
+    ) -> Result<Self> {
+        // Each region must start at an aligned offset.
+        let region_size = device.uniform_buffer_stride(region_size);
+
+        // Create the buffer.
+        let buffer = MappedBuffer::new(
+            device,
+            usage,
+            coherence,
+            &vec![0u8; region_size as usize * region_count]
+        )?;
+
+        Ok(Self {
+            buffer,
+            region_size,
+            region_count
+        })
+    }
+
+    /// Returns the size of a region in bytes, including the padding.
+    pub fn region_size(&self) -> vk::DeviceSize {
+        self.region_size
+    }
+
+    /// Returns the number of regions.
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    /// Returns the offset of the given region in the buffer.
+    pub fn region_offset(&self, region: usize) -> vk::DeviceSize {
+        region as vk::DeviceSize * self.region_size
+    }
+
+    /// Write a value to a region, at the given offset in bytes from the
+    /// start of the region. The rest of the region is left as is.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must not be reading the region, e.g. it belongs to a frame whose
+    /// fence has been waited on.
+    pub unsafe fn write<T: Pod>(
+        &mut self,
+        region: usize,
+        offset: vk::DeviceSize,
+        data: &T
+    ) -> Result<()> {
+        let bytes = bytes_of(data);
+
+        if region >= self.region_count {
+            return Err(anyhow!(
+                "Cannot write to region {} of a buffer with {} regions.",
+                region,
+                self.region_count
+            ));
+        }
+
+        if offset + bytes.len() as vk::DeviceSize > self.region_size {
+            return Err(anyhow!(
+                "Cannot write {} bytes at offset {} to a region of {} bytes.",
+                bytes.len(),
+                offset,
+                self.region_size
+            ));
+        }
+
+        self.buffer
+            .overwrite_range((self.region_offset(region) + offset) as usize, bytes)
+    }
+
+    /// Make writes to non-coherent memory visible to the GPU.
+    /// This does nothing if the memory is coherent.
+    ///
+    /// # Safety
+    ///
+    /// The device must be the one the buffer was created on.
+    pub unsafe fn flush(&self, device: &Device) -> Result<()> {
+        self.buffer.flush(device)
+    }
+
+    /// Destroy the buffer.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with every region of the buffer.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.buffer.destroy(device);
+    }
+}
+
+impl Deref for RegionBuffer {
+    type Target = vk::Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
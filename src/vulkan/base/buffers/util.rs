@@ -1,14 +1,15 @@
-use crate::{find_memory_type, Device};
+use crate::{Allocation, Allocator, Device, ResourceKind};
 use anyhow::Result;
 use ash::vk;
 
-/// Create an internal buffer.
+/// Create an internal buffer, with its memory from the given allocator.
 pub unsafe fn new_buffer(
     device: &Device,
+    allocator: &mut Allocator,
     size: vk::DeviceSize,
     usage: vk::BufferUsageFlags,
     memory_properties: vk::MemoryPropertyFlags
-) -> Result<(vk::Buffer, vk::DeviceMemory, vk::DeviceSize)> {
+) -> Result<(vk::Buffer, Allocation)> {
     // Create the buffer info.
     let buffer_info = vk::BufferCreateInfo::default()
         .size(size)
@@ -18,22 +19,22 @@ pub unsafe fn new_buffer(
     // Create the buffer.
     let buffer = device.create_buffer(&buffer_info, None)?;
 
+    device.track_resource(ResourceKind::Buffer);
+
     // Get the buffer's memory requirements.
     let memory_requirements = device.get_buffer_memory_requirements(buffer);
 
-    // Find a suitable memory type.
-    let memory_index = find_memory_type(device, &memory_requirements, memory_properties)?;
-
-    // Create the memory allocation info.
-    let memory_info = vk::MemoryAllocateInfo::default()
-        .allocation_size(memory_requirements.size)
-        .memory_type_index(memory_index);
-
     // Allocate the memory.
-    let memory = device.allocate_memory(&memory_info, None)?;
+    let allocation = allocator.allocate(
+        device,
+        &memory_requirements,
+        memory_properties,
+        true,
+        "Buffer"
+    )?;
 
     // Bind the memory to the buffer.
-    device.bind_buffer_memory(buffer, memory, 0)?;
+    device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset())?;
 
-    Ok((buffer, memory, memory_requirements.size))
+    Ok((buffer, allocation))
 }
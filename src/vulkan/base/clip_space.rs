@@ -0,0 +1,41 @@
+use ash::vk;
+
+/// Which way the y axis points in clip space. Vulkan's points down,
+/// unlike OpenGL's, so projection matrices built for OpenGL, e.g. by
+/// glam's perspective functions, render upside down as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ClipSpace {
+    /// The y axis points up, as in OpenGL. The viewport is flipped with
+    /// a negative height, which is core since Vulkan 1.1, so standard
+    /// projection matrices can be used without changes. The flip also
+    /// mirrors the winding, so counter-clockwise triangles in the model
+    /// are still counter-clockwise on screen, and front_face should be
+    /// COUNTER_CLOCKWISE just like in OpenGL.
+    #[default]
+    YUp,
+
+    /// The y axis points down, which is Vulkan's own convention. The
+    /// projection matrix must flip y itself, e.g. by negating y_axis.y,
+    /// which mirrors the winding the same way flipping the viewport does.
+    YDown
+}
+
+impl ClipSpace {
+    /// The viewport covering the whole extent in this convention.
+    pub fn viewport(self, extent: &vk::Extent2D) -> vk::Viewport {
+        let (y, height) = match self {
+            // Start at the bottom and go up.
+            Self::YUp => (extent.height as f32, -(extent.height as f32)),
+            Self::YDown => (0.0, extent.height as f32)
+        };
+
+        vk::Viewport {
+            x: 0.0,
+            y,
+            width: extent.width as f32,
+            height,
+            min_depth: 0.0,
+            max_depth: 1.0
+        }
+    }
+}
@@ -41,6 +41,40 @@ impl CommandPool {
         Ok(device.allocate_command_buffers(&command_buffer_info)?[0])
     }
 
+    /// Free command buffers allocated from the pool, returning them to it.
+    /// None of them may be in use by the GPU.
+    ///
+    /// # Safety
+    ///
+    /// The command buffers must have been allocated from this pool, and none of
+    /// them may be pending on the GPU.
+    pub unsafe fn free(&self, device: &Device, command_buffers: &[vk::CommandBuffer]) {
+        if !command_buffers.is_empty() {
+            device.free_command_buffers(self.0, command_buffers);
+        }
+    }
+
+    /// Reset every command buffer allocated from the pool at once, which is
+    /// cheaper than resetting them one by one and doesn't need the reset
+    /// command buffer flag. None of them may be in use by the GPU. If
+    /// release_resources is set, the pool gives its memory back to the
+    /// system, otherwise it's kept for the next round of recording.
+    ///
+    /// # Safety
+    ///
+    /// None of the pool's command buffers may be pending on the GPU or be
+    /// recorded on another thread.
+    pub unsafe fn reset(&self, device: &Device, release_resources: bool) -> Result<()> {
+        let flags = match release_resources {
+            true => vk::CommandPoolResetFlags::RELEASE_RESOURCES,
+            false => vk::CommandPoolResetFlags::empty()
+        };
+
+        device.reset_command_pool(self.0, flags)?;
+
+        Ok(())
+    }
+
     /// Destroy the command pool.
     pub unsafe fn destroy(&mut self, device: &Device) {
         device.destroy_command_pool(self.0, None);
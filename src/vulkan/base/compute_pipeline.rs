@@ -0,0 +1,252 @@
+use crate::{
+    DeferredDestroyQueue, DeferredResource, Device, Pipeline, PipelineCache, ShaderSource,
+    SpecValue, Specialization
+};
+use anyhow::{Context, Result};
+use ash::vk;
+use bytemuck::{bytes_of, Pod};
+use std::{mem::take, ops::Deref, sync::Arc};
+
+/// The compute pipeline settings.
+pub struct ComputePipelineSettings {
+    /// The comp shader.
+    pub comp_shader: ShaderSource,
+
+    /// The entry point of the comp shader, or none for main.
+    pub comp_entry: Option<String>,
+
+    /// The specialization constants of the comp shader, keyed by constant id.
+    pub comp_specialization: Vec<(u32, SpecValue)>,
+
+    /// The descriptor set layouts.
+    pub descriptor_set_layouts: Option<Vec<vk::DescriptorSetLayout>>,
+
+    /// The push constant ranges.
+    pub push_constant_ranges: Vec<vk::PushConstantRange>
+}
+
+/// Wraps a Vulkan compute pipeline. Compute work is submitted
+/// to the graphics queue, which is required to support compute.
+/// Dropping the pipeline without destroying it defers its
+/// destruction to the device's next wait for idle.
+pub struct ComputePipeline {
+    /// The pipeline layout.
+    pipeline_layout: vk::PipelineLayout,
+
+    /// The pipeline.
+    pipeline: vk::Pipeline,
+
+    /// Where the pipeline goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
+}
+
+impl ComputePipeline {
+    /// Create a new compute pipeline. Nothing is leaked if this fails.
+    ///
+    /// # Safety
+    ///
+    /// The pipeline cache and descriptor set layouts must belong to the device,
+    /// and the pipeline must be dropped or destroyed before the device is.
+    pub unsafe fn new(
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        settings: &ComputePipelineSettings
+    ) -> Result<Self> {
+        // Create the shader.
+        let comp_shader = Pipeline::load_shader(device, &settings.comp_shader)?;
+
+        // This is the entry function for the shader.
+        let shader_entry_name = Pipeline::entry_name(settings.comp_entry.as_deref())?;
+
+        // Pack the specialization constants.
+        let comp_specialization = Specialization::new(&settings.comp_specialization);
+        let comp_specialization_info = comp_specialization.info();
+
+        // The pipeline layout create info.
+        let pipeline_layout_create_info = match &settings.descriptor_set_layouts {
+            Some(set_layouts) => vk::PipelineLayoutCreateInfo::default().set_layouts(set_layouts),
+            None => vk::PipelineLayoutCreateInfo::default()
+        }
+        .push_constant_ranges(&settings.push_constant_ranges);
+
+        // Create the pipeline layout.
+        let pipeline_layout =
+            match device.create_pipeline_layout(&pipeline_layout_create_info, None) {
+                Ok(pipeline_layout) => pipeline_layout,
+                Err(result) => {
+                    device.destroy_shader_module(comp_shader, None);
+
+                    return Err(result.into());
+                }
+            };
+
+        // Create the pipeline create info.
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::default()
+            .stage(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .module(comp_shader)
+                    .name(&shader_entry_name)
+                    .stage(vk::ShaderStageFlags::COMPUTE)
+                    .specialization_info(&comp_specialization_info)
+            )
+            .layout(pipeline_layout);
+
+        // Create the pipeline.
+        let pipelines =
+            device.create_compute_pipelines(**pipeline_cache, &[pipeline_create_info], None);
+
+        // Destroy the shader, which isn't needed whether or not that worked.
+        device.destroy_shader_module(comp_shader, None);
+
+        // Don't leak the layout if the pipeline couldn't be created. The
+        // Vulkan error is kept, so callers can still downcast to it.
+        let pipeline = match pipelines {
+            Ok(pipelines) => pipelines[0],
+            Err((_, result)) => {
+                device.destroy_pipeline_layout(pipeline_layout, None);
+
+                return Err(result).context("Failed to create compute pipeline.");
+            }
+        };
+
+        // Name the pipeline after its shader for debugging.
+        let name = settings.comp_shader.name();
+
+        device.set_object_name(pipeline, &format!("Compute pipeline ({})", name));
+        device.set_object_name(
+            pipeline_layout,
+            &format!("Compute pipeline layout ({})", name)
+        );
+
+        Ok(Self {
+            pipeline_layout,
+            pipeline,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
+        })
+    }
+
+    /// The pipeline layout.
+    pub fn pipeline_layout(&self) -> &vk::PipelineLayout {
+        &self.pipeline_layout
+    }
+
+    /// Bind the pipeline.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the pipeline must outlive its
+    /// execution.
+    pub unsafe fn bind(&self, device: &Device, command_buffer: &vk::CommandBuffer) {
+        device.cmd_bind_pipeline(
+            *command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline
+        );
+    }
+
+    /// Bind descriptor sets to the pipeline, starting at the first set.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the sets must match the
+    /// pipeline's layouts and stay valid until it executes.
+    pub unsafe fn bind_descriptor_sets(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet]
+    ) {
+        device.cmd_bind_descriptor_sets(
+            *command_buffer,
+            vk::PipelineBindPoint::COMPUTE,
+            self.pipeline_layout,
+            first_set,
+            descriptor_sets,
+            &[]
+        );
+    }
+
+    /// Push constants to the pipeline. The data is written at the given
+    /// offset and must lie within a range declared in the settings.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the data must lie within a
+    /// push constant range of the pipeline.
+    pub unsafe fn push_constants<T: Pod>(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        offset: u32,
+        data: &T
+    ) {
+        device.cmd_push_constants(
+            *command_buffer,
+            self.pipeline_layout,
+            vk::ShaderStageFlags::COMPUTE,
+            offset,
+            bytes_of(data)
+        );
+    }
+
+    /// Dispatch the given number of workgroups. The pipeline must be bound.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording with the pipeline bound, along with
+    /// every descriptor set and push constant its shader reads.
+    pub unsafe fn dispatch(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        x: u32,
+        y: u32,
+        z: u32
+    ) {
+        device.cmd_dispatch(*command_buffer, x, y, z);
+    }
+
+    /// Destroy the pipeline right away.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the pipeline.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handles out of the pipeline, leaving it destroyed.
+    /// Returns None if the pipeline was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.pipeline == vk::Pipeline::null() {
+            return None;
+        }
+
+        Some(DeferredResource::Pipeline(
+            take(&mut self.pipeline),
+            take(&mut self.pipeline_layout)
+        ))
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
+    }
+}
+
+impl Deref for ComputePipeline {
+    type Target = vk::Pipeline;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pipeline
+    }
+}
@@ -1,48 +1,209 @@
 use anyhow::Result;
 use ash::vk;
-use std::ffi::{c_void, CStr};
+use std::{
+    ffi::{c_char, c_void, CStr},
+    mem::take,
+    ptr::null_mut,
+    slice::from_raw_parts,
+    sync::{Arc, Mutex}
+};
 use tracing::{debug, error, trace, warn};
 
-/// Wraps the data for the debug messenger.
+/// The most messages that are collected at once. Past this, the
+/// oldest message that isn't an error makes room for each new one,
+/// so a caller that only takes the errors never loses any of them.
+const MAX_COLLECTED_MESSAGES: usize = 1024;
+
+/// The minimum severity of the debug messages to report.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DebugLevel {
+    /// Report everything, including verbose driver and loader messages.
+    #[default]
+    Verbose,
+
+    /// Report informational messages and above.
+    Info,
+
+    /// Report warnings and errors.
+    Warning,
+
+    /// Report errors only.
+    Error
+}
+
+impl DebugLevel {
+    /// The severities at or above this level.
+    pub fn severities(&self) -> vk::DebugUtilsMessageSeverityFlagsEXT {
+        [
+            (
+                Self::Verbose,
+                vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
+            ),
+            (Self::Info, vk::DebugUtilsMessageSeverityFlagsEXT::INFO),
+            (
+                Self::Warning,
+                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            ),
+            (Self::Error, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+        ]
+        .into_iter()
+        .filter(|(level, _)| level >= self)
+        .fold(
+            vk::DebugUtilsMessageSeverityFlagsEXT::empty(),
+            |severities, (_, severity)| severities | severity
+        )
+    }
+}
+
+/// A message reported by the debug messenger.
+#[derive(Clone, Debug)]
+pub struct DebugMessage {
+    /// The severity.
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+
+    /// The message types.
+    pub message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+
+    /// The message id, e.g. the id of the violated validation rule.
+    pub id: i32,
+
+    /// The message text.
+    pub text: String
+}
+
+impl DebugMessage {
+    /// Returns true if this is an error.
+    pub fn is_error(&self) -> bool {
+        self.severity
+            .contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+    }
+}
+
+/// Wraps the data for the debug messenger. The messenger is destroyed
+/// when it's dropped, if it wasn't already, so it must be dropped
+/// before the instance.
 pub struct Debugging {
     /// The function pointers.
     functions: ash::ext::debug_utils::Instance,
 
     /// The messenger.
-    messenger: vk::DebugUtilsMessengerEXT
+    messenger: vk::DebugUtilsMessengerEXT,
+
+    /// The collected messages, if collecting. The callback gets a pointer
+    /// to the mutex as its user data, which stays valid as long as we
+    /// hold the Arc. The messenger is destroyed before it's dropped.
+    messages: Option<Arc<Mutex<Vec<DebugMessage>>>>
 }
 
 impl Debugging {
-    pub unsafe fn new(entry: &ash::Entry, instance: &ash::Instance) -> Result<Self> {
+    /// Create a new debug messenger that reports messages of the
+    /// given types at or above the given level.
+    ///
+    /// # Safety
+    ///
+    /// The instance must have been created with the debug utils extension, and
+    /// the messenger must be destroyed before the instance is.
+    pub unsafe fn new(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        level: DebugLevel,
+        message_types: vk::DebugUtilsMessageTypeFlagsEXT
+    ) -> Result<Self> {
+        Self::make(entry, instance, level, message_types, false)
+    }
+
+    /// Create a new debug messenger that also collects the messages it
+    /// reports, e.g. so a test can fail if validation reported errors.
+    ///
+    /// # Safety
+    ///
+    /// The instance must have been created with the debug utils extension, and
+    /// the messenger must be destroyed before the instance is.
+    pub unsafe fn new_collecting(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        level: DebugLevel,
+        message_types: vk::DebugUtilsMessageTypeFlagsEXT
+    ) -> Result<Self> {
+        Self::make(entry, instance, level, message_types, true)
+    }
+
+    /// Create the debug messenger.
+    unsafe fn make(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        level: DebugLevel,
+        message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+        collect: bool
+    ) -> Result<Self> {
         // Load debug functions.
         let functions = ash::ext::debug_utils::Instance::new(entry, instance);
 
+        // The collected messages.
+        let messages = collect.then(|| Arc::new(Mutex::new(Vec::new())));
+
+        // The user data is the mutex the callback pushes messages into.
+        let user_data = match &messages {
+            Some(messages) => Arc::as_ptr(messages) as *mut c_void,
+            None => null_mut()
+        };
+
         // Create the messenger info.
-        let messenger_info = Self::messenger_info();
+        let messenger_info = Self::messenger_info(level, message_types).user_data(user_data);
 
         // Create the messenger.
         let messenger = functions.create_debug_utils_messenger(&messenger_info, None)?;
 
         Ok(Self {
             functions,
-            messenger
+            messenger,
+            messages
         })
     }
 
+    /// Returns the errors collected since the last call and clears them.
+    /// Messages of other severities are kept for take_messages, up to
+    /// a limit, after which the oldest are dropped. Returns nothing if
+    /// the messenger isn't collecting.
+    pub fn errors_since(&self) -> Vec<DebugMessage> {
+        match &self.messages {
+            Some(messages) => match messages.lock() {
+                Ok(mut messages) => {
+                    let (errors, others) = take(&mut *messages)
+                        .into_iter()
+                        .partition(DebugMessage::is_error);
+
+                    *messages = others;
+
+                    errors
+                },
+                Err(_) => Vec::new()
+            },
+            None => Vec::new()
+        }
+    }
+
+    /// Returns the messages collected since the last call and clears
+    /// them. Past the limit, the oldest messages that aren't errors
+    /// were dropped. Returns nothing if the messenger isn't collecting.
+    pub fn take_messages(&self) -> Vec<DebugMessage> {
+        match &self.messages {
+            Some(messages) => match messages.lock() {
+                Ok(mut messages) => take(&mut *messages),
+                Err(_) => Vec::new()
+            },
+            None => Vec::new()
+        }
+    }
+
     /// Create the debug messenger info.
-    pub fn messenger_info<'a>() -> vk::DebugUtilsMessengerCreateInfoEXT<'a> {
+    pub fn messenger_info<'a>(
+        level: DebugLevel,
+        message_types: vk::DebugUtilsMessageTypeFlagsEXT
+    ) -> vk::DebugUtilsMessengerCreateInfoEXT<'a> {
         vk::DebugUtilsMessengerCreateInfoEXT::default()
-            .message_severity(
-                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
-                    | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE
-            )
-            .message_type(
-                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
-                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
-                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
-            )
+            .message_severity(level.severities())
+            .message_type(message_types)
             .pfn_user_callback(Some(Self::c_debug_callback))
     }
 
@@ -51,12 +212,36 @@ impl Debugging {
         message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
         message_types: vk::DebugUtilsMessageTypeFlagsEXT,
         callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-        _user_data: *mut c_void
+        user_data: *mut c_void
     ) -> vk::Bool32 {
         let callback_data = &*callback_data;
 
         Self::debug_callback(message_severity, message_types, callback_data);
 
+        // Collect the message if we were given somewhere to put it.
+        if !user_data.is_null() {
+            let messages = &*(user_data as *const Mutex<Vec<DebugMessage>>);
+
+            if let Ok(mut messages) = messages.lock() {
+                // Make room by dropping the oldest message that isn't an error.
+                if messages.len() >= MAX_COLLECTED_MESSAGES {
+                    if let Some(index) = messages
+                        .iter()
+                        .position(|message| !message.is_error())
+                    {
+                        messages.remove(index);
+                    }
+                }
+
+                messages.push(DebugMessage {
+                    severity: message_severity,
+                    message_types,
+                    id: callback_data.message_id_number,
+                    text: Self::c_str(callback_data.p_message).unwrap_or_default()
+                });
+            }
+        }
+
         vk::FALSE
     }
 
@@ -66,19 +251,59 @@ impl Debugging {
         message_types: vk::DebugUtilsMessageTypeFlagsEXT,
         callback_data: &vk::DebugUtilsMessengerCallbackDataEXT
     ) {
-        // TODO: Add queue and command buffer labels and objects.
-
-        let message = unsafe {
+        let mut message = unsafe {
             format!(
                 "{:?}: (0x{:x?}) {}",
                 message_types,
                 callback_data.message_id_number,
-                CStr::from_ptr(callback_data.p_message)
-                    .to_str()
-                    .unwrap()
+                Self::c_str(callback_data.p_message).unwrap_or_default()
+            )
+        };
+
+        // Get the labels and objects.
+        let (queue_labels, cmd_buf_labels, objects) = unsafe {
+            (
+                Self::slice(
+                    callback_data.p_queue_labels,
+                    callback_data.queue_label_count
+                ),
+                Self::slice(
+                    callback_data.p_cmd_buf_labels,
+                    callback_data.cmd_buf_label_count
+                ),
+                Self::slice(callback_data.p_objects, callback_data.object_count)
             )
         };
 
+        // Append the queue and command buffer labels.
+        for (title, labels) in [
+            ("Queue labels", queue_labels),
+            ("Command buffer labels", cmd_buf_labels)
+        ] {
+            if labels.is_empty() {
+                continue;
+            }
+
+            let names = labels
+                .iter()
+                .map(|label| unsafe { Self::c_str(label.p_label_name) }.unwrap_or_default())
+                .collect::<Vec<_>>();
+
+            message.push_str(&format!("\n    {}: {}", title, names.join(", ")));
+        }
+
+        // Append the objects.
+        for object in objects {
+            message.push_str(&format!(
+                "\n    Object: {:?} 0x{:x}",
+                object.object_type, object.object_handle
+            ));
+
+            if let Some(name) = unsafe { Self::c_str(object.p_object_name) } {
+                message.push_str(&format!(" \"{}\"", name));
+            }
+        }
+
         if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
             error!(message);
         } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
@@ -90,9 +315,47 @@ impl Debugging {
         }
     }
 
-    /// Destroy the debug messenger.
+    /// Read a string passed to the callback. Returns None if it's null.
+    unsafe fn c_str(ptr: *const c_char) -> Option<String> {
+        match ptr.is_null() {
+            true => None,
+            false => Some(
+                CStr::from_ptr(ptr)
+                    .to_string_lossy()
+                    .into_owned()
+            )
+        }
+    }
+
+    /// Read an array passed to the callback. Returns an
+    /// empty slice if it's null or the count is zero.
+    unsafe fn slice<'a, T>(ptr: *const T, count: u32) -> &'a [T] {
+        match ptr.is_null() || count == 0 {
+            true => &[],
+            false => from_raw_parts(ptr, count as usize)
+        }
+    }
+
+    /// Destroy the debug messenger. Does nothing if it was already destroyed.
+    ///
+    /// # Safety
+    ///
+    /// The instance the messenger was created with must not have been destroyed
+    /// yet.
     pub unsafe fn destroy(&mut self) {
+        if self.messenger == vk::DebugUtilsMessengerEXT::null() {
+            return;
+        }
+
         self.functions
-            .destroy_debug_utils_messenger(self.messenger, None);
+            .destroy_debug_utils_messenger(take(&mut self.messenger), None);
+    }
+}
+
+impl Drop for Debugging {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy();
+        }
     }
 }
@@ -0,0 +1,147 @@
+use crate::{Allocation, Device, ResourceKind};
+use ash::vk;
+use std::{
+    mem::take,
+    sync::{Mutex, PoisonError}
+};
+use tracing::debug;
+
+/// The handles of a resource wrapper, taken out of it to be destroyed.
+pub enum DeferredResource {
+    /// A buffer and its memory.
+    Buffer(vk::Buffer, Allocation),
+
+    /// A buffer and its memory, which is mapped.
+    MappedBuffer(vk::Buffer, Allocation),
+
+    /// A pipeline and its layout, graphics or compute.
+    Pipeline(vk::Pipeline, vk::PipelineLayout),
+
+    /// An image, its view, and its memory.
+    Image(vk::Image, vk::ImageView, Allocation),
+
+    /// A sampler.
+    Sampler(vk::Sampler),
+
+    /// A descriptor set layout.
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+
+    /// A render pass.
+    RenderPass(vk::RenderPass)
+}
+
+impl DeferredResource {
+    /// Destroy the resource. The GPU must be done with it.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the resource, and the device must be the one
+    /// it was created on.
+    pub unsafe fn destroy(self, device: &Device) {
+        match self {
+            Self::Buffer(buffer, allocation) => {
+                // Destroy the buffer.
+                device.destroy_buffer(buffer, None);
+                device.untrack_resource(ResourceKind::Buffer);
+
+                // Free the memory.
+                device
+                    .allocator()
+                    .free(device, allocation);
+            },
+            Self::MappedBuffer(buffer, allocation) => {
+                // Unmap the memory.
+                allocation.unmap(device);
+
+                Self::Buffer(buffer, allocation).destroy(device);
+            },
+            Self::Pipeline(pipeline, pipeline_layout) => {
+                // Destroy the pipeline.
+                device.destroy_pipeline(pipeline, None);
+
+                // Destroy the pipeline layout.
+                device.destroy_pipeline_layout(pipeline_layout, None);
+            },
+            Self::Image(image, view, allocation) => {
+                // Destroy the image view.
+                device.destroy_image_view(view, None);
+
+                // Destroy the image.
+                device.destroy_image(image, None);
+                device.untrack_resource(ResourceKind::Image);
+
+                // Free the memory.
+                device
+                    .allocator()
+                    .free(device, allocation);
+            },
+            Self::Sampler(sampler) => {
+                device.destroy_sampler(sampler, None);
+            },
+            Self::DescriptorSetLayout(layout) => {
+                device.destroy_descriptor_set_layout(layout, None);
+            },
+            Self::RenderPass(render_pass) => {
+                device.destroy_render_pass(render_pass, None);
+            }
+        }
+    }
+}
+
+/// Collects the resources whose wrappers were dropped without being
+/// destroyed. They can't be destroyed on drop, since that needs the
+/// device and the GPU may still be using them, so the device destroys
+/// them the next time it waits to idle, or when it's destroyed itself.
+#[derive(Default)]
+pub struct DeferredDestroyQueue {
+    /// The resources waiting to be destroyed.
+    resources: Mutex<Vec<DeferredResource>>
+}
+
+impl DeferredDestroyQueue {
+    /// Queue a resource to be destroyed once the device is idle.
+    pub fn push(&self, resource: DeferredResource) {
+        self.resources
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(resource);
+    }
+
+    /// Returns the number of resources waiting to be destroyed.
+    pub fn len(&self) -> usize {
+        self.resources
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns whether no resources are waiting to be destroyed.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Destroy every queued resource. The device must be idle.
+    ///
+    /// # Safety
+    ///
+    /// The device must be idle, so the GPU is done with every queued resource.
+    pub unsafe fn drain(&self, device: &Device) {
+        // Take the resources so none are locked while destroying.
+        let resources = take(
+            &mut *self
+                .resources
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        );
+
+        if resources.is_empty() {
+            return;
+        }
+
+        debug!("Destroying {} dropped resources.", resources.len());
+
+        resources
+            .into_iter()
+            .for_each(|resource| resource.destroy(device));
+    }
+}
@@ -0,0 +1,543 @@
+use crate::{DeferredDestroyQueue, DeferredResource, Device};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use std::{mem::take, ops::Deref, slice::from_ref, sync::Arc};
+
+/// A binding in a descriptor set layout.
+#[derive(Clone, Copy, Debug)]
+pub struct DescriptorBinding {
+    /// The binding number used by the shaders.
+    pub binding: u32,
+
+    /// The type of the descriptors.
+    pub descriptor_type: vk::DescriptorType,
+
+    /// The shader stages that can access the binding.
+    pub stage_flags: vk::ShaderStageFlags,
+
+    /// The number of descriptors, e.g. the length of an array of textures.
+    pub count: u32
+}
+
+/// The resources written to a binding of a descriptor set.
+/// Pass more than one to fill an array binding.
+#[derive(Clone, Debug)]
+pub enum DescriptorResource {
+    /// Buffers, for uniform and storage buffer bindings.
+    Buffers(Vec<vk::DescriptorBufferInfo>),
+
+    /// Images, for sampler, image, and input attachment bindings.
+    Images(Vec<vk::DescriptorImageInfo>)
+}
+
+impl DescriptorResource {
+    /// A range of a single buffer.
+    pub fn buffer(buffer: vk::Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> Self {
+        Self::Buffers(vec![vk::DescriptorBufferInfo {
+            buffer,
+            offset,
+            range
+        }])
+    }
+
+    /// A single image, with a sampler if the binding needs one.
+    pub fn image(view: vk::ImageView, sampler: vk::Sampler, layout: vk::ImageLayout) -> Self {
+        Self::Images(vec![vk::DescriptorImageInfo {
+            sampler,
+            image_view: view,
+            image_layout: layout
+        }])
+    }
+
+    /// The number of descriptors written.
+    fn len(&self) -> usize {
+        match self {
+            Self::Buffers(infos) => infos.len(),
+            Self::Images(infos) => infos.len()
+        }
+    }
+}
+
+/// Wraps a Vulkan descriptor set layout. The bindings are kept so
+/// pools can be sized for the layout and writes can be checked.
+/// Dropping the layout without destroying it defers its
+/// destruction to the device's next wait for idle.
+pub struct DescriptorSetLayout {
+    /// The descriptor set layout.
+    layout: vk::DescriptorSetLayout,
+
+    /// The bindings.
+    bindings: Vec<DescriptorBinding>,
+
+    /// Whether the descriptors are pushed instead of allocated.
+    push: bool,
+
+    /// Whether the descriptors can be updated after the set is bound.
+    update_after_bind: bool,
+
+    /// Where the layout goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
+}
+
+impl DescriptorSetLayout {
+    /// Create a new descriptor set layout from the given bindings.
+    /// Sets of this layout are allocated from a descriptor pool.
+    ///
+    /// # Safety
+    ///
+    /// The layout must be dropped or destroyed before the device is.
+    pub unsafe fn new(device: &Device, bindings: &[DescriptorBinding]) -> Result<Self> {
+        Self::make(device, bindings, false, false)
+    }
+
+    /// Create a new descriptor set layout whose descriptors are pushed
+    /// straight into command buffers. No pool is needed, but this needs
+    /// the push descriptor extension and dynamic buffers aren't allowed.
+    ///
+    /// # Safety
+    ///
+    /// The push descriptor extension must be enabled, and the layout must be
+    /// dropped or destroyed before the device is.
+    pub unsafe fn new_push(device: &Device, bindings: &[DescriptorBinding]) -> Result<Self> {
+        if !device.push_descriptors_enabled() {
+            return Err(anyhow!("Push descriptors are not enabled."));
+        }
+
+        Self::make(device, bindings, true, false)
+    }
+
+    /// Create a new descriptor set layout whose bindings may be partially
+    /// bound and updated after the set is bound, as long as the descriptors
+    /// being updated aren't in use. Sets of this layout are allocated from
+    /// a pool made by new_pool. This needs descriptor indexing, and only
+    /// sampled image bindings are supported.
+    ///
+    /// # Safety
+    ///
+    /// Descriptor indexing must be enabled on the device, and the layout must
+    /// be dropped or destroyed before the device is.
+    pub unsafe fn new_update_after_bind(
+        device: &Device,
+        bindings: &[DescriptorBinding]
+    ) -> Result<Self> {
+        if !device.descriptor_indexing_enabled() {
+            return Err(anyhow!("Descriptor indexing is not enabled."));
+        }
+
+        if let Some(binding) = bindings.iter().find(|binding| {
+            binding.descriptor_type != vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+                && binding.descriptor_type != vk::DescriptorType::SAMPLED_IMAGE
+        }) {
+            return Err(anyhow!(
+                "Binding {} of type {:?} can't be updated after bind.",
+                binding.binding,
+                binding.descriptor_type
+            ));
+        }
+
+        Self::make(device, bindings, false, true)
+    }
+
+    /// Create the descriptor set layout.
+    unsafe fn make(
+        device: &Device,
+        bindings: &[DescriptorBinding],
+        push: bool,
+        update_after_bind: bool
+    ) -> Result<Self> {
+        // The Vulkan bindings.
+        let layout_bindings = bindings
+            .iter()
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.count)
+                    .stage_flags(binding.stage_flags)
+            })
+            .collect::<Vec<_>>();
+
+        // Push and update-after-bind descriptor layouts need a flag.
+        let flags = match (push, update_after_bind) {
+            (true, _) => vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
+            (false, true) => vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+            (false, false) => vk::DescriptorSetLayoutCreateFlags::empty()
+        };
+
+        // Update-after-bind bindings may also be partially bound.
+        let binding_flags = vec![
+            match update_after_bind {
+                true => {
+                    vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                        | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                },
+                false => vk::DescriptorBindingFlags::empty()
+            };
+            bindings.len()
+        ];
+
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&binding_flags);
+
+        // Create the descriptor set layout info.
+        let mut layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(flags)
+            .bindings(&layout_bindings);
+
+        if update_after_bind {
+            layout_info = layout_info.push_next(&mut binding_flags_info);
+        }
+
+        // Create the descriptor set layout.
+        let layout = device.create_descriptor_set_layout(&layout_info, None)?;
+
+        Ok(Self {
+            layout,
+            bindings: bindings.to_vec(),
+            push,
+            update_after_bind,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
+        })
+    }
+
+    /// Returns the bindings.
+    pub fn bindings(&self) -> &[DescriptorBinding] {
+        &self.bindings
+    }
+
+    /// Returns true if the descriptors are pushed instead of allocated.
+    pub fn is_push(&self) -> bool {
+        self.push
+    }
+
+    /// Returns true if the descriptors can be updated after the set is bound.
+    pub fn is_update_after_bind(&self) -> bool {
+        self.update_after_bind
+    }
+
+    /// Create a descriptor pool large enough for the given number of sets.
+    ///
+    /// # Safety
+    ///
+    /// The pool must be destroyed before the device is, after the GPU is done
+    /// with the sets allocated from it.
+    pub unsafe fn new_pool(&self, device: &Device, set_count: u32) -> Result<vk::DescriptorPool> {
+        // Add up the descriptors of each type.
+        let mut pool_sizes = Vec::<vk::DescriptorPoolSize>::new();
+
+        for binding in &self.bindings {
+            match pool_sizes
+                .iter_mut()
+                .find(|size| size.ty == binding.descriptor_type)
+            {
+                Some(size) => size.descriptor_count += binding.count * set_count,
+                None => pool_sizes.push(
+                    vk::DescriptorPoolSize::default()
+                        .ty(binding.descriptor_type)
+                        .descriptor_count(binding.count * set_count)
+                )
+            }
+        }
+
+        // Update-after-bind sets need a pool that allows them.
+        let flags = match self.update_after_bind {
+            true => vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND,
+            false => vk::DescriptorPoolCreateFlags::empty()
+        };
+
+        // Create the descriptor pool.
+        let descriptor_pool = device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::default()
+                .flags(flags)
+                .pool_sizes(&pool_sizes)
+                .max_sets(set_count),
+            None
+        )?;
+
+        Ok(descriptor_pool)
+    }
+
+    /// Allocate a descriptor set from the pool and write
+    /// the given resources to it, keyed by binding number.
+    ///
+    /// # Safety
+    ///
+    /// The pool must have been made for this layout and have room for another
+    /// set. The resources must stay valid while the set is in use.
+    pub unsafe fn allocate(
+        &self,
+        device: &Device,
+        descriptor_pool: &vk::DescriptorPool,
+        resources: &[(u32, DescriptorResource)]
+    ) -> Result<vk::DescriptorSet> {
+        if self.push {
+            return Err(anyhow!("Push descriptor sets can't be allocated."));
+        }
+
+        // Create the descriptor set.
+        let descriptor_set = device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(*descriptor_pool)
+                .set_layouts(from_ref(&self.layout))
+        )?[0];
+
+        // Update the descriptor set.
+        self.write(device, &descriptor_set, resources)?;
+
+        Ok(descriptor_set)
+    }
+
+    /// Write resources to an existing descriptor set, keyed by binding
+    /// number. Bindings that aren't mentioned are left as they are.
+    ///
+    /// # Safety
+    ///
+    /// The set must have this layout and must not be in use by the GPU, unless
+    /// its bindings allow updates after binding.
+    pub unsafe fn write(
+        &self,
+        device: &Device,
+        descriptor_set: &vk::DescriptorSet,
+        resources: &[(u32, DescriptorResource)]
+    ) -> Result<()> {
+        let writes = self.writes(device, *descriptor_set, resources)?;
+
+        device.update_descriptor_sets(&writes, &[]);
+
+        Ok(())
+    }
+
+    /// Write resources to a binding of an existing descriptor set, starting
+    /// at the given array element. This fills part of an array binding,
+    /// e.g. a single slot of a bindless texture array.
+    ///
+    /// # Safety
+    ///
+    /// The set must have this layout, and the elements must not be in use by
+    /// the GPU. The binding must have room for every resource past the first
+    /// element.
+    pub unsafe fn write_elements(
+        &self,
+        device: &Device,
+        descriptor_set: &vk::DescriptorSet,
+        binding: u32,
+        array_element: u32,
+        resource: &DescriptorResource
+    ) -> Result<()> {
+        let write =
+            self.binding_write(device, *descriptor_set, binding, array_element, resource)?;
+
+        device.update_descriptor_sets(from_ref(&write), &[]);
+
+        Ok(())
+    }
+
+    /// Push resources into a command buffer as the given set of the
+    /// pipeline layout, keyed by binding number. The layout must have
+    /// been created for push descriptors.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, the layout must have been created
+    /// with new_push, and the pipeline layout must use it at the given set.
+    pub unsafe fn push(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        pipeline_bind_point: vk::PipelineBindPoint,
+        pipeline_layout: &vk::PipelineLayout,
+        set: u32,
+        resources: &[(u32, DescriptorResource)]
+    ) -> Result<()> {
+        if !self.push {
+            return Err(anyhow!("The layout was not created for push descriptors."));
+        }
+
+        // Pushed writes have no destination set.
+        let writes = self.writes(device, vk::DescriptorSet::null(), resources)?;
+
+        device.cmd_push_descriptor_set(
+            command_buffer,
+            pipeline_bind_point,
+            pipeline_layout,
+            set,
+            &writes
+        )
+    }
+
+    /// Build the writes for the given resources, checking
+    /// them against the bindings of the layout.
+    fn writes<'a>(
+        &self,
+        device: &Device,
+        descriptor_set: vk::DescriptorSet,
+        resources: &'a [(u32, DescriptorResource)]
+    ) -> Result<Vec<vk::WriteDescriptorSet<'a>>> {
+        resources
+            .iter()
+            .map(|(binding_number, resource)| {
+                self.binding_write(device, descriptor_set, *binding_number, 0, resource)
+            })
+            .collect()
+    }
+
+    /// Build the write of a resource to a binding, starting at
+    /// the given array element, checking it against the binding.
+    fn binding_write<'a>(
+        &self,
+        device: &Device,
+        descriptor_set: vk::DescriptorSet,
+        binding_number: u32,
+        array_element: u32,
+        resource: &'a DescriptorResource
+    ) -> Result<vk::WriteDescriptorSet<'a>> {
+        // Find the binding.
+        let binding = self
+            .bindings
+            .iter()
+            .find(|binding| binding.binding == binding_number)
+            .ok_or_else(|| anyhow!("The layout has no binding {}.", binding_number))?;
+
+        // The binding must have room for every descriptor.
+        if array_element + resource.len() as u32 > binding.count {
+            return Err(anyhow!(
+                "Binding {} holds {} descriptors but {} were written at element {}.",
+                binding_number,
+                binding.count,
+                resource.len(),
+                array_element
+            ));
+        }
+
+        // Texel buffers are written with buffer views, which we don't use.
+        if matches!(
+            binding.descriptor_type,
+            vk::DescriptorType::UNIFORM_TEXEL_BUFFER | vk::DescriptorType::STORAGE_TEXEL_BUFFER
+        ) {
+            return Err(anyhow!(
+                "Binding {} is a texel buffer, which isn't supported.",
+                binding_number
+            ));
+        }
+
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(binding.binding)
+            .dst_array_element(array_element)
+            .descriptor_type(binding.descriptor_type);
+
+        // The resource must match the type of the binding.
+        match (Self::is_buffer_type(binding.descriptor_type), resource) {
+            (true, DescriptorResource::Buffers(infos)) => {
+                Self::check_buffer_infos(device, binding, infos)?;
+
+                Ok(write.buffer_info(infos))
+            },
+            (false, DescriptorResource::Images(infos)) => Ok(write.image_info(infos)),
+            _ => Err(anyhow!(
+                "Binding {} of type {:?} can't hold {:?}.",
+                binding_number,
+                binding.descriptor_type,
+                resource
+            ))
+        }
+    }
+
+    /// Check buffer ranges against the device limits of the binding's type.
+    /// Without the validation layers, a bad range goes unnoticed.
+    fn check_buffer_infos(
+        device: &Device,
+        binding: &DescriptorBinding,
+        infos: &[vk::DescriptorBufferInfo]
+    ) -> Result<()> {
+        let limits = &device.properties().limits;
+
+        // The offset alignment and maximum range of the type.
+        let (alignment, max_range) = match binding.descriptor_type {
+            vk::DescriptorType::STORAGE_BUFFER | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC => (
+                limits.min_storage_buffer_offset_alignment,
+                limits.max_storage_buffer_range
+            ),
+            _ => (
+                limits.min_uniform_buffer_offset_alignment,
+                limits.max_uniform_buffer_range
+            )
+        };
+
+        for info in infos {
+            if info.offset % alignment.max(1) != 0 {
+                return Err(anyhow!(
+                    "Binding {} has offset {}, which isn't a multiple of {}.",
+                    binding.binding,
+                    info.offset,
+                    alignment
+                ));
+            }
+
+            if info.range != vk::WHOLE_SIZE && info.range > max_range as vk::DeviceSize {
+                return Err(anyhow!(
+                    "Binding {} has range {}, but at most {} is allowed.",
+                    binding.binding,
+                    info.range,
+                    max_range
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether descriptors of the given type are written with buffer infos.
+    fn is_buffer_type(descriptor_type: vk::DescriptorType) -> bool {
+        matches!(
+            descriptor_type,
+            vk::DescriptorType::UNIFORM_BUFFER
+                | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                | vk::DescriptorType::STORAGE_BUFFER
+                | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+        )
+    }
+
+    /// Destroy the descriptor set layout right away.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the layout, and no pipeline layout may still
+    /// need it.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handle out of the layout, leaving it destroyed.
+    /// Returns None if the layout was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.layout == vk::DescriptorSetLayout::null() {
+            return None;
+        }
+
+        Some(DeferredResource::DescriptorSetLayout(take(
+            &mut self.layout
+        )))
+    }
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
+    }
+}
+
+impl Deref for DescriptorSetLayout {
+    type Target = vk::DescriptorSetLayout;
+
+    fn deref(&self) -> &Self::Target {
+        &self.layout
+    }
+}
@@ -1,11 +1,282 @@
-use crate::{CommandPool, Instance, Surface};
-use anyhow::{anyhow, Result};
+use crate::{
+    Allocator, ColorSpacePreference, CommandPool, DeferredDestroyQueue, DepthBias, Instance, Queue,
+    ResourceCounts, ResourceKind, ResourceTracker, Surface, Swapchain, VulkanError, DEPTH_FORMATS,
+    DEPTH_STENCIL_FORMATS
+};
+use anyhow::{anyhow, Error, Result};
 use ash::vk::{self};
-use std::{ffi::CStr, ops::Deref, slice::from_ref};
-use tracing::info;
+use std::{
+    ffi::{CStr, CString},
+    fmt::{self, Display, Formatter},
+    mem::size_of,
+    ops::Deref,
+    slice::{from_raw_parts, from_raw_parts_mut, from_ref},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError
+    }
+};
+use tracing::{debug, error, info, warn};
+
+/// The fraction of maxMemoryAllocationCount past which we warn.
+const ALLOCATION_COUNT_WARNING: f32 = 0.8;
+
+/// How to choose the physical device.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum DeviceSelector {
+    /// Choose the highest scoring device.
+    #[default]
+    Auto,
+
+    /// Choose the device at this index in the enumeration order.
+    Index(usize),
+
+    /// Choose the highest scoring device whose name
+    /// contains this string, ignoring case.
+    Name(String),
+
+    /// Choose the highest scoring integrated GPU if there is one.
+    PreferIntegrated
+}
+
+/// A summary of a physical device, for diagnostics and for choosing
+/// a DeviceSelector. See Instance::list_physical_devices.
+#[derive(Clone, Debug)]
+pub struct DeviceInfo {
+    /// The index of the device in the enumeration order.
+    pub index: usize,
+
+    /// The name of the device.
+    pub name: String,
+
+    /// The type of the device, e.g. a discrete or integrated GPU.
+    pub device_type: vk::PhysicalDeviceType,
+
+    /// The Vulkan version the device supports.
+    pub api_version: u32,
+
+    /// The driver version, in the vendor's own encoding.
+    pub driver_version: u32,
+
+    /// The total size of the device-local memory heaps in bytes.
+    pub device_local_memory: vk::DeviceSize
+}
+
+impl DeviceInfo {
+    /// Summarize the device at the given index.
+    pub fn new(
+        index: usize,
+        properties: &vk::PhysicalDeviceProperties,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties
+    ) -> Self {
+        let device_local_memory = memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| {
+                heap.flags
+                    .contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+            })
+            .map(|heap| heap.size)
+            .sum();
+
+        Self {
+            index,
+            name: Device::device_name(properties),
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            driver_version: properties.driver_version,
+            device_local_memory
+        }
+    }
+}
+
+impl Display for DeviceInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({:?}, Vulkan {}.{}.{}, {} MiB)",
+            self.index,
+            self.name,
+            self.device_type,
+            vk::api_version_major(self.api_version),
+            vk::api_version_minor(self.api_version),
+            vk::api_version_patch(self.api_version),
+            self.device_local_memory / (1024 * 1024)
+        )
+    }
+}
+
+/// A queue family of a physical device, along with what we queried
+/// about the device, considered as the graphics queue family while
+/// choosing a device.
+struct DeviceCandidate {
+    /// The index of the device in the enumeration order.
+    device_index: usize,
+
+    /// The physical device.
+    physical_device: vk::PhysicalDevice,
+
+    /// The physical device properties.
+    properties: vk::PhysicalDeviceProperties,
+
+    /// The physical device features.
+    features: vk::PhysicalDeviceFeatures,
+
+    /// The index of the queue family.
+    queue_family_index: u32,
+
+    /// The queue family properties.
+    queue: vk::QueueFamilyProperties
+}
+
+/// The extensions and features a device must or may support. Devices
+/// missing anything required are skipped during selection. Optional
+/// extensions and features are enabled whenever they're available.
+/// The swapchain extension, and the portability subset on macOS,
+/// are added automatically.
+#[derive(Clone, Debug)]
+pub struct DeviceRequirements {
+    /// The extensions a device must support.
+    pub required_extensions: Vec<&'static CStr>,
+
+    /// The extensions enabled if the device supports them.
+    pub optional_extensions: Vec<&'static CStr>,
+
+    /// The features a device must support.
+    pub required_features: vk::PhysicalDeviceFeatures,
+
+    /// The features enabled if the device supports them.
+    pub optional_features: vk::PhysicalDeviceFeatures
+}
+
+impl Default for DeviceRequirements {
+    fn default() -> Self {
+        Self {
+            required_extensions: vec![],
+            // Push descriptors save allocating descriptor sets for
+            // simple bindings. Without them, we use descriptor pools.
+            optional_extensions: vec![ash::khr::push_descriptor::NAME],
+            required_features:   vk::PhysicalDeviceFeatures::default(),
+            // Storage image writes without a format are only needed to
+            // write to swapchain images. Without multi-draw indirect,
+            // indirect draws are issued one at a time. Wireframes need
+            // non-solid fill modes, and lines are one pixel wide without
+            // wide lines. Depth bias can't be clamped without its feature.
+            // Texture arrays can't be indexed by anything but constants
+            // without dynamic indexing, so bindless textures need it.
+            optional_features:   vk::PhysicalDeviceFeatures::default()
+                .sampler_anisotropy(true)
+                .shader_sampled_image_array_dynamic_indexing(true)
+                .shader_storage_image_write_without_format(true)
+                .multi_draw_indirect(true)
+                .fill_mode_non_solid(true)
+                .wide_lines(true)
+                .depth_bias_clamp(true)
+        }
+    }
+}
+
+impl DeviceRequirements {
+    /// Require an extension.
+    pub fn require_extension(mut self, extension: &'static CStr) -> Self {
+        self.required_extensions
+            .push(extension);
 
-/// Wraps a Vulkan device.
+        self
+    }
+
+    /// Enable an extension if it's available.
+    pub fn optional_extension(mut self, extension: &'static CStr) -> Self {
+        self.optional_extensions
+            .push(extension);
+
+        self
+    }
+
+    /// Require every feature that is set in the given features.
+    pub fn require_features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        Self::merge_features(&mut self.required_features, &features);
+
+        self
+    }
+
+    /// Enable every feature that is set in the given features if it's available.
+    pub fn optional_features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        Self::merge_features(&mut self.optional_features, &features);
+
+        self
+    }
+
+    /// Returns true if the given features include all required features.
+    fn features_supported(&self, features: &vk::PhysicalDeviceFeatures) -> bool {
+        Self::flags(&self.required_features)
+            .iter()
+            .zip(Self::flags(features))
+            .all(|(required, supported)| *required == 0 || *supported != 0)
+    }
+
+    /// The features to enable on a device with the given features.
+    fn enabled_features(
+        &self,
+        features: &vk::PhysicalDeviceFeatures
+    ) -> vk::PhysicalDeviceFeatures {
+        let mut enabled = self.required_features;
+
+        // Add the optional features the device supports.
+        Self::flags_mut(&mut enabled)
+            .iter_mut()
+            .zip(Self::flags(&self.optional_features))
+            .zip(Self::flags(features))
+            .for_each(|((enabled, optional), supported)| {
+                if *optional != 0 && *supported != 0 {
+                    *enabled = vk::TRUE;
+                }
+            });
+
+        enabled
+    }
+
+    /// Set every feature in the destination that is set in the source.
+    fn merge_features(dst: &mut vk::PhysicalDeviceFeatures, src: &vk::PhysicalDeviceFeatures) {
+        Self::flags_mut(dst)
+            .iter_mut()
+            .zip(Self::flags(src))
+            .for_each(|(dst, src)| {
+                if *src != 0 {
+                    *dst = vk::TRUE;
+                }
+            });
+    }
+
+    /// View the features as a slice of flags. The
+    /// struct is nothing but booleans, in order.
+    fn flags(features: &vk::PhysicalDeviceFeatures) -> &[vk::Bool32] {
+        unsafe {
+            from_raw_parts(
+                (features as *const vk::PhysicalDeviceFeatures).cast(),
+                size_of::<vk::PhysicalDeviceFeatures>() / size_of::<vk::Bool32>()
+            )
+        }
+    }
+
+    /// View the features as a mutable slice of flags.
+    fn flags_mut(features: &mut vk::PhysicalDeviceFeatures) -> &mut [vk::Bool32] {
+        unsafe {
+            from_raw_parts_mut(
+                (features as *mut vk::PhysicalDeviceFeatures).cast(),
+                size_of::<vk::PhysicalDeviceFeatures>() / size_of::<vk::Bool32>()
+            )
+        }
+    }
+}
+
+/// Wraps a Vulkan device. The device is destroyed when it's dropped,
+/// if it wasn't already, so it must be dropped after everything
+/// made on it and before the instance.
 pub struct Device {
+    /// The instance functions, for querying the physical device.
+    instance: ash::Instance,
+
     /// The physical device.
     physical_device: vk::PhysicalDevice,
 
@@ -15,29 +286,92 @@ pub struct Device {
     /// The physical device features.
     features: vk::PhysicalDeviceFeatures,
 
+    /// The features enabled on the logical device.
+    enabled_features: vk::PhysicalDeviceFeatures,
+
+    /// The extensions enabled on the logical device.
+    enabled_extensions: Vec<&'static CStr>,
+
+    /// Whether timeline semaphores are enabled.
+    timeline_semaphore: bool,
+
+    /// Whether descriptor indexing is enabled for sampled images.
+    descriptor_indexing: bool,
+
+    /// Whether multiview is enabled.
+    multiview: bool,
+
     /// The memory properties.
     memory_properties: vk::PhysicalDeviceMemoryProperties,
 
     /// The logical device.
     device: ash::Device,
 
+    /// The debug utils functions, if debugging is enabled.
+    debug_utils: Option<ash::ext::debug_utils::Device>,
+
+    /// The push descriptor functions, if the extension is enabled.
+    push_descriptor: Option<ash::khr::push_descriptor::Device>,
+
     /// The graphics queue.
-    queue: vk::Queue,
+    queue: Queue,
 
-    /// The queue family index.
-    queue_family_index: u32,
+    /// The queue family properties.
+    queue_family_properties: vk::QueueFamilyProperties,
+
+    /// The transfer queue. This is the graphics queue if
+    /// there is no dedicated transfer queue family.
+    transfer_queue: Queue,
+
+    /// The present queue. This is the graphics queue if the graphics
+    /// queue family can present, or if we aren't presenting at all.
+    present_queue: Queue,
 
     /// The regular command pool.
     command_pool: CommandPool,
 
     /// The transient command pool.
-    transient_command_pool: CommandPool
+    transient_command_pool: CommandPool,
+
+    /// The transient command pool for the transfer queue.
+    transfer_command_pool: CommandPool,
+
+    /// The allocator for buffer and image memory.
+    allocator: Mutex<Allocator>,
+
+    /// The number of live memory allocations.
+    allocation_count: AtomicU32,
+
+    /// Counts the live buffers, images, and allocations. This
+    /// is only kept in debug builds, to catch resource leaks.
+    resource_tracker: Option<ResourceTracker>,
+
+    /// The resources that were dropped without being destroyed.
+    /// They're destroyed the next time the device waits to idle.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>,
+
+    /// Whether the device was destroyed.
+    destroyed: bool
 }
 
 impl Device {
-    pub unsafe fn new(instance: &Instance, surface: &Surface) -> Result<Self> {
-        // We at least require the swapchain extension.
-        let mut required_extensions = vec![ash::khr::swapchain::NAME];
+    /// Create a new device. Without a surface, the device is only
+    /// used for headless rendering and can't present.
+    pub unsafe fn new(
+        instance: &Instance,
+        surface: Option<&Surface>,
+        selector: &DeviceSelector,
+        requirements: &DeviceRequirements
+    ) -> Result<Self> {
+        // Start with the extensions the caller requires.
+        let mut required_extensions = requirements
+            .required_extensions
+            .clone();
+
+        // We require the swapchain extension if we're presenting.
+        if surface.is_some() {
+            required_extensions.push(ash::khr::swapchain::NAME);
+        }
 
         // On macOS, we also require the portability extension.
         if cfg!(target_os = "macos") {
@@ -49,13 +383,21 @@ impl Device {
             info!("Device extension: {:?}", extension);
         }
 
+        // Print every device, whether or not it's chosen.
+        for device_info in instance.list_physical_devices()? {
+            info!("Available device {}", device_info);
+        }
+
         // First, get a list of all candidates and their properties. Filter
         // out the ones that we can't use and compute a score for each one.
-        let mut candidates = instance
-            .enumerate_physical_devices()?
-            .into_iter()
-            // Compute a tuple for each queue family and its index.
-            .flat_map(|physical_device| {
+        let physical_devices = instance.enumerate_physical_devices()?;
+
+        let mut candidates = physical_devices
+            .iter()
+            .copied()
+            .enumerate()
+            // Make a candidate for each queue family of each device.
+            .flat_map(|(device_index, physical_device)| {
                 let properties = instance.get_physical_device_properties(physical_device);
                 let features = instance.get_physical_device_features(physical_device);
 
@@ -63,92 +405,242 @@ impl Device {
                     .get_physical_device_queue_family_properties(physical_device)
                     .into_iter()
                     .enumerate()
-                    .map(move |(queue_family_index, queue)| {
-                        (
-                            physical_device,
-                            properties,
-                            features,
-                            queue_family_index as u32,
-                            queue
-                        )
-                    })
-            })
-            // Filter out unsuitable candidates.
-            .filter(
-                |(physical_device, properties, features, queue_family_index, queue)| {
-                    Self::is_suitable(
-                        instance,
-                        surface,
-                        &required_extensions,
+                    .map(move |(queue_family_index, queue)| DeviceCandidate {
+                        device_index,
                         physical_device,
                         properties,
                         features,
-                        *queue_family_index,
+                        queue_family_index: queue_family_index as u32,
                         queue
-                    )
-                    .unwrap_or(false)
-                }
-            )
+                    })
+            })
+            // Filter out unsuitable candidates.
+            .filter(|candidate| {
+                Self::is_suitable(
+                    instance,
+                    surface,
+                    requirements,
+                    &required_extensions,
+                    candidate
+                )
+                .unwrap_or(false)
+            })
             // Compute a score for each candidate.
-            .map(
-                |(physical_device, properties, features, queue_family_index, queue)| {
-                    let score = Self::score(
-                        &physical_device,
-                        &properties,
-                        &features,
-                        queue_family_index,
-                        &queue
-                    );
-
-                    (
-                        score,
-                        physical_device,
-                        properties,
-                        features,
-                        queue_family_index,
-                        queue
-                    )
-                }
-            )
+            .map(|candidate| {
+                let memory_properties =
+                    instance.get_physical_device_memory_properties(candidate.physical_device);
+
+                // Whether the queue family can present as well.
+                let shared_present = Self::find_present_queue_family(
+                    instance,
+                    surface,
+                    &candidate.physical_device,
+                    candidate.queue_family_index
+                ) == Some(candidate.queue_family_index);
+
+                let score = Self::score(&candidate, &memory_properties, shared_present);
+
+                debug!(
+                    "Device {} ({}), queue family {}: score {}",
+                    candidate.device_index,
+                    Self::device_name(&candidate.properties),
+                    candidate.queue_family_index,
+                    score
+                );
+
+                (score, candidate)
+            })
             .collect::<Vec<_>>();
 
-        // Score the candidates by score.
-        candidates.sort_by(|a, b| b.0.cmp(&a.0));
+        // Sort the candidates by score. Ties go to the device and then
+        // the queue family that was enumerated first, so the choice is
+        // deterministic.
+        candidates.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then(a.device_index.cmp(&b.device_index))
+                .then(
+                    a.queue_family_index
+                        .cmp(&b.queue_family_index)
+                )
+        });
+
+        // Take the highest scoring candidate that matches the selector.
+        let mut candidates = candidates
+            .iter()
+            .map(|(_, candidate)| candidate);
+
+        let candidate = match selector {
+            DeviceSelector::Auto => candidates.next(),
+            DeviceSelector::Index(index) => {
+                candidates.find(|candidate| candidate.device_index == *index)
+            },
+            DeviceSelector::Name(name) => candidates.find(|candidate| {
+                Self::device_name(&candidate.properties)
+                    .to_lowercase()
+                    .contains(&name.to_lowercase())
+            }),
+            DeviceSelector::PreferIntegrated => candidates
+                .clone()
+                .find(|candidate| {
+                    candidate.properties.device_type == vk::PhysicalDeviceType::INTEGRATED_GPU
+                })
+                .or_else(|| candidates.next())
+        };
+
+        let DeviceCandidate {
+            physical_device,
+            properties,
+            features,
+            queue_family_index,
+            queue: queue_properties,
+            ..
+        } = match candidate {
+            Some(candidate) => candidate,
+            None => {
+                // List every device so the user can pick one that exists.
+                let available = instance
+                    .list_physical_devices()?
+                    .iter()
+                    .map(|device_info| device_info.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                return Err(Error::new(VulkanError::NoSuitableDevice).context(format!(
+                    "No suitable physical device found for {:?}. Available devices: [{}]",
+                    selector, available
+                )));
+            }
+        };
 
-        // Take the highest scoring candidate.
-        let (_score, physical_device, properties, features, queue_family_index, _queue) =
-            candidates
-                .first()
-                .ok_or_else(|| anyhow!("No suitable physical device found!"))?;
+        info!("Physical device: {}", Self::device_name(properties));
 
         // Get the memory properties.
         let memory_properties = instance.get_physical_device_memory_properties(*physical_device);
 
-        // Create one queue for graphics and presentation.
-        let queue_info = vk::DeviceQueueCreateInfo::default()
-            .queue_family_index(*queue_family_index)
-            .queue_priorities(&[1.0]);
+        // Find a transfer queue family, falling back to the graphics one.
+        let transfer_queue_family_index =
+            Self::find_transfer_queue_family(instance, physical_device, *queue_family_index);
+
+        // Find a present queue family. Suitable candidates always have one.
+        let present_queue_family_index = Self::find_present_queue_family(
+            instance,
+            surface,
+            physical_device,
+            *queue_family_index
+        )
+        .ok_or_else(|| anyhow!("No queue family can present to the surface."))?;
+
+        info!("Queue family: {}", queue_family_index);
+        info!("Transfer queue family: {}", transfer_queue_family_index);
+        info!("Present queue family: {}", present_queue_family_index);
+
+        // Create one queue per distinct family among the
+        // graphics, transfer and present queue families.
+        let mut queue_family_indices = vec![*queue_family_index];
+
+        for index in [transfer_queue_family_index, present_queue_family_index] {
+            if !queue_family_indices.contains(&index) {
+                queue_family_indices.push(index);
+            }
+        }
+
+        let queue_infos = queue_family_indices
+            .iter()
+            .map(|index| {
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(*index)
+                    .queue_priorities(&[1.0])
+            })
+            .collect::<Vec<_>>();
 
-        // Create our device features.
-        let enabled_features = vk::PhysicalDeviceFeatures::default().sampler_anisotropy(true);
+        // Create our device features. These are the required features
+        // plus whichever optional features the device supports.
+        let enabled_features = requirements.enabled_features(features);
+
+        // Enable the required extensions plus whichever
+        // optional extensions the device supports.
+        let mut enabled_extensions = required_extensions;
+
+        for extension in &requirements.optional_extensions {
+            if Self::device_has_extensions(instance, physical_device, from_ref(extension)) {
+                info!("Optional device extension: {:?}", extension);
+
+                enabled_extensions.push(*extension);
+            }
+        }
 
         // We have to pass this as &[*const c_char].
-        let required_extensions = required_extensions
+        let extension_names = enabled_extensions
             .iter()
             .map(|extension| extension.as_ptr())
             .collect::<Vec<_>>();
 
+        // Timeline semaphores are core in Vulkan 1.2. We enable
+        // them if we can and fall back to fences if we can't.
+        let timeline_semaphore = Self::supports_timeline_semaphore(instance, physical_device);
+
+        info!("Timeline semaphores: {}", timeline_semaphore);
+
+        // Descriptor indexing is core in Vulkan 1.2 as well. We enable it
+        // if we can, and bindless textures fall back to a fixed-size array
+        // if we can't.
+        let descriptor_indexing = Self::supports_descriptor_indexing(instance, physical_device);
+
+        info!("Descriptor indexing: {}", descriptor_indexing);
+
+        // Multiview is core in Vulkan 1.1. We enable it if we can, so
+        // render passes can draw to several array layers at once.
+        let multiview = Self::supports_multiview(instance, physical_device);
+
+        info!("Multiview: {}", multiview);
+
+        // The Vulkan 1.1 features struct is itself only valid on 1.2,
+        // so multiview is enabled with its own struct.
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::default().multiview(true);
+
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default()
+            .timeline_semaphore(timeline_semaphore)
+            .runtime_descriptor_array(descriptor_indexing)
+            .descriptor_binding_partially_bound(descriptor_indexing)
+            .descriptor_binding_sampled_image_update_after_bind(descriptor_indexing)
+            .shader_sampled_image_array_non_uniform_indexing(descriptor_indexing);
+
         // Create the device info.
-        let device_info = vk::DeviceCreateInfo::default()
-            .enabled_extension_names(&required_extensions)
-            .queue_create_infos(from_ref(&queue_info))
+        let mut device_info = vk::DeviceCreateInfo::default()
+            .enabled_extension_names(&extension_names)
+            .queue_create_infos(&queue_infos)
             .enabled_features(&enabled_features);
 
+        if timeline_semaphore || descriptor_indexing {
+            device_info = device_info.push_next(&mut vulkan_12_features);
+        }
+
+        if multiview {
+            device_info = device_info.push_next(&mut multiview_features);
+        }
+
         // Create the device.
         let device = instance.create_device(*physical_device, &device_info, None)?;
 
-        // Get the queue.
-        let queue = device.get_device_queue(*queue_family_index, 0);
+        // Load the debug utils functions. The instance only
+        // enables the extension when validation is enabled.
+        let debug_utils = match instance.validation_enabled() {
+            true => Some(ash::ext::debug_utils::Device::new(instance, &device)),
+            false => None
+        };
+
+        // Load the push descriptor functions if the extension is enabled.
+        let push_descriptor = enabled_extensions
+            .contains(&ash::khr::push_descriptor::NAME)
+            .then(|| ash::khr::push_descriptor::Device::new(instance, &device));
+
+        info!("Push descriptors: {}", push_descriptor.is_some());
+
+        // Get the queues.
+        let queue = Queue::new(&device, *queue_family_index);
+        let transfer_queue = Queue::new(&device, transfer_queue_family_index);
+        let present_queue = Queue::new(&device, present_queue_family_index);
 
         // Create the command pool.
         let command_pool = CommandPool::new(
@@ -164,19 +656,135 @@ impl Device {
             vk::CommandPoolCreateFlags::TRANSIENT
         )?;
 
+        // Create the transient command pool for the transfer queue.
+        let transfer_command_pool = CommandPool::new(
+            &device,
+            transfer_queue_family_index,
+            vk::CommandPoolCreateFlags::TRANSIENT
+        )?;
+
+        // Create the memory allocator.
+        let allocator = Allocator::new(instance, &device, *physical_device)?;
+
         Ok(Self {
+            instance: (**instance).clone(),
             physical_device: *physical_device,
             properties: *properties,
             features: *features,
+            enabled_features,
+            enabled_extensions,
+            timeline_semaphore,
+            descriptor_indexing,
+            multiview,
             memory_properties,
             device,
+            debug_utils,
+            push_descriptor,
             queue,
-            queue_family_index: *queue_family_index,
+            queue_family_properties: *queue_properties,
+            transfer_queue,
+            present_queue,
             command_pool,
-            transient_command_pool
+            transient_command_pool,
+            transfer_command_pool,
+            allocator: Mutex::new(allocator),
+            allocation_count: AtomicU32::new(0),
+            resource_tracker: cfg!(debug_assertions).then(ResourceTracker::default),
+            deferred_destroy_queue: Arc::default(),
+            destroyed: false
         })
     }
 
+    /// Returns the allocator for buffer and image memory. Only hold on
+    /// to it while allocating or freeing. A poisoned lock is still
+    /// used, so resources can be freed while unwinding.
+    pub fn allocator(&self) -> MutexGuard<'_, Allocator> {
+        self.allocator
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the number of live memory allocations. The blocks
+    /// of the gpu-allocator sub-allocator aren't counted.
+    pub fn allocation_count(&self) -> u32 {
+        self.allocation_count
+            .load(Ordering::Relaxed)
+    }
+
+    /// Count a memory allocation before it's made. This fails at the
+    /// device's maxMemoryAllocationCount, where the driver would fail
+    /// with ERROR_TOO_MANY_OBJECTS, and warns when nearing it.
+    pub fn add_allocation(&self) -> Result<()> {
+        let limit = self
+            .properties
+            .limits
+            .max_memory_allocation_count;
+
+        // Count the allocation, unless we're at the limit.
+        let count = self
+            .allocation_count
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                (count < limit).then_some(count + 1)
+            })
+            .map_err(|count| {
+                anyhow!(
+                    "Cannot allocate memory, {} of {} allocations are in use.",
+                    count,
+                    limit
+                )
+            })?
+            + 1;
+
+        // Warn when crossing the threshold.
+        if count == (limit as f32 * ALLOCATION_COUNT_WARNING) as u32 {
+            warn!(
+                "{} of {} memory allocations are in use. Consider the gpu-allocator feature.",
+                count, limit
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Stop counting a memory allocation after it's freed.
+    pub fn remove_allocation(&self) {
+        self.allocation_count
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Count a buffer or image after it's created.
+    /// This does nothing unless resources are being tracked.
+    pub fn track_resource(&self, kind: ResourceKind) {
+        if let Some(resource_tracker) = &self.resource_tracker {
+            resource_tracker.add(kind);
+        }
+    }
+
+    /// Stop counting a buffer or image after it's destroyed.
+    /// This does nothing unless resources are being tracked.
+    pub fn untrack_resource(&self, kind: ResourceKind) {
+        if let Some(resource_tracker) = &self.resource_tracker {
+            resource_tracker.remove(kind);
+        }
+    }
+
+    /// Returns the queue that dropped resource wrappers push their
+    /// handles onto. Wrappers keep a clone so they can drop without
+    /// the device.
+    pub fn deferred_destroy_queue(&self) -> &Arc<DeferredDestroyQueue> {
+        &self.deferred_destroy_queue
+    }
+
+    /// Returns the number of live buffers, images, and allocations, or
+    /// None if resources aren't tracked, which is the case in release
+    /// builds. Compare the counts before and after some work to check
+    /// that it destroyed everything it created.
+    pub fn live_resource_counts(&self) -> Option<ResourceCounts> {
+        self.resource_tracker
+            .as_ref()
+            .map(|resource_tracker| resource_tracker.counts(self.allocation_count()))
+    }
+
     /// Returns the physical device.
     pub fn physical_device(&self) -> &vk::PhysicalDevice {
         &self.physical_device
@@ -187,24 +795,529 @@ impl Device {
         &self.properties
     }
 
+    /// Returns the preferred depth stencil format that can be used as an
+    /// attachment. Every format considered has a stencil component.
+    ///
+    /// # Safety
+    ///
+    /// The device must not have been destroyed.
+    pub unsafe fn depth_stencil_format(&self) -> Result<vk::Format> {
+        DEPTH_STENCIL_FORMATS
+            .into_iter()
+            .find(|format| {
+                self.format_properties(*format)
+                    .optimal_tiling_features
+                    .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .ok_or_else(|| anyhow!("No depth stencil format with a stencil component found."))
+    }
+
+    /// Returns the preferred depth format that can be both rendered to and
+    /// sampled, e.g. for shadow maps. None of the formats have stencil.
+    ///
+    /// # Safety
+    ///
+    /// The device must not have been destroyed.
+    pub unsafe fn sampled_depth_format(&self) -> Result<vk::Format> {
+        let required_features = vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE;
+
+        DEPTH_FORMATS
+            .into_iter()
+            .find(|format| {
+                self.format_properties(*format)
+                    .optimal_tiling_features
+                    .contains(required_features)
+            })
+            .ok_or_else(|| anyhow!("No depth format that can be sampled found."))
+    }
+
+    /// Returns the properties of a format on the physical device.
+    ///
+    /// # Safety
+    ///
+    /// The device must not have been destroyed.
+    pub unsafe fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        self.instance
+            .get_physical_device_format_properties(self.physical_device, format)
+    }
+
+    /// Returns the limits of images created with the given parameters.
+    /// Fails if the physical device doesn't support the combination.
+    ///
+    /// # Safety
+    ///
+    /// The device must not have been destroyed.
+    pub unsafe fn image_format_properties(
+        &self,
+        format: vk::Format,
+        tiling: vk::ImageTiling,
+        usage: vk::ImageUsageFlags,
+        flags: vk::ImageCreateFlags
+    ) -> Result<vk::ImageFormatProperties> {
+        match self
+            .instance
+            .get_physical_device_image_format_properties(
+                self.physical_device,
+                format,
+                vk::ImageType::TYPE_2D,
+                tiling,
+                usage,
+                flags
+            ) {
+            Ok(properties) => Ok(properties),
+            Err(vk::Result::ERROR_FORMAT_NOT_SUPPORTED) => Err(anyhow!(
+                "{:?} images with {:?} tiling and {:?} usage are not supported.",
+                format,
+                tiling,
+                usage
+            )),
+            Err(e) => Err(e.into())
+        }
+    }
+
+    /// Returns the stride of an element of the given size in a dynamic
+    /// uniform buffer. Dynamic offsets must be a multiple of the device's
+    /// minimum uniform buffer offset alignment, so the size is rounded up.
+    pub fn uniform_buffer_stride(&self, size: vk::DeviceSize) -> vk::DeviceSize {
+        let alignment = self
+            .properties
+            .limits
+            .min_uniform_buffer_offset_alignment;
+
+        size.next_multiple_of(alignment.max(1))
+    }
+
+    /// Returns the stride of an element of the given size in a dynamic
+    /// storage buffer, rounded up to the device's minimum storage buffer
+    /// offset alignment like uniform_buffer_stride.
+    pub fn storage_buffer_stride(&self, size: vk::DeviceSize) -> vk::DeviceSize {
+        let alignment = self
+            .properties
+            .limits
+            .min_storage_buffer_offset_alignment;
+
+        size.next_multiple_of(alignment.max(1))
+    }
+
     /// Returns the physical device features.
     pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
         &self.features
     }
 
+    /// Returns the features enabled on the logical device.
+    pub fn enabled_features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.enabled_features
+    }
+
+    /// Returns true if the extension is enabled on the logical device.
+    pub fn extension_enabled(&self, extension: &CStr) -> bool {
+        self.enabled_extensions
+            .contains(&extension)
+    }
+
+    /// Returns true if descriptors can be pushed straight into command
+    /// buffers, which needs the push descriptor extension.
+    pub fn push_descriptors_enabled(&self) -> bool {
+        self.push_descriptor.is_some()
+    }
+
+    /// Push descriptors into a command buffer, in place of binding a
+    /// descriptor set. The set's layout must have been created for push
+    /// descriptors. This fails if push descriptors aren't enabled.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, the pipeline layout must use a
+    /// push descriptor layout at the given set, and the writes must match it.
+    pub unsafe fn cmd_push_descriptor_set(
+        &self,
+        command_buffer: &vk::CommandBuffer,
+        pipeline_bind_point: vk::PipelineBindPoint,
+        pipeline_layout: &vk::PipelineLayout,
+        set: u32,
+        writes: &[vk::WriteDescriptorSet]
+    ) -> Result<()> {
+        let Some(push_descriptor) = &self.push_descriptor else {
+            return Err(anyhow!("Push descriptors are not enabled."));
+        };
+
+        push_descriptor.cmd_push_descriptor_set(
+            *command_buffer,
+            pipeline_bind_point,
+            *pipeline_layout,
+            set,
+            writes
+        );
+
+        Ok(())
+    }
+
+    /// Returns true if polygons can be drawn as lines or points, e.g. for
+    /// wireframes. Without this, only the fill polygon mode may be used.
+    pub fn fill_mode_non_solid_enabled(&self) -> bool {
+        self.enabled_features
+            .fill_mode_non_solid
+            != 0
+    }
+
+    /// Returns true if shaders may index arrays of sampled images with
+    /// dynamically uniform values, e.g. a push constant. Without this,
+    /// the index must be a constant expression.
+    pub fn sampled_image_dynamic_indexing_enabled(&self) -> bool {
+        self.enabled_features
+            .shader_sampled_image_array_dynamic_indexing
+            != 0
+    }
+
+    /// Returns true if pipelines may clamp their depth bias. Without
+    /// this, the depth bias clamp must be zero.
+    pub fn depth_bias_clamp_enabled(&self) -> bool {
+        self.enabled_features
+            .depth_bias_clamp
+            != 0
+    }
+
+    /// Set the line width of a pipeline with a dynamic line width. Without
+    /// wide lines, the width is always 1.0. Otherwise it's clamped to the
+    /// range the device supports.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the bound pipeline must have a
+    /// dynamic line width.
+    pub unsafe fn set_line_width(&self, command_buffer: &vk::CommandBuffer, line_width: f32) {
+        let line_width = match self.enabled_features.wide_lines != 0 {
+            true => {
+                let [min, max] = self
+                    .properties
+                    .limits
+                    .line_width_range;
+
+                line_width.clamp(min, max)
+            },
+            false => 1.0
+        };
+
+        self.cmd_set_line_width(*command_buffer, line_width);
+    }
+
+    /// Set the scissor to part of the extent, e.g. to clip a UI element
+    /// or one half of a split screen. The rect is in framebuffer pixels
+    /// from the top left, whatever the clip space. It's clamped to the
+    /// extent, as scissor offsets can't be negative. Call reset_scissor
+    /// after the clipped draws.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the bound pipeline must have a
+    /// dynamic scissor.
+    pub unsafe fn set_scissor(
+        &self,
+        command_buffer: &vk::CommandBuffer,
+        extent: &vk::Extent2D,
+        rect: &vk::Rect2D
+    ) {
+        // Clamp the corners of the rect to the extent.
+        let clamp = |start: i32, size: u32, max: u32| {
+            let start = start as i64;
+            let end = start + size as i64;
+
+            (start.clamp(0, max as i64), end.clamp(0, max as i64))
+        };
+
+        let (x0, x1) = clamp(rect.offset.x, rect.extent.width, extent.width);
+        let (y0, y1) = clamp(rect.offset.y, rect.extent.height, extent.height);
+
+        let rect = vk::Rect2D {
+            offset: vk::Offset2D {
+                x: x0 as i32,
+                y: y0 as i32
+            },
+            extent: vk::Extent2D {
+                width:  (x1 - x0) as u32,
+                height: (y1 - y0) as u32
+            }
+        };
+
+        self.cmd_set_scissor(*command_buffer, 0, &[rect]);
+    }
+
+    /// Reset the scissor to cover the whole extent.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the bound pipeline must have a
+    /// dynamic scissor.
+    pub unsafe fn reset_scissor(&self, command_buffer: &vk::CommandBuffer, extent: &vk::Extent2D) {
+        self.cmd_set_scissor(*command_buffer, 0, &[(*extent).into()]);
+    }
+
+    /// Set the depth bias of a pipeline with a dynamic depth bias. Without
+    /// the depth bias clamp feature, the bias is never clamped.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the bound pipeline must have
+    /// been created with a dynamic depth bias.
+    pub unsafe fn set_depth_bias(
+        &self,
+        command_buffer: &vk::CommandBuffer,
+        depth_bias: &DepthBias
+    ) {
+        let clamp = match self.depth_bias_clamp_enabled() {
+            true => depth_bias.clamp,
+            false => 0.0
+        };
+
+        self.cmd_set_depth_bias(
+            *command_buffer,
+            depth_bias.constant_factor,
+            clamp,
+            depth_bias.slope_factor
+        );
+    }
+
+    /// Returns true if anisotropic filtering is enabled. Some
+    /// software implementations don't support it at all.
+    pub fn anisotropy_enabled(&self) -> bool {
+        self.enabled_features
+            .sampler_anisotropy
+            != 0
+    }
+
+    /// Record indexed draws whose parameters are read from a buffer of
+    /// vk::DrawIndexedIndirectCommand. If multi-draw indirect isn't
+    /// enabled, the draws are recorded one at a time instead.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside a render pass with a
+    /// pipeline and index buffer bound. The buffer must hold the draws at the
+    /// given offset and stride, and be finished being written before the draws
+    /// execute.
+    pub unsafe fn draw_indexed_indirect(
+        &self,
+        command_buffer: &vk::CommandBuffer,
+        buffer: &vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32
+    ) {
+        match draw_count <= 1
+            || self
+                .enabled_features
+                .multi_draw_indirect
+                != 0
+        {
+            true => {
+                self.cmd_draw_indexed_indirect(*command_buffer, *buffer, offset, draw_count, stride)
+            },
+            false => {
+                for draw in 0..draw_count {
+                    self.cmd_draw_indexed_indirect(
+                        *command_buffer,
+                        *buffer,
+                        offset + draw as vk::DeviceSize * stride as vk::DeviceSize,
+                        1,
+                        stride
+                    );
+                }
+            },
+        }
+    }
+
+    /// Returns true if timeline semaphores are enabled.
+    pub fn timeline_semaphore_enabled(&self) -> bool {
+        self.timeline_semaphore
+    }
+
+    /// Returns true if descriptor indexing is enabled, so arrays of sampled
+    /// images can be partially bound, updated after binding, and indexed
+    /// with non-uniform indices.
+    pub fn descriptor_indexing_enabled(&self) -> bool {
+        self.descriptor_indexing
+    }
+
+    /// Returns true if multiview is enabled, so render passes can
+    /// have a view mask.
+    pub fn multiview_enabled(&self) -> bool {
+        self.multiview
+    }
+
+    /// Returns the most views a multiview render pass can have,
+    /// or zero if multiview isn't enabled.
+    ///
+    /// # Safety
+    ///
+    /// The device must not have been destroyed.
+    pub unsafe fn max_multiview_view_count(&self) -> u32 {
+        if !self.multiview {
+            return 0;
+        }
+
+        let mut multiview_properties = vk::PhysicalDeviceMultiviewProperties::default();
+
+        self.instance
+            .get_physical_device_properties2(
+                self.physical_device,
+                &mut vk::PhysicalDeviceProperties2::default().push_next(&mut multiview_properties)
+            );
+
+        multiview_properties.max_multiview_view_count
+    }
+
+    /// Returns the most combined image samplers a single update-after-bind
+    /// binding can hold, or zero if descriptor indexing isn't enabled.
+    ///
+    /// # Safety
+    ///
+    /// The device must not have been destroyed.
+    pub unsafe fn max_update_after_bind_textures(&self) -> u32 {
+        if !self.descriptor_indexing {
+            return 0;
+        }
+
+        let mut vulkan_12_properties = vk::PhysicalDeviceVulkan12Properties::default();
+
+        self.instance
+            .get_physical_device_properties2(
+                self.physical_device,
+                &mut vk::PhysicalDeviceProperties2::default().push_next(&mut vulkan_12_properties)
+            );
+
+        // A combined image sampler counts as both a sampler and a sampled image.
+        [
+            vulkan_12_properties.max_per_stage_descriptor_update_after_bind_samplers,
+            vulkan_12_properties.max_per_stage_descriptor_update_after_bind_sampled_images,
+            vulkan_12_properties.max_descriptor_set_update_after_bind_samplers,
+            vulkan_12_properties.max_descriptor_set_update_after_bind_sampled_images,
+            vulkan_12_properties.max_per_stage_update_after_bind_resources
+        ]
+        .into_iter()
+        .min()
+        .unwrap_or(0)
+    }
+
     /// Returns the memory properties.
     pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
         &self.memory_properties
     }
 
-    /// Returns the queue.
-    pub fn queue(&self) -> &vk::Queue {
+    /// Returns the graphics queue.
+    pub fn queue(&self) -> &Queue {
         &self.queue
     }
 
-    /// Returns the queue family index.
+    /// Returns the graphics queue family index.
     pub fn queue_family_index(&self) -> u32 {
-        self.queue_family_index
+        self.queue.family_index()
+    }
+
+    /// Returns the queue family properties.
+    pub fn queue_family_properties(&self) -> &vk::QueueFamilyProperties {
+        &self.queue_family_properties
+    }
+
+    /// Give an object a name that shows up in validation messages and
+    /// graphics debuggers. This does nothing if debugging is disabled.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be a live object created on this device.
+    pub unsafe fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+
+        // Names can't contain nul bytes.
+        let Ok(name) = CString::new(name) else {
+            warn!("Invalid object name: {:?}", name);
+
+            return;
+        };
+
+        // Naming is purely a debugging aid, so failures are only logged.
+        if let Err(e) = debug_utils.set_debug_utils_object_name(
+            &vk::DebugUtilsObjectNameInfoEXT::default()
+                .object_handle(handle)
+                .object_name(&name)
+        ) {
+            warn!("Failed to name object {:?}: {}", name, e);
+        }
+    }
+
+    /// Open a named region in a command buffer that shows up in graphics
+    /// debuggers. This does nothing if debugging is disabled.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and every label it opens must be
+    /// closed with end_debug_label before it ends.
+    pub unsafe fn begin_debug_label(
+        &self,
+        command_buffer: &vk::CommandBuffer,
+        name: &str,
+        color: [f32; 4]
+    ) {
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+
+        // Names can't contain nul bytes.
+        let name = CString::new(name).unwrap_or_default();
+
+        debug_utils.cmd_begin_debug_utils_label(
+            *command_buffer,
+            &vk::DebugUtilsLabelEXT::default()
+                .label_name(&name)
+                .color(color)
+        );
+    }
+
+    /// Close the most recently opened region in a command
+    /// buffer. This does nothing if debugging is disabled.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, with a label opened by
+    /// begin_debug_label.
+    pub unsafe fn end_debug_label(&self, command_buffer: &vk::CommandBuffer) {
+        if let Some(debug_utils) = &self.debug_utils {
+            debug_utils.cmd_end_debug_utils_label(*command_buffer);
+        }
+    }
+
+    /// Returns the transfer queue.
+    pub fn transfer_queue(&self) -> &Queue {
+        &self.transfer_queue
+    }
+
+    /// Returns the transfer queue family index.
+    pub fn transfer_queue_family_index(&self) -> u32 {
+        self.transfer_queue.family_index()
+    }
+
+    /// Whether transfers run on a different queue family than graphics. If
+    /// so, uploaded resources need a queue family ownership transfer.
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.transfer_queue_family_index() != self.queue_family_index()
+    }
+
+    /// Returns the present queue.
+    pub fn present_queue(&self) -> &Queue {
+        &self.present_queue
+    }
+
+    /// Returns the present queue family index.
+    pub fn present_queue_family_index(&self) -> u32 {
+        self.present_queue.family_index()
+    }
+
+    /// Whether presentation runs on a different queue family than graphics.
+    /// If so, swapchain images are shared concurrently by both families.
+    pub fn has_dedicated_present_queue(&self) -> bool {
+        self.present_queue_family_index() != self.queue_family_index()
     }
 
     /// Returns the command pool.
@@ -212,15 +1325,93 @@ impl Device {
         &self.command_pool
     }
 
-    /// Execute a one-time command.
+    /// Execute a one-time command on the transfer queue. Only record
+    /// commands that the transfer queue family supports (copies and
+    /// barriers on transfer stages).
+    ///
+    /// # Safety
+    ///
+    /// Whatever the closure records must only use objects that outlive the
+    /// call. The command has finished by the time this returns.
     pub unsafe fn one_time_command<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer) -> Result<()>
+    {
+        self.submit_one_time_command(&self.transfer_queue, &self.transfer_command_pool, f)
+    }
+
+    /// Execute a one-time command on the graphics queue.
+    ///
+    /// # Safety
+    ///
+    /// Whatever the closure records must only use objects that outlive the
+    /// call. The command has finished by the time this returns.
+    pub unsafe fn one_time_graphics_command<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(vk::CommandBuffer) -> Result<()>
+    {
+        self.submit_one_time_command(&self.queue, &self.transient_command_pool, f)
+    }
+
+    /// Wait for the device to finish all of its work, then destroy the
+    /// resources that were dropped in the meantime. A lost device is
+    /// logged and returned as VulkanError::DeviceLost.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may be submitting to the device's queues while this
+    /// runs, and nothing dropped since the last wait may be needed by work
+    /// submitted afterwards.
+    pub unsafe fn wait_idle(&self) -> Result<()> {
+        self.device
+            .device_wait_idle()
+            .map_err(|result| Self::wait_idle_error(result, "device"))?;
+
+        // Nothing can be using the dropped resources anymore.
+        self.deferred_destroy_queue
+            .drain(self);
+
+        Ok(())
+    }
+
+    /// Wait for the graphics queue to finish all of its work. A lost
+    /// device is logged and returned as VulkanError::DeviceLost.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may be submitting to the graphics queue while this runs.
+    pub unsafe fn wait_queue_idle(&self) -> Result<()> {
+        self.device
+            .queue_wait_idle(*self.queue)
+            .map_err(|result| Self::wait_idle_error(result, "graphics queue"))
+    }
+
+    /// Convert the result of waiting for something to idle to an error.
+    fn wait_idle_error(result: vk::Result, what: &str) -> anyhow::Error {
+        let error = VulkanError::from_result(result.into());
+
+        if VulkanError::is_device_lost(&error) {
+            error!(
+                "The device was lost while waiting for the {} to idle.",
+                what
+            );
+        }
+
+        error
+    }
+
+    /// Record and submit a one-time command, then wait for it to complete.
+    unsafe fn submit_one_time_command<F>(
+        &self,
+        queue: &Queue,
+        command_pool: &CommandPool,
+        f: F
+    ) -> Result<()>
     where
         F: FnOnce(vk::CommandBuffer) -> Result<()>
     {
         // Create the command buffer.
-        let command_buffer = self
-            .transient_command_pool
-            .new_command_buffer(self, true)?;
+        let command_buffer = command_pool.new_command_buffer(self, true)?;
 
         // Create the fence so we can wait for completion.
         let fence = self.create_fence(&vk::FenceCreateInfo::default(), None)?;
@@ -242,8 +1433,7 @@ impl Device {
         let submit_info = vk::SubmitInfo::default().command_buffers(from_ref(&command_buffer));
 
         // Submit the command buffer.
-        self.device
-            .queue_submit(self.queue, &[submit_info], fence)?;
+        queue.submit(self, &[submit_info], Some(fence))?;
 
         // Wait for the fence indefinitely.
         self.device
@@ -253,11 +1443,154 @@ impl Device {
         self.destroy_fence(fence, None);
 
         // Free the command buffer.
-        self.free_command_buffers(*self.transient_command_pool, from_ref(&command_buffer));
+        command_pool.free(self, from_ref(&command_buffer));
 
         Ok(())
     }
 
+    /// Find a queue family for transfers. Prefer a family dedicated to
+    /// transfers, then one without graphics support. If neither exists,
+    /// fall back to the graphics queue family.
+    unsafe fn find_transfer_queue_family(
+        instance: &Instance,
+        physical_device: &vk::PhysicalDevice,
+        queue_family_index: u32
+    ) -> u32 {
+        let queues = instance.get_physical_device_queue_family_properties(*physical_device);
+
+        // Find the first transfer family without any of the excluded flags.
+        let find = |excluded: vk::QueueFlags| {
+            queues
+                .iter()
+                .enumerate()
+                .find(|(_, queue)| {
+                    queue.queue_count > 0
+                        && queue
+                            .queue_flags
+                            .contains(vk::QueueFlags::TRANSFER)
+                        && !queue
+                            .queue_flags
+                            .intersects(excluded)
+                })
+                .map(|(index, _)| index as u32)
+        };
+
+        find(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
+            .or_else(|| find(vk::QueueFlags::GRAPHICS))
+            .unwrap_or(queue_family_index)
+    }
+
+    /// Find a queue family that can present to the surface. Prefer the
+    /// graphics queue family, so that a single queue does everything.
+    /// Without a surface, the graphics queue family is returned as is.
+    unsafe fn find_present_queue_family(
+        instance: &Instance,
+        surface: Option<&Surface>,
+        physical_device: &vk::PhysicalDevice,
+        queue_family_index: u32
+    ) -> Option<u32> {
+        let Some(surface) = surface else {
+            return Some(queue_family_index);
+        };
+
+        if surface.supports_presentation(physical_device, queue_family_index) {
+            return Some(queue_family_index);
+        }
+
+        let queue_family_count = instance
+            .get_physical_device_queue_family_properties(*physical_device)
+            .len() as u32;
+
+        (0..queue_family_count).find(|index| surface.supports_presentation(physical_device, *index))
+    }
+
+    /// Returns true if the device supports timeline semaphores through Vulkan 1.2.
+    unsafe fn supports_timeline_semaphore(
+        instance: &Instance,
+        physical_device: &vk::PhysicalDevice
+    ) -> bool {
+        Self::vulkan_12_features(instance, physical_device)
+            .is_some_and(|features| features.timeline_semaphore != 0)
+    }
+
+    /// Returns true if the device supports the parts of descriptor indexing
+    /// that bindless textures need, through Vulkan 1.2.
+    unsafe fn supports_descriptor_indexing(
+        instance: &Instance,
+        physical_device: &vk::PhysicalDevice
+    ) -> bool {
+        Self::vulkan_12_features(instance, physical_device).is_some_and(|features| {
+            features.runtime_descriptor_array != 0
+                && features.descriptor_binding_partially_bound != 0
+                && features.descriptor_binding_sampled_image_update_after_bind != 0
+                && features.shader_sampled_image_array_non_uniform_indexing != 0
+        })
+    }
+
+    /// Returns true if the device supports multiview through Vulkan 1.1.
+    unsafe fn supports_multiview(
+        instance: &Instance,
+        physical_device: &vk::PhysicalDevice
+    ) -> bool {
+        Self::multiview_features(instance, physical_device)
+            .is_some_and(|features| features.multiview != 0)
+    }
+
+    /// Returns the multiview features of the device, or none if the
+    /// device is older than Vulkan 1.1. These are queried with their
+    /// own struct, as the Vulkan 1.1 features struct needs Vulkan 1.2.
+    unsafe fn multiview_features(
+        instance: &Instance,
+        physical_device: &vk::PhysicalDevice
+    ) -> Option<vk::PhysicalDeviceMultiviewFeatures<'static>> {
+        // The multiview features can't be queried on older devices.
+        let properties = instance.get_physical_device_properties(*physical_device);
+
+        if properties.api_version < vk::API_VERSION_1_1 {
+            return None;
+        }
+
+        let mut multiview_features = vk::PhysicalDeviceMultiviewFeatures::default();
+
+        instance.get_physical_device_features2(
+            *physical_device,
+            &mut vk::PhysicalDeviceFeatures2::default().push_next(&mut multiview_features)
+        );
+
+        Some(multiview_features)
+    }
+
+    /// Returns the Vulkan 1.2 features of the device,
+    /// or none if the device is older than Vulkan 1.2.
+    unsafe fn vulkan_12_features(
+        instance: &Instance,
+        physical_device: &vk::PhysicalDevice
+    ) -> Option<vk::PhysicalDeviceVulkan12Features<'static>> {
+        // The Vulkan 1.2 features can't be queried on older devices.
+        let properties = instance.get_physical_device_properties(*physical_device);
+
+        if properties.api_version < vk::API_VERSION_1_2 {
+            return None;
+        }
+
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+
+        instance.get_physical_device_features2(
+            *physical_device,
+            &mut vk::PhysicalDeviceFeatures2::default().push_next(&mut vulkan_12_features)
+        );
+
+        Some(vulkan_12_features)
+    }
+
+    /// Returns the name of a physical device.
+    fn device_name(properties: &vk::PhysicalDeviceProperties) -> String {
+        properties
+            .device_name_as_c_str()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
     /// Checks if the device has the required extensions.
     unsafe fn device_has_extensions(
         instance: &ash::Instance,
@@ -293,64 +1626,148 @@ impl Device {
     /// Returns true if the device is suitable.
     unsafe fn is_suitable(
         instance: &Instance,
-        surface: &Surface,
+        surface: Option<&Surface>,
+        requirements: &DeviceRequirements,
         required_extensions: &Vec<&CStr>,
-        physical_device: &vk::PhysicalDevice,
-        _properties: &vk::PhysicalDeviceProperties,
-        features: &vk::PhysicalDeviceFeatures,
-        queue_family_index: u32,
-        queue: &vk::QueueFamilyProperties
+        candidate: &DeviceCandidate
     ) -> Result<bool> {
+        let DeviceCandidate {
+            physical_device,
+            features,
+            queue_family_index,
+            queue,
+            ..
+        } = candidate;
+
         // A candidate must support our required extensions.
         if !Self::device_has_extensions(instance, physical_device, required_extensions) {
             return Ok(false);
         }
 
-        // We require ansitropic filtering.
-        if features.sampler_anisotropy == 0 {
+        // A candidate must support our required features.
+        if !requirements.features_supported(features) {
             return Ok(false);
         }
 
+        // We must have a queue with graphics support.
+        let graphics_support = queue
+            .queue_flags
+            .contains(vk::QueueFlags::GRAPHICS);
+
+        // There's nothing else to check if we aren't presenting.
+        let Some(surface) = surface else {
+            return Ok(graphics_support);
+        };
+
         let formats = surface.formats(&physical_device)?;
         let present_modes = surface.present_modes(&physical_device)?;
 
-        // We'd better have at least one surface format and present mode.
-        if formats.is_empty() || present_modes.is_empty() {
+        // We'd better have a usable surface format and at least one present
+        // mode. Any format will do, as selection falls back to the first.
+        if Swapchain::select_format(&formats, ColorSpacePreference::default()).is_none()
+            || present_modes.is_empty()
+        {
             return Ok(false);
         }
 
-        // We must have a queue with graphics support.
-        let graphics_support = queue
-            .queue_flags
-            .contains(vk::QueueFlags::GRAPHICS);
-
-        // We must have a queue with presentation support.
-        let presentation_support =
-            surface.supports_presentation(physical_device, queue_family_index);
+        // Some queue family must support presentation. It
+        // doesn't have to be the graphics queue family.
+        let presentation_support = Self::find_present_queue_family(
+            instance,
+            Some(surface),
+            physical_device,
+            *queue_family_index
+        )
+        .is_some();
 
         Ok(graphics_support && presentation_support)
     }
 
     // Computes a score for the physical device.
     unsafe fn score(
-        _physical_device: &vk::PhysicalDevice,
-        properties: &vk::PhysicalDeviceProperties,
-        _features: &vk::PhysicalDeviceFeatures,
-        _queue_family_index: u32,
-        _queue: &vk::QueueFamilyProperties
-    ) -> u32 {
+        candidate: &DeviceCandidate,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        shared_present: bool
+    ) -> u64 {
+        let DeviceCandidate {
+            properties,
+            features,
+            ..
+        } = candidate;
+
         let mut score = 0;
 
-        // Give discrete GPUs a higher score.
+        // Prefer a queue family that can present as well,
+        // which saves sharing the swapchain across families.
+        if shared_present {
+            score += 1;
+        }
+
+        // Give discrete GPUs a much higher score.
         if properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU {
-            score += 1000;
+            score += 1_000_000;
+        }
+
+        // Prefer devices with anisotropic filtering, but don't require it.
+        if features.sampler_anisotropy != 0 {
+            score += 100_000;
         }
 
+        // Add a point per MiB of device local memory.
+        score += memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| {
+                heap.flags
+                    .contains(vk::MemoryHeapFlags::DEVICE_LOCAL)
+            })
+            .map(|heap| heap.size / (1024 * 1024))
+            .sum::<u64>();
+
+        // Add a point per 1024 pixels of maximum image size.
+        score += (properties
+            .limits
+            .max_image_dimension2_d
+            / 1024) as u64;
+
         score
     }
 
-    /// Destroy the device.
+    /// Destroy the device. Does nothing if it was already destroyed.
+    ///
+    /// # Safety
+    ///
+    /// Everything created on the device must have been destroyed or dropped,
+    /// and the GPU must be done with all of it.
     pub unsafe fn destroy(&mut self) {
+        if self.destroyed {
+            return;
+        }
+
+        self.destroyed = true;
+
+        // Destroy the resources that were dropped. Everything
+        // else should have been destroyed already.
+        self.deferred_destroy_queue
+            .drain(self);
+
+        // Everything made on the device should be destroyed by now.
+        if let Some(counts) = self.live_resource_counts() {
+            if !counts.is_empty() {
+                error!("Destroying the device with live resources: {}", counts);
+            }
+        }
+
+        // Destroy the allocator.
+        self.allocator
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .destroy();
+
+        // Destroy the transfer command pool.
+        self.transfer_command_pool
+            .destroy(&self.device);
+
         // Destroy the transient command pool.
         self.transient_command_pool
             .destroy(&self.device);
@@ -364,6 +1781,14 @@ impl Device {
     }
 }
 
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy();
+        }
+    }
+}
+
 impl Deref for Device {
     type Target = ash::Device;
 
@@ -0,0 +1,52 @@
+use anyhow::Error;
+use ash::vk;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors that the application is expected to handle
+/// itself rather than treat as generic fatal errors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VulkanError {
+    /// The device was lost, e.g. because the driver crashed or the GPU
+    /// was reset. The device and everything created from it must be
+    /// destroyed and recreated before rendering can continue.
+    DeviceLost,
+
+    /// No physical device is suitable, e.g. because none support
+    /// the required extensions, or because there are none at all.
+    NoSuitableDevice
+}
+
+impl VulkanError {
+    /// Convert a device lost result into a VulkanError::DeviceLost.
+    /// Any other error is returned as is.
+    pub fn from_result(error: Error) -> Error {
+        match error.downcast_ref::<vk::Result>() {
+            Some(&vk::Result::ERROR_DEVICE_LOST) => Self::DeviceLost.into(),
+            _ => error
+        }
+    }
+
+    /// Whether an error is caused by the device being lost.
+    pub fn is_device_lost(error: &Error) -> bool {
+        error.downcast_ref::<Self>() == Some(&Self::DeviceLost)
+            || error.downcast_ref::<vk::Result>() == Some(&vk::Result::ERROR_DEVICE_LOST)
+    }
+
+    /// Whether an error is caused by there being no device to render
+    /// with, including there being no Vulkan driver at all.
+    pub fn is_no_suitable_device(error: &Error) -> bool {
+        error.downcast_ref::<Self>() == Some(&Self::NoSuitableDevice)
+            || error.downcast_ref::<vk::Result>() == Some(&vk::Result::ERROR_INCOMPATIBLE_DRIVER)
+    }
+}
+
+impl Display for VulkanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeviceLost => write!(f, "The device was lost."),
+            Self::NoSuitableDevice => write!(f, "No suitable physical device found.")
+        }
+    }
+}
+
+impl std::error::Error for VulkanError {}
@@ -1,40 +1,82 @@
-use crate::{Device, Swapchain};
-use anyhow::Result;
-use ash::vk::{self, RenderPass};
+use crate::{Device, RenderPass, Swapchain};
+use anyhow::{anyhow, Result};
+use ash::vk;
 use std::ops::Deref;
 
 /// Wraps the Vulkan frame buffers.
 pub struct FrameBuffers(Vec<vk::Framebuffer>);
 
 impl FrameBuffers {
-    pub unsafe fn new(
+    /// Create the frame buffers. The attachments function is called once per
+    /// swapchain image with its index and view, and returns the full ordered
+    /// list of attachments for that frame buffer (e.g. the swapchain view
+    /// followed by any shared depth or multisampled views).
+    pub unsafe fn new<F>(
         device: &Device,
         swapchain: &Swapchain,
-        render_pass: &RenderPass
-    ) -> Result<Self> {
-        // The swapchain extent.
-        let extent = swapchain.extent();
+        render_pass: &RenderPass,
+        attachments: F
+    ) -> Result<Self>
+    where
+        F: Fn(usize, vk::ImageView) -> Vec<vk::ImageView>
+    {
+        Self::new_from_views(
+            device,
+            swapchain.views(),
+            &swapchain.extent(),
+            render_pass,
+            attachments
+        )
+    }
 
+    /// Create one frame buffer per view, for rendering to images
+    /// that aren't owned by a swapchain. See new for the details.
+    ///
+    /// # Safety
+    ///
+    /// The views must be compatible with the render pass, and must outlive the
+    /// frame buffers, which must be destroyed before the device is.
+    pub unsafe fn new_from_views<F>(
+        device: &Device,
+        views: &[vk::ImageView],
+        extent: &vk::Extent2D,
+        render_pass: &RenderPass,
+        attachments: F
+    ) -> Result<Self>
+    where
+        F: Fn(usize, vk::ImageView) -> Vec<vk::ImageView>
+    {
         // Create the frame buffers.
-        let frame_buffers = swapchain
-            .views()
+        let frame_buffers = views
             .iter()
-            .map(|view| {
+            .enumerate()
+            .map(|(index, view)| {
                 // The framebuffer attachments.
-                let attachments = [*view];
+                let attachments = attachments(index, *view);
+
+                // The attachments must match the render pass.
+                if attachments.len() as u32 != render_pass.attachment_count() {
+                    return Err(anyhow!(
+                        "Frame buffer has {} attachments but the render pass expects {}.",
+                        attachments.len(),
+                        render_pass.attachment_count()
+                    ));
+                }
 
-                // Create the frame buffer create info.
+                // Create the frame buffer create info. Multiview render
+                // passes render to the layers of the attachments instead,
+                // so the frame buffer itself always has one layer.
                 let framebuffer_create_info = vk::FramebufferCreateInfo::default()
-                    .render_pass(*render_pass)
+                    .render_pass(**render_pass)
                     .attachments(&attachments)
                     .width(extent.width)
                     .height(extent.height)
                     .layers(1);
 
                 // Create the frame buffer.
-                device.create_framebuffer(&framebuffer_create_info, None)
+                Ok(device.create_framebuffer(&framebuffer_create_info, None)?)
             })
-            .collect::<Result<Vec<_>, _>>()?;
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(Self(frame_buffers))
     }
@@ -0,0 +1,96 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant}
+};
+
+/// Frame time statistics over the most recent frames.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// The average frame time.
+    pub average: Duration,
+
+    /// The shortest frame time.
+    pub min: Duration,
+
+    /// The longest frame time.
+    pub max: Duration,
+
+    /// The frames per second, based on the average frame time.
+    pub fps: f32
+}
+
+/// Measures CPU frame times, i.e. the time between consecutive
+/// frames, and keeps a rolling window of the most recent ones.
+pub struct FrameTimer {
+    /// The most recent frame times, oldest first.
+    frame_times: VecDeque<Duration>,
+
+    /// The maximum number of frame times kept.
+    capacity: usize,
+
+    /// When the last frame started.
+    last_frame: Option<Instant>
+}
+
+impl FrameTimer {
+    /// Create a new frame timer that keeps the given number of frame times.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frame_times: VecDeque::with_capacity(capacity),
+            capacity:    capacity.max(1),
+            last_frame:  None
+        }
+    }
+
+    /// Call at the start of every frame. The first call
+    /// only starts the clock, as there's no frame to time.
+    pub fn tick(&mut self) {
+        let now = Instant::now();
+
+        if let Some(last_frame) = self.last_frame {
+            // Make room for the new frame time.
+            if self.frame_times.len() == self.capacity {
+                self.frame_times.pop_front();
+            }
+
+            self.frame_times
+                .push_back(now - last_frame);
+        }
+
+        self.last_frame = Some(now);
+    }
+
+    /// Compute the statistics over the window. These are
+    /// all zero until at least two frames have been timed.
+    pub fn stats(&self) -> FrameStats {
+        if self.frame_times.is_empty() {
+            return FrameStats::default();
+        }
+
+        let total = self
+            .frame_times
+            .iter()
+            .sum::<Duration>();
+        let average = total / self.frame_times.len() as u32;
+
+        FrameStats {
+            average,
+            min: self
+                .frame_times
+                .iter()
+                .min()
+                .copied()
+                .unwrap_or_default(),
+            max: self
+                .frame_times
+                .iter()
+                .max()
+                .copied()
+                .unwrap_or_default(),
+            fps: match average.is_zero() {
+                true => 0.0,
+                false => 1.0 / average.as_secs_f32()
+            }
+        }
+    }
+}
@@ -0,0 +1,395 @@
+use crate::{
+    new_buffer, AttachmentImage, Debugging, Device, DeviceRequirements, DeviceSelector,
+    FrameBuffers, ImageKind, ImageSettings, Instance, PipelineCache, RenderPass, ResourceKind,
+    TriangleRenderer, ValidationSettings
+};
+use anyhow::{anyhow, Result};
+use ash::{vk, Entry};
+use image::RgbaImage;
+use std::{path::PathBuf, slice::from_raw_parts};
+use tracing::{error, info};
+
+/// The format of the rendered image.
+const FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// The requested number of MSAA samples. This
+/// is clamped to what the device supports.
+const MSAA_SAMPLES: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4;
+
+/// A renderer that draws to an image it owns instead of a window.
+/// There is no surface or swapchain, so this works on machines
+/// without a display, e.g. for server-side rendering and tests.
+/// Fields are dropped in order, so everything made on the device
+/// comes before it, and the instance comes last.
+pub struct HeadlessRenderer {
+    /// The size of the rendered image.
+    extent: vk::Extent2D,
+
+    /// The render pass wrapper.
+    render_pass: RenderPass,
+
+    /// The pipeline cache.
+    pipeline_cache: PipelineCache,
+
+    /// The multisampled color image, if multisampling is enabled. The
+    /// frame buffer refers to it, so it's kept until the renderer drops.
+    _color_image: Option<AttachmentImage>,
+
+    /// The image we render to. This takes the place of a swapchain image.
+    target_image: AttachmentImage,
+
+    /// The frame buffers wrapper. There is only one.
+    frame_buffers: FrameBuffers,
+
+    /// The triangle renderer.
+    triangle_renderer: TriangleRenderer,
+
+    /// The device wrapper.
+    device: Device,
+
+    /// The debugging wrapper.
+    debugging: Option<Debugging>,
+
+    /// The instance wrapper. It's only kept to be destroyed last.
+    _instance: Instance
+}
+
+impl HeadlessRenderer {
+    /// Create a new headless renderer that renders images of the given
+    /// size on a device chosen by the selector among those that meet
+    /// the requirements.
+    ///
+    /// # Safety
+    ///
+    /// The renderer owns its instance and device, so anything created on its
+    /// device must be dropped or destroyed before the renderer is.
+    pub unsafe fn new(
+        assets_path: PathBuf,
+        extent: vk::Extent2D,
+        device_selector: &DeviceSelector,
+        requirements: &DeviceRequirements,
+        validation: &ValidationSettings
+    ) -> Result<Self> {
+        // Load the Vulkan library.
+        let entry = Entry::linked();
+
+        // Create the instance wrapper without any surface extensions.
+        let instance = Instance::new_headless(&entry, validation)?;
+
+        // Capture messages for everything else.
+        let debugging = match (instance.validation_enabled(), validation.collect_messages) {
+            (true, true) => Some(Debugging::new_collecting(
+                &entry,
+                &instance,
+                validation.debug_level,
+                validation.message_types
+            )?),
+            (true, false) => Some(Debugging::new(
+                &entry,
+                &instance,
+                validation.debug_level,
+                validation.message_types
+            )?),
+            (false, _) => None
+        };
+
+        // Create the device wrapper. We never present.
+        let device = Device::new(&instance, None, device_selector, requirements)?;
+
+        // Create the render pass wrapper. The image is left
+        // ready to be copied out once the pass is done.
+        let render_pass = RenderPass::new_with_format(
+            &device,
+            FORMAT,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            MSAA_SAMPLES
+        )?;
+
+        // Create the pipeline cache.
+        let pipeline_cache = PipelineCache::new(&device, &PipelineCache::default_path()?)?;
+
+        // Create the multisampled color image.
+        let color_image = match render_pass.samples() {
+            vk::SampleCountFlags::TYPE_1 => None,
+            samples => Some(AttachmentImage::new(
+                &device,
+                &ImageSettings {
+                    format: FORMAT,
+                    usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    samples,
+                    mip_levels: 1,
+                    kind: ImageKind::Flat,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+                },
+                &extent,
+                vk::ImageAspectFlags::COLOR
+            )?)
+        };
+
+        // Create the image we render to.
+        let target_image = AttachmentImage::new(
+            &device,
+            &ImageSettings {
+                format:            FORMAT,
+                usage:             vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC,
+                tiling:            vk::ImageTiling::OPTIMAL,
+                samples:           vk::SampleCountFlags::TYPE_1,
+                mip_levels:        1,
+                kind:              ImageKind::Flat,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+            },
+            &extent,
+            vk::ImageAspectFlags::COLOR
+        )?;
+
+        // Name the images for debugging.
+        if let Some(color_image) = &color_image {
+            device.set_object_name(**color_image, "Headless MSAA color image");
+        }
+
+        device.set_object_name(*target_image, "Headless target image");
+
+        // Create the frame buffers wrapper. The multisampled color
+        // image, if any, comes first and is resolved to the target.
+        let frame_buffers = FrameBuffers::new_from_views(
+            &device,
+            &[*target_image.view()],
+            &extent,
+            &render_pass,
+            |_, view| match &color_image {
+                Some(color_image) => vec![*color_image.view(), view],
+                None => vec![view]
+            }
+        )?;
+
+        // Create the triangle renderer. Every frame waits
+        // for the GPU, so we only need one frame in flight.
+        let triangle_renderer =
+            TriangleRenderer::new(&assets_path, &device, &pipeline_cache, &render_pass, 1)?;
+
+        info!("Headless renderer: {}x{}", extent.width, extent.height);
+
+        Ok(Self {
+            _instance: instance,
+            debugging,
+            device,
+            extent,
+            render_pass,
+            pipeline_cache,
+            _color_image: color_image,
+            target_image,
+            frame_buffers,
+            triangle_renderer
+        })
+    }
+
+    /// Returns the device.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the debugging wrapper, if validation is enabled.
+    pub fn debugging(&self) -> Option<&Debugging> {
+        self.debugging.as_ref()
+    }
+
+    /// Returns the size of the rendered image.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Draw a frame and wait for it to finish.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost. The renderables must only
+    /// use objects that are still alive.
+    pub unsafe fn draw(&mut self) -> Result<()> {
+        let Self {
+            device,
+            extent,
+            render_pass,
+            frame_buffers,
+            triangle_renderer,
+            ..
+        } = self;
+
+        device.one_time_graphics_command(|command_buffer| {
+            // Set the viewport state. It matches the triangle renderer's clip space.
+            device.cmd_set_viewport(
+                command_buffer,
+                0,
+                &[triangle_renderer
+                    .clip_space()
+                    .viewport(extent)]
+            );
+
+            // Set the scissor state.
+            device.cmd_set_scissor(command_buffer, 0, &[(*extent).into()]);
+
+            // Begin the render pass.
+            render_pass.begin(
+                device,
+                extent,
+                frame_buffers,
+                &command_buffer,
+                0,
+                vk::SubpassContents::INLINE
+            );
+
+            // Render the triangle.
+            triangle_renderer.draw(device, extent, &command_buffer, 0)?;
+
+            // End the render pass.
+            render_pass.end(device, &command_buffer);
+
+            Ok(())
+        })
+    }
+
+    /// Read back the most recently drawn frame. Call draw first.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost.
+    pub unsafe fn screenshot(&self) -> Result<RgbaImage> {
+        // The size of the image in bytes.
+        let size = (self.extent.width * self.extent.height * 4) as vk::DeviceSize;
+
+        // Create a buffer the host can read.
+        let (buffer, allocation) = new_buffer(
+            &self.device,
+            &mut self.device.allocator(),
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+        )?;
+
+        // Copy the image to the buffer.
+        let result = self
+            .device
+            .one_time_graphics_command(|command_buffer| {
+                // Wait for the render pass to finish writing the image.
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier::default()
+                        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(*self.target_image)
+                        .subresource_range(vk::ImageSubresourceRange {
+                            aspect_mask:      vk::ImageAspectFlags::COLOR,
+                            base_mip_level:   0,
+                            level_count:      1,
+                            base_array_layer: 0,
+                            layer_count:      1
+                        })]
+                );
+
+                // Copy the image.
+                self.device
+                    .cmd_copy_image_to_buffer(
+                        command_buffer,
+                        *self.target_image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        buffer,
+                        &[vk::BufferImageCopy::default()
+                            .image_subresource(vk::ImageSubresourceLayers {
+                                aspect_mask:      vk::ImageAspectFlags::COLOR,
+                                mip_level:        0,
+                                base_array_layer: 0,
+                                layer_count:      1
+                            })
+                            .image_extent(vk::Extent3D {
+                                width:  self.extent.width,
+                                height: self.extent.height,
+                                depth:  1
+                            })]
+                    );
+
+                // Make the copy visible to the host.
+                self.device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::HOST,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[vk::BufferMemoryBarrier::default()
+                        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::HOST_READ)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .buffer(buffer)
+                        .offset(0)
+                        .size(size)],
+                    &[]
+                );
+
+                Ok(())
+            })
+            .and_then(|_| {
+                // Map the memory so we can read from it.
+                let ptr = allocation.map(&self.device)?;
+
+                // Copy the pixels out.
+                let pixels = from_raw_parts(ptr.as_ptr().cast::<u8>(), size as usize).to_vec();
+
+                // Unmap the memory.
+                allocation.unmap(&self.device);
+
+                RgbaImage::from_raw(self.extent.width, self.extent.height, pixels)
+                    .ok_or_else(|| anyhow!("The screenshot has the wrong size."))
+            });
+
+        // Destroy the buffer.
+        self.device
+            .destroy_buffer(buffer, None);
+        self.device
+            .untrack_resource(ResourceKind::Buffer);
+
+        // Free the memory.
+        self.device
+            .allocator()
+            .free(&self.device, allocation);
+
+        result
+    }
+}
+
+impl Drop for HeadlessRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            // Wait for the device to finish. We must do this or we may
+            // be in the middle of rendering on the GPU. If the device
+            // was lost, there's nothing left to wait for.
+            if let Err(e) = self.device.wait_idle() {
+                error!("Failed to wait for the device to idle: {}", e);
+            }
+
+            // Destroy the triangle renderer.
+            self.triangle_renderer
+                .destroy(&self.device);
+
+            // Destroy the frame buffers.
+            self.frame_buffers
+                .destroy(&self.device);
+
+            // Save and destroy the pipeline cache.
+            self.pipeline_cache
+                .destroy(&self.device);
+
+            // The images and render pass are destroyed by the device when
+            // it's dropped, and the debugging data and instance after it.
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{new_image, Allocation, DeferredDestroyQueue, DeferredResource, Device, ImageSettings};
+use anyhow::Result;
+use ash::vk;
+use std::{mem::take, ops::Deref, sync::Arc};
+
+/// Wraps a Vulkan image that is only ever rendered to on the GPU,
+/// such as a multisampled color or a depth attachment. Nothing is
+/// uploaded, so these are typically recreated with the swapchain.
+/// Array images are viewed as a whole, e.g. to render every layer
+/// at once with a multiview render pass. Dropping the image without
+/// destroying it defers its destruction to the device's next wait
+/// for idle.
+pub struct AttachmentImage {
+    /// The image.
+    image: vk::Image,
+
+    /// The memory.
+    allocation: Allocation,
+
+    /// The image view.
+    view: vk::ImageView,
+
+    /// Where the image goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
+}
+
+impl AttachmentImage {
+    /// Create a new attachment image.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new(
+        device: &Device,
+        settings: &ImageSettings,
+        size: &vk::Extent2D,
+        aspect_mask: vk::ImageAspectFlags
+    ) -> Result<Self> {
+        // We need a 3D size.
+        let size = vk::Extent3D {
+            width:  size.width,
+            height: size.height,
+            depth:  1
+        };
+
+        // Create the image.
+        let (image, allocation) = new_image(
+            device,
+            &mut device.allocator(),
+            settings,
+            &size,
+            settings.memory_properties
+        )?;
+
+        // Create the image view.
+        let view = device.create_image_view(
+            &vk::ImageViewCreateInfo::default()
+                .image(image)
+                .view_type(settings.kind.view_type())
+                .format(settings.format)
+                .components(vk::ComponentMapping {
+                    r: vk::ComponentSwizzle::IDENTITY,
+                    g: vk::ComponentSwizzle::IDENTITY,
+                    b: vk::ComponentSwizzle::IDENTITY,
+                    a: vk::ComponentSwizzle::IDENTITY
+                })
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: settings.kind.array_layers()
+                }),
+            None
+        )?;
+
+        Ok(Self {
+            image,
+            allocation,
+            view,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
+        })
+    }
+
+    /// Returns the image view.
+    pub fn view(&self) -> &vk::ImageView {
+        &self.view
+    }
+
+    /// Destroy the image right away.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the image and its view.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handles out of the image, leaving it destroyed.
+    /// Returns None if the image was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.image == vk::Image::null() {
+            return None;
+        }
+
+        Some(DeferredResource::Image(
+            take(&mut self.image),
+            take(&mut self.view),
+            take(&mut self.allocation)
+        ))
+    }
+}
+
+impl Drop for AttachmentImage {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
+    }
+}
+
+impl Deref for AttachmentImage {
+    type Target = vk::Image;
+
+    fn deref(&self) -> &Self::Target {
+        &self.image
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{new_image, Allocation, DeferredDestroyQueue, DeferredResource, Device, ImageSettings};
+use anyhow::Result;
+use ash::vk;
+use std::{mem::take, ops::Deref, sync::Arc};
+
+/// Wraps a Vulkan image, its memory, and a view of it. This makes no
+/// assumptions about how the image is used, so it works for textures,
+/// depth images, storage images, and render targets alike. The current
+/// layout is tracked so transitions only need the new layout. Dropping
+/// the image without destroying it defers its destruction to the
+/// device's next wait for idle.
+pub struct Image {
+    /// The image.
+    image: vk::Image,
+
+    /// The memory.
+    allocation: Allocation,
+
+    /// The image view.
+    view: vk::ImageView,
+
+    /// The size of the image.
+    extent: vk::Extent2D,
+
+    /// The aspects of the image the view covers.
+    aspect_mask: vk::ImageAspectFlags,
+
+    /// The number of mip levels.
+    mip_levels: u32,
+
+    /// The number of array layers.
+    array_layers: u32,
+
+    /// The current layout.
+    layout: vk::ImageLayout,
+
+    /// Where the image goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
+}
+
+impl Image {
+    /// Create a new image and transition it to the given layout.
+    /// Pass UNDEFINED to leave the image as it was created.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new(
+        device: &Device,
+        settings: &ImageSettings,
+        extent: &vk::Extent2D,
+        aspect_mask: vk::ImageAspectFlags,
+        layout: vk::ImageLayout
+    ) -> Result<Self> {
+        // We need a 3D size.
+        let size = vk::Extent3D {
+            width:  extent.width,
+            height: extent.height,
+            depth:  1
+        };
+
+        // Create the image.
+        let (image, allocation) = new_image(
+            device,
+            &mut device.allocator(),
+            settings,
+            &size,
+            settings.memory_properties
+        )?;
+
+        // Create the image view.
+        let view = device.create_image_view(
+            &vk::ImageViewCreateInfo::default()
+                .image(image)
+                .view_type(settings.kind.view_type())
+                .format(settings.format)
+                .components(vk::ComponentMapping {
+                    r: vk::ComponentSwizzle::IDENTITY,
+                    g: vk::ComponentSwizzle::IDENTITY,
+                    b: vk::ComponentSwizzle::IDENTITY,
+                    a: vk::ComponentSwizzle::IDENTITY
+                })
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask,
+                    base_mip_level: 0,
+                    level_count: settings.mip_levels,
+                    base_array_layer: 0,
+                    layer_count: settings.kind.array_layers()
+                }),
+            None
+        )?;
+
+        // Create the image wrapper. New images start out undefined.
+        let mut this = Self {
+            image,
+            allocation,
+            view,
+            extent: *extent,
+            aspect_mask,
+            mip_levels: settings.mip_levels,
+            array_layers: settings.kind.array_layers(),
+            layout: vk::ImageLayout::UNDEFINED,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
+        };
+
+        // Move the image to the requested layout.
+        if layout != vk::ImageLayout::UNDEFINED {
+            this.transition(device, layout)?;
+        }
+
+        Ok(this)
+    }
+
+    /// Transition the image to a new layout and wait for it to finish.
+    /// This runs on the graphics queue, which supports every layout.
+    ///
+    /// # Safety
+    ///
+    /// The image must not be in use by the GPU, and its tracked layout must be
+    /// the one it's actually in.
+    pub unsafe fn transition(&mut self, device: &Device, layout: vk::ImageLayout) -> Result<()> {
+        let old_layout = self.layout;
+
+        device.one_time_graphics_command(|command_buffer| {
+            self.record_transition(device, &command_buffer, old_layout, layout);
+
+            Ok(())
+        })?;
+
+        self.layout = layout;
+
+        Ok(())
+    }
+
+    /// Record a transition to a new layout into a command buffer. The
+    /// caller must make sure the command buffer executes before the
+    /// image is used again, as the new layout is assumed from now on.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and it must execute before
+    /// anything else uses the image, as its layout is assumed to have changed.
+    pub unsafe fn cmd_transition(
+        &mut self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        layout: vk::ImageLayout
+    ) {
+        self.record_transition(device, command_buffer, self.layout, layout);
+
+        self.layout = layout;
+    }
+
+    /// Record the barrier for a layout transition.
+    unsafe fn record_transition(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout
+    ) {
+        // Work out what to wait for and what waits on us.
+        let (src_access_mask, src_stage_mask) = Self::access_and_stage(old_layout);
+        let (dst_access_mask, dst_stage_mask) = Self::access_and_stage(new_layout);
+
+        device.cmd_pipeline_barrier(
+            *command_buffer,
+            src_stage_mask,
+            dst_stage_mask,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::default()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_access_mask(src_access_mask)
+                .dst_access_mask(dst_access_mask)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.image)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask:      self.aspect_mask,
+                    base_mip_level:   0,
+                    level_count:      self.mip_levels,
+                    base_array_layer: 0,
+                    layer_count:      self.array_layers
+                })]
+        );
+    }
+
+    /// The accesses and pipeline stages that use an image in the
+    /// given layout. Unknown layouts wait on everything.
+    fn access_and_stage(layout: vk::ImageLayout) -> (vk::AccessFlags, vk::PipelineStageFlags) {
+        match layout {
+            vk::ImageLayout::UNDEFINED => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::TOP_OF_PIPE
+            ),
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (
+                vk::AccessFlags::TRANSFER_READ,
+                vk::PipelineStageFlags::TRANSFER
+            ),
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL => (
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::PipelineStageFlags::TRANSFER
+            ),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+                vk::AccessFlags::SHADER_READ,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER
+            ),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            ),
+            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+            ),
+            vk::ImageLayout::PRESENT_SRC_KHR => (
+                vk::AccessFlags::empty(),
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE
+            ),
+            _ => (
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+                vk::PipelineStageFlags::ALL_COMMANDS
+            )
+        }
+    }
+
+    /// Record that the image was moved to a new layout by other means,
+    /// e.g. a barrier recorded by hand or a render pass.
+    ///
+    /// # Safety
+    ///
+    /// The image must really be in the new layout by the time it's next used.
+    pub unsafe fn assume_layout(&mut self, layout: vk::ImageLayout) {
+        self.layout = layout;
+    }
+
+    /// Returns the image view.
+    pub fn view(&self) -> &vk::ImageView {
+        &self.view
+    }
+
+    /// Returns the size of the image.
+    pub fn extent(&self) -> &vk::Extent2D {
+        &self.extent
+    }
+
+    /// Returns the aspects of the image the view covers.
+    pub fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        self.aspect_mask
+    }
+
+    /// Returns the number of mip levels.
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Returns the number of array layers.
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    /// Returns the current layout.
+    pub fn layout(&self) -> vk::ImageLayout {
+        self.layout
+    }
+
+    /// Destroy the image right away.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the image and its view.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handles out of the image, leaving it destroyed.
+    /// Returns None if the image was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.image == vk::Image::null() {
+            return None;
+        }
+
+        Some(DeferredResource::Image(
+            take(&mut self.image),
+            take(&mut self.view),
+            take(&mut self.allocation)
+        ))
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
+    }
+}
+
+impl Deref for Image {
+    type Target = vk::Image;
+
+    fn deref(&self) -> &Self::Target {
+        &self.image
+    }
+}
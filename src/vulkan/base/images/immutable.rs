@@ -1,22 +1,28 @@
-use crate::{new_image, Device, ImageSettings, MappedBuffer};
-use anyhow::Result;
+use crate::{
+    Device, Image, ImageKind, ImageSettings, Instance, Ktx2, MappedBuffer, MemoryCoherence,
+    CUBE_FACE_COUNT
+};
+use anyhow::{anyhow, Result};
 use ash::vk;
-use image::io::Reader;
-use std::{ops::Deref, path::Path};
+use image::{io::Reader, load_from_memory, RgbaImage};
+use std::{cmp::max, fs::read, ops::Deref, path::Path, slice::from_ref};
+use tracing::warn;
+
+/// The width and height of the checkerboard image.
+const CHECKERBOARD_SIZE: u32 = 64;
+
+/// The width and height of a checkerboard cell.
+const CHECKERBOARD_CELL_SIZE: u32 = 8;
 
 /// Wraps a Vulkan image. This version uses a staging buffer to
 /// directly upload data to the GPU exactly once. No CPU-side
 /// buffer is kept around for copying. Use this for data like
-/// textures that never change and should be uploaded once.
+/// textures that never change and should be uploaded once. Dropping
+/// the image without destroying it defers its destruction to the
+/// device's next wait for idle, as the wrapped Image does.
 pub struct ImmutableImage {
     /// The image.
-    image: vk::Image,
-
-    /// The memory.
-    memory: vk::DeviceMemory,
-
-    /// The image view.
-    view: vk::ImageView
+    image: Image
 }
 
 impl ImmutableImage {
@@ -27,22 +33,334 @@ impl ImmutableImage {
         data: &[u8],
         size: &vk::Extent2D
     ) -> Result<Self> {
-        // We need a 3D size.
-        let size = vk::Extent3D {
-            width:  size.width,
-            height: size.height,
-            depth:  1
+        // The data fills the first mip level.
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask:      vk::ImageAspectFlags::COLOR,
+                mip_level:        0,
+                base_array_layer: 0,
+                layer_count:      1
+            })
+            .image_extent(vk::Extent3D {
+                width:  size.width,
+                height: size.height,
+                depth:  1
+            });
+
+        Self::upload(device, settings, data, size, from_ref(&region))
+    }
+
+    /// Create a new image from a KTX2 file. The image is uploaded in the
+    /// file's format, which is usually a compressed one like BC7, ETC2,
+    /// or ASTC, along with every mip level the file contains.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_from_ktx2(instance: &Instance, device: &Device, path: &Path) -> Result<Self> {
+        // Load the file from disk.
+        let data = read(path)?;
+
+        // Parse the header.
+        let ktx2 = Ktx2::parse(&data)?;
+
+        // The device must be able to sample the format.
+        let format_properties =
+            instance.get_physical_device_format_properties(*device.physical_device(), ktx2.format);
+
+        if !format_properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+        {
+            return Err(anyhow!(
+                "The device can't sample {:?} images, needed by {:?}.",
+                ktx2.format,
+                path
+            ));
+        }
+
+        // The image settings.
+        let settings = ImageSettings {
+            format:            ktx2.format,
+            usage:             vk::ImageUsageFlags::SAMPLED,
+            tiling:            vk::ImageTiling::OPTIMAL,
+            samples:           vk::SampleCountFlags::TYPE_1,
+            mip_levels:        ktx2.level_offsets.len() as u32,
+            kind:              ImageKind::Flat,
+            memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+        };
+
+        // The whole file is uploaded, so each level is
+        // copied from where it sits in the file.
+        let regions = ktx2
+            .level_offsets
+            .iter()
+            .enumerate()
+            .map(|(level, offset)| {
+                vk::BufferImageCopy::default()
+                    .buffer_offset(*offset)
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask:      vk::ImageAspectFlags::COLOR,
+                        mip_level:        level as u32,
+                        base_array_layer: 0,
+                        layer_count:      1
+                    })
+                    .image_extent(vk::Extent3D {
+                        width:  max(ktx2.extent.width >> level, 1),
+                        height: max(ktx2.extent.height >> level, 1),
+                        depth:  1
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        // Create the image.
+        let image = Self::upload(device, &settings, &data, &ktx2.extent, &regions)?;
+
+        Ok(image)
+    }
+
+    /// Create a new cubemap from the raw data of its six faces, in the
+    /// order +X, -X, +Y, -Y, +Z, -Z. The faces must be square, all of
+    /// the given size, and the settings must be for a cube.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_cube(
+        device: &Device,
+        settings: &ImageSettings,
+        faces: &[&[u8]],
+        size: &vk::Extent2D
+    ) -> Result<Self> {
+        if settings.kind != ImageKind::Cube {
+            return Err(anyhow!("Cubemaps must be created with cube settings."));
+        }
+
+        if faces.len() != CUBE_FACE_COUNT as usize {
+            return Err(anyhow!(
+                "A cubemap has {} faces but {} were given.",
+                CUBE_FACE_COUNT,
+                faces.len()
+            ));
+        }
+
+        if size.width != size.height {
+            return Err(anyhow!(
+                "Cubemap faces must be square, not {}x{}.",
+                size.width,
+                size.height
+            ));
+        }
+
+        Self::upload_layers(device, settings, faces, size)
+    }
+
+    /// Create a new 2D texture array from the raw data of its layers. The
+    /// layers must all be of the given size, and the settings must be for
+    /// an array with as many layers as are given.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_array(
+        device: &Device,
+        settings: &ImageSettings,
+        layers: &[&[u8]],
+        size: &vk::Extent2D
+    ) -> Result<Self> {
+        if settings.kind != ImageKind::Array(layers.len() as u32) {
+            return Err(anyhow!(
+                "Texture arrays must be created with array settings of {} layers.",
+                layers.len()
+            ));
+        }
+
+        Self::upload_layers(device, settings, layers, size)
+    }
+
+    /// Create a new 2D texture array from image files, one layer per file.
+    /// The images must all be the same size.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_array_from_files(
+        device: &Device,
+        settings: &ImageSettings,
+        paths: &[&Path]
+    ) -> Result<Self> {
+        // Load the layers from disk.
+        let layers = paths
+            .iter()
+            .map(|path| Self::load_rgba(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        // The size of the first layer. The others are checked against its data.
+        let size = Self::rgba_size(&layers);
+
+        // Get the raw data of each layer.
+        let layers = layers
+            .iter()
+            .map(|layer| layer.as_raw().as_slice())
+            .collect::<Vec<_>>();
+
+        Self::new_array(device, settings, &layers, &size)
+    }
+
+    /// Create a new cubemap from six image files, in the order +X, -X, +Y, -Y, +Z, -Z.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_cube_from_files(
+        device: &Device,
+        settings: &ImageSettings,
+        paths: &[&Path]
+    ) -> Result<Self> {
+        // Load the faces from disk.
+        let faces = paths
+            .iter()
+            .map(|path| Self::load_rgba(path))
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new_cube_from_rgba(device, settings, &faces)
+    }
+
+    /// Create a new cubemap from six encoded images in memory,
+    /// in the order +X, -X, +Y, -Y, +Z, -Z.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_cube_from_encoded_bytes(
+        device: &Device,
+        settings: &ImageSettings,
+        faces: &[&[u8]]
+    ) -> Result<Self> {
+        // Decode the faces.
+        let faces = faces
+            .iter()
+            .map(|bytes| Ok(load_from_memory(bytes)?.to_rgba8()))
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new_cube_from_rgba(device, settings, &faces)
+    }
+
+    /// Create a new cubemap from six decoded faces of the same size.
+    unsafe fn new_cube_from_rgba(
+        device: &Device,
+        settings: &ImageSettings,
+        faces: &[RgbaImage]
+    ) -> Result<Self> {
+        // The size of the first face. The others are checked against its data.
+        let size = Self::rgba_size(faces);
+
+        // Get the raw data of each face.
+        let faces = faces
+            .iter()
+            .map(|face| face.as_raw().as_slice())
+            .collect::<Vec<_>>();
+
+        Self::new_cube(device, settings, &faces, &size)
+    }
+
+    /// Returns the size of the first of the given images, or zero if there are none.
+    fn rgba_size(images: &[RgbaImage]) -> vk::Extent2D {
+        let size = images
+            .first()
+            .map(|image| image.dimensions())
+            .unwrap_or_default();
+
+        vk::Extent2D {
+            width:  size.0,
+            height: size.1
+        }
+    }
+
+    /// Create a new image with one layer per slice of data, packing the
+    /// layers into the staging buffer and copying each to its own array layer.
+    unsafe fn upload_layers(
+        device: &Device,
+        settings: &ImageSettings,
+        layers: &[&[u8]],
+        size: &vk::Extent2D
+    ) -> Result<Self> {
+        // Every layer must hold the same amount of data.
+        let layer_size = layers
+            .first()
+            .map(|layer| layer.len())
+            .ok_or_else(|| anyhow!("An image needs at least one layer."))?;
+
+        if layers
+            .iter()
+            .any(|layer| layer.len() != layer_size)
+        {
+            return Err(anyhow!("Image layers must all be the same size."));
+        }
+
+        // Pack the layers one after the other.
+        let data = layers.concat();
+
+        // Copy each layer to its own array layer.
+        let regions = (0..layers.len() as u32)
+            .map(|layer| {
+                vk::BufferImageCopy::default()
+                    .buffer_offset(layer as vk::DeviceSize * layer_size as vk::DeviceSize)
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask:      vk::ImageAspectFlags::COLOR,
+                        mip_level:        0,
+                        base_array_layer: layer,
+                        layer_count:      1
+                    })
+                    .image_extent(vk::Extent3D {
+                        width:  size.width,
+                        height: size.height,
+                        depth:  1
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        Self::upload(device, settings, &data, size, &regions)
+    }
+
+    /// Create a new image and copy the regions of the data into it.
+    unsafe fn upload(
+        device: &Device,
+        settings: &ImageSettings,
+        data: &[u8],
+        size: &vk::Extent2D,
+        regions: &[vk::BufferImageCopy]
+    ) -> Result<Self> {
+        // The image is copied to.
+        let settings = &ImageSettings {
+            usage: settings.usage | vk::ImageUsageFlags::TRANSFER_DST,
+            ..*settings
+        };
+
+        // Every mip level is written.
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask:      vk::ImageAspectFlags::COLOR,
+            base_mip_level:   0,
+            level_count:      settings.mip_levels,
+            base_array_layer: 0,
+            layer_count:      settings.kind.array_layers()
         };
 
         // Create the src buffer.
-        let src = MappedBuffer::new(device, vk::BufferUsageFlags::TRANSFER_SRC, data)?;
+        let mut src = MappedBuffer::new(
+            device,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryCoherence::default(),
+            data
+        )?;
 
         // Create the dst image.
-        let (image, memory, _memory_size) = new_image(
+        let mut image = Image::new(
             device,
             settings,
-            &size,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL
+            size,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED
         )?;
 
         // Issue the command to copy the image.
@@ -62,102 +380,101 @@ impl ImmutableImage {
                     .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
                     .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
                     .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                    .image(image)
-                    .subresource_range(vk::ImageSubresourceRange {
-                        aspect_mask:      vk::ImageAspectFlags::COLOR,
-                        base_mip_level:   0,
-                        level_count:      1,
-                        base_array_layer: 0,
-                        layer_count:      1
-                    })]
+                    .image(*image)
+                    .subresource_range(subresource_range)]
             );
 
             // Copy the buffer to the image.
             device.cmd_copy_buffer_to_image(
                 command_buffer,
                 *src,
-                image,
+                *image,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &[vk::BufferImageCopy::default()
-                    .image_subresource(vk::ImageSubresourceLayers {
-                        aspect_mask:      vk::ImageAspectFlags::COLOR,
-                        mip_level:        0,
-                        base_array_layer: 0,
-                        layer_count:      1
-                    })
-                    .image_extent(size)]
+                regions
             );
 
-            // Prepare the image for shader reads.
-            device.cmd_pipeline_barrier(
-                command_buffer,
-                vk::PipelineStageFlags::TRANSFER,
-                vk::PipelineStageFlags::FRAGMENT_SHADER,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                &[vk::ImageMemoryBarrier::default()
-                    .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-                    .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                    .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
-                    .dst_access_mask(vk::AccessFlags::SHADER_READ)
-                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                    .image(image)
-                    .subresource_range(vk::ImageSubresourceRange {
-                        aspect_mask:      vk::ImageAspectFlags::COLOR,
-                        base_mip_level:   0,
-                        level_count:      1,
-                        base_array_layer: 0,
-                        layer_count:      1
-                    })]
-            );
+            // Prepare the image for shader reads. With a dedicated transfer
+            // queue, this releases ownership to the graphics queue family.
+            match device.has_dedicated_transfer_queue() {
+                true => device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[Self::ownership_barrier(device, *image, subresource_range)
+                        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::empty())]
+                ),
+                false => device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[vk::ImageMemoryBarrier::default()
+                        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                        .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                        .image(*image)
+                        .subresource_range(subresource_range)]
+                )
+            }
 
             Ok(())
         })?;
 
-        // Create the image view.
-        let view = device.create_image_view(
-            &vk::ImageViewCreateInfo::default()
-                .image(image)
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(settings.format)
-                .components(vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::IDENTITY,
-                    g: vk::ComponentSwizzle::IDENTITY,
-                    b: vk::ComponentSwizzle::IDENTITY,
-                    a: vk::ComponentSwizzle::IDENTITY
-                })
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask:      vk::ImageAspectFlags::COLOR,
-                    base_mip_level:   0,
-                    level_count:      1,
-                    base_array_layer: 0,
-                    layer_count:      1
-                }),
-            None
-        )?;
+        // Acquire ownership on the graphics queue family.
+        if device.has_dedicated_transfer_queue() {
+            device.one_time_graphics_command(|command_buffer| {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[Self::ownership_barrier(device, *image, subresource_range)
+                        .src_access_mask(vk::AccessFlags::empty())
+                        .dst_access_mask(vk::AccessFlags::SHADER_READ)]
+                );
+
+                Ok(())
+            })?;
+        }
+
+        // The barriers above left the image ready for shader reads.
+        image.assume_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
 
         // Destroy the src buffer.
         src.destroy(device);
 
-        Ok(Self {
-            image,
-            memory,
-            view
-        })
+        Ok(Self { image })
     }
 
-    /// Create a new image from a file.
+    /// Create a new image from a file. If fallback is set and the file
+    /// can't be loaded, a checkerboard "missing texture" is used instead.
     pub unsafe fn new_from_file(
         device: &Device,
         settings: &ImageSettings,
-        path: &Path
+        path: &Path,
+        fallback: bool
     ) -> Result<Self> {
         // Load the texture from disk.
-        let data = Reader::open(path)?
-            .decode()?
-            .to_rgba8();
+        let data = match Self::load_rgba(path) {
+            Ok(data) => data,
+            Err(e) if fallback => {
+                warn!("Failed to load {:?} ({}), using a checkerboard.", path, e);
+
+                return Self::new_checkerboard(device, settings);
+            },
+            Err(e) => return Err(e)
+        };
 
         // Get the image size.
         let size = data.dimensions();
@@ -172,21 +489,98 @@ impl ImmutableImage {
         Ok(image)
     }
 
-    /// Returns the image view.
-    pub fn view(&self) -> &vk::ImageView {
-        &self.view
+    /// Create a new image from an encoded image in memory, e.g. a PNG
+    /// embedded with include_bytes! or downloaded over the network.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_from_encoded_bytes(
+        device: &Device,
+        settings: &ImageSettings,
+        bytes: &[u8]
+    ) -> Result<Self> {
+        // Decode the image.
+        let data = load_from_memory(bytes)?.to_rgba8();
+
+        // Get the image size.
+        let size = data.dimensions();
+        let size = vk::Extent2D {
+            width:  size.0,
+            height: size.1
+        };
+
+        // Create the image.
+        let image = Self::new(device, settings, &data, &size)?;
+
+        Ok(image)
     }
 
-    /// Destroy the image.
-    pub unsafe fn destroy(&self, device: &Device) {
-        // Destroy the image view.
-        device.destroy_image_view(self.view, None);
+    /// Load an image from disk as RGBA.
+    fn load_rgba(path: &Path) -> Result<RgbaImage> {
+        let data = Reader::open(path)?
+            .decode()?
+            .to_rgba8();
+
+        Ok(data)
+    }
+
+    /// Create a magenta and black checkerboard image. This is
+    /// the placeholder for textures that fail to load.
+    ///
+    /// # Safety
+    ///
+    /// The image must be dropped or destroyed before the device is.
+    pub unsafe fn new_checkerboard(device: &Device, settings: &ImageSettings) -> Result<Self> {
+        // Generate the RGBA pixels.
+        let data = (0..CHECKERBOARD_SIZE)
+            .flat_map(|y| (0..CHECKERBOARD_SIZE).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                match (x / CHECKERBOARD_CELL_SIZE + y / CHECKERBOARD_CELL_SIZE) % 2 {
+                    0 => [255, 0, 255, 255],
+                    _ => [0, 0, 0, 255]
+                }
+            })
+            .collect::<Vec<u8>>();
+
+        // Create the image.
+        let image = Self::new(
+            device,
+            settings,
+            &data,
+            &vk::Extent2D {
+                width:  CHECKERBOARD_SIZE,
+                height: CHECKERBOARD_SIZE
+            }
+        )?;
+
+        Ok(image)
+    }
+
+    /// The barrier that moves the image from the transfer queue family to the
+    /// graphics queue family. The same barrier is recorded on both queues.
+    fn ownership_barrier<'a>(
+        device: &Device,
+        image: vk::Image,
+        subresource_range: vk::ImageSubresourceRange
+    ) -> vk::ImageMemoryBarrier<'a> {
+        vk::ImageMemoryBarrier::default()
+            .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .src_queue_family_index(device.transfer_queue_family_index())
+            .dst_queue_family_index(device.queue_family_index())
+            .image(image)
+            .subresource_range(subresource_range)
+    }
 
-        // Destroy the image.
-        device.destroy_image(self.image, None);
+    /// Returns the image view.
+    pub fn view(&self) -> &vk::ImageView {
+        self.image.view()
+    }
 
-        // Free the memory.
-        device.free_memory(self.memory, None);
+    /// Destroy the image right away.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        self.image.destroy(device);
     }
 }
 
@@ -0,0 +1,186 @@
+use anyhow::{anyhow, Result};
+use ash::vk;
+use ktx2::Reader;
+
+/// The header of a KTX2 file. Only plain 2D textures are supported,
+/// so arrays, cube maps, 3D textures, and supercompressed or Basis
+/// Universal data are rejected.
+#[derive(Clone, Debug)]
+pub struct Ktx2 {
+    /// The format of the image data.
+    pub format: vk::Format,
+
+    /// The size of the base mip level.
+    pub extent: vk::Extent2D,
+
+    /// Where each mip level's data starts in the file, base level first.
+    pub level_offsets: Vec<vk::DeviceSize>
+}
+
+impl Ktx2 {
+    /// Parse the header of a KTX2 file. The reader checks that every mip
+    /// level lies within the data. The levels are aligned within the file
+    /// the way buffer to image copies need them to be.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        // Read the header and the level index.
+        let reader = Reader::new(data).map_err(|e| anyhow!("Invalid KTX2 file: {}.", e))?;
+        let header = reader.header();
+
+        // No format means Basis Universal data, which must be transcoded.
+        let Some(format) = header.format else {
+            return Err(anyhow!(
+                "KTX2 files without a Vulkan format are not supported."
+            ));
+        };
+
+        if header
+            .supercompression_scheme
+            .is_some()
+        {
+            return Err(anyhow!("Supercompressed KTX2 files are not supported."));
+        }
+
+        if header.pixel_height == 0
+            || header.pixel_depth != 0
+            || header.layer_count > 1
+            || header.face_count != 1
+        {
+            return Err(anyhow!("Only 2D KTX2 textures are supported."));
+        }
+
+        // The reader hands out each level as a slice of the
+        // data, so a level starts where its slice does.
+        let level_offsets = reader
+            .levels()
+            .map(|level| (level.data.as_ptr() as usize - data.as_ptr() as usize) as vk::DeviceSize)
+            .collect();
+
+        Ok(Self {
+            format: vk::Format::from_raw(format.value() as i32),
+            extent: vk::Extent2D {
+                width:  header.pixel_width,
+                height: header.pixel_height
+            },
+            level_offsets
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ktx2::{Format, Header, Index, LevelIndex, SupercompressionScheme};
+
+    /// The size of the base level of a 4x4 RGBA8 texture.
+    const LEVEL_SIZE: u64 = 64;
+
+    /// A 4x4 RGBA8 header with one level and an empty data format
+    /// descriptor, which sits between the level index and the level.
+    fn header() -> Header {
+        let dfd_byte_offset = (Header::LENGTH + LevelIndex::LENGTH) as u32;
+
+        Header {
+            format:                  Some(Format::R8G8B8A8_UNORM),
+            type_size:               1,
+            pixel_width:             4,
+            pixel_height:            4,
+            pixel_depth:             0,
+            layer_count:             0,
+            face_count:              1,
+            level_count:             1,
+            supercompression_scheme: None,
+            index:                   Index {
+                dfd_byte_offset,
+                dfd_byte_length: 4,
+                kvd_byte_offset: 0,
+                kvd_byte_length: 0,
+                sgd_byte_offset: 0,
+                sgd_byte_length: 0
+            }
+        }
+    }
+
+    /// Write a file with the given header and a level of the given length.
+    fn file(header: &Header, level_length: u64) -> Vec<u8> {
+        let level = LevelIndex {
+            byte_offset:              (Header::LENGTH + LevelIndex::LENGTH + 4) as u64,
+            byte_length:              level_length,
+            uncompressed_byte_length: level_length
+        };
+
+        let mut data = header.as_bytes().to_vec();
+
+        data.extend(level.as_bytes());
+        data.extend(4u32.to_le_bytes());
+        data.extend(vec![0; LEVEL_SIZE as usize]);
+
+        data
+    }
+
+    #[test]
+    fn parses_a_2d_texture() -> Result<()> {
+        let ktx2 = Ktx2::parse(&file(&header(), LEVEL_SIZE))?;
+
+        assert_eq!(ktx2.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(
+            ktx2.extent,
+            vk::Extent2D {
+                width:  4,
+                height: 4
+            }
+        );
+        assert_eq!(
+            ktx2.level_offsets,
+            vec![(Header::LENGTH + LevelIndex::LENGTH + 4) as u64]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Ktx2::parse(b"Not a KTX2 file at all, just some bytes.").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let data = file(&header(), LEVEL_SIZE);
+
+        assert!(Ktx2::parse(&data[..Header::LENGTH + 8]).is_err());
+    }
+
+    #[test]
+    fn rejects_levels_out_of_bounds() {
+        assert!(Ktx2::parse(&file(&header(), LEVEL_SIZE + 1)).is_err());
+    }
+
+    #[test]
+    fn rejects_basis_universal() {
+        let header = Header {
+            format: None,
+            ..header()
+        };
+
+        assert!(Ktx2::parse(&file(&header, LEVEL_SIZE)).is_err());
+    }
+
+    #[test]
+    fn rejects_supercompression() {
+        let header = Header {
+            supercompression_scheme: Some(SupercompressionScheme::Zstandard),
+            ..header()
+        };
+
+        assert!(Ktx2::parse(&file(&header, LEVEL_SIZE)).is_err());
+    }
+
+    #[test]
+    fn rejects_cube_maps() {
+        let header = Header {
+            face_count: 6,
+            ..header()
+        };
+
+        assert!(Ktx2::parse(&file(&header, LEVEL_SIZE)).is_err());
+    }
+}
@@ -1,5 +1,11 @@
+mod attachment;
+mod image;
 mod immutable;
+mod ktx2;
 mod util;
 
+pub use attachment::*;
+pub use image::*;
 pub use immutable::*;
+pub use ktx2::*;
 pub use util::*;
@@ -1,61 +1,224 @@
-use crate::{find_memory_type, Device};
-use anyhow::Result;
+use crate::{Allocation, Allocator, Device, ResourceKind};
+use anyhow::{anyhow, Result};
 use ash::vk;
+use tracing::warn;
+
+/// The number of faces of a cubemap.
+pub const CUBE_FACE_COUNT: u32 = 6;
+
+/// The depth stencil formats with a stencil component, in order of preference.
+pub const DEPTH_STENCIL_FORMATS: [vk::Format; 3] = [
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D16_UNORM_S8_UINT
+];
+
+/// The depth formats without a stencil component, in order of preference.
+pub const DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D16_UNORM,
+    vk::Format::X8_D24_UNORM_PACK32
+];
+
+/// Returns true if the format has a depth component.
+pub fn has_depth_component(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::D16_UNORM
+            | vk::Format::X8_D24_UNORM_PACK32
+            | vk::Format::D32_SFLOAT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+/// Returns true if the format has a stencil component.
+pub fn has_stencil_component(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::S8_UINT
+            | vk::Format::D16_UNORM_S8_UINT
+            | vk::Format::D24_UNORM_S8_UINT
+            | vk::Format::D32_SFLOAT_S8_UINT
+    )
+}
+
+/// The shape of an image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageKind {
+    /// A single 2D image.
+    #[default]
+    Flat,
+
+    /// A cubemap, stored as six 2D array layers in the order
+    /// +X, -X, +Y, -Y, +Z, -Z and viewed as a cube.
+    Cube,
+
+    /// An array of 2D images of the same size, with the given number of
+    /// layers. Shaders sample it as a sampler2DArray, indexed by layer.
+    Array(u32)
+}
+
+impl ImageKind {
+    /// The number of array layers.
+    pub fn array_layers(&self) -> u32 {
+        match self {
+            Self::Flat => 1,
+            Self::Cube => CUBE_FACE_COUNT,
+            Self::Array(layers) => *layers
+        }
+    }
+
+    /// The type of the image view.
+    pub fn view_type(&self) -> vk::ImageViewType {
+        match self {
+            Self::Flat => vk::ImageViewType::TYPE_2D,
+            Self::Cube => vk::ImageViewType::CUBE,
+            Self::Array(_) => vk::ImageViewType::TYPE_2D_ARRAY
+        }
+    }
+
+    /// The flags the image is created with.
+    pub fn create_flags(&self) -> vk::ImageCreateFlags {
+        match self {
+            Self::Flat | Self::Array(_) => vk::ImageCreateFlags::empty(),
+            Self::Cube => vk::ImageCreateFlags::CUBE_COMPATIBLE
+        }
+    }
+}
 
 /// The image settings.
+#[derive(Clone, Copy, Debug)]
 pub struct ImageSettings {
     /// The image format.
     pub format: vk::Format,
 
-    /// The usage flags.
+    /// The usage flags. Add TRANSFER_DST if the image is copied to.
     pub usage: vk::ImageUsageFlags,
 
+    /// The tiling. Use LINEAR for images the host reads directly.
+    pub tiling: vk::ImageTiling,
+
     /// The multisampling flags.
-    pub samples: vk::SampleCountFlags
+    pub samples: vk::SampleCountFlags,
+
+    /// The number of mip levels.
+    pub mip_levels: u32,
+
+    /// The shape of the image.
+    pub kind: ImageKind,
+
+    /// The preferred memory properties. If device-local memory
+    /// runs out, host-visible memory is used as a fallback.
+    pub memory_properties: vk::MemoryPropertyFlags
 }
 
-/// Create an internal image.
+/// Create an internal image, with its memory from the given allocator.
+///
+/// # Safety
+///
+/// The allocator must belong to the device. The image must be destroyed and
+/// its memory freed with the same allocator before the device is.
 pub unsafe fn new_image(
     device: &Device,
+    allocator: &mut Allocator,
     settings: &ImageSettings,
     size: &vk::Extent3D,
     memory_properties: vk::MemoryPropertyFlags
-) -> Result<(vk::Image, vk::DeviceMemory, vk::DeviceSize)> {
-    // Make sure the image is a transfer destination.
-    let usage = settings.usage | vk::ImageUsageFlags::TRANSFER_DST;
+) -> Result<(vk::Image, Allocation)> {
+    // Make sure the device supports images like this.
+    let flags = settings.kind.create_flags();
+    let format_properties =
+        device.image_format_properties(settings.format, settings.tiling, settings.usage, flags)?;
+
+    if size.width > format_properties.max_extent.width
+        || size.height > format_properties.max_extent.height
+    {
+        return Err(anyhow!(
+            "{:?} images can be at most {}x{}, not {}x{}.",
+            settings.format,
+            format_properties.max_extent.width,
+            format_properties.max_extent.height,
+            size.width,
+            size.height
+        ));
+    }
+
+    if settings.mip_levels > format_properties.max_mip_levels
+        || settings.kind.array_layers() == 0
+        || settings.kind.array_layers() > format_properties.max_array_layers
+        || !format_properties
+            .sample_counts
+            .contains(settings.samples)
+    {
+        return Err(anyhow!(
+            "{:?} images don't support {} mip levels, {} layers, and {:?} samples.",
+            settings.format,
+            settings.mip_levels,
+            settings.kind.array_layers(),
+            settings.samples
+        ));
+    }
 
     // Create the image info.
     let image_info = vk::ImageCreateInfo::default()
+        .flags(flags)
         .image_type(vk::ImageType::TYPE_2D)
         .extent(*size)
-        .mip_levels(1)
-        .array_layers(1)
+        .mip_levels(settings.mip_levels)
+        .array_layers(settings.kind.array_layers())
         .format(settings.format)
-        .tiling(vk::ImageTiling::OPTIMAL)
+        .tiling(settings.tiling)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .usage(usage)
+        .usage(settings.usage)
         .sharing_mode(vk::SharingMode::EXCLUSIVE)
         .samples(settings.samples);
 
     // Create the image.
     let image = device.create_image(&image_info, None)?;
 
+    device.track_resource(ResourceKind::Image);
+
     // Get the image's memory requirements.
     let memory_requirements = device.get_image_memory_requirements(image);
 
-    // Find a suitable memory type.
-    let memory_index = find_memory_type(device, &memory_requirements, memory_properties)?;
+    // Allocate the memory. If we're out of device-local memory,
+    // retry with host-visible memory instead of failing outright.
+    // Linearly tiled images are kept apart from optimally tiled ones.
+    let linear = settings.tiling == vk::ImageTiling::LINEAR;
+
+    let allocation = match allocator.allocate(
+        device,
+        &memory_requirements,
+        memory_properties,
+        linear,
+        "Image"
+    ) {
+        Ok(allocation) => allocation,
+        Err(e) if memory_properties.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL) => {
+            // Swap device-local for host-visible memory.
+            let fallback_properties = (memory_properties & !vk::MemoryPropertyFlags::DEVICE_LOCAL)
+                | vk::MemoryPropertyFlags::HOST_VISIBLE;
 
-    // Create the memory allocation info.
-    let memory_info = vk::MemoryAllocateInfo::default()
-        .allocation_size(memory_requirements.size)
-        .memory_type_index(memory_index);
+            warn!(
+                "Failed to allocate device-local image memory ({}), falling back to {:?}.",
+                e, fallback_properties
+            );
 
-    // Allocate the memory.
-    let memory = device.allocate_memory(&memory_info, None)?;
+            allocator.allocate(
+                device,
+                &memory_requirements,
+                fallback_properties,
+                linear,
+                "Image"
+            )?
+        },
+        Err(e) => return Err(e)
+    };
 
     // Bind the memory to the image.
-    device.bind_image_memory(image, memory, 0)?;
+    device.bind_image_memory(image, allocation.memory(), allocation.offset())?;
 
-    Ok((image, memory, memory_requirements.size))
+    Ok((image, allocation))
 }
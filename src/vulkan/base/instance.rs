@@ -1,20 +1,160 @@
-use crate::Debugging;
+use crate::{DebugLevel, Debugging, DeviceInfo};
 use anyhow::Result;
 use ash::vk;
 use ash_window::enumerate_required_extensions;
 use raw_window_handle::HasDisplayHandle;
-use std::{ffi::CStr, ops::Deref, sync::Arc};
-use tracing::info;
+use std::{
+    ffi::{c_char, CStr},
+    ops::Deref,
+    sync::Arc
+};
+use tracing::{info, warn};
 use winit::window::Window;
 
 /// The Vulkan version we're using.
 pub const VK_VERSION: u32 = vk::make_api_version(0, 1, 3, 0);
 
-/// Wraps a Vulkan instance.
-pub struct Instance(ash::Instance);
+/// The name of the Khronos validation layer.
+pub const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// Whether to enable validation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Validate in debug builds only.
+    #[default]
+    Auto,
+
+    /// Always validate, e.g. to diagnose a crash in a release build.
+    On,
+
+    /// Never validate, e.g. for performance in a debug build.
+    Off
+}
+
+impl ValidationMode {
+    /// Whether validation is enabled in this mode.
+    pub fn enabled(&self) -> bool {
+        match self {
+            Self::Auto => cfg!(debug_assertions),
+            Self::On => true,
+            Self::Off => false
+        }
+    }
+}
+
+/// The validation settings. The extra features make
+/// validation slower, so they're off by default.
+#[derive(Clone, Copy, Debug)]
+pub struct ValidationSettings {
+    /// Whether to enable validation.
+    pub mode: ValidationMode,
+
+    /// Instrument shaders to catch errors that can only be seen on the
+    /// GPU, e.g. out of bounds descriptor indexing.
+    pub gpu_assisted: bool,
+
+    /// Warn about valid usage that's likely to perform badly.
+    pub best_practices: bool,
+
+    /// The minimum severity of the messages to report.
+    pub debug_level: DebugLevel,
+
+    /// The types of messages to report.
+    pub message_types: vk::DebugUtilsMessageTypeFlagsEXT,
+
+    /// Collect the reported messages so they can be checked, e.g. by a
+    /// test that fails if validation reported any errors.
+    pub collect_messages: bool
+}
+
+impl Default for ValidationSettings {
+    fn default() -> Self {
+        Self {
+            mode:             ValidationMode::default(),
+            gpu_assisted:     false,
+            best_practices:   false,
+            debug_level:      DebugLevel::default(),
+            message_types:    vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            collect_messages: false
+        }
+    }
+}
+
+impl ValidationSettings {
+    /// The validation features to enable.
+    fn enabled_features(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut features = Vec::new();
+
+        if self.gpu_assisted {
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+
+        if self.best_practices {
+            features.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+        }
+
+        features
+    }
+}
+
+/// Wraps a Vulkan instance. The instance is destroyed when it's
+/// dropped, if it wasn't already, so it must be dropped last.
+pub struct Instance {
+    /// The instance.
+    instance: ash::Instance,
+
+    /// Whether validation is enabled. This also enables the debug utils
+    /// extension, which the validation messages are reported through.
+    validation: bool,
+
+    /// Whether the swapchain color space extension is enabled,
+    /// which adds the HDR color spaces.
+    swapchain_colorspace: bool,
+
+    /// Whether the instance was destroyed.
+    destroyed: bool
+}
 
 impl Instance {
-    pub unsafe fn new(window: Arc<Window>, entry: &ash::Entry) -> Result<Self> {
+    /// Create a new instance that can present to the window.
+    ///
+    /// # Safety
+    ///
+    /// The entry must stay loaded for as long as the instance lives.
+    pub unsafe fn new(
+        window: Arc<Window>,
+        entry: &ash::Entry,
+        validation: &ValidationSettings
+    ) -> Result<Self> {
+        // The extensions required to create a surface for the window.
+        let surface_extensions =
+            enumerate_required_extensions(window.display_handle()?.as_raw())?.to_vec();
+
+        Self::make(entry, surface_extensions, validation)
+    }
+
+    /// Create an instance for headless rendering. No surface
+    /// extensions are enabled, so no window is needed.
+    ///
+    /// # Safety
+    ///
+    /// The entry must stay loaded for as long as the instance lives.
+    pub unsafe fn new_headless(
+        entry: &ash::Entry,
+        validation: &ValidationSettings
+    ) -> Result<Self> {
+        Self::make(entry, Vec::new(), validation)
+    }
+
+    /// Create the instance with the given surface extensions.
+    unsafe fn make(
+        entry: &ash::Entry,
+        surface_extensions: Vec<*const c_char>,
+        validation: &ValidationSettings
+    ) -> Result<Self> {
         let name = CStr::from_bytes_with_nul(b"vulkan-renderer\0")?;
 
         // Create the application info.
@@ -32,11 +172,54 @@ impl Instance {
             vk::InstanceCreateFlags::default()
         };
 
-        // The required extensions. The initial extensions come from the
-        // ash_window crate. macOS requires the portability extension.
+        // The layers. The validation layer is only enabled if it's
+        // installed, otherwise we'd fail to create the instance.
+        let layers = match validation.mode.enabled() {
+            true if Self::has_layer(entry, VALIDATION_LAYER)? => vec![VALIDATION_LAYER.as_ptr()],
+            true => {
+                warn!(
+                    "Validation was requested but {:?} is not installed.",
+                    VALIDATION_LAYER
+                );
+
+                vec![]
+            },
+            false => vec![]
+        };
+
+        // The validation features. These are configured through an
+        // extension that the validation layer provides.
+        let validation_features = match layers.is_empty() {
+            true => vec![],
+            false => validation.enabled_features()
+        };
+
+        let validation_features = match validation_features.is_empty()
+            || Self::has_layer_extension(
+                entry,
+                VALIDATION_LAYER,
+                ash::ext::validation_features::NAME
+            )? {
+            true => validation_features,
+            false => {
+                warn!("Validation features were requested but are not supported.");
+
+                vec![]
+            }
+        };
+
+        // Whether we're validating.
+        let enabled = validation.mode.enabled();
+
+        // HDR swapchains need extended color spaces. We enable
+        // them if we're presenting and the loader has them.
+        let swapchain_colorspace = !surface_extensions.is_empty()
+            && Self::has_extension(entry, ash::ext::swapchain_colorspace::NAME)?;
+
+        // The required extensions. The initial extensions are the surface
+        // extensions, if any. macOS requires the portability extension.
         let required_extensions = {
-            let mut extensions =
-                enumerate_required_extensions(window.display_handle()?.as_raw())?.to_vec();
+            let mut extensions = surface_extensions;
 
             // This is required on macOS.
             if cfg!(target_os = "macos") {
@@ -44,15 +227,32 @@ impl Instance {
                 extensions.push(ash::khr::get_physical_device_properties2::NAME.as_ptr());
             }
 
-            // If we're in debug mode, add the extension that
+            // If we're validating, add the extension that
             // allows us to print validation layer messages.
-            if cfg!(debug_assertions) {
+            if enabled {
                 extensions.push(ash::ext::debug_utils::NAME.as_ptr());
             }
 
+            // The extension that turns on the validation features.
+            if !validation_features.is_empty() {
+                extensions.push(ash::ext::validation_features::NAME.as_ptr());
+            }
+
+            // The extension that adds the HDR color spaces.
+            if swapchain_colorspace {
+                extensions.push(ash::ext::swapchain_colorspace::NAME.as_ptr());
+            }
+
             extensions
         };
 
+        // Print the layers.
+        for layer in &layers {
+            let layer = CStr::from_ptr(*layer);
+
+            info!("Instance layer: {:?}", layer);
+        }
+
         // Print the required extensions.
         for extension in &required_extensions {
             let extension = CStr::from_ptr(*extension);
@@ -64,25 +264,131 @@ impl Instance {
         let mut instance_info = vk::InstanceCreateInfo::default()
             .flags(instance_flags)
             .application_info(&app_info)
+            .enabled_layer_names(&layers)
             .enabled_extension_names(&required_extensions);
 
         // This has to live as long as the instance_info.
-        let mut messenger_info = Debugging::messenger_info();
+        let mut messenger_info =
+            Debugging::messenger_info(validation.debug_level, validation.message_types);
 
         // Capture messages for instance functions.
-        if cfg!(debug_assertions) {
+        if enabled {
             instance_info = instance_info.push_next(&mut messenger_info);
         }
 
+        // This has to live as long as the instance_info.
+        let mut validation_features_info =
+            vk::ValidationFeaturesEXT::default().enabled_validation_features(&validation_features);
+
+        // Turn on the validation features.
+        if !validation_features.is_empty() {
+            info!("Validation features: {:?}", validation_features);
+
+            instance_info = instance_info.push_next(&mut validation_features_info);
+        }
+
         // Create the instance.
         let instance = entry.create_instance(&instance_info, None)?;
 
-        Ok(Self(instance))
+        Ok(Self {
+            instance,
+            validation: enabled,
+            swapchain_colorspace,
+            destroyed: false
+        })
+    }
+
+    /// Returns true if the instance layer is available.
+    unsafe fn has_layer(entry: &ash::Entry, name: &CStr) -> Result<bool> {
+        let layers = entry.enumerate_instance_layer_properties()?;
+
+        Ok(layers
+            .iter()
+            .any(|layer| layer.layer_name_as_c_str() == Ok(name)))
     }
 
-    /// Destroy the instance.
-    pub unsafe fn destroy(&self) {
-        self.0.destroy_instance(None);
+    /// Returns true if the loader provides the instance extension.
+    unsafe fn has_extension(entry: &ash::Entry, extension: &CStr) -> Result<bool> {
+        let extensions = entry.enumerate_instance_extension_properties(None)?;
+
+        Ok(extensions
+            .iter()
+            .any(|properties| properties.extension_name_as_c_str() == Ok(extension)))
+    }
+
+    /// Returns true if the layer provides the instance extension.
+    unsafe fn has_layer_extension(
+        entry: &ash::Entry,
+        layer: &CStr,
+        extension: &CStr
+    ) -> Result<bool> {
+        let extensions = entry.enumerate_instance_extension_properties(Some(layer))?;
+
+        Ok(extensions
+            .iter()
+            .any(|properties| properties.extension_name_as_c_str() == Ok(extension)))
+    }
+
+    /// Returns true if validation is enabled.
+    pub fn validation_enabled(&self) -> bool {
+        self.validation
+    }
+
+    /// Summarize every physical device, in enumeration order, whether or
+    /// not it's suitable. The index of each matches DeviceSelector::Index.
+    ///
+    /// # Safety
+    ///
+    /// The instance must not have been destroyed.
+    pub unsafe fn list_physical_devices(&self) -> Result<Vec<DeviceInfo>> {
+        let physical_devices = self
+            .instance
+            .enumerate_physical_devices()?;
+
+        Ok(physical_devices
+            .iter()
+            .enumerate()
+            .map(|(index, physical_device)| {
+                DeviceInfo::new(
+                    index,
+                    &self
+                        .instance
+                        .get_physical_device_properties(*physical_device),
+                    &self
+                        .instance
+                        .get_physical_device_memory_properties(*physical_device)
+                )
+            })
+            .collect())
+    }
+
+    /// Returns true if HDR color spaces can be requested for swapchains.
+    pub fn swapchain_colorspace_enabled(&self) -> bool {
+        self.swapchain_colorspace
+    }
+
+    /// Destroy the instance. Does nothing if it was already destroyed.
+    ///
+    /// # Safety
+    ///
+    /// The device, surface, and debug messenger created from the instance must
+    /// have been destroyed first.
+    pub unsafe fn destroy(&mut self) {
+        if self.destroyed {
+            return;
+        }
+
+        self.destroyed = true;
+
+        self.instance.destroy_instance(None);
+    }
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy();
+        }
     }
 }
 
@@ -90,6 +396,6 @@ impl Deref for Instance {
     type Target = ash::Instance;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.instance
     }
 }
@@ -1,27 +1,75 @@
+mod allocator;
+mod assets;
+mod bindless_textures;
 mod buffers;
+mod clip_space;
 mod command_pool;
+mod compute_pipeline;
 mod debugging;
+mod deferred_destroy;
+mod descriptors;
 mod device;
+mod error;
 mod frame_buffers;
+mod frame_stats;
+mod headless_renderer;
 mod images;
 mod instance;
 mod memory;
+mod model;
+mod offscreen_target;
 mod pipeline;
+mod pipeline_cache;
+mod query_pool;
+mod queue;
 mod render_pass;
+mod renderable;
 mod renderer;
+mod renderer_builder;
+mod resource_tracker;
+mod sampler;
+mod shader_watcher;
+mod shadow_map;
 mod surface;
 mod swapchain;
+mod threaded_recorder;
+mod timeline_semaphore;
+mod vertex;
 
+pub use allocator::*;
+pub use assets::*;
+pub use bindless_textures::*;
 pub use buffers::*;
+pub use clip_space::*;
 pub use command_pool::*;
+pub use compute_pipeline::*;
 pub use debugging::*;
+pub use deferred_destroy::*;
+pub use descriptors::*;
 pub use device::*;
+pub use error::*;
 pub use frame_buffers::*;
+pub use frame_stats::*;
+pub use headless_renderer::*;
 pub use images::*;
 pub use instance::*;
 pub use memory::*;
+pub use model::*;
+pub use offscreen_target::*;
 pub use pipeline::*;
+pub use pipeline_cache::*;
+pub use query_pool::*;
+pub use queue::*;
 pub use render_pass::*;
+pub use renderable::*;
 pub use renderer::*;
+pub use renderer_builder::*;
+pub use resource_tracker::*;
+pub use sampler::*;
+pub use shader_watcher::*;
+pub use shadow_map::*;
 pub use surface::*;
 pub use swapchain::*;
+pub use threaded_recorder::*;
+pub use timeline_semaphore::*;
+pub use vertex::*;
@@ -0,0 +1,364 @@
+use crate::{Device, ImmutableBuffer, Vertex};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use bytemuck::{cast_slice, Pod, Zeroable};
+use glam::{Vec2, Vec3};
+use std::{
+    collections::HashMap,
+    mem::{offset_of, size_of, size_of_val},
+    path::Path
+};
+
+/// The vertex type of a loaded model.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct ModelVertex {
+    pub position: Vec3,
+    pub normal:   Vec3,
+    pub uv:       Vec2
+}
+
+unsafe impl Zeroable for ModelVertex {}
+unsafe impl Pod for ModelVertex {}
+
+impl Vertex for ModelVertex {
+    fn bindings() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding:    0,
+            stride:     size_of::<ModelVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 0,
+                format:   vk::Format::R32G32B32_SFLOAT,
+                offset:   offset_of!(ModelVertex, position) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 1,
+                format:   vk::Format::R32G32B32_SFLOAT,
+                offset:   offset_of!(ModelVertex, normal) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 2,
+                format:   vk::Format::R32G32_SFLOAT,
+                offset:   offset_of!(ModelVertex, uv) as u32
+            },
+        ]
+    }
+}
+
+impl ModelVertex {
+    /// The key used to deduplicate vertices. Floats aren't hashable,
+    /// so we compare the bit patterns of the components instead.
+    fn key(&self) -> [u32; 8] {
+        [
+            self.position.x.to_bits(),
+            self.position.y.to_bits(),
+            self.position.z.to_bits(),
+            self.normal.x.to_bits(),
+            self.normal.y.to_bits(),
+            self.normal.z.to_bits(),
+            self.uv.x.to_bits(),
+            self.uv.y.to_bits()
+        ]
+    }
+}
+
+/// A model with its vertex and index buffers uploaded to the GPU.
+pub struct Model {
+    /// The vertex buffer.
+    vertices: ImmutableBuffer,
+
+    /// The index buffer, if the indices are stored separately.
+    /// Otherwise they're stored in the vertex buffer.
+    indices: Option<ImmutableBuffer>,
+
+    /// The offset of the indices in the index buffer.
+    index_offset: vk::DeviceSize,
+
+    /// The number of indices.
+    index_count: u32
+}
+
+impl Model {
+    /// Create a new model from vertices and indices.
+    ///
+    /// # Safety
+    ///
+    /// The model must be destroyed before the device is.
+    pub unsafe fn new(device: &Device, vertices: &[ModelVertex], indices: &[u32]) -> Result<Self> {
+        // Create the vertex buffer.
+        let vertices = ImmutableBuffer::new(device, vk::BufferUsageFlags::VERTEX_BUFFER, vertices)?;
+
+        // Create the index buffer.
+        let index_count = indices.len() as u32;
+        let indices = ImmutableBuffer::new(device, vk::BufferUsageFlags::INDEX_BUFFER, indices)?;
+
+        Ok(Self {
+            vertices,
+            indices: Some(indices),
+            index_offset: 0,
+            index_count
+        })
+    }
+
+    /// Create a new model with the vertices and indices in a single buffer.
+    /// The vertices come first and the indices follow, aligned to the size
+    /// of an index. This needs one allocation instead of two.
+    ///
+    /// # Safety
+    ///
+    /// The model must be destroyed before the device is.
+    pub unsafe fn upload_combined(
+        device: &Device,
+        vertices: &[ModelVertex],
+        indices: &[u32]
+    ) -> Result<Self> {
+        // The indices must be aligned to the index size.
+        let index_offset = size_of_val(vertices).next_multiple_of(size_of::<u32>());
+
+        // Pack the vertices and indices into one block of bytes.
+        let mut data = vec![0u8; index_offset + size_of_val(indices)];
+
+        data[..size_of_val(vertices)].copy_from_slice(cast_slice(vertices));
+        data[index_offset..].copy_from_slice(cast_slice(indices));
+
+        // Create the combined buffer.
+        let vertices = ImmutableBuffer::new(
+            device,
+            vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER,
+            &data
+        )?;
+
+        Ok(Self {
+            vertices,
+            indices: None,
+            index_offset: index_offset as vk::DeviceSize,
+            index_count: indices.len() as u32
+        })
+    }
+
+    /// Create a new model from an OBJ file.
+    ///
+    /// # Safety
+    ///
+    /// The model must be destroyed before the device is.
+    pub unsafe fn new_from_obj(device: &Device, path: &Path) -> Result<Self> {
+        // Load the vertices and indices from disk.
+        let (vertices, indices) = Self::load_obj(path)?;
+
+        // Create the model.
+        let model = Self::new(device, &vertices, &indices)?;
+
+        Ok(model)
+    }
+
+    /// Load the vertices and indices of an OBJ file. All meshes in the
+    /// file are merged and identical vertices are deduplicated. Missing
+    /// normals default to the face normal and missing UVs to zero.
+    pub fn load_obj(path: &Path) -> Result<(Vec<ModelVertex>, Vec<u32>)> {
+        // Load the file, triangulating all faces.
+        let (models, _materials) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                triangulate: true,
+                ignore_points: true,
+                ignore_lines: true,
+                ..Default::default()
+            }
+        )?;
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut unique = HashMap::new();
+
+        for model in &models {
+            let mesh = &model.mesh;
+
+            // Every face must be a triangle.
+            if mesh.indices.len() % 3 != 0 {
+                return Err(anyhow!(
+                    "Mesh {} has {} indices, which is not a multiple of 3.",
+                    model.name,
+                    mesh.indices.len()
+                ));
+            }
+
+            // Normals and uvs must be given for every corner of every face, or not at all.
+            for (name, attribute_indices) in [
+                ("normal", &mesh.normal_indices),
+                ("uv", &mesh.texcoord_indices)
+            ] {
+                if !attribute_indices.is_empty() && attribute_indices.len() != mesh.indices.len() {
+                    return Err(anyhow!(
+                        "Mesh {} has {} {} indices but {} position indices.",
+                        model.name,
+                        attribute_indices.len(),
+                        name,
+                        mesh.indices.len()
+                    ));
+                }
+            }
+
+            for face in 0..mesh.indices.len() / 3 {
+                // Read the positions of the face up front. We need
+                // them all to compute the face normal if necessary.
+                let positions = [0, 1, 2].map(|corner| {
+                    let index = mesh.indices[face * 3 + corner] as usize;
+
+                    Vec3::from_slice(&mesh.positions[index * 3..index * 3 + 3])
+                });
+
+                // The face normal.
+                let face_normal = (positions[1] - positions[0])
+                    .cross(positions[2] - positions[0])
+                    .normalize_or_zero();
+
+                for (corner, position) in positions.into_iter().enumerate() {
+                    let i = face * 3 + corner;
+
+                    // Use the normal if the file has one.
+                    let normal = match mesh.normal_indices.get(i) {
+                        Some(index) => {
+                            let index = *index as usize;
+
+                            Vec3::from_slice(&mesh.normals[index * 3..index * 3 + 3])
+                        },
+                        None => face_normal
+                    };
+
+                    // Use the uv if the file has one. OBJ's v axis points
+                    // up while Vulkan's points down, so we flip it.
+                    let uv = match mesh.texcoord_indices.get(i) {
+                        Some(index) => {
+                            let index = *index as usize;
+
+                            Vec2::new(
+                                mesh.texcoords[index * 2],
+                                1.0 - mesh.texcoords[index * 2 + 1]
+                            )
+                        },
+                        None => Vec2::ZERO
+                    };
+
+                    let vertex = ModelVertex {
+                        position,
+                        normal,
+                        uv
+                    };
+
+                    // Reuse the vertex if we've seen it before.
+                    let index = *unique
+                        .entry(vertex.key())
+                        .or_insert_with(|| {
+                            vertices.push(vertex);
+
+                            (vertices.len() - 1) as u32
+                        });
+
+                    indices.push(index);
+                }
+            }
+        }
+
+        Ok((vertices, indices))
+    }
+
+    /// Returns the vertex buffer.
+    pub fn vertices(&self) -> &ImmutableBuffer {
+        &self.vertices
+    }
+
+    /// Returns the index buffer. This is the vertex
+    /// buffer if the model was uploaded combined.
+    pub fn indices(&self) -> &ImmutableBuffer {
+        self.indices
+            .as_ref()
+            .unwrap_or(&self.vertices)
+    }
+
+    /// Returns the offset of the indices in the index buffer.
+    pub fn index_offset(&self) -> vk::DeviceSize {
+        self.index_offset
+    }
+
+    /// Returns the number of indices.
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    /// Bind the buffers and draw the model.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside a render pass, with a
+    /// pipeline bound whose vertex layout matches the model.
+    pub unsafe fn draw(&self, device: &Device, command_buffer: &vk::CommandBuffer) {
+        // Bind the buffers.
+        self.bind(device, command_buffer);
+
+        // Issue the draw command.
+        device.cmd_draw_indexed(*command_buffer, self.index_count, 1, 0, 0, 0);
+    }
+
+    /// Bind the buffers and draw the model with parameters read from an
+    /// indirect buffer, e.g. one created with ImmutableBuffer::new_indirect.
+    /// The stride is the distance between commands in bytes.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside a render pass, with a
+    /// pipeline bound whose vertex layout matches the model. The indirect
+    /// buffer must hold the draws at the given offset and stride.
+    pub unsafe fn draw_indirect(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        indirect_buffer: &vk::Buffer,
+        offset: vk::DeviceSize,
+        draw_count: u32,
+        stride: u32
+    ) {
+        // Bind the buffers.
+        self.bind(device, command_buffer);
+
+        // Issue the draw commands.
+        device.draw_indexed_indirect(command_buffer, indirect_buffer, offset, draw_count, stride);
+    }
+
+    /// Bind the vertex and index buffers.
+    unsafe fn bind(&self, device: &Device, command_buffer: &vk::CommandBuffer) {
+        // Bind the vertex buffer.
+        device.cmd_bind_vertex_buffers(*command_buffer, 0, &[*self.vertices], &[0]);
+
+        // Bind the index buffer.
+        device.cmd_bind_index_buffer(
+            *command_buffer,
+            **self.indices(),
+            self.index_offset,
+            vk::IndexType::UINT32
+        );
+    }
+
+    /// Destroy the model.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the model's buffers.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the index buffer.
+        if let Some(indices) = &mut self.indices {
+            indices.destroy(device);
+        }
+
+        // Destroy the vertex buffer.
+        self.vertices.destroy(device);
+    }
+}
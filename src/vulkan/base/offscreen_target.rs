@@ -0,0 +1,312 @@
+use crate::{AttachmentImage, Device, FrameBuffers, ImageKind, ImageSettings, RenderPass};
+use anyhow::{anyhow, Result};
+use ash::vk;
+
+/// The subresource range of a single color image.
+const COLOR_SUBRESOURCE_RANGE: vk::ImageSubresourceRange = vk::ImageSubresourceRange {
+    aspect_mask:      vk::ImageAspectFlags::COLOR,
+    base_mip_level:   0,
+    level_count:      1,
+    base_array_layer: 0,
+    layer_count:      1
+};
+
+/// The subresource layers of a single color image.
+const COLOR_SUBRESOURCE_LAYERS: vk::ImageSubresourceLayers = vk::ImageSubresourceLayers {
+    aspect_mask:      vk::ImageAspectFlags::COLOR,
+    mip_level:        0,
+    base_array_layer: 0,
+    layer_count:      1
+};
+
+/// An offscreen color target that the scene is rendered to at a fixed
+/// internal resolution, independent of the window size. Each frame it's
+/// blitted to the acquired swapchain image, scaling it to fit. This is
+/// the place to hook in post-processing.
+pub struct OffscreenTarget {
+    /// The internal resolution.
+    extent: vk::Extent2D,
+
+    /// The render pass. It ends with the target in the transfer source layout.
+    render_pass: RenderPass,
+
+    /// The multisampled color image, if multisampling.
+    color_image: Option<AttachmentImage>,
+
+    /// The image we render to and blit from.
+    target_image: AttachmentImage,
+
+    /// The frame buffers wrapper. There's only one.
+    frame_buffers: FrameBuffers
+}
+
+impl OffscreenTarget {
+    /// Create a new offscreen target. Use the swapchain's format and the
+    /// main render pass's sample count so that pipelines built for the
+    /// main render pass are compatible with this one.
+    ///
+    /// # Safety
+    ///
+    /// The target must be destroyed before the device is.
+    pub unsafe fn new(
+        device: &Device,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        samples: vk::SampleCountFlags
+    ) -> Result<Self> {
+        // The format must support being blitted from and to with linear filtering.
+        let required_features = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+
+        if !device
+            .format_properties(format)
+            .optimal_tiling_features
+            .contains(required_features)
+        {
+            return Err(anyhow!(
+                "Format {:?} can't be blitted with linear filtering.",
+                format
+            ));
+        }
+
+        // Create the render pass wrapper.
+        let render_pass = RenderPass::new_with_format(
+            device,
+            format,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            samples
+        )?;
+
+        // Create the multisampled color image.
+        let color_image = match render_pass.samples() {
+            vk::SampleCountFlags::TYPE_1 => None,
+            samples => Some(AttachmentImage::new(
+                device,
+                &ImageSettings {
+                    format,
+                    usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                    tiling: vk::ImageTiling::OPTIMAL,
+                    samples,
+                    mip_levels: 1,
+                    kind: ImageKind::Flat,
+                    memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+                },
+                &extent,
+                vk::ImageAspectFlags::COLOR
+            )?)
+        };
+
+        // Create the image we render to. It's sampleable so that
+        // post-processing passes can read it in their shaders.
+        let target_image = AttachmentImage::new(
+            device,
+            &ImageSettings {
+                format,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::SAMPLED,
+                tiling: vk::ImageTiling::OPTIMAL,
+                samples: vk::SampleCountFlags::TYPE_1,
+                mip_levels: 1,
+                kind: ImageKind::Flat,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+            },
+            &extent,
+            vk::ImageAspectFlags::COLOR
+        )?;
+
+        // Name the images for debugging.
+        if let Some(color_image) = &color_image {
+            device.set_object_name(**color_image, "Offscreen MSAA color image");
+        }
+
+        device.set_object_name(*target_image, "Offscreen target image");
+
+        // Create the frame buffers wrapper. The multisampled color
+        // image, if any, comes first and is resolved to the target.
+        let frame_buffers = FrameBuffers::new_from_views(
+            device,
+            &[*target_image.view()],
+            &extent,
+            &render_pass,
+            |_, view| match &color_image {
+                Some(color_image) => vec![*color_image.view(), view],
+                None => vec![view]
+            }
+        )?;
+
+        Ok(Self {
+            extent,
+            render_pass,
+            color_image,
+            target_image,
+            frame_buffers
+        })
+    }
+
+    /// Returns the internal resolution.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Returns the render pass.
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    /// Set the values the render pass clears the attachments to, see
+    /// RenderPass::set_clear_values.
+    pub fn set_clear_values(&mut self, clear_values: Vec<vk::ClearValue>) -> Result<()> {
+        self.render_pass
+            .set_clear_values(clear_values)
+    }
+
+    /// Returns the frame buffers wrapper. Begin the render pass with index 0.
+    pub fn frame_buffers(&self) -> &FrameBuffers {
+        &self.frame_buffers
+    }
+
+    /// Returns the image we render to.
+    pub fn target_image(&self) -> &AttachmentImage {
+        &self.target_image
+    }
+
+    /// Wait for the previous frame's blit to finish reading the target
+    /// before the render pass writes to it again. Record this before
+    /// beginning the render pass. The render pass's own dependency
+    /// chains with this one to cover its layout transition.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording outside of a render pass.
+    pub unsafe fn cmd_wait_for_blit(&self, device: &Device, command_buffer: &vk::CommandBuffer) {
+        device.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[]
+        );
+    }
+
+    /// Blit the target to a swapchain image, scaling it to the given
+    /// extent. Record this after ending the render pass. The swapchain
+    /// image must allow transfer writes, and it ends up ready to present.
+    /// The submission should wait for the image to be acquired at the
+    /// transfer stage, which the layout transition is chained to.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording outside of a render pass, after the
+    /// target's render pass has ended. The swapchain image must have been
+    /// acquired and allow transfer writes.
+    pub unsafe fn cmd_blit(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        image: vk::Image,
+        extent: &vk::Extent2D
+    ) {
+        // Wait for the render pass to finish writing the target, and move
+        // the swapchain image to a layout we can blit to. Its contents
+        // are about to be overwritten, so the old layout doesn't matter.
+        device.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[
+                vk::ImageMemoryBarrier::default()
+                    .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(*self.target_image)
+                    .subresource_range(COLOR_SUBRESOURCE_RANGE),
+                vk::ImageMemoryBarrier::default()
+                    .old_layout(vk::ImageLayout::UNDEFINED)
+                    .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .src_access_mask(vk::AccessFlags::empty())
+                    .dst_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .image(image)
+                    .subresource_range(COLOR_SUBRESOURCE_RANGE)
+            ]
+        );
+
+        // Blit the whole target to the whole swapchain image.
+        device.cmd_blit_image(
+            *command_buffer,
+            *self.target_image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            &[vk::ImageBlit::default()
+                .src_subresource(COLOR_SUBRESOURCE_LAYERS)
+                .src_offsets(Self::offsets(&self.extent))
+                .dst_subresource(COLOR_SUBRESOURCE_LAYERS)
+                .dst_offsets(Self::offsets(extent))],
+            vk::Filter::LINEAR
+        );
+
+        // Move the swapchain image to the present layout. Presentation
+        // waits on a semaphore, so there's nothing else to wait for.
+        device.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::empty())
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(COLOR_SUBRESOURCE_RANGE)]
+        );
+    }
+
+    /// The corners of a blit region covering the given extent.
+    fn offsets(extent: &vk::Extent2D) -> [vk::Offset3D; 2] {
+        [
+            vk::Offset3D::default(),
+            vk::Offset3D {
+                x: extent.width as i32,
+                y: extent.height as i32,
+                z: 1
+            }
+        ]
+    }
+
+    /// Destroy the offscreen target.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the target's image and frame buffers.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the frame buffers.
+        self.frame_buffers.destroy(device);
+
+        // Destroy the images.
+        self.target_image.destroy(device);
+
+        if let Some(color_image) = &mut self.color_image {
+            color_image.destroy(device);
+        }
+
+        // Destroy the render pass.
+        self.render_pass.destroy(device);
+    }
+}
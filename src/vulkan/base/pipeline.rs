@@ -1,8 +1,15 @@
-use crate::{Device, RenderPass};
+use crate::{DeferredDestroyQueue, DeferredResource, Device, PipelineCache, RenderPass, Vertex};
 use anyhow::{anyhow, Result};
 use ash::vk;
-use bytemuck::cast_slice;
-use std::{ffi::CStr, fs::read, ops::Deref, path::PathBuf};
+use bytemuck::{bytes_of, pod_collect_to_vec, Pod};
+use std::{
+    ffi::CString,
+    fs::read,
+    mem::{size_of, take},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc
+};
 
 /// The vertex descriptions.
 pub struct VertexDescriptions {
@@ -13,16 +20,377 @@ pub struct VertexDescriptions {
     pub attributes: Vec<vk::VertexInputAttributeDescription>
 }
 
+impl VertexDescriptions {
+    /// Create the vertex descriptions for a vertex type.
+    pub fn for_vertex<V: Vertex>() -> Self {
+        Self {
+            bindings:   vec![V::bindings()],
+            attributes: V::attributes()
+        }
+    }
+
+    /// Add a per-vertex binding after the existing ones, e.g. to keep
+    /// positions and the other attributes in separate buffers. Bind a
+    /// buffer for every binding, in order, when drawing. The type's
+    /// binding and attribute locations are shifted like with_instances.
+    pub fn with_vertices<V: Vertex>(self) -> Self {
+        self.with_binding::<V>(vk::VertexInputRate::VERTEX)
+    }
+
+    /// Add a per-instance binding after the existing ones. The type's
+    /// binding and attribute locations are shifted to follow the ones
+    /// already described, so every type can number its own from zero.
+    pub fn with_instances<I: Vertex>(self) -> Self {
+        self.with_binding::<I>(vk::VertexInputRate::INSTANCE)
+    }
+
+    /// Add a binding with the given input rate after the existing ones.
+    fn with_binding<I: Vertex>(mut self, input_rate: vk::VertexInputRate) -> Self {
+        // The next free binding.
+        let binding = self.bindings.len() as u32;
+
+        // The next free attribute location.
+        let location = self
+            .attributes
+            .iter()
+            .map(|attribute| attribute.location + 1)
+            .max()
+            .unwrap_or(0);
+
+        self.bindings
+            .push(vk::VertexInputBindingDescription {
+                binding,
+                input_rate,
+                ..I::bindings()
+            });
+
+        self.attributes.extend(
+            I::attributes()
+                .into_iter()
+                .map(|attribute| vk::VertexInputAttributeDescription {
+                    binding,
+                    location: location + attribute.location,
+                    ..attribute
+                })
+        );
+
+        self
+    }
+}
+
+/// How a pipeline blends its output with what's already in the attachment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The output replaces the attachment.
+    #[default]
+    None,
+
+    /// The output is blended by its alpha, for translucent geometry.
+    AlphaBlend,
+
+    /// The output is added to the attachment, e.g. for glows and particles.
+    Additive,
+
+    /// Like alpha blending, but the output color is already multiplied by its alpha.
+    PremultipliedAlpha
+}
+
+impl BlendMode {
+    /// The color blend attachment state for the blend mode.
+    fn attachment_state(self) -> vk::PipelineColorBlendAttachmentState {
+        // The source and destination color factors, or none if blending is off.
+        let color_factors = match self {
+            Self::None => None,
+            Self::AlphaBlend => Some((
+                vk::BlendFactor::SRC_ALPHA,
+                vk::BlendFactor::ONE_MINUS_SRC_ALPHA
+            )),
+            Self::Additive => Some((vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE)),
+            Self::PremultipliedAlpha => {
+                Some((vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA))
+            },
+        };
+
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA);
+
+        match color_factors {
+            Some((src_color_blend_factor, dst_color_blend_factor)) => state
+                .blend_enable(true)
+                .src_color_blend_factor(src_color_blend_factor)
+                .dst_color_blend_factor(dst_color_blend_factor)
+                .color_blend_op(vk::BlendOp::ADD)
+                // Alpha accumulates coverage the same way in every mode.
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .alpha_blend_op(vk::BlendOp::ADD),
+            None => state.blend_enable(false)
+        }
+    }
+}
+
+/// The depth and stencil tests of a pipeline. The render pass
+/// must have a depth stencil attachment for these to apply.
+#[derive(Clone, Copy, Debug)]
+pub struct DepthStencilSettings {
+    /// Whether fragments are tested against the depth buffer.
+    pub depth_test: bool,
+
+    /// Whether passing fragments write their depth.
+    pub depth_write: bool,
+
+    /// How fragment depths are compared to the depth buffer.
+    pub depth_compare_op: vk::CompareOp,
+
+    /// Whether fragments are tested against the stencil buffer. The
+    /// attachment's format must have a stencil component.
+    pub stencil_test: bool,
+
+    /// The stencil operations for front facing polygons.
+    pub front: vk::StencilOpState,
+
+    /// The stencil operations for back facing polygons.
+    pub back: vk::StencilOpState
+}
+
+impl Default for DepthStencilSettings {
+    fn default() -> Self {
+        Self {
+            depth_test:       true,
+            depth_write:      true,
+            depth_compare_op: vk::CompareOp::LESS,
+            stencil_test:     false,
+            front:            vk::StencilOpState::default(),
+            back:             vk::StencilOpState::default()
+        }
+    }
+}
+
+impl DepthStencilSettings {
+    /// Enable the stencil test with the same operations for both faces.
+    pub fn with_stencil(self, stencil: vk::StencilOpState) -> Self {
+        Self {
+            stencil_test: true,
+            front: stencil,
+            back: stencil,
+            ..self
+        }
+    }
+
+    /// Stencil operations that write the reference value wherever the
+    /// geometry is drawn, e.g. to mark an object before outlining it.
+    pub fn stencil_write(reference: u32) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::REPLACE,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::ALWAYS,
+            compare_mask: 0xff,
+            write_mask: 0xff,
+            reference
+        }
+    }
+
+    /// Stencil operations that only draw where the stencil buffer doesn't
+    /// hold the reference value, e.g. to draw an outline around a mark.
+    pub fn stencil_not_equal(reference: u32) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: vk::StencilOp::KEEP,
+            pass_op: vk::StencilOp::KEEP,
+            depth_fail_op: vk::StencilOp::KEEP,
+            compare_op: vk::CompareOp::NOT_EQUAL,
+            compare_mask: 0xff,
+            write_mask: 0x00,
+            reference
+        }
+    }
+
+    /// The depth stencil state create info.
+    fn state_create_info(&self) -> vk::PipelineDepthStencilStateCreateInfo<'static> {
+        vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.depth_test)
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.depth_compare_op)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(self.stencil_test)
+            .front(self.front)
+            .back(self.back)
+    }
+}
+
+/// A bias added to the depth of each fragment. Shadow maps use this
+/// to keep surfaces from shadowing themselves, which shows up as acne.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBias {
+    /// A constant depth added to each fragment, in units of the
+    /// smallest resolvable depth difference.
+    pub constant_factor: f32,
+
+    /// A factor scaled by the fragment's depth slope, so surfaces
+    /// nearly parallel to the light get more bias.
+    pub slope_factor: f32,
+
+    /// The largest bias applied, or zero for no limit. Anything but
+    /// zero needs the depth bias clamp device feature.
+    pub clamp: f32
+}
+
+impl Default for DepthBias {
+    fn default() -> Self {
+        Self {
+            constant_factor: 1.25,
+            slope_factor:    1.75,
+            clamp:           0.0
+        }
+    }
+}
+
+impl DepthBias {
+    /// A bias that pulls geometry towards the viewer, e.g. for decals
+    /// drawn on top of coplanar surfaces, so they don't z-fight. The
+    /// depth compare op must be less or less or equal.
+    pub fn coplanar() -> Self {
+        Self {
+            constant_factor: -1.0,
+            slope_factor:    -1.0,
+            clamp:           0.0
+        }
+    }
+}
+
+/// The value of a specialization constant. Every value is 32 bits
+/// wide, matching int, uint, float, and bool constants in GLSL.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpecValue {
+    /// A signed integer.
+    Int(i32),
+
+    /// An unsigned integer.
+    UInt(u32),
+
+    /// A float.
+    Float(f32),
+
+    /// A bool, which SPIR-V stores as a 32-bit integer.
+    Bool(bool)
+}
+
+impl SpecValue {
+    /// The bytes of the value, as the shader reads them.
+    fn to_ne_bytes(self) -> [u8; 4] {
+        match self {
+            Self::Int(value) => value.to_ne_bytes(),
+            Self::UInt(value) => value.to_ne_bytes(),
+            Self::Float(value) => value.to_ne_bytes(),
+            Self::Bool(value) => vk::Bool32::from(value).to_ne_bytes()
+        }
+    }
+}
+
+/// The specialization constants of a shader stage,
+/// packed into map entries and a blob of data.
+pub struct Specialization {
+    /// The map entries, one per constant.
+    map_entries: Vec<vk::SpecializationMapEntry>,
+
+    /// The values of the constants.
+    data: Vec<u8>
+}
+
+impl Specialization {
+    /// Pack the given constants, keyed by constant id.
+    pub fn new(constants: &[(u32, SpecValue)]) -> Self {
+        let mut map_entries = Vec::with_capacity(constants.len());
+        let mut data = Vec::with_capacity(constants.len() * size_of::<u32>());
+
+        for (constant_id, value) in constants {
+            let bytes = value.to_ne_bytes();
+
+            map_entries.push(vk::SpecializationMapEntry {
+                constant_id: *constant_id,
+                offset:      data.len() as u32,
+                size:        bytes.len()
+            });
+
+            data.extend_from_slice(&bytes);
+        }
+
+        Self { map_entries, data }
+    }
+
+    /// The specialization info to attach to a shader stage.
+    pub fn info(&self) -> vk::SpecializationInfo<'_> {
+        vk::SpecializationInfo::default()
+            .map_entries(&self.map_entries)
+            .data(&self.data)
+    }
+}
+
+/// Where the code of a shader comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShaderSource {
+    /// A file on disk. SPIR-V files end in .spv, anything else is
+    /// compiled from source, which needs the shaderc feature.
+    File(PathBuf),
+
+    /// SPIR-V bytes, e.g. embedded with include_bytes!.
+    Spirv(Vec<u8>),
+
+    /// SPIR-V words, e.g. generated at runtime.
+    SpirvWords(Vec<u32>)
+}
+
+impl ShaderSource {
+    /// Returns the path of the shader if it's a file.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::File(path) => Some(path),
+            _ => None
+        }
+    }
+
+    /// A name for the shader, for debugging.
+    pub fn name(&self) -> String {
+        match self {
+            Self::File(path) => path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            Self::Spirv(_) | Self::SpirvWords(_) => "SPIR-V".to_string()
+        }
+    }
+}
+
+impl From<PathBuf> for ShaderSource {
+    fn from(path: PathBuf) -> Self {
+        Self::File(path)
+    }
+}
+
 /// The pipeline settings.
 pub struct PipelineSettings {
     /// What subpass to render to.
     pub subpass: u32,
 
-    /// The vert shader path.
-    pub vert_shader_path: PathBuf,
+    /// The vert shader.
+    pub vert_shader: ShaderSource,
+
+    /// The frag shader, or none for a depth-only pipeline
+    /// that doesn't write color, e.g. to render a shadow map.
+    pub frag_shader: Option<ShaderSource>,
+
+    /// The entry point of the vert shader, or none for main.
+    pub vert_entry: Option<String>,
+
+    /// The entry point of the frag shader, or none for main.
+    pub frag_entry: Option<String>,
 
-    /// The frag shader path.
-    pub frag_shader_path: PathBuf,
+    /// The specialization constants of the vert shader, keyed by constant id.
+    pub vert_specialization: Vec<(u32, SpecValue)>,
+
+    /// The specialization constants of the frag shader, keyed by constant id.
+    pub frag_specialization: Vec<(u32, SpecValue)>,
 
     /// The vertex descriptions.
     pub vertex_descriptions: Option<VertexDescriptions>,
@@ -30,7 +398,14 @@ pub struct PipelineSettings {
     /// The topology.
     pub topology: vk::PrimitiveTopology,
 
-    /// The polygon mode.
+    /// Whether a special index value restarts the strip or fan, so several
+    /// can be drawn at once. This only applies to indexed draws with strip
+    /// and fan topologies. The value depends on the index type, see
+    /// Pipeline::primitive_restart_index.
+    pub primitive_restart: bool,
+
+    /// The polygon mode. Anything but fill needs
+    /// the fill mode non-solid device feature.
     pub polygon_mode: vk::PolygonMode,
 
     /// The cull mode.
@@ -39,47 +414,134 @@ pub struct PipelineSettings {
     /// The front face.
     pub front_face: vk::FrontFace,
 
+    /// How the output is blended with the color attachments. There's a
+    /// blend state for each color attachment of the subpass, so pipelines
+    /// for multiple render targets blend every target the same way.
+    pub blend: BlendMode,
+
+    /// The number of samples. This must match the render pass.
+    pub samples: vk::SampleCountFlags,
+
+    /// The depth and stencil tests, or none if the render
+    /// pass has no depth stencil attachment.
+    pub depth_stencil: Option<DepthStencilSettings>,
+
+    /// The bias added to fragment depths, or none for no bias.
+    pub depth_bias: Option<DepthBias>,
+
+    /// Whether the depth bias is dynamic state, set per draw with
+    /// Device::set_depth_bias. The bias above is then only used to
+    /// enable it, and must be set before drawing.
+    pub dynamic_depth_bias: bool,
+
     /// The descriptor set layouts.
-    pub descriptor_set_layouts: Option<Vec<vk::DescriptorSetLayout>>
+    pub descriptor_set_layouts: Option<Vec<vk::DescriptorSetLayout>>,
+
+    /// The push constant ranges.
+    pub push_constant_ranges: Vec<vk::PushConstantRange>
 }
 
-/// Wraps a Vulkan pipeline.
+/// Wraps a Vulkan pipeline. Dropping the pipeline without destroying
+/// it defers its destruction to the device's next wait for idle.
 pub struct Pipeline {
     /// The pipeline layout.
     pipeline_layout: vk::PipelineLayout,
 
     /// The pipeline.
-    pipeline: vk::Pipeline
+    pipeline: vk::Pipeline,
+
+    /// Where the pipeline goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
 }
 
 impl Pipeline {
     pub unsafe fn new(
         device: &Device,
+        pipeline_cache: &PipelineCache,
         render_pass: &RenderPass,
         settings: &PipelineSettings
     ) -> Result<Self> {
-        // Create the shaders.
-        let vert_shader = Self::load_shader(device, &settings.vert_shader_path)?;
-        let frag_shader = Self::load_shader(device, &settings.frag_shader_path)?;
+        // Only fill mode is available without the fill mode non-solid feature.
+        if settings.polygon_mode != vk::PolygonMode::FILL && !device.fill_mode_non_solid_enabled() {
+            return Err(anyhow!(
+                "Polygon mode {:?} needs the fill mode non-solid feature.",
+                settings.polygon_mode
+            ));
+        }
+
+        // Primitive restart is only allowed with strips and fans.
+        if settings.primitive_restart && !Self::is_strip_or_fan(settings.topology) {
+            return Err(anyhow!(
+                "Primitive restart needs a strip or fan topology, not {:?}.",
+                settings.topology
+            ));
+        }
 
-        // This is the entry function for the shaders.
-        let shader_entry_name = CStr::from_bytes_with_nul_unchecked(b"main\0");
+        // Clamping the depth bias needs the depth bias clamp feature.
+        if let Some(depth_bias) = &settings.depth_bias {
+            if depth_bias.clamp != 0.0 && !device.depth_bias_clamp_enabled() {
+                return Err(anyhow!(
+                    "Clamping the depth bias needs the depth bias clamp feature."
+                ));
+            }
+        }
+
+        // Create the shaders. Depth-only pipelines have no frag shader.
+        let vert_shader = Self::load_shader(device, &settings.vert_shader)?;
+        let frag_shader = match &settings.frag_shader {
+            Some(frag_shader) => Some(Self::load_shader(device, frag_shader)?),
+            None => None
+        };
+
+        // These are the entry functions for the shaders. They must
+        // outlive the stage create infos that point at them.
+        let vert_entry_name = Self::entry_name(settings.vert_entry.as_deref())?;
+        let frag_entry_name = Self::entry_name(settings.frag_entry.as_deref())?;
+
+        // Pack the specialization constants.
+        let vert_specialization = Specialization::new(&settings.vert_specialization);
+        let frag_specialization = Specialization::new(&settings.frag_specialization);
+        let vert_specialization_info = vert_specialization.info();
+        let frag_specialization_info = frag_specialization.info();
 
         // Setup the shader stage create infos.
-        let shader_stage_create_infos = [
-            vk::PipelineShaderStageCreateInfo::default()
-                .module(vert_shader)
-                .name(&shader_entry_name)
-                .stage(vk::ShaderStageFlags::VERTEX),
-            vk::PipelineShaderStageCreateInfo::default()
-                .module(frag_shader)
-                .name(&shader_entry_name)
-                .stage(vk::ShaderStageFlags::FRAGMENT)
+        let mut shader_stage_create_infos = vec![vk::PipelineShaderStageCreateInfo::default()
+            .module(vert_shader)
+            .name(&vert_entry_name)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .specialization_info(&vert_specialization_info)];
+
+        if let Some(frag_shader) = frag_shader {
+            shader_stage_create_infos.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .module(frag_shader)
+                    .name(&frag_entry_name)
+                    .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .specialization_info(&frag_specialization_info)
+            );
+        }
+
+        // A dynamic depth bias needs the depth bias to be enabled.
+        if settings.dynamic_depth_bias && settings.depth_bias.is_none() {
+            return Err(anyhow!("A dynamic depth bias needs a depth bias."));
+        }
+
+        // The dynamic states. The line width is dynamic so it can change
+        // without rebuilding the pipeline, which means it must be set
+        // before drawing, see Device::set_line_width.
+        let mut dynamic_states = vec![
+            vk::DynamicState::VIEWPORT,
+            vk::DynamicState::SCISSOR,
+            vk::DynamicState::LINE_WIDTH,
         ];
 
+        if settings.dynamic_depth_bias {
+            dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+        }
+
         // Setup the dynamic state create info.
-        let dynamic_state_create_info = vk::PipelineDynamicStateCreateInfo::default()
-            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let dynamic_state_create_info =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
 
         // Setup the vertex input state create info.
         let vertex_input_state_create_info = match &settings.vertex_descriptions {
@@ -90,14 +552,20 @@ impl Pipeline {
         };
 
         // Setup the input assembly state create info.
-        let input_assembly_state_create_info =
-            vk::PipelineInputAssemblyStateCreateInfo::default().topology(settings.topology);
+        let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(settings.topology)
+            .primitive_restart_enable(settings.primitive_restart);
 
         // The pipeline viewport state create info.
         let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::default()
             .viewport_count(1)
             .scissor_count(1);
 
+        // The depth bias. Its factors are ignored when there's none.
+        let depth_bias = settings
+            .depth_bias
+            .unwrap_or_default();
+
         // The rasterization state create info.
         let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::default()
             .depth_clamp_enable(false)
@@ -106,17 +574,26 @@ impl Pipeline {
             .line_width(1.0)
             .cull_mode(settings.cull_mode)
             .front_face(settings.front_face)
-            .depth_bias_enable(false);
+            .depth_bias_enable(settings.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias.constant_factor)
+            .depth_bias_slope_factor(depth_bias.slope_factor)
+            .depth_bias_clamp(depth_bias.clamp);
 
         // The multisample state create info.
         let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::default()
             .sample_shading_enable(false)
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+            .rasterization_samples(settings.samples);
 
-        // The color blend attachment state.
-        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::default()
-            .color_write_mask(vk::ColorComponentFlags::RGBA)
-            .blend_enable(false)];
+        // The depth stencil state create info, if there's a depth stencil attachment.
+        let depth_stencil_state_create_info = settings
+            .depth_stencil
+            .map(|depth_stencil| depth_stencil.state_create_info());
+
+        // The color blend attachment states, one per color attachment
+        // of the subpass. Depth-only subpasses have none.
+        let color_attachment_count = render_pass.color_attachment_count(settings.subpass);
+        let color_blend_attachment_states =
+            vec![settings.blend.attachment_state(); color_attachment_count as usize];
 
         // The color blend state create info.
         let color_blend_state_create_info = vk::PipelineColorBlendStateCreateInfo::default()
@@ -126,13 +603,14 @@ impl Pipeline {
         let pipeline_layout_create_info = match &settings.descriptor_set_layouts {
             Some(set_layouts) => vk::PipelineLayoutCreateInfo::default().set_layouts(set_layouts),
             None => vk::PipelineLayoutCreateInfo::default()
-        };
+        }
+        .push_constant_ranges(&settings.push_constant_ranges);
 
         // Create the pipeline layout.
         let pipeline_layout = device.create_pipeline_layout(&pipeline_layout_create_info, None)?;
 
         // Create the pipeline create info.
-        let pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
+        let mut pipeline_create_info = vk::GraphicsPipelineCreateInfo::default()
             .stages(&shader_stage_create_infos)
             .vertex_input_state(&vertex_input_state_create_info)
             .input_assembly_state(&input_assembly_state_create_info)
@@ -145,46 +623,158 @@ impl Pipeline {
             .render_pass(**render_pass)
             .subpass(settings.subpass);
 
+        if let Some(depth_stencil_state_create_info) = &depth_stencil_state_create_info {
+            pipeline_create_info =
+                pipeline_create_info.depth_stencil_state(depth_stencil_state_create_info);
+        }
+
         // Create the pipeline.
-        let pipeline = match device.create_graphics_pipelines(
-            vk::PipelineCache::null(),
-            &[pipeline_create_info],
-            None
-        ) {
-            Ok(pipelines) => pipelines,
-            _ => return Err(anyhow!("Failed to create graphics pipeline."))
-        }[0];
+        let pipeline =
+            match device.create_graphics_pipelines(**pipeline_cache, &[pipeline_create_info], None)
+            {
+                Ok(pipelines) => pipelines,
+                _ => return Err(anyhow!("Failed to create graphics pipeline."))
+            }[0];
 
         // Destroy the shaders.
         device.destroy_shader_module(vert_shader, None);
-        device.destroy_shader_module(frag_shader, None);
+
+        if let Some(frag_shader) = frag_shader {
+            device.destroy_shader_module(frag_shader, None);
+        }
+
+        // Name the pipeline after its shaders for debugging.
+        let name = match &settings.frag_shader {
+            Some(frag_shader) => {
+                format!("{} + {}", settings.vert_shader.name(), frag_shader.name())
+            },
+            None => settings.vert_shader.name()
+        };
+
+        device.set_object_name(pipeline, &format!("Pipeline ({})", name));
+        device.set_object_name(pipeline_layout, &format!("Pipeline layout ({})", name));
 
         Ok(Self {
             pipeline_layout,
-            pipeline
+            pipeline,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
         })
     }
 
+    /// Rebuild the pipeline, e.g. after its shaders changed. The new pipeline
+    /// is built first, so on failure the current pipeline is left untouched.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be compatible with the one the pipeline is used in.
+    /// This waits for the device to go idle, so no other thread may be
+    /// submitting work meanwhile.
+    pub unsafe fn recreate(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass,
+        settings: &PipelineSettings
+    ) -> Result<()> {
+        // Create the new pipeline.
+        let pipeline = Self::new(device, pipeline_cache, render_pass, settings)?;
+
+        // Wait for the device to finish. We must do this
+        // or the old pipeline may still be in use.
+        device.wait_idle()?;
+
+        // Destroy the old pipeline.
+        self.destroy(device);
+
+        // Swap in the new pipeline.
+        *self = pipeline;
+
+        Ok(())
+    }
+
     /// The pipeline layout.
     pub fn pipeline_layout(&self) -> &vk::PipelineLayout {
         &self.pipeline_layout
     }
 
-    /// Load a shader.
-    unsafe fn load_shader(device: &Device, path: &PathBuf) -> Result<vk::ShaderModule> {
-        // Read the file from disk.
-        let bytes = read(path)?;
+    /// Push constants to the pipeline. The data is written at the given
+    /// offset and must lie within a range declared in the settings.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording, and the data must lie within a
+    /// push constant range of the pipeline for the given stages.
+    pub unsafe fn push_constants<T: Pod>(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        stage_flags: vk::ShaderStageFlags,
+        offset: u32,
+        data: &T
+    ) {
+        device.cmd_push_constants(
+            *command_buffer,
+            self.pipeline_layout,
+            stage_flags,
+            offset,
+            bytes_of(data)
+        );
+    }
 
-        // Error if the SPIR-V shader is not aligned to 4 bytes.
-        if bytes.len() % 4 != 0 {
-            return Err(anyhow!("The SPIR-V shader is not aligned to 4 bytes."));
+    /// The index value that restarts a strip or fan when primitive
+    /// restart is enabled. It's the largest value of the index type.
+    pub fn primitive_restart_index(index_type: vk::IndexType) -> Result<u32> {
+        match index_type {
+            vk::IndexType::UINT16 => Ok(u16::MAX as u32),
+            vk::IndexType::UINT32 => Ok(u32::MAX),
+            vk::IndexType::UINT8_EXT => Ok(u8::MAX as u32),
+            _ => Err(anyhow!(
+                "Index type {:?} can't restart primitives.",
+                index_type
+            ))
         }
+    }
+
+    /// Returns true if the topology is a strip or a fan.
+    fn is_strip_or_fan(topology: vk::PrimitiveTopology) -> bool {
+        matches!(
+            topology,
+            vk::PrimitiveTopology::LINE_STRIP
+                | vk::PrimitiveTopology::TRIANGLE_STRIP
+                | vk::PrimitiveTopology::TRIANGLE_FAN
+                | vk::PrimitiveTopology::LINE_STRIP_WITH_ADJACENCY
+                | vk::PrimitiveTopology::TRIANGLE_STRIP_WITH_ADJACENCY
+        )
+    }
+
+    /// The name of a shader entry point, or main if none is given.
+    pub fn entry_name(name: Option<&str>) -> Result<CString> {
+        let name = name.unwrap_or("main");
 
-        // We must pass the data to Vulkan as u32's.
-        let bytes: &[u32] = cast_slice(&bytes);
+        CString::new(name).map_err(|_| anyhow!("Invalid shader entry point: {:?}", name))
+    }
+
+    /// Load a shader. Precompiled SPIR-V is loaded as is. With the shaderc
+    /// feature, GLSL sources (.vert, .frag, .comp, ..) and HLSL sources
+    /// (.vert.hlsl, .frag.hlsl, ..) are compiled at runtime.
+    pub unsafe fn load_shader(device: &Device, source: &ShaderSource) -> Result<vk::ShaderModule> {
+        // Load the SPIR-V code.
+        let code = match source {
+            ShaderSource::File(path) => match path
+                .extension()
+                .and_then(|x| x.to_str())
+            {
+                Some("spv") => Self::read_spirv(path)?,
+                _ => Self::compile_shader(path)?
+            },
+            ShaderSource::Spirv(bytes) => Self::spirv_words(bytes)?,
+            ShaderSource::SpirvWords(words) => words.clone()
+        };
 
         // Create the shader create info.
-        let shader_create_info = vk::ShaderModuleCreateInfo::default().code(&bytes);
+        let shader_create_info = vk::ShaderModuleCreateInfo::default().code(&code);
 
         // Create the shader.
         let shader = device.create_shader_module(&shader_create_info, None)?;
@@ -192,13 +782,139 @@ impl Pipeline {
         Ok(shader)
     }
 
-    /// Destroy the pipeline.
+    /// The path of the shader to load for a source file. With the shaderc
+    /// feature this is the source itself, otherwise the precompiled SPIR-V.
+    pub fn shader_path(source_path: &Path) -> PathBuf {
+        match cfg!(feature = "shaderc") {
+            true => source_path.to_path_buf(),
+            false => {
+                let mut path = source_path
+                    .as_os_str()
+                    .to_os_string();
+
+                path.push(".spv");
+
+                path.into()
+            }
+        }
+    }
+
+    /// Read precompiled SPIR-V from disk.
+    fn read_spirv(path: &Path) -> Result<Vec<u32>> {
+        // Read the file from disk.
+        let bytes = read(path)?;
+
+        Self::spirv_words(&bytes)
+    }
+
+    /// Convert SPIR-V bytes to the words Vulkan takes.
+    fn spirv_words(bytes: &[u8]) -> Result<Vec<u32>> {
+        // Error if the SPIR-V shader is not aligned to 4 bytes.
+        if !bytes.len().is_multiple_of(4) {
+            return Err(anyhow!("The SPIR-V shader is not aligned to 4 bytes."));
+        }
+
+        // We must pass the data to Vulkan as u32's. The bytes may
+        // not be aligned for u32's, e.g. from include_bytes!.
+        Ok(pod_collect_to_vec(bytes))
+    }
+
+    /// Compile a shader source file to SPIR-V.
+    #[cfg(feature = "shaderc")]
+    fn compile_shader(path: &Path) -> Result<Vec<u32>> {
+        use shaderc::{CompileOptions, Compiler, ShaderKind, SourceLanguage};
+        use std::fs::read_to_string;
+
+        // HLSL files carry the stage in the extension before .hlsl.
+        let (language, stage_path) = match path
+            .extension()
+            .and_then(|x| x.to_str())
+        {
+            Some("hlsl") => (
+                SourceLanguage::HLSL,
+                Path::new(path.file_stem().unwrap_or_default())
+            ),
+            _ => (SourceLanguage::GLSL, path)
+        };
+
+        // Infer the shader kind from the extension.
+        let kind = match stage_path
+            .extension()
+            .and_then(|x| x.to_str())
+        {
+            Some("vert") => ShaderKind::Vertex,
+            Some("frag") => ShaderKind::Fragment,
+            Some("comp") => ShaderKind::Compute,
+            Some("geom") => ShaderKind::Geometry,
+            Some("tesc") => ShaderKind::TessControl,
+            Some("tese") => ShaderKind::TessEvaluation,
+            _ => return Err(anyhow!("Unknown shader stage: {:?}", path))
+        };
+
+        // Read the source from disk.
+        let source = read_to_string(path)?;
+
+        // Create the compiler.
+        let mut compiler =
+            Compiler::new().ok_or_else(|| anyhow!("Failed to create shader compiler."))?;
+
+        // Create the compile options.
+        let mut options =
+            CompileOptions::new().ok_or_else(|| anyhow!("Failed to create compile options."))?;
+
+        options.set_source_language(language);
+
+        // Compile the shader. The error message includes
+        // the file name and line of each error.
+        let artifact = compiler
+            .compile_into_spirv(
+                &source,
+                kind,
+                &path.to_string_lossy(),
+                "main",
+                Some(&options)
+            )
+            .map_err(|e| anyhow!("Failed to compile shader:\n{}", e))?;
+
+        Ok(artifact.as_binary().to_vec())
+    }
+
+    /// Compile a shader source file to SPIR-V.
+    #[cfg(not(feature = "shaderc"))]
+    fn compile_shader(path: &Path) -> Result<Vec<u32>> {
+        Err(anyhow!(
+            "Compiling {:?} requires the shaderc feature. Use a precompiled .spv instead.",
+            path
+        ))
+    }
+
+    /// Destroy the pipeline right away.
     pub unsafe fn destroy(&mut self, device: &Device) {
-        // Destroy the pipeline.
-        device.destroy_pipeline(self.pipeline, None);
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
 
-        // Destroy the pipeline layout.
-        device.destroy_pipeline_layout(self.pipeline_layout, None);
+    /// Take the handles out of the pipeline, leaving it destroyed.
+    /// Returns None if the pipeline was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.pipeline == vk::Pipeline::null() {
+            return None;
+        }
+
+        Some(DeferredResource::Pipeline(
+            take(&mut self.pipeline),
+            take(&mut self.pipeline_layout)
+        ))
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
     }
 }
 
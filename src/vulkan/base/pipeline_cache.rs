@@ -0,0 +1,164 @@
+use crate::Device;
+use anyhow::Result;
+use ash::vk;
+use std::{
+    env::{temp_dir, var_os},
+    fs::{create_dir_all, read, write},
+    mem::size_of,
+    ops::Deref,
+    path::{Path, PathBuf}
+};
+use tracing::{info, warn};
+
+/// The environment variable that overrides where the pipeline cache is.
+pub const CACHE_DIR_VAR: &str = "VULKAN_CACHE_DIR";
+
+/// The name of the pipeline cache file.
+const PIPELINE_CACHE_NAME: &str = "pipeline.cache";
+
+/// Wraps a Vulkan pipeline cache that is persisted to disk. The
+/// cache is loaded when created and written back when destroyed,
+/// so pipelines compile faster on every run after the first.
+pub struct PipelineCache {
+    /// The pipeline cache.
+    pipeline_cache: vk::PipelineCache,
+
+    /// The path of the cache file.
+    path: PathBuf
+}
+
+impl PipelineCache {
+    /// Create a new pipeline cache, loading its initial data from the given
+    /// path. A missing or invalid cache file results in an empty cache.
+    ///
+    /// # Safety
+    ///
+    /// The cache must be destroyed before the device is.
+    pub unsafe fn new(device: &Device, path: &Path) -> Result<Self> {
+        // Load the cache data, ignoring anything we can't use.
+        let data = match read(path) {
+            Ok(data) => match Self::is_compatible(device, &data) {
+                true => data,
+                false => {
+                    warn!("Ignoring incompatible pipeline cache: {:?}", path);
+
+                    Vec::new()
+                }
+            },
+            Err(_) => Vec::new()
+        };
+
+        // Create the pipeline cache. The driver may still reject the
+        // data, in which case we fall back to an empty cache.
+        let pipeline_cache = match device.create_pipeline_cache(
+            &vk::PipelineCacheCreateInfo::default().initial_data(&data),
+            None
+        ) {
+            Ok(pipeline_cache) => pipeline_cache,
+            Err(e) if !data.is_empty() => {
+                warn!("Failed to load pipeline cache {:?}: {}", path, e);
+
+                device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)?
+            },
+            Err(e) => return Err(e.into())
+        };
+
+        info!("Pipeline cache loaded: {} bytes", data.len());
+
+        Ok(Self {
+            pipeline_cache,
+            path: path.to_path_buf()
+        })
+    }
+
+    /// The default path of the cache file. This is in the directory in
+    /// VULKAN_CACHE_DIR if it's set, and otherwise in a directory for this
+    /// crate in the user's cache directory, or the temp directory if there
+    /// is none. It's never next to the assets, which may be read-only.
+    pub fn default_path() -> Result<PathBuf> {
+        // The platform's cache directory.
+        let user_cache_dir = || -> Option<PathBuf> {
+            let home = || var_os("HOME").map(PathBuf::from);
+
+            if cfg!(windows) {
+                var_os("LOCALAPPDATA").map(PathBuf::from)
+            } else if cfg!(target_os = "macos") {
+                home().map(|home| home.join("Library/Caches"))
+            } else {
+                var_os("XDG_CACHE_HOME")
+                    .map(PathBuf::from)
+                    .or_else(|| home().map(|home| home.join(".cache")))
+            }
+        };
+
+        // The directory from the environment, or ours in the cache directory.
+        let dir = match var_os(CACHE_DIR_VAR) {
+            Some(dir) => PathBuf::from(dir),
+            None => user_cache_dir()
+                .unwrap_or_else(temp_dir)
+                .join(env!("CARGO_PKG_NAME"))
+        };
+
+        // Make sure the directory exists so the cache can be written.
+        create_dir_all(&dir)?;
+
+        Ok(dir.join(PIPELINE_CACHE_NAME))
+    }
+
+    /// Whether the cache data was created by this device and driver. We check
+    /// the header ourselves since not every driver handles bad data gracefully.
+    fn is_compatible(device: &Device, data: &[u8]) -> bool {
+        // The size of the version one header.
+        let header_size = 16 + vk::UUID_SIZE;
+
+        if data.len() < header_size {
+            return false;
+        }
+
+        // Read a u32 from the header.
+        let read_u32 = |offset: usize| {
+            u32::from_ne_bytes(
+                data[offset..offset + size_of::<u32>()]
+                    .try_into()
+                    .unwrap()
+            )
+        };
+
+        let properties = device.properties();
+
+        read_u32(0) as usize >= header_size
+            && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+            && read_u32(8) == properties.vendor_id
+            && read_u32(12) == properties.device_id
+            && data[16..header_size] == properties.pipeline_cache_uuid
+    }
+
+    /// Write the cache data to disk and destroy the pipeline cache.
+    ///
+    /// # Safety
+    ///
+    /// No pipeline may be being created with the cache.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Write the cache data to disk. Failing
+        // to do so only costs us startup time.
+        match device.get_pipeline_cache_data(self.pipeline_cache) {
+            Ok(data) => {
+                if let Err(e) = write(&self.path, data) {
+                    warn!("Failed to write pipeline cache {:?}: {}", self.path, e);
+                }
+            },
+            Err(e) => warn!("Failed to get pipeline cache data: {}", e)
+        }
+
+        // Destroy the pipeline cache.
+        device.destroy_pipeline_cache(self.pipeline_cache, None);
+    }
+}
+
+impl Deref for PipelineCache {
+    type Target = vk::PipelineCache;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pipeline_cache
+    }
+}
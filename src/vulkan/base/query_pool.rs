@@ -0,0 +1,172 @@
+use crate::Device;
+use anyhow::{anyhow, Result};
+use ash::vk;
+
+/// Wraps a Vulkan timestamp query pool. Each frame in flight gets
+/// a pair of queries that bracket the work recorded for that frame.
+pub struct QueryPool {
+    /// The query pool.
+    query_pool: vk::QueryPool,
+
+    /// The number of nanoseconds per timestamp tick.
+    timestamp_period: f32,
+
+    /// The mask of valid timestamp bits.
+    timestamp_mask: u64,
+
+    /// Whether the queries for each frame have been written.
+    written: Vec<bool>
+}
+
+impl QueryPool {
+    /// Create a new query pool with a pair of timestamps per frame in flight.
+    ///
+    /// # Safety
+    ///
+    /// The query pool must be destroyed before the device is.
+    pub unsafe fn new(device: &Device, frames_in_flight: u32) -> Result<Self> {
+        // The graphics queue must support timestamps.
+        if !Self::is_supported(device) {
+            return Err(anyhow!("Timestamps are not supported."));
+        }
+
+        // Create the query pool with a pair of queries per frame.
+        let query_pool = device.create_query_pool(
+            &vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(frames_in_flight * 2),
+            None
+        )?;
+
+        // Only the low bits of each timestamp are valid.
+        let timestamp_valid_bits = device
+            .queue_family_properties()
+            .timestamp_valid_bits;
+        let timestamp_mask = match timestamp_valid_bits {
+            64 => u64::MAX,
+            bits => (1 << bits) - 1
+        };
+
+        Ok(Self {
+            query_pool,
+            timestamp_period: device
+                .properties()
+                .limits
+                .timestamp_period,
+            timestamp_mask,
+            written: vec![false; frames_in_flight as usize]
+        })
+    }
+
+    /// Whether the graphics queue supports timestamps.
+    pub fn is_supported(device: &Device) -> bool {
+        device
+            .queue_family_properties()
+            .timestamp_valid_bits
+            > 0
+    }
+
+    /// Write the starting timestamp of a frame.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording on the graphics queue, and the
+    /// index must be less than the number of frames in flight.
+    pub unsafe fn begin(
+        &mut self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) {
+        let first_query = per_frame_index as u32 * 2;
+
+        // Reset the queries before reusing them.
+        device.cmd_reset_query_pool(*command_buffer, self.query_pool, first_query, 2);
+
+        // Write the starting timestamp.
+        device.cmd_write_timestamp(
+            *command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pool,
+            first_query
+        );
+    }
+
+    /// Write the ending timestamp of a frame.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording on the graphics queue, after begin
+    /// was recorded for the same index.
+    pub unsafe fn end(
+        &mut self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) {
+        // Write the ending timestamp.
+        device.cmd_write_timestamp(
+            *command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool,
+            per_frame_index as u32 * 2 + 1
+        );
+
+        self.written[per_frame_index] = true;
+    }
+
+    /// Read the GPU time of the last frame that used this index in
+    /// milliseconds. Call this after waiting for that frame's fence.
+    /// Returns None if the queries haven't been written or aren't ready.
+    ///
+    /// # Safety
+    ///
+    /// The frame that last used the index must have finished, e.g. its fence
+    /// has been waited on.
+    pub unsafe fn elapsed_ms(
+        &self,
+        device: &Device,
+        per_frame_index: usize
+    ) -> Result<Option<f32>> {
+        // The queries must have been written at least once.
+        if !self.written[per_frame_index] {
+            return Ok(None);
+        }
+
+        // Read the timestamps without waiting.
+        let mut timestamps = [0u64; 2];
+
+        match device.get_query_pool_results(
+            self.query_pool,
+            per_frame_index as u32 * 2,
+            &mut timestamps,
+            vk::QueryResultFlags::TYPE_64
+        ) {
+            Ok(()) => {},
+            Err(vk::Result::NOT_READY) => return Ok(None),
+            Err(e) => return Err(e.into())
+        }
+
+        // Convert the ticks to milliseconds.
+        let ticks = (timestamps[1] & self.timestamp_mask)
+            .wrapping_sub(timestamps[0] & self.timestamp_mask)
+            & self.timestamp_mask;
+
+        Ok(Some(ticks as f32 * self.timestamp_period / 1_000_000.0))
+    }
+
+    /// Forget which queries have been written. Call this when
+    /// recorded frames may never have been submitted.
+    pub fn reset(&mut self) {
+        self.written.fill(false);
+    }
+
+    /// Destroy the query pool.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the query pool.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        device.destroy_query_pool(self.query_pool, None);
+    }
+}
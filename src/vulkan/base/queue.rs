@@ -0,0 +1,97 @@
+use crate::{Device, VulkanError};
+use anyhow::Result;
+use ash::vk;
+use std::ops::Deref;
+
+/// The state of a swapchain after presenting to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentStatus {
+    /// The swapchain matches the surface.
+    Optimal,
+
+    /// The image was presented, but the swapchain no longer matches the
+    /// surface exactly and should be recreated when convenient. Some
+    /// platforms report this indefinitely, e.g. for rotated displays.
+    Suboptimal,
+
+    /// The image couldn't be presented, the swapchain must be recreated.
+    OutOfDate
+}
+
+/// Wraps a Vulkan queue and the family it belongs to. Submissions and
+/// presentation go through here, so a lost device is always reported
+/// as VulkanError::DeviceLost.
+#[derive(Clone, Copy, Debug)]
+pub struct Queue {
+    /// The queue.
+    queue: vk::Queue,
+
+    /// The queue family index.
+    family_index: u32
+}
+
+impl Queue {
+    /// Get the first queue of the given family. The
+    /// device must have been created with that queue.
+    ///
+    /// # Safety
+    ///
+    /// The device must have been created with a queue in the family.
+    pub unsafe fn new(device: &ash::Device, family_index: u32) -> Self {
+        Self {
+            queue: device.get_device_queue(family_index, 0),
+            family_index
+        }
+    }
+
+    /// Returns the queue family index.
+    pub fn family_index(&self) -> u32 {
+        self.family_index
+    }
+
+    /// Submit work to the queue, signaling the fence once it's done.
+    ///
+    /// # Safety
+    ///
+    /// The command buffers, semaphores, and fence must belong to the device,
+    /// and the fence must be unsignaled. No other thread may use the queue
+    /// meanwhile.
+    pub unsafe fn submit(
+        &self,
+        device: &Device,
+        submits: &[vk::SubmitInfo],
+        fence: Option<vk::Fence>
+    ) -> Result<()> {
+        device
+            .queue_submit(self.queue, submits, fence.unwrap_or_default())
+            .map_err(|result| VulkanError::from_result(result.into()))
+    }
+
+    /// Present swapchain images. Returns whether
+    /// a swapchain is suboptimal or out of date.
+    ///
+    /// # Safety
+    ///
+    /// The images must have been acquired and rendered, and no other thread may
+    /// use the queue meanwhile.
+    pub unsafe fn present(
+        &self,
+        functions: &ash::khr::swapchain::Device,
+        present_info: &vk::PresentInfoKHR
+    ) -> Result<PresentStatus> {
+        match functions.queue_present(self.queue, present_info) {
+            Ok(false) => Ok(PresentStatus::Optimal),
+            Ok(true) => Ok(PresentStatus::Suboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(PresentStatus::OutOfDate),
+            Err(result) => Err(VulkanError::from_result(result.into()))
+        }
+    }
+}
+
+impl Deref for Queue {
+    type Target = vk::Queue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.queue
+    }
+}
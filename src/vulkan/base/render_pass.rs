@@ -1,77 +1,798 @@
-use crate::{Device, FrameBuffers, Swapchain};
-use anyhow::Result;
+use crate::{
+    has_depth_component, has_stencil_component, DeferredDestroyQueue, DeferredResource, Device,
+    FrameBuffers, Swapchain
+};
+use anyhow::{anyhow, Result};
 use ash::vk;
-use std::ops::Deref;
+use std::{mem::take, ops::Deref, sync::Arc};
+use tracing::info;
 
-/// Wraps a Vulkan render pass.
-pub struct RenderPass(vk::RenderPass);
+/// The attachments a subpass uses, by index into the render pass attachments.
+#[derive(Clone, Debug, Default)]
+pub struct SubpassSettings {
+    /// The attachments the subpass reads as input attachments.
+    pub input_attachments: Vec<vk::AttachmentReference>,
 
-impl RenderPass {
-    /// Create a new render pass.
-    pub unsafe fn new(device: &Device, swapchain: &Swapchain) -> Result<Self> {
-        // Get the swapchain's format.
-        let format = swapchain.format();
+    /// The attachments the subpass writes color to.
+    pub color_attachments: Vec<vk::AttachmentReference>,
+
+    /// The attachments the color attachments are resolved to. This
+    /// is either empty or has one entry per color attachment.
+    pub resolve_attachments: Vec<vk::AttachmentReference>,
+
+    /// The depth stencil attachment.
+    pub depth_stencil_attachment: Option<vk::AttachmentReference>
+}
+
+/// Builds a render pass from a list of attachments and subpasses. Use
+/// this for render passes with more than one subpass, e.g. when a later
+/// subpass reads an earlier one's output as an input attachment.
+#[derive(Clone, Debug, Default)]
+pub struct RenderPassBuilder {
+    /// The attachments.
+    attachments: Vec<vk::AttachmentDescription>,
+
+    /// The subpasses.
+    subpasses: Vec<SubpassSettings>,
+
+    /// The dependencies between subpasses.
+    dependencies: Vec<vk::SubpassDependency>,
+
+    /// The views every subpass renders, one bit per view. Zero
+    /// means the render pass doesn't use multiview.
+    view_mask: u32
+}
+
+impl RenderPassBuilder {
+    /// Add an attachment. Attachments are referenced in the order they're added.
+    pub fn attachment(mut self, attachment: vk::AttachmentDescription) -> Self {
+        self.attachments.push(attachment);
+
+        self
+    }
+
+    /// Add a subpass. Subpasses are numbered in the order they're added.
+    pub fn subpass(mut self, subpass: SubpassSettings) -> Self {
+        self.subpasses.push(subpass);
+
+        self
+    }
+
+    /// Add a dependency between subpasses.
+    pub fn dependency(mut self, dependency: vk::SubpassDependency) -> Self {
+        self.dependencies.push(dependency);
+
+        self
+    }
+
+    /// Render every subpass to the views in the mask, one bit per view.
+    /// Each view is drawn to the array layer of the same index in every
+    /// attachment, and shaders tell them apart with gl_ViewIndex. For
+    /// example, 0b11 renders the left and right eyes in a single pass.
+    pub fn view_mask(mut self, view_mask: u32) -> Self {
+        self.view_mask = view_mask;
+
+        self
+    }
+
+    /// Add a dependency that makes the color output of one subpass
+    /// readable as an input attachment in a later subpass.
+    pub fn input_dependency(self, src_subpass: u32, dst_subpass: u32) -> Self {
+        self.dependency(vk::SubpassDependency {
+            src_subpass,
+            dst_subpass,
+            src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access_mask: vk::AccessFlags::INPUT_ATTACHMENT_READ,
+            // Each pixel only reads the pixel written at the same place.
+            dependency_flags: vk::DependencyFlags::BY_REGION
+        })
+    }
+
+    /// Build the render pass.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be dropped or destroyed before the device is.
+    pub unsafe fn build(&self, device: &Device, name: &str) -> Result<RenderPass> {
+        if self.subpasses.is_empty() {
+            return Err(anyhow!("A render pass needs at least one subpass."));
+        }
+
+        // Every reference must point at an attachment.
+        let attachment_count = self.attachments.len() as u32;
+
+        for (index, subpass) in self.subpasses.iter().enumerate() {
+            let references = subpass
+                .input_attachments
+                .iter()
+                .chain(&subpass.color_attachments)
+                .chain(&subpass.resolve_attachments)
+                .chain(&subpass.depth_stencil_attachment);
+
+            for reference in references {
+                if reference.attachment != vk::ATTACHMENT_UNUSED
+                    && reference.attachment >= attachment_count
+                {
+                    return Err(anyhow!(
+                        "Subpass {} references attachment {} but there are only {}.",
+                        index,
+                        reference.attachment,
+                        attachment_count
+                    ));
+                }
+            }
+
+            if !subpass
+                .resolve_attachments
+                .is_empty()
+                && subpass.resolve_attachments.len() != subpass.color_attachments.len()
+            {
+                return Err(anyhow!(
+                    "Subpass {} must resolve every color attachment or none.",
+                    index
+                ));
+            }
+        }
+
+        // Multiview needs the device feature and can't have more views than it allows.
+        if self.view_mask != 0 {
+            if !device.multiview_enabled() {
+                return Err(anyhow!("Multiview isn't supported by the device."));
+            }
+
+            let view_count = 32 - self.view_mask.leading_zeros();
+            let max_view_count = device.max_multiview_view_count();
+
+            if view_count > max_view_count {
+                return Err(anyhow!(
+                    "The view mask {:#b} needs {} views but the device allows {}.",
+                    self.view_mask,
+                    view_count,
+                    max_view_count
+                ));
+            }
+        }
+
+        // The subpass descriptions.
+        let subpasses = self
+            .subpasses
+            .iter()
+            .map(|subpass| {
+                let mut description = vk::SubpassDescription::default()
+                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+                    .input_attachments(&subpass.input_attachments)
+                    .color_attachments(&subpass.color_attachments);
+
+                if !subpass
+                    .resolve_attachments
+                    .is_empty()
+                {
+                    description = description.resolve_attachments(&subpass.resolve_attachments);
+                }
+
+                if let Some(depth_stencil_attachment) = &subpass.depth_stencil_attachment {
+                    description = description.depth_stencil_attachment(depth_stencil_attachment);
+                }
+
+                description
+            })
+            .collect::<Vec<_>>();
+
+        // Every subpass renders the same views, and they're all
+        // correlated, so the driver can render them concurrently.
+        let view_masks = vec![self.view_mask; self.subpasses.len()];
+        let correlation_masks = [self.view_mask];
+
+        let mut multiview_info = vk::RenderPassMultiviewCreateInfo::default()
+            .view_masks(&view_masks)
+            .correlation_masks(&correlation_masks);
+
+        // Create the render pass info.
+        let mut render_pass_info = vk::RenderPassCreateInfo::default()
+            .attachments(&self.attachments)
+            .subpasses(&subpasses)
+            .dependencies(&self.dependencies);
+
+        if self.view_mask != 0 {
+            render_pass_info = render_pass_info.push_next(&mut multiview_info);
+        }
 
         // Create the render pass.
-        let render_pass = device.create_render_pass(
-            &vk::RenderPassCreateInfo::default()
-                .attachments(&[vk::AttachmentDescription {
-                    format: format.format,
-                    samples: vk::SampleCountFlags::TYPE_1,
+        let render_pass = device.create_render_pass(&render_pass_info, None)?;
+
+        // Name the render pass for debugging.
+        device.set_object_name(render_pass, name);
+
+        // The samples of the first subpass's first color attachment.
+        let samples = self.subpasses[0]
+            .color_attachments
+            .first()
+            .and_then(|reference| {
+                self.attachments
+                    .get(reference.attachment as usize)
+            })
+            .map_or(vk::SampleCountFlags::TYPE_1, |attachment| {
+                attachment.samples
+            });
+
+        // The values each attachment is cleared to, if it's cleared on load.
+        let clear_values = self
+            .attachments
+            .iter()
+            .map(|attachment| RenderPass::default_clear_value(attachment.format))
+            .collect();
+
+        // The number of color attachments of each subpass.
+        let color_attachment_counts = self
+            .subpasses
+            .iter()
+            .map(|subpass| subpass.color_attachments.len() as u32)
+            .collect();
+
+        Ok(RenderPass {
+            render_pass,
+            attachment_count,
+            subpass_count: self.subpasses.len() as u32,
+            color_attachment_counts,
+            samples,
+            view_mask: self.view_mask,
+            clear_values,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
+        })
+    }
+}
+
+/// Wraps a Vulkan render pass. Dropping the render pass without
+/// destroying it defers its destruction to the device's next wait
+/// for idle.
+pub struct RenderPass {
+    /// The render pass.
+    render_pass: vk::RenderPass,
+
+    /// The number of attachments.
+    attachment_count: u32,
+
+    /// The number of subpasses.
+    subpass_count: u32,
+
+    /// The number of color attachments of each subpass.
+    color_attachment_counts: Vec<u32>,
+
+    /// The number of samples of the color attachment.
+    samples: vk::SampleCountFlags,
+
+    /// The views every subpass renders, or zero without multiview.
+    view_mask: u32,
+
+    /// The values the attachments are cleared to, one per attachment.
+    clear_values: Vec<vk::ClearValue>,
+
+    /// Where the render pass goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
+}
+
+impl RenderPass {
+    /// Create a new render pass. The sample count is clamped to what the
+    /// device supports. With more than one sample, attachment 0 is the
+    /// multisampled color attachment and attachment 1 is the swapchain
+    /// image that it's resolved to. Otherwise, attachment 0 is the
+    /// swapchain image.
+    pub unsafe fn new(
+        device: &Device,
+        swapchain: &Swapchain,
+        samples: vk::SampleCountFlags
+    ) -> Result<Self> {
+        Self::new_with_format(
+            device,
+            swapchain.format().format,
+            vk::ImageLayout::PRESENT_SRC_KHR,
+            samples
+        )
+    }
+
+    /// Create a new render pass that renders to an image of the given format
+    /// instead of a swapchain image. The image ends up in the final layout.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be dropped or destroyed before the device is.
+    pub unsafe fn new_with_format(
+        device: &Device,
+        format: vk::Format,
+        final_layout: vk::ImageLayout,
+        samples: vk::SampleCountFlags
+    ) -> Result<Self> {
+        Self::new_with_views(
+            device,
+            format,
+            final_layout,
+            samples,
+            None,
+            0,
+            "Main render pass"
+        )
+    }
+
+    /// Create a new multiview render pass that renders the given number
+    /// of views at once, e.g. two for stereo. It's laid out like the
+    /// one from new_with_format, followed by a depth attachment if
+    /// there's a depth format, but its attachments must be array
+    /// images with a layer per view. Each draw is broadcast to every
+    /// view, and shaders pick the view's transform with gl_ViewIndex.
+    ///
+    /// # Safety
+    ///
+    /// Multiview must be enabled on the device, and the render pass must be
+    /// dropped or destroyed before the device is.
+    pub unsafe fn new_multiview(
+        device: &Device,
+        format: vk::Format,
+        final_layout: vk::ImageLayout,
+        samples: vk::SampleCountFlags,
+        depth_format: Option<vk::Format>,
+        view_count: u32
+    ) -> Result<Self> {
+        if view_count == 0 || view_count > 32 {
+            return Err(anyhow!(
+                "A multiview render pass can't have {} views.",
+                view_count
+            ));
+        }
+
+        // One bit per view, e.g. 0b11 for two views.
+        let view_mask = u32::MAX >> (32 - view_count);
+
+        Self::new_with_views(
+            device,
+            format,
+            final_layout,
+            samples,
+            depth_format,
+            view_mask,
+            "Multiview render pass"
+        )
+    }
+
+    /// Create a new render pass that renders to an image of the given
+    /// format, tests against a depth attachment if there's a depth
+    /// format, and renders the views in the mask, if it isn't zero.
+    unsafe fn new_with_views(
+        device: &Device,
+        format: vk::Format,
+        final_layout: vk::ImageLayout,
+        samples: vk::SampleCountFlags,
+        depth_format: Option<vk::Format>,
+        view_mask: u32,
+        name: &str
+    ) -> Result<Self> {
+        if let Some(depth_format) = depth_format.filter(|format| !has_depth_component(*format)) {
+            return Err(anyhow!("Format {:?} has no depth component.", depth_format));
+        }
+
+        // Clamp the sample count to what the device supports.
+        let samples = Self::supported_samples(device, samples);
+
+        info!("Render pass samples: {:?}", samples);
+
+        // The swapchain attachment. When multisampling, we only resolve to
+        // it, so we don't care about its contents when the pass begins.
+        let swapchain_attachment = vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: match samples {
+                vk::SampleCountFlags::TYPE_1 => vk::AttachmentLoadOp::CLEAR,
+                _ => vk::AttachmentLoadOp::DONT_CARE
+            },
+            store_op: vk::AttachmentStoreOp::STORE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            final_layout,
+            ..Default::default()
+        };
+
+        // The attachments.
+        let mut attachments = match samples {
+            vk::SampleCountFlags::TYPE_1 => vec![swapchain_attachment],
+            _ => vec![
+                vk::AttachmentDescription {
+                    format,
+                    samples,
                     load_op: vk::AttachmentLoadOp::CLEAR,
-                    store_op: vk::AttachmentStoreOp::STORE,
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
                     stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
                     stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
                     initial_layout: vk::ImageLayout::UNDEFINED,
-                    final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
-                    ..Default::default()
-                }])
-                .subpasses(&[vk::SubpassDescription::default()
-                    .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                    .color_attachments(&[vk::AttachmentReference {
-                        attachment: 0,
-                        layout:     vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
-                    }])])
-                .dependencies(&[vk::SubpassDependency {
-                    src_subpass: vk::SUBPASS_EXTERNAL,
-                    dst_subpass: 0,
-                    src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                    src_access_mask: vk::AccessFlags::empty(),
-                    dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                    dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
                     ..Default::default()
-                }]),
-            None
-        )?;
+                },
+                swapchain_attachment,
+            ]
+        };
+
+        // The depth attachment comes last. It's only used for
+        // testing during the pass, so it doesn't need to be stored.
+        let depth_attachment = depth_format.map(|depth_format| {
+            attachments.push(vk::AttachmentDescription {
+                format: depth_format,
+                samples,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::DONT_CARE,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            });
+
+            vk::AttachmentReference {
+                attachment: attachments.len() as u32 - 1,
+                layout:     vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            }
+        });
+
+        // The subpass renders to attachment 0. When multisampling,
+        // it's resolved to the swapchain image at attachment 1.
+        let subpass = SubpassSettings {
+            color_attachments: vec![vk::AttachmentReference {
+                attachment: 0,
+                layout:     vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            }],
+            resolve_attachments: match samples {
+                vk::SampleCountFlags::TYPE_1 => vec![],
+                _ => vec![vk::AttachmentReference {
+                    attachment: 1,
+                    layout:     vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                }]
+            },
+            depth_stencil_attachment: depth_attachment,
+            ..Default::default()
+        };
+
+        // Wait for the previous frame's color and depth writes, if
+        // there's a depth attachment, before writing them again.
+        let (src_stage_mask, src_access_mask, dst_stage_mask, dst_access_mask) =
+            match depth_attachment {
+                Some(_) => (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                        | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                ),
+                None => (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags::empty(),
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                )
+            };
+
+        // Build the render pass.
+        RenderPassBuilder {
+            attachments,
+            ..Default::default()
+        }
+        .subpass(subpass)
+        .dependency(vk::SubpassDependency {
+            src_subpass: vk::SUBPASS_EXTERNAL,
+            dst_subpass: 0,
+            src_stage_mask,
+            src_access_mask,
+            dst_stage_mask,
+            dst_access_mask,
+            ..Default::default()
+        })
+        .view_mask(view_mask)
+        .build(device, name)
+    }
+
+    /// Create a new render pass with a single subpass that writes to a color
+    /// attachment for each of the given formats at once, e.g. the albedo,
+    /// normal, and position targets of a deferred renderer. The color
+    /// attachments come first, in order, followed by the depth attachment
+    /// if there's a depth format. Every attachment is cleared, and the
+    /// color images end up in the final layout. Writes are made visible
+    /// to fragment shaders that sample the color images afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be dropped or destroyed before the device is.
+    pub unsafe fn new_with_formats(
+        device: &Device,
+        color_formats: &[vk::Format],
+        final_layout: vk::ImageLayout,
+        depth_format: Option<vk::Format>
+    ) -> Result<Self> {
+        if color_formats.is_empty() {
+            return Err(anyhow!("A render pass needs at least one color format."));
+        }
+
+        if let Some(depth_format) = depth_format.filter(|format| !has_depth_component(*format)) {
+            return Err(anyhow!("Format {:?} has no depth component.", depth_format));
+        }
+
+        // The color attachments.
+        let mut builder =
+            color_formats
+                .iter()
+                .fold(RenderPassBuilder::default(), |builder, format| {
+                    builder.attachment(vk::AttachmentDescription {
+                        format: *format,
+                        samples: vk::SampleCountFlags::TYPE_1,
+                        load_op: vk::AttachmentLoadOp::CLEAR,
+                        store_op: vk::AttachmentStoreOp::STORE,
+                        stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                        stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                        initial_layout: vk::ImageLayout::UNDEFINED,
+                        final_layout,
+                        ..Default::default()
+                    })
+                });
+
+        // The depth attachment. It's only used for testing
+        // during the pass, so it doesn't need to be stored.
+        if let Some(depth_format) = depth_format {
+            builder = builder.attachment(vk::AttachmentDescription {
+                format: depth_format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::DONT_CARE,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                ..Default::default()
+            });
+        }
+
+        // The subpass writes every color attachment, and tests against the depth.
+        let color_attachment_count = color_formats.len() as u32;
+
+        let subpass = SubpassSettings {
+            color_attachments: (0..color_attachment_count)
+                .map(|attachment| vk::AttachmentReference {
+                    attachment,
+                    layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                })
+                .collect(),
+            depth_stencil_attachment: depth_format.map(|_| vk::AttachmentReference {
+                attachment: color_attachment_count,
+                layout:     vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+            }),
+            ..Default::default()
+        };
+
+        builder
+            .subpass(subpass)
+            // Wait for earlier reads of the images, and the previous
+            // frame's depth writes, before writing them again.
+            .dependency(vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER
+                    | vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::SHADER_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            })
+            // Finish writing color before later passes sample the images.
+            // They read anywhere in them, so this can't be by region.
+            .dependency(vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                src_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            })
+            .build(device, "Multiple render target pass")
+    }
+
+    /// Create a new depth-only render pass with a single depth attachment
+    /// of the given format and no color, e.g. for a shadow map. The depth
+    /// is cleared, and the image ends up in the final layout. Writes are
+    /// made visible to fragment shaders that sample the image afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be dropped or destroyed before the device is.
+    pub unsafe fn new_depth_only(
+        device: &Device,
+        format: vk::Format,
+        final_layout: vk::ImageLayout
+    ) -> Result<Self> {
+        if !has_depth_component(format) {
+            return Err(anyhow!("Format {:?} has no depth component.", format));
+        }
+
+        RenderPassBuilder::default()
+            .attachment(vk::AttachmentDescription {
+                format,
+                samples: vk::SampleCountFlags::TYPE_1,
+                load_op: vk::AttachmentLoadOp::CLEAR,
+                store_op: vk::AttachmentStoreOp::STORE,
+                stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+                stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                final_layout,
+                ..Default::default()
+            })
+            .subpass(SubpassSettings {
+                depth_stencil_attachment: Some(vk::AttachmentReference {
+                    attachment: 0,
+                    layout:     vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                }),
+                ..Default::default()
+            })
+            // Wait for earlier reads of the image before writing depth.
+            .dependency(vk::SubpassDependency {
+                src_subpass: vk::SUBPASS_EXTERNAL,
+                dst_subpass: 0,
+                src_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                src_access_mask: vk::AccessFlags::SHADER_READ,
+                dst_stage_mask: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+                dst_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                ..Default::default()
+            })
+            // Finish writing depth before later passes sample the image.
+            // They read anywhere in it, so this can't be by region.
+            .dependency(vk::SubpassDependency {
+                src_subpass: 0,
+                dst_subpass: vk::SUBPASS_EXTERNAL,
+                src_stage_mask: vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                dst_stage_mask: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                ..Default::default()
+            })
+            .build(device, "Depth-only render pass")
+    }
+
+    /// The number of attachments.
+    pub fn attachment_count(&self) -> u32 {
+        self.attachment_count
+    }
+
+    /// The number of subpasses.
+    pub fn subpass_count(&self) -> u32 {
+        self.subpass_count
+    }
+
+    /// The number of color attachments of the given subpass.
+    pub fn color_attachment_count(&self, subpass: u32) -> u32 {
+        self.color_attachment_counts
+            .get(subpass as usize)
+            .copied()
+            .unwrap_or(0)
+    }
 
-        Ok(Self(render_pass))
+    /// The number of samples of the color attachment.
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.samples
     }
 
-    /// Begin the render pass.
+    /// The views every subpass renders, one bit per view,
+    /// or zero if the render pass doesn't use multiview.
+    pub fn view_mask(&self) -> u32 {
+        self.view_mask
+    }
+
+    /// The number of views every subpass renders. This
+    /// is one if the render pass doesn't use multiview.
+    pub fn view_count(&self) -> u32 {
+        self.view_mask.count_ones().max(1)
+    }
+
+    /// Set the values the attachments are cleared to, one per
+    /// attachment. Only attachments cleared on load use them.
+    pub fn set_clear_values(&mut self, clear_values: Vec<vk::ClearValue>) -> Result<()> {
+        if clear_values.len() as u32 != self.attachment_count {
+            return Err(anyhow!(
+                "The render pass has {} attachments but {} clear values were given.",
+                self.attachment_count,
+                clear_values.len()
+            ));
+        }
+
+        self.clear_values = clear_values;
+
+        Ok(())
+    }
+
+    /// The default clear value for an attachment of the given format.
+    /// Color is cleared to black, depth to the far plane, and stencil
+    /// to zero.
+    pub fn default_clear_value(format: vk::Format) -> vk::ClearValue {
+        match has_depth_component(format) || has_stencil_component(format) {
+            true => vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth:   1.0,
+                    stencil: 0
+                }
+            },
+            false => vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0]
+                }
+            }
+        }
+    }
+
+    /// Returns the highest sample count supported by the
+    /// device that doesn't exceed the requested one.
+    pub fn supported_samples(
+        device: &Device,
+        requested: vk::SampleCountFlags
+    ) -> vk::SampleCountFlags {
+        // The sample counts the device supports for color attachments.
+        let supported = device
+            .properties()
+            .limits
+            .framebuffer_color_sample_counts;
+
+        [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2
+        ]
+        .into_iter()
+        .find(|samples| samples.as_raw() <= requested.as_raw() && supported.contains(*samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// Begin the render pass. The contents say whether the first subpass
+    /// is recorded inline or in secondary command buffers.
     pub unsafe fn begin(
         &self,
         device: &Device,
-        swapchain: &Swapchain,
+        extent: &vk::Extent2D,
         frame_buffers: &FrameBuffers,
         command_buffer: &vk::CommandBuffer,
-        present_index: u32
+        present_index: u32,
+        contents: vk::SubpassContents
     ) {
-        // The swapchain extent.
-        let extent = swapchain.extent();
-
-        // Create the begin info.
+        // Create the begin info. Attachments that are cleared on
+        // load are cleared to their clear values.
         let begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.0)
+            .render_pass(self.render_pass)
             .framebuffer(frame_buffers[present_index as usize])
-            .render_area(extent.into())
-            .clear_values(&[vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.0, 1.0]
-                }
-            }]);
+            .render_area((*extent).into())
+            .clear_values(&self.clear_values);
 
         // Begin the render pass.
-        device.cmd_begin_render_pass(*command_buffer, &begin_info, vk::SubpassContents::INLINE);
+        device.cmd_begin_render_pass(*command_buffer, &begin_info, contents);
+    }
+
+    /// Move on to the next subpass. The contents say whether it's
+    /// recorded inline or in secondary command buffers.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside this render pass, and not in
+    /// its last subpass.
+    pub unsafe fn next_subpass(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        contents: vk::SubpassContents
+    ) {
+        device.cmd_next_subpass(*command_buffer, contents);
     }
 
     /// End the render pass.
@@ -82,7 +803,28 @@ impl RenderPass {
 
     /// Destroy the render pass.
     pub unsafe fn destroy(&mut self, device: &Device) {
-        device.destroy_render_pass(self.0, None);
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handle out of the render pass, leaving it destroyed.
+    /// Returns None if the render pass was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.render_pass == vk::RenderPass::null() {
+            return None;
+        }
+
+        Some(DeferredResource::RenderPass(take(&mut self.render_pass)))
+    }
+}
+
+impl Drop for RenderPass {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
     }
 }
 
@@ -90,6 +832,6 @@ impl Deref for RenderPass {
     type Target = vk::RenderPass;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.render_pass
     }
 }
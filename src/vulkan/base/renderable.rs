@@ -0,0 +1,50 @@
+use crate::{Device, PipelineCache, RenderPass};
+use anyhow::Result;
+use ash::vk;
+
+/// Something the renderer draws in the main render pass. Renderables are
+/// drawn in the order they're added, after the built-in triangles. They
+/// may be drawn from a recording thread, so they must be Send.
+pub trait Renderable: Send {
+    /// Record the draws for this frame. This is called inside the main
+    /// render pass with the viewport and scissor covering the extent.
+    /// Use the device's set_scissor to clip draws, the scissor is reset
+    /// afterwards. The per-frame index says which frame in flight is
+    /// being recorded.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside the main render pass, and
+    /// the per-frame index must be less than the number of frames in flight.
+    unsafe fn draw(
+        &mut self,
+        device: &Device,
+        extent: &vk::Extent2D,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) -> Result<()>;
+
+    /// Rebuild the pipelines made for the main render pass. This is called
+    /// when the render pass is recreated, e.g. because the swapchain format
+    /// changed. Renderables without pipelines can leave this as is.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be the new main render pass, and the GPU must be
+    /// done with the old pipelines.
+    unsafe fn recreate_pipelines(
+        &mut self,
+        _device: &Device,
+        _pipeline_cache: &PipelineCache,
+        _render_pass: &RenderPass
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Destroy the renderable. The device is idle when this is called.
+    ///
+    /// # Safety
+    ///
+    /// The device must be idle.
+    unsafe fn destroy(&mut self, device: &Device);
+}
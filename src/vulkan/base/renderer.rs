@@ -1,56 +1,125 @@
 use crate::{
-    Debugging, Device, FrameBuffers, Instance, RenderPass, Surface, Swapchain, TriangleRenderer
+    AttachmentImage, ClipSpace, ColorSpacePreference, CommandPool, ComputePresentRenderer,
+    Debugging, Device, FrameBuffers, FrameStats, FrameTimer, ImageCountPolicy, ImageKind,
+    ImageSettings, Instance, OffscreenTarget, PipelineCache, PresentModePreference, PresentStatus,
+    QueryPool, RenderPass, Renderable, RendererBuilder, ShaderWatcher, Surface, Swapchain,
+    TextRenderer, ThreadedRecorder, TimelineSemaphore, TriangleRenderer, VulkanError
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ash::{vk, Entry};
-use std::{cmp::max, path::PathBuf, slice::from_ref, sync::Arc};
-use tracing::{debug, info};
+use glam::{Vec2, Vec3};
+use std::{
+    slice::from_ref,
+    sync::{Arc, Mutex}
+};
+use tracing::{debug, error, info, warn};
 use winit::{dpi::PhysicalSize, window::Window};
 
-/// The maximum number of frames in flight.
-const FRAMES_IN_FLIGHT: u32 = 2;
+/// The debug label color of render passes.
+const PASS_LABEL_COLOR: [f32; 4] = [0.2, 0.4, 0.8, 1.0];
+
+/// The debug label color of draws.
+const DRAW_LABEL_COLOR: [f32; 4] = [0.2, 0.8, 0.4, 1.0];
+
+/// The debug label color of compute work.
+const COMPUTE_LABEL_COLOR: [f32; 4] = [0.8, 0.4, 0.2, 1.0];
+
+/// The number of frames the frame statistics are computed over.
+const FRAME_STATS_WINDOW: usize = 120;
+
+/// The smallest render scale. Below this the scene is a blur.
+const MIN_RENDER_SCALE: f32 = 0.25;
+
+/// The largest render scale. Above this supersampling costs
+/// far more than it's worth.
+const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// What the renderer draws each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Draw the triangle with the graphics pipeline.
+    Triangle,
+
+    /// Write to the swapchain images with a compute shader.
+    ComputePresent
+}
+
+/// The swapchain options that can change while the renderer runs.
+/// See Renderer::set_swapchain_config.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SwapchainConfig {
+    /// Which kind of swapchain format to prefer.
+    pub color_space_preference: ColorSpacePreference,
+
+    /// Which present mode to prefer.
+    pub present_mode_preference: PresentModePreference,
+
+    /// The render scale, if the scene is rendered at a multiple of
+    /// the window's resolution. See Renderer::set_render_scale.
+    pub render_scale: Option<f32>
+}
 
 /// Per-frame data.
 struct PerFrameData {
+    /// The command pool. This is reset as a whole each frame.
+    pub command_pool: CommandPool,
+
     /// The command buffer.
     pub command_buffer: vk::CommandBuffer,
 
-    /// The image ready semaphore.
+    /// The image ready semaphore. This is keyed on the frame rather than
+    /// the swapchain image, as we don't know the image before acquiring.
+    /// The render done semaphores belong to the swapchain images.
     pub semaphore_image_ready: vk::Semaphore,
 
-    /// The render done semaphore.
-    pub semaphore_render_done: vk::Semaphore,
-
-    /// The frame done fence.
-    pub fence_frame_done: vk::Fence
+    /// The frame done fence. This is only used when timeline
+    /// semaphores aren't available.
+    pub fence_frame_done: Option<vk::Fence>
 }
 
 impl PerFrameData {
-    pub unsafe fn new(device: &Device) -> Result<Self> {
-        // Get the command pool.
-        let command_pool = device.command_pool();
+    pub unsafe fn new(device: &Device, index: u32) -> Result<Self> {
+        // Create the command pool.
+        let command_pool = CommandPool::new(
+            device,
+            device.queue_family_index(),
+            vk::CommandPoolCreateFlags::TRANSIENT
+        )?;
 
         // Create the command buffer.
         let command_buffer = command_pool.new_command_buffer(&device, true)?;
 
-        // Create the semaphores.
+        // Create the semaphore.
         let semaphore_image_ready = device.create_semaphore(&Default::default(), None)?;
-        let semaphore_render_done = device.create_semaphore(&Default::default(), None)?;
-
-        // Create the fence. Start in the signaled state so that the first
-        // frame doesn't wait indefinitely for the fence to be signaled.
-        let fence_frame_done = device.create_fence(
-            &vk::FenceCreateInfo {
-                flags: vk::FenceCreateFlags::SIGNALED,
-                ..Default::default()
-            },
-            None
-        )?;
+
+        // Create the fence if we can't use a timeline semaphore. Start in the
+        // signaled state so that the first frame doesn't wait indefinitely.
+        let fence_frame_done = match device.timeline_semaphore_enabled() {
+            true => None,
+            false => Some(device.create_fence(
+                &vk::FenceCreateInfo {
+                    flags: vk::FenceCreateFlags::SIGNALED,
+                    ..Default::default()
+                },
+                None
+            )?)
+        };
+
+        // Name the objects for debugging.
+        device.set_object_name(command_buffer, &format!("Frame {} command buffer", index));
+        device.set_object_name(
+            semaphore_image_ready,
+            &format!("Frame {} image ready", index)
+        );
+
+        if let Some(fence_frame_done) = fence_frame_done {
+            device.set_object_name(fence_frame_done, &format!("Frame {} frame done", index));
+        }
 
         Ok(Self {
+            command_pool,
             command_buffer,
             semaphore_image_ready,
-            semaphore_render_done,
             fence_frame_done
         })
     }
@@ -58,33 +127,36 @@ impl PerFrameData {
     /// Destroy the per-frame data.
     pub unsafe fn destroy(&mut self, device: &Device) {
         // Destroy the fence.
-        device.destroy_fence(self.fence_frame_done, None);
+        if let Some(fence_frame_done) = self.fence_frame_done {
+            device.destroy_fence(fence_frame_done, None);
+        }
 
-        // Destroy the semaphores.
+        // Destroy the semaphore.
         device.destroy_semaphore(self.semaphore_image_ready, None);
-        device.destroy_semaphore(self.semaphore_render_done, None);
+
+        // Destroy the command pool.
+        self.command_pool.destroy(device);
     }
 }
 
-/// The renderer.
+/// The renderer. Fields are dropped in order, so everything made on
+/// the device comes before it, and the instance and window come last.
 pub struct Renderer {
-    /// A handle to the window.
-    window: Arc<Window>,
+    /// The number of frames in flight.
+    frames_in_flight: u32,
 
-    /// The instance wrapper.
-    instance: Instance,
+    /// The swapchain image count policy.
+    image_count_policy: ImageCountPolicy,
 
-    /// The debugging wrapper.
-    debugging: Option<Debugging>,
+    /// The kind of swapchain format to prefer. If a change of preference
+    /// changes the format, the render pass and pipelines are rebuilt.
+    color_space_preference: ColorSpacePreference,
 
-    /// The surface wrapper.
-    surface: Surface,
+    /// The present mode to prefer.
+    present_mode_preference: PresentModePreference,
 
-    /// The device wrapper.
-    device: Device,
-
-    /// The number of frames in flight.
-    frames_in_flight: u32,
+    /// The color the frame is cleared to.
+    clear_color: [f32; 4],
 
     /// The swapchain wrapper.
     swapchain: Swapchain,
@@ -92,42 +164,146 @@ pub struct Renderer {
     /// The render pass wrapper.
     render_pass: RenderPass,
 
+    /// The pipeline cache.
+    pipeline_cache: PipelineCache,
+
+    /// The multisampled color image, if multisampling is enabled.
+    color_image: Option<AttachmentImage>,
+
     /// The frame buffers wrapper.
     frame_buffers: FrameBuffers,
 
+    /// The offscreen target the scene is rendered to before it's
+    /// blitted to the swapchain, if there's an internal resolution.
+    offscreen_target: Option<OffscreenTarget>,
+
+    /// The render scale, if the internal resolution follows the window.
+    render_scale: Option<f32>,
+
     /// The triangle renderer.
     triangle_renderer: TriangleRenderer,
 
+    /// The renderables drawn after the triangles, in order.
+    renderables: Vec<Box<dyn Renderable>>,
+
+    /// The text renderer, if text is enabled. It's drawn
+    /// last so text overlays everything else.
+    text_renderer: Option<TextRenderer>,
+
+    /// The compute present renderer, if the swapchain supports it.
+    compute_present_renderer: Option<ComputePresentRenderer>,
+
+    /// Watches the triangle renderer's shaders, if watching is possible.
+    shader_watcher: Option<ShaderWatcher>,
+
+    /// The render mode.
+    render_mode: RenderMode,
+
+    /// The window's scale factor. The text renderer multiplies
+    /// its sizes by this to stay physically consistent.
+    scale_factor: f64,
+
+    /// The latest size the window was resized to, if the swapchain
+    /// hasn't been recreated for it yet. Resizes are applied lazily
+    /// at the next draw so a window drag only recreates it once.
+    pending_size: Option<PhysicalSize<u32>>,
+
+    /// The extent of a swapchain that was recreated because it was
+    /// suboptimal and still is. Some platforms report suboptimal
+    /// forever, so it isn't recreated again until the extent changes.
+    suboptimal_extent: Option<vk::Extent2D>,
+
+    /// The timestamp query pool, if the device supports timestamps.
+    query_pool: Option<QueryPool>,
+
+    /// The GPU time of the most recently completed frame in milliseconds.
+    gpu_time: Option<f32>,
+
+    /// Times the frames on the CPU.
+    frame_timer: FrameTimer,
+
+    /// The timeline semaphore that counts submitted frames, if
+    /// supported. Otherwise each frame has its own fence.
+    timeline_semaphore: Option<TimelineSemaphore>,
+
+    /// Records the main pass on several threads, if enabled.
+    threaded_recorder: Option<ThreadedRecorder>,
+
     /// The per-frame data.
     per_frame_data: Vec<PerFrameData>,
 
     /// The per-frame index.
-    per_frame_index: usize
+    per_frame_index: usize,
+
+    /// The device wrapper.
+    device: Device,
+
+    /// The surface wrapper.
+    surface: Surface,
+
+    /// The debugging wrapper.
+    debugging: Option<Debugging>,
+
+    /// The instance wrapper.
+    instance: Instance,
+
+    /// The Vulkan entry point.
+    entry: Entry,
+
+    /// A handle to the window.
+    window: Arc<Window>
 }
 
 impl Renderer {
-    /// Create a new Vulkan instance.
-    pub unsafe fn new(window: Arc<Window>, assets_path: PathBuf) -> Result<Self> {
+    /// Create a new renderer with the given options. See RendererBuilder.
+    pub unsafe fn new(window: Arc<Window>, settings: &RendererBuilder) -> Result<Self> {
+        let RendererBuilder {
+            assets_path,
+            device_selector,
+            requirements,
+            validation,
+            color_space_preference,
+            present_mode_preference,
+            image_count_policy,
+            clear_color,
+            render_scale,
+            pipeline_cache_path,
+            text,
+            ..
+        } = settings.clone();
+
         // Load the Vulkan library.
         let entry = Entry::linked();
 
         // Create the instance wrapper.
-        let instance = Instance::new(window.clone(), &entry)?;
+        let instance = Instance::new(window.clone(), &entry, &validation)?;
 
         // Capture messages for everything else.
-        let debugging = match cfg!(debug_assertions) {
-            true => Some(Debugging::new(&entry, &instance)?),
-            false => None
+        let debugging = match (instance.validation_enabled(), validation.collect_messages) {
+            (true, true) => Some(Debugging::new_collecting(
+                &entry,
+                &instance,
+                validation.debug_level,
+                validation.message_types
+            )?),
+            (true, false) => Some(Debugging::new(
+                &entry,
+                &instance,
+                validation.debug_level,
+                validation.message_types
+            )?),
+            (false, _) => None
         };
 
         // Create the surface wrapper.
         let surface = Surface::new(window.clone(), &entry, &instance)?;
 
         // Create the device wrapper.
-        let device = Device::new(&instance, &surface)?;
+        let device = Device::new(&instance, Some(&surface), &device_selector, &requirements)?;
 
-        // Compute how many frames we can have in flight.
-        let frames_in_flight = Self::frames_in_flight(&device, &surface)?;
+        // The frames in flight are independent of the swapchain's
+        // image count, which the image count policy decides.
+        let frames_in_flight = settings.frames_in_flight.max(1);
 
         info!("Frames in flight: {}", frames_in_flight);
 
@@ -137,78 +313,722 @@ impl Renderer {
             &instance,
             &device,
             &surface,
-            frames_in_flight
+            image_count_policy,
+            color_space_preference,
+            present_mode_preference
         )?;
 
+        info!("Swapchain images: {}", swapchain.images().len());
+
         // Create the render pass wrapper.
-        let render_pass = RenderPass::new(&device, &swapchain)?;
+        let mut render_pass = RenderPass::new(&device, &swapchain, settings.samples)?;
+
+        render_pass.set_clear_values(Self::clear_values(&render_pass, clear_color))?;
+
+        // Create the pipeline cache.
+        let pipeline_cache_path = match pipeline_cache_path {
+            Some(pipeline_cache_path) => pipeline_cache_path,
+            None => PipelineCache::default_path()?
+        };
+        let pipeline_cache = PipelineCache::new(&device, &pipeline_cache_path)?;
+
+        // Create the multisampled color image.
+        let color_image = Self::new_color_image(&device, &swapchain, &render_pass)?;
 
         // Create the frame buffers wrapper.
-        let frame_buffers = FrameBuffers::new(&device, &swapchain, &render_pass)?;
+        let frame_buffers =
+            Self::new_frame_buffers(&device, &swapchain, &render_pass, &color_image)?;
 
         // Create the triangle renderer.
-        let triangle_renderer =
-            TriangleRenderer::new(&assets_path, &device, &render_pass, frames_in_flight)?;
+        let mut triangle_renderer = TriangleRenderer::new(
+            &assets_path,
+            &device,
+            &pipeline_cache,
+            &render_pass,
+            frames_in_flight
+        )?;
+
+        triangle_renderer.set_clip_space(settings.clip_space);
+
+        // Create the text renderer if text is enabled.
+        let mut text_renderer = match text {
+            true => Some(TextRenderer::new(
+                &assets_path,
+                &device,
+                &pipeline_cache,
+                &render_pass,
+                frames_in_flight
+            )?),
+            false => None
+        };
+
+        // Create the compute present renderer if it's supported.
+        let compute_present_renderer =
+            match ComputePresentRenderer::is_supported(&device, &swapchain) {
+                true => Some(ComputePresentRenderer::new(
+                    &assets_path,
+                    &device,
+                    &pipeline_cache,
+                    &swapchain
+                )?),
+                false => None
+            };
+
+        info!(
+            "Compute present supported: {}",
+            compute_present_renderer.is_some()
+        );
+
+        // Watch the triangle renderer's shaders for changes.
+        let shader_watcher = match ShaderWatcher::new(&triangle_renderer.shader_paths()) {
+            Ok(shader_watcher) => Some(shader_watcher),
+            Err(e) => {
+                warn!("Shader hot reloading is disabled: {}", e);
+
+                None
+            }
+        };
+
+        // Get the window's scale factor.
+        let scale_factor = window.scale_factor();
+
+        if let Some(text_renderer) = &mut text_renderer {
+            text_renderer.set_scale_factor(scale_factor as f32);
+        }
+
+        // Create the timestamp query pool if it's supported.
+        let query_pool = match QueryPool::is_supported(&device) {
+            true => Some(QueryPool::new(&device, frames_in_flight)?),
+            false => None
+        };
+
+        info!("GPU timestamps supported: {}", query_pool.is_some());
+
+        // Create the timeline semaphore if it's supported.
+        let timeline_semaphore = match device.timeline_semaphore_enabled() {
+            true => {
+                let timeline_semaphore = TimelineSemaphore::new(&device)?;
+
+                device.set_object_name(*timeline_semaphore, "Frame timeline");
+
+                Some(timeline_semaphore)
+            },
+            false => None
+        };
 
         // Create the per-frame data.
         let per_frame_data = (0..frames_in_flight)
-            .map(|_| PerFrameData::new(&device))
+            .map(|index| PerFrameData::new(&device, index))
             .collect::<Result<Vec<_>>>()?;
 
-        Ok(Self {
+        let mut this = Self {
             window,
+            entry,
             instance,
             debugging,
             surface,
             device,
             frames_in_flight,
+            image_count_policy,
+            color_space_preference,
+            present_mode_preference,
+            clear_color,
             swapchain,
             render_pass,
+            pipeline_cache,
+            color_image,
             frame_buffers,
+            offscreen_target: None,
+            render_scale: None,
             triangle_renderer,
+            renderables: Vec::new(),
+            text_renderer,
+            compute_present_renderer,
+            shader_watcher,
+            render_mode: RenderMode::Triangle,
+            scale_factor,
+            pending_size: None,
+            suboptimal_extent: None,
+            query_pool,
+            gpu_time: None,
+            frame_timer: FrameTimer::new(FRAME_STATS_WINDOW),
+            timeline_semaphore,
+            threaded_recorder: None,
             per_frame_data,
             per_frame_index: 0
+        };
+
+        // Render at a fraction of the window's resolution.
+        if render_scale.is_some() {
+            this.set_render_scale(render_scale)?;
+        }
+
+        Ok(this)
+    }
+
+    /// Returns the render mode.
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    /// Set the render mode. Fails if the mode isn't supported.
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) -> Result<()> {
+        if render_mode == RenderMode::ComputePresent
+            && self
+                .compute_present_renderer
+                .is_none()
+        {
+            return Err(anyhow!("Compute present is not supported."));
+        }
+
+        self.render_mode = render_mode;
+
+        Ok(())
+    }
+
+    /// Returns the window's scale factor.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Call when the window's scale factor changes, e.g.
+    /// when it's moved to a monitor with a different DPI.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+
+        if let Some(text_renderer) = &mut self.text_renderer {
+            text_renderer.set_scale_factor(scale_factor as f32);
+        }
+    }
+
+    /// Returns the swapchain image count policy.
+    pub fn image_count_policy(&self) -> ImageCountPolicy {
+        self.image_count_policy
+    }
+
+    /// Returns the kind of swapchain format the renderer prefers. If none
+    /// are available, the swapchain falls back to another format and warns.
+    pub fn color_space_preference(&self) -> ColorSpacePreference {
+        self.color_space_preference
+    }
+
+    /// Returns the present mode the renderer prefers. See
+    /// Swapchain::present_mode for the one actually used.
+    pub fn present_mode_preference(&self) -> PresentModePreference {
+        self.present_mode_preference
+    }
+
+    /// Returns the color the frame is cleared to.
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.clear_color
+    }
+
+    /// Set the swapchain image count policy. This recreates the swapchain.
+    ///
+    /// # Safety
+    ///
+    /// The window the renderer was built for must still exist, as the swapchain
+    /// is recreated.
+    pub unsafe fn set_image_count_policy(
+        &mut self,
+        image_count_policy: ImageCountPolicy
+    ) -> Result<()> {
+        self.image_count_policy = image_count_policy;
+
+        // Recreate the swapchain with the new image count.
+        self.recreate_swapchain(None)?;
+
+        Ok(())
+    }
+
+    /// Returns the formats and color spaces the surface supports.
+    ///
+    /// # Safety
+    ///
+    /// The window the renderer was built for must still exist, as the surface
+    /// refers to it.
+    pub unsafe fn supported_formats(&self) -> Result<Vec<vk::SurfaceFormatKHR>> {
+        self.surface
+            .formats(self.device.physical_device())
+    }
+
+    /// Returns the present modes the surface supports. FIFO is
+    /// always among them, e.g. mailbox may or may not be.
+    ///
+    /// # Safety
+    ///
+    /// The window the renderer was built for must still exist, as the surface
+    /// refers to it.
+    pub unsafe fn supported_present_modes(&self) -> Result<Vec<vk::PresentModeKHR>> {
+        self.surface
+            .present_modes(self.device.physical_device())
+    }
+
+    /// Returns the present mode preferences the surface can honor without
+    /// falling back, e.g. for the options of a settings menu.
+    ///
+    /// # Safety
+    ///
+    /// The window the renderer was built for must still exist, as the surface
+    /// refers to it.
+    pub unsafe fn supported_present_mode_preferences(&self) -> Result<Vec<PresentModePreference>> {
+        let present_modes = self.supported_present_modes()?;
+
+        Ok(PresentModePreference::ALL
+            .into_iter()
+            .filter(|preference| preference.is_supported(&present_modes))
+            .collect())
+    }
+
+    /// Returns the swapchain options that can change at runtime.
+    pub fn swapchain_config(&self) -> SwapchainConfig {
+        SwapchainConfig {
+            color_space_preference:  self.color_space_preference,
+            present_mode_preference: self.present_mode_preference,
+            render_scale:            self.render_scale
+        }
+    }
+
+    /// Change the swapchain options, e.g. to toggle vsync. The swapchain
+    /// is recreated if the format or present mode preference changed, and
+    /// if that changes the format, the render pass and every pipeline made
+    /// for it are rebuilt too. Nothing is recreated if nothing changed.
+    ///
+    /// # Safety
+    ///
+    /// The window the renderer was built for must still exist, as the swapchain
+    /// may be recreated.
+    pub unsafe fn set_swapchain_config(&mut self, config: SwapchainConfig) -> Result<()> {
+        if config.color_space_preference != self.color_space_preference
+            || config.present_mode_preference != self.present_mode_preference
+        {
+            self.color_space_preference = config.color_space_preference;
+            self.present_mode_preference = config.present_mode_preference;
+
+            // Recreate the swapchain with the new preferences.
+            self.recreate_swapchain(None)?;
+
+            info!(
+                "Swapchain: {:?}, {:?}",
+                self.swapchain.format(),
+                self.swapchain.present_mode()
+            );
+        }
+
+        if config.render_scale != self.render_scale {
+            self.set_render_scale(config.render_scale)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the internal resolution the scene is rendered at, or None
+    /// if it's rendered straight to the swapchain at the window's size.
+    pub fn internal_resolution(&self) -> Option<vk::Extent2D> {
+        self.offscreen_target
+            .as_ref()
+            .map(|offscreen_target| offscreen_target.extent())
+    }
+
+    /// Render the scene to an offscreen target at the given internal
+    /// resolution and scale it to the swapchain image each frame, or
+    /// pass None to render straight to the swapchain again. The
+    /// resolution stays fixed when the window is resized. This replaces
+    /// any render scale.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost. This waits for it to go
+    /// idle before replacing the offscreen target.
+    pub unsafe fn set_internal_resolution(&mut self, extent: Option<vk::Extent2D>) -> Result<()> {
+        self.render_scale = None;
+
+        self.replace_offscreen_target(extent)
+    }
+
+    /// Returns the render scale, or None if the scene isn't
+    /// rendered at a multiple of the window's resolution.
+    pub fn render_scale(&self) -> Option<f32> {
+        self.render_scale
+    }
+
+    /// Render the scene at a multiple of the window's resolution, e.g.
+    /// 0.75 for speed, and scale it to the swapchain image each frame.
+    /// Unlike a fixed internal resolution, this follows the window as
+    /// it's resized. The scale is clamped between 0.25 and 2. Pass None
+    /// to render straight to the swapchain again. This replaces any
+    /// internal resolution.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost. This waits for it to go
+    /// idle before replacing the offscreen target.
+    pub unsafe fn set_render_scale(&mut self, render_scale: Option<f32>) -> Result<()> {
+        let Some(render_scale) = render_scale else {
+            return self.set_internal_resolution(None);
+        };
+
+        if !render_scale.is_finite() {
+            return Err(anyhow!(
+                "The render scale must be finite, not {}.",
+                render_scale
+            ));
+        }
+
+        let render_scale = render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+
+        self.replace_offscreen_target(Some(Self::scaled_extent(
+            &self.swapchain.extent(),
+            render_scale
+        )))?;
+
+        self.render_scale = Some(render_scale);
+
+        info!("Render scale: {}", render_scale);
+
+        Ok(())
+    }
+
+    /// The extent scaled by the render scale, at least one pixel.
+    fn scaled_extent(extent: &vk::Extent2D, render_scale: f32) -> vk::Extent2D {
+        vk::Extent2D {
+            width:  ((extent.width as f32 * render_scale).round() as u32).max(1),
+            height: ((extent.height as f32 * render_scale).round() as u32).max(1)
+        }
+    }
+
+    /// Replace the offscreen target with one of the given extent, or
+    /// render straight to the swapchain if there's no extent.
+    unsafe fn replace_offscreen_target(&mut self, extent: Option<vk::Extent2D>) -> Result<()> {
+        // Wait for the device to finish. We must do this or
+        // we may destroy a target that's still in use.
+        self.device.wait_idle()?;
+
+        // Destroy the current target.
+        if let Some(offscreen_target) = &mut self.offscreen_target {
+            offscreen_target.destroy(&self.device);
+        }
+
+        self.offscreen_target = None;
+
+        let Some(extent) = extent else {
+            info!("Internal resolution: window");
+
+            return Ok(());
+        };
+
+        if extent.width == 0 || extent.height == 0 {
+            return Err(anyhow!("The internal resolution can't be empty."));
+        }
+
+        // We blit the target to the swapchain images.
+        if !self
+            .swapchain
+            .usage()
+            .contains(vk::ImageUsageFlags::TRANSFER_DST)
+        {
+            return Err(anyhow!("The swapchain images can't be blitted to."));
+        }
+
+        // Create the new target. Its format and sample count match the main
+        // render pass, so the existing pipelines can render to it.
+        let mut offscreen_target = OffscreenTarget::new(
+            &self.device,
+            self.swapchain.format().format,
+            extent,
+            self.render_pass.samples()
+        )?;
+
+        offscreen_target.set_clear_values(Self::clear_values(
+            offscreen_target.render_pass(),
+            self.clear_color
+        ))?;
+
+        self.offscreen_target = Some(offscreen_target);
+
+        info!("Internal resolution: {}x{}", extent.width, extent.height);
+
+        Ok(())
+    }
+
+    /// Returns the number of threads the main pass is recorded
+    /// on, or zero if it's recorded inline on the calling thread.
+    pub fn recording_threads(&self) -> usize {
+        self.threaded_recorder
+            .as_ref()
+            .map_or(0, |threaded_recorder| threaded_recorder.thread_count())
+    }
+
+    /// Set the number of threads the main pass is recorded on. Each
+    /// thread records a share of the draws into a secondary command
+    /// buffer. Pass zero to record inline on the calling thread.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost. This waits for it to go
+    /// idle before replacing the recorder.
+    pub unsafe fn set_recording_threads(&mut self, threads: usize) -> Result<()> {
+        // Wait for the device to finish. We must do this or
+        // we may destroy command buffers that are still in use.
+        self.device.wait_idle()?;
+
+        // Destroy the current recorder.
+        if let Some(threaded_recorder) = &mut self.threaded_recorder {
+            threaded_recorder.destroy(&self.device);
+        }
+
+        self.threaded_recorder = None;
+
+        // Create the new recorder.
+        if threads > 0 {
+            self.threaded_recorder = Some(ThreadedRecorder::new(
+                &self.device,
+                threads,
+                self.frames_in_flight
+            )?);
+        }
+
+        info!("Recording threads: {}", threads);
+
+        Ok(())
+    }
+
+    /// Returns true if the triangles are drawn as wireframes.
+    pub fn wireframe(&self) -> bool {
+        self.triangle_renderer.wireframe()
+    }
+
+    /// Draw the triangles as wireframes or filled. This needs
+    /// the fill mode non-solid device feature for wireframes.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost. This waits for it to go
+    /// idle before replacing the pipeline.
+    pub unsafe fn set_wireframe(&mut self, wireframe: bool) -> Result<()> {
+        self.triangle_renderer
+            .set_wireframe(
+                &self.device,
+                &self.pipeline_cache,
+                &self.render_pass,
+                wireframe
+            )?;
+
+        info!("Wireframe: {}", wireframe);
+
+        Ok(())
+    }
+
+    /// Returns the width of wireframe lines.
+    pub fn line_width(&self) -> f32 {
+        self.triangle_renderer.line_width()
+    }
+
+    /// Set the width of wireframe lines. Widths other than 1.0
+    /// need the wide lines device feature and are ignored otherwise.
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.triangle_renderer
+            .set_line_width(line_width);
+    }
+
+    /// Returns which way the y axis points in clip space.
+    pub fn clip_space(&self) -> ClipSpace {
+        self.triangle_renderer.clip_space()
+    }
+
+    /// Set which way the y axis points in clip space. By default it points
+    /// up, so renderables can use OpenGL style projection matrices as they
+    /// are. See ClipSpace for how this affects the front face.
+    pub fn set_clip_space(&mut self, clip_space: ClipSpace) {
+        self.triangle_renderer
+            .set_clip_space(clip_space);
+
+        info!("Clip space: {:?}", clip_space);
+    }
+
+    /// Returns the device.
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    /// Returns the debugging wrapper, if validation is enabled.
+    pub fn debugging(&self) -> Option<&Debugging> {
+        self.debugging.as_ref()
+    }
+
+    /// Returns the main render pass. Renderables build their pipelines for it.
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    /// Returns the pipeline cache.
+    pub fn pipeline_cache(&self) -> &PipelineCache {
+        &self.pipeline_cache
+    }
+
+    /// Returns the maximum number of frames in flight. Renderables
+    /// need this much per-frame data to avoid overwriting data the
+    /// GPU is still reading.
+    pub fn max_frames_in_flight(&self) -> u32 {
+        self.frames_in_flight
+    }
+
+    /// Add a renderable to draw in the main render pass every frame.
+    /// It's drawn after the triangles and any renderables added before
+    /// it, and destroyed along with the renderer.
+    pub fn add_renderable(&mut self, renderable: Box<dyn Renderable>) {
+        self.renderables.push(renderable);
+    }
+
+    /// Draw text over everything else in the next frame. The position
+    /// is the top left corner of the first line and the size is the
+    /// height of a line, both in logical pixels, so text is the same
+    /// physical size at any scale factor. Lines are split at newlines.
+    /// Does nothing unless text is enabled, see RendererBuilder::text.
+    pub fn draw_text(&mut self, text: &str, position: Vec2, size: f32, color: Vec3) {
+        if let Some(text_renderer) = &mut self.text_renderer {
+            text_renderer.draw_text(text, position, size, color);
+        }
+    }
+
+    /// Returns the GPU time of the most recently completed frame in
+    /// milliseconds, or None if timestamps aren't supported or no
+    /// frame has completed yet.
+    pub fn gpu_time(&self) -> Option<f32> {
+        self.gpu_time
+    }
+
+    /// Returns the CPU frame time statistics over the most recent frames.
+    /// The frame time is the time between consecutive calls to draw.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_timer.stats()
+    }
+
+    /// Draw the frame. If the device is lost, e.g. during acquire,
+    /// submit, or present, this returns VulkanError::DeviceLost.
+    /// The renderer can't recover from that, it must be recreated.
+    pub unsafe fn draw(&mut self) -> Result<()> {
+        // Time the frame.
+        self.frame_timer.tick();
+
+        let result = self.draw_frame();
+
+        // The text was only for this frame, even if it wasn't drawn,
+        // e.g. in compute present mode or when the frame failed.
+        if let Some(text_renderer) = &mut self.text_renderer {
+            text_renderer.clear();
+        }
+
+        result.map_err(|e| {
+            let e = VulkanError::from_result(e);
+
+            if VulkanError::is_device_lost(&e) {
+                error!("Device lost while drawing the frame.");
+            }
+
+            e
         })
     }
 
     /// Draw the frame.
-    pub unsafe fn draw(&mut self) -> Result<()> {
+    unsafe fn draw_frame(&mut self) -> Result<()> {
         // Get the per-frame data.
         let per_frame_data = &self.per_frame_data[self.per_frame_index];
         let command_buffer = per_frame_data.command_buffer;
         let semaphore_image_ready = per_frame_data.semaphore_image_ready;
-        let semaphore_render_done = per_frame_data.semaphore_render_done;
         let fence_frame_done = per_frame_data.fence_frame_done;
 
-        // Wait for the fence indefinitely.
-        self.device
-            .wait_for_fences(&[fence_frame_done], true, std::u64::MAX)?;
+        // Wait for the GPU to finish the last frame that used this index.
+        match (&self.timeline_semaphore, fence_frame_done) {
+            (Some(timeline_semaphore), _) => {
+                // That frame signaled the value frames in flight before the next one.
+                let value = timeline_semaphore
+                    .next_value()
+                    .saturating_sub(self.frames_in_flight as u64);
 
-        // Reset the fence.
-        self.device
-            .reset_fences(&[fence_frame_done])?;
+                timeline_semaphore.wait(&self.device, value)?;
+            },
+            (None, Some(fence_frame_done)) => {
+                // Wait for the fence indefinitely.
+                self.device
+                    .wait_for_fences(&[fence_frame_done], true, std::u64::MAX)?;
+
+                // Reset the fence.
+                self.device
+                    .reset_fences(&[fence_frame_done])?;
+            },
+            (None, None) => return Err(anyhow!("Frame has neither a fence nor a timeline."))
+        }
+
+        // Read the GPU time of the last frame that used this index.
+        if let Some(query_pool) = &self.query_pool {
+            if let Some(gpu_time) = query_pool.elapsed_ms(&self.device, self.per_frame_index)? {
+                self.gpu_time = Some(gpu_time);
+            }
+        }
+
+        // Rebuild the pipeline if its shaders changed. On
+        // failure we keep drawing with the old pipeline.
+        if self
+            .shader_watcher
+            .as_ref()
+            .is_some_and(|shader_watcher| shader_watcher.changed())
+        {
+            info!("Shaders changed, reloading pipeline.");
+
+            if let Err(e) = self
+                .triangle_renderer
+                .reload_pipeline(&self.device, &self.pipeline_cache, &self.render_pass)
+            {
+                error!("Failed to reload pipeline: {}", e);
+            }
+        }
 
-        // Acquire the next swapchain image.
-        let present_index = loop {
+        // Apply the latest resize, if any.
+        if let Some(size) = self.pending_size.take() {
+            let extent = self.swapchain.extent();
+
+            if size.width != extent.width || size.height != extent.height {
+                self.recreate_swapchain(Some(&size))?;
+            }
+        }
+
+        // Acquire the next swapchain image. A suboptimal
+        // swapchain is still rendered to and recreated after.
+        let (present_index, acquired_suboptimal) = loop {
             match self
                 .swapchain
-                .acquire(&semaphore_image_ready)?
+                .acquire(&semaphore_image_ready)
             {
-                Some(present_index) => break present_index,
-                None => {
+                Ok(Some(image)) => break (image.index, image.suboptimal),
+                Ok(None) => {
                     debug!(
                         "Acquire failed, recreating swapchain: {:?}",
                         self.window.inner_size()
                     );
 
                     self.recreate_swapchain(None)?;
-                }
+                },
+                Err(e) if Surface::is_lost(&e) => {
+                    warn!("Surface lost during acquire, recreating surface.");
+
+                    self.recreate_surface()?;
+                },
+                Err(e) => return Err(e)
             }
         };
 
-        // Reset the command buffer.
-        self.device
-            .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())?;
+        // The render done semaphore belongs to the acquired image. Its last
+        // presentation finished waiting on it before the image was acquired.
+        let semaphore_render_done = self
+            .swapchain
+            .render_done_semaphore(present_index);
+
+        // Reset the command pool. The wait above guarantees that
+        // the GPU is done with this frame's command buffers.
+        self.per_frame_data[self.per_frame_index]
+            .command_pool
+            .reset(&self.device, false)?;
 
         // Create the begin info.
         let begin_info = vk::CommandBufferBeginInfo::default();
@@ -217,69 +1037,114 @@ impl Renderer {
         self.device
             .begin_command_buffer(command_buffer, &begin_info)?;
 
-        // Get the swapchain extent.
-        let extent = self.swapchain.extent();
+        // Write the starting timestamp.
+        if let Some(query_pool) = &mut self.query_pool {
+            query_pool.begin(&self.device, &command_buffer, self.per_frame_index);
+        }
 
-        // Set the viewport state.
-        self.device.cmd_set_viewport(
-            command_buffer,
-            0,
-            &[vk::Viewport {
-                x:         0.0,
-                y:         0.0,
-                width:     extent.width as f32,
-                height:    extent.height as f32,
-                min_depth: 0.0,
-                max_depth: 1.0
-            }]
-        );
+        // Record the frame. The returned stage is the one that
+        // must wait for the swapchain image to be acquired.
+        let wait_stage = match (self.render_mode, &mut self.compute_present_renderer) {
+            (RenderMode::ComputePresent, Some(compute_present_renderer)) => {
+                // Open the debug label.
+                self.device.begin_debug_label(
+                    &command_buffer,
+                    "Compute present",
+                    COMPUTE_LABEL_COLOR
+                );
 
-        // Set the scissor state.
-        self.device
-            .cmd_set_scissor(command_buffer, 0, &[extent.into()]);
+                // Write to the swapchain image with the compute shader.
+                compute_present_renderer.draw(
+                    &self.device,
+                    &self.swapchain,
+                    &command_buffer,
+                    present_index
+                )?;
 
-        // Begin the render pass.
-        self.render_pass.begin(
-            &self.device,
-            &self.swapchain,
-            &self.frame_buffers,
-            &command_buffer,
-            present_index
-        );
+                // Close the debug label.
+                self.device
+                    .end_debug_label(&command_buffer);
 
-        // Render the triangle.
-        self.triangle_renderer.draw(
-            &self.device,
-            &self.swapchain,
-            &command_buffer,
-            self.per_frame_index
-        )?;
+                vk::PipelineStageFlags::COMPUTE_SHADER
+            },
 
-        // End the render pass.
-        self.render_pass
-            .end(&self.device, &command_buffer);
+            _ => {
+                self.record_triangle(command_buffer, present_index)?;
+
+                match &self.offscreen_target {
+                    Some(offscreen_target) => {
+                        // Scale the offscreen target to the swapchain image.
+                        offscreen_target.cmd_blit(
+                            &self.device,
+                            &command_buffer,
+                            self.swapchain.images()[present_index as usize],
+                            &self.swapchain.extent()
+                        );
+
+                        vk::PipelineStageFlags::TRANSFER
+                    },
+                    None => vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                }
+            }
+        };
+
+        // Write the ending timestamp.
+        if let Some(query_pool) = &mut self.query_pool {
+            query_pool.end(&self.device, &command_buffer, self.per_frame_index);
+        }
 
         // End the command buffer.
         self.device
             .end_command_buffer(command_buffer)?;
 
-        // Create the submit info.
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(from_ref(&semaphore_image_ready))
-            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-            .command_buffers(from_ref(&command_buffer))
-            .signal_semaphores(from_ref(&semaphore_render_done));
-
         // Submit the command buffer.
-        self.device
-            .queue_submit(*self.device.queue(), &[submit_info], fence_frame_done)?;
+        match &mut self.timeline_semaphore {
+            Some(timeline_semaphore) => {
+                // Signal the timeline alongside the binary semaphore
+                // that presentation waits on. Binary semaphores
+                // ignore their values.
+                let signal_semaphores = [semaphore_render_done, **timeline_semaphore];
+                let signal_values = [0, timeline_semaphore.next_value()];
+
+                // Create the submit info.
+                let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
+                    .wait_semaphore_values(&[0])
+                    .signal_semaphore_values(&signal_values);
+
+                let submit_info = vk::SubmitInfo::default()
+                    .wait_semaphores(from_ref(&semaphore_image_ready))
+                    .wait_dst_stage_mask(from_ref(&wait_stage))
+                    .command_buffers(from_ref(&command_buffer))
+                    .signal_semaphores(&signal_semaphores)
+                    .push_next(&mut timeline_info);
+
+                self.device
+                    .queue()
+                    .submit(&self.device, &[submit_info], None)?;
+
+                // Only count the frame once it was submitted.
+                timeline_semaphore.advance();
+            },
+            None => {
+                // Create the submit info.
+                let submit_info = vk::SubmitInfo::default()
+                    .wait_semaphores(from_ref(&semaphore_image_ready))
+                    .wait_dst_stage_mask(from_ref(&wait_stage))
+                    .command_buffers(from_ref(&command_buffer))
+                    .signal_semaphores(from_ref(&semaphore_render_done));
+
+                self.device
+                    .queue()
+                    .submit(&self.device, &[submit_info], fence_frame_done)?;
+            }
+        }
 
         // Present the image.
         match self
             .swapchain
-            .present(&self.device, &semaphore_render_done, present_index)?
+            .present(&self.device, &semaphore_render_done, present_index)
         {
-            true => {
+            Ok(PresentStatus::OutOfDate) => {
                 debug!(
                     "Present failed, recreating swapchain: {:?}",
                     self.window.inner_size()
@@ -287,7 +1152,17 @@ impl Renderer {
 
                 self.recreate_swapchain(None)?;
             },
-            _ => {}
+            Ok(PresentStatus::Suboptimal) => self.recreate_suboptimal_swapchain()?,
+            Ok(PresentStatus::Optimal) if acquired_suboptimal => {
+                self.recreate_suboptimal_swapchain()?
+            },
+            Ok(PresentStatus::Optimal) => self.suboptimal_extent = None,
+            Err(e) if Surface::is_lost(&e) => {
+                warn!("Surface lost during present, recreating surface.");
+
+                self.recreate_surface()?;
+            },
+            Err(e) => return Err(e)
         };
 
         // Advance the per-frame index.
@@ -296,19 +1171,269 @@ impl Renderer {
         Ok(())
     }
 
-    /// Call when a resize occurs.
-    pub unsafe fn resize(&mut self, size: &PhysicalSize<u32>) -> Result<()> {
-        // Recreate the swapchain.
-        self.recreate_swapchain(Some(size))?;
+    /// Call after draw fails to bring the frame loop back to a known
+    /// state. A failure between acquire and submit can leave a fence
+    /// unsignaled or a semaphore signaled with nothing waiting on it,
+    /// so we recreate all of the per-frame data and start over.
+    ///
+    /// # Safety
+    ///
+    /// The renderer's device must not have been lost. This waits for it to go
+    /// idle before recreating the per-frame data.
+    pub unsafe fn recover(&mut self) -> Result<()> {
+        // Wait for the device to finish. We must do this or
+        // we may destroy objects that are still in use.
+        self.device.wait_idle()?;
+
+        // Destroy the per-frame data.
+        self.per_frame_data
+            .iter_mut()
+            .for_each(|data| data.destroy(&self.device));
+
+        // Recreate the per-frame data. The fences start signaled, and
+        // the timeline semaphore only counts frames that were submitted.
+        self.per_frame_data = (0..self.frames_in_flight)
+            .map(|index| PerFrameData::new(&self.device, index))
+            .collect::<Result<Vec<_>>>()?;
+
+        // The failed frame may have signaled a render done semaphore
+        // without presenting, leaving nothing to wait on it.
+        self.swapchain
+            .reset_render_done_semaphores(&self.device)?;
+
+        // Queries recorded by the failed frame may never have run.
+        if let Some(query_pool) = &mut self.query_pool {
+            query_pool.reset();
+        }
+
+        // Start over with the first frame.
+        self.per_frame_index = 0;
+
+        Ok(())
+    }
+
+    /// Record the triangle render pass, to the offscreen target if
+    /// there is one and otherwise to the swapchain image.
+    unsafe fn record_triangle(
+        &mut self,
+        command_buffer: vk::CommandBuffer,
+        present_index: u32
+    ) -> Result<()> {
+        // Pick what we render to.
+        let (render_pass, frame_buffer_index, frame_buffers, extent) = match &self.offscreen_target
+        {
+            Some(offscreen_target) => {
+                // The previous frame's blit may still be reading the target.
+                offscreen_target.cmd_wait_for_blit(&self.device, &command_buffer);
+
+                (
+                    offscreen_target.render_pass(),
+                    0,
+                    offscreen_target.frame_buffers(),
+                    offscreen_target.extent()
+                )
+            },
+            None => (
+                &self.render_pass,
+                present_index,
+                &self.frame_buffers,
+                self.swapchain.extent()
+            )
+        };
+
+        // Set the viewport and scissor state.
+        Self::set_viewport_and_scissor(&self.device, &command_buffer, &extent, self.clip_space());
+
+        // Open the render pass debug label.
+        self.device
+            .begin_debug_label(&command_buffer, "Main pass", PASS_LABEL_COLOR);
+
+        // Begin the render pass. With threaded recording,
+        // the draws live in secondary command buffers.
+        render_pass.begin(
+            &self.device,
+            &extent,
+            frame_buffers,
+            &command_buffer,
+            frame_buffer_index,
+            match self.threaded_recorder {
+                Some(_) => vk::SubpassContents::SECONDARY_COMMAND_BUFFERS,
+                None => vk::SubpassContents::INLINE
+            }
+        );
+
+        // Open the triangle debug label.
+        self.device
+            .begin_debug_label(&command_buffer, "Triangle", DRAW_LABEL_COLOR);
+
+        match &mut self.threaded_recorder {
+            Some(threaded_recorder) => {
+                // Update the uniforms once, up front.
+                self.triangle_renderer
+                    .update(&self.device, &extent)?;
+
+                // Split the objects evenly across the threads.
+                let object_count = self
+                    .triangle_renderer
+                    .object_count();
+                let objects_per_thread = object_count.div_ceil(threaded_recorder.thread_count());
+
+                let device = &self.device;
+                let triangle_renderer = &self.triangle_renderer;
+                let per_frame_index = self.per_frame_index;
+                let clip_space = triangle_renderer.clip_space();
+                let screen_extent = self.swapchain.extent();
+
+                // The renderables and text are drawn on the last thread, after
+                // its share of the triangles. Only that thread locks them.
+                let last_thread = threaded_recorder.thread_count() - 1;
+                let renderables = Mutex::new((&mut self.renderables, self.text_renderer.as_mut()));
+
+                // Record each thread's share of the objects.
+                let command_buffers = threaded_recorder.record(
+                    device,
+                    self.per_frame_index,
+                    render_pass,
+                    0,
+                    &frame_buffers[frame_buffer_index as usize],
+                    |thread, secondary_command_buffer| {
+                        let start = (thread * objects_per_thread).min(object_count);
+                        let end = (start + objects_per_thread).min(object_count);
+
+                        // Dynamic state isn't inherited.
+                        Self::set_viewport_and_scissor(
+                            device,
+                            secondary_command_buffer,
+                            &extent,
+                            clip_space
+                        );
+
+                        // Render the triangles.
+                        triangle_renderer.record(device, secondary_command_buffer, start..end)?;
+
+                        // Render the renderables and the text.
+                        if thread == last_thread {
+                            let mut renderables = renderables
+                                .lock()
+                                .map_err(|_| anyhow!("The renderables lock is poisoned."))?;
+                            let (renderables, text_renderer) = &mut *renderables;
+
+                            for renderable in renderables.iter_mut() {
+                                renderable.draw(
+                                    device,
+                                    &extent,
+                                    secondary_command_buffer,
+                                    per_frame_index
+                                )?;
+
+                                // Undo any clipping for the next renderable.
+                                device.reset_scissor(secondary_command_buffer, &extent);
+                            }
+
+                            if let Some(text_renderer) = text_renderer {
+                                text_renderer.draw(
+                                    device,
+                                    secondary_command_buffer,
+                                    &extent,
+                                    &screen_extent,
+                                    per_frame_index
+                                )?;
+                            }
+                        }
+
+                        Ok(())
+                    }
+                )?;
+
+                // Run the secondary command buffers.
+                self.device
+                    .cmd_execute_commands(command_buffer, command_buffers);
+            },
+            None => {
+                // Render the triangle.
+                self.triangle_renderer.draw(
+                    &self.device,
+                    &extent,
+                    &command_buffer,
+                    self.per_frame_index
+                )?;
+
+                // Render the renderables.
+                for renderable in &mut self.renderables {
+                    renderable.draw(
+                        &self.device,
+                        &extent,
+                        &command_buffer,
+                        self.per_frame_index
+                    )?;
+
+                    // Undo any clipping for the next renderable.
+                    self.device
+                        .reset_scissor(&command_buffer, &extent);
+                }
+
+                // Render the text.
+                if let Some(text_renderer) = &mut self.text_renderer {
+                    text_renderer.draw(
+                        &self.device,
+                        &command_buffer,
+                        &extent,
+                        &self.swapchain.extent(),
+                        self.per_frame_index
+                    )?;
+                }
+            }
+        }
+
+        // Close the triangle debug label.
+        self.device
+            .end_debug_label(&command_buffer);
+
+        // End the render pass.
+        render_pass.end(&self.device, &command_buffer);
+
+        // Close the render pass debug label.
+        self.device
+            .end_debug_label(&command_buffer);
 
         Ok(())
     }
 
+    /// Set the viewport and scissor to cover the whole extent. The
+    /// viewport is flipped if clip space y points up.
+    unsafe fn set_viewport_and_scissor(
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        extent: &vk::Extent2D,
+        clip_space: ClipSpace
+    ) {
+        // Set the viewport state.
+        device.cmd_set_viewport(*command_buffer, 0, &[clip_space.viewport(extent)]);
+
+        // Set the scissor state.
+        device.reset_scissor(command_buffer, extent);
+    }
+
+    /// Call when a resize occurs. The swapchain isn't recreated until
+    /// the next draw, so a burst of resizes, e.g. while dragging the
+    /// window or toggling fullscreen, only recreates it once. Nothing
+    /// is recreated if the size ends up matching the current extent.
+    pub fn resize(&mut self, size: &PhysicalSize<u32>) {
+        // A minimized window has no area to render to. We'll
+        // get another resize when it's restored.
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        // Remember the size for the next draw.
+        self.pending_size = Some(*size);
+    }
+
     /// Recreate the swapchain.
     unsafe fn recreate_swapchain(&mut self, size: Option<&PhysicalSize<u32>>) -> Result<()> {
         // Wait for the device to finish. We must do this or
         // we may be in the middle of rendering on the GPU.
-        self.device.device_wait_idle()?;
+        self.device.wait_idle()?;
 
         // Compute the new size.
         let size = match size {
@@ -316,81 +1441,341 @@ impl Renderer {
             None => self.window.inner_size()
         };
 
+        // Destroy the swapchain and everything that depends on it.
+        self.destroy_swapchain();
+
+        // Create the swapchain and everything that depends on it.
+        self.create_swapchain(&size)
+    }
+
+    /// Recreate a swapchain that's suboptimal, unless it was already
+    /// recreated at this extent and stayed suboptimal. Recreating it
+    /// again wouldn't help and would stall every frame.
+    unsafe fn recreate_suboptimal_swapchain(&mut self) -> Result<()> {
+        if self.suboptimal_extent == Some(self.swapchain.extent()) {
+            return Ok(());
+        }
+
+        debug!(
+            "Swapchain is suboptimal, recreating swapchain: {:?}",
+            self.window.inner_size()
+        );
+
+        self.recreate_swapchain(None)?;
+
+        // Remember the extent in case the new swapchain is suboptimal too.
+        self.suboptimal_extent = Some(self.swapchain.extent());
+
+        Ok(())
+    }
+
+    /// Recreate the surface and the swapchain. Call this when
+    /// the surface is lost, e.g. during a display change.
+    unsafe fn recreate_surface(&mut self) -> Result<()> {
+        // Wait for the device to finish. We must do this or
+        // we may be in the middle of rendering on the GPU.
+        self.device.wait_idle()?;
+
+        // The swapchain must be destroyed before its surface.
+        self.destroy_swapchain();
+
+        // Replace the surface.
+        self.replace_surface()?;
+
+        // Create the swapchain and everything that depends on it.
+        self.create_swapchain(&self.window.inner_size())
+    }
+
+    /// Replace the surface with a new one for the same window. The
+    /// swapchain must already be destroyed when this is called.
+    unsafe fn replace_surface(&mut self) -> Result<()> {
+        // Create the new surface before destroying the old one
+        // so that we're never left holding a destroyed surface.
+        let surface = Surface::new(self.window.clone(), &self.entry, &self.instance)?;
+
+        // Replacing the old surface destroys it.
+        self.surface = surface;
+
+        // The device must still be able to present to the new surface.
+        if !self.surface.supports_presentation(
+            self.device.physical_device(),
+            self.device.queue_family_index()
+        ) {
+            return Err(anyhow!(
+                "The device cannot present to the recreated surface."
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Destroy the swapchain and everything that depends on it.
+    unsafe fn destroy_swapchain(&mut self) {
         // Destroy the frame buffers.
         self.frame_buffers
             .destroy(&self.device);
 
+        // Destroy the multisampled color image.
+        if let Some(color_image) = &mut self.color_image {
+            color_image.destroy(&self.device);
+        }
+
         // Destroy the swapchain.
         self.swapchain.destroy(&self.device);
+    }
 
-        // Create the swapchain wrapper.
-        self.swapchain = Swapchain::new(
-            &size,
+    /// Create the swapchain and everything that depends on it.
+    unsafe fn create_swapchain(&mut self, size: &PhysicalSize<u32>) -> Result<()> {
+        // The destroyed swapchain still knows its format.
+        let previous_format = self.swapchain.format().format;
+
+        // Create the swapchain wrapper. If the surface was lost,
+        // recreate it and try again.
+        self.swapchain = match Swapchain::new(
+            size,
             &self.instance,
             &self.device,
             &self.surface,
-            self.frames_in_flight
-        )?;
+            self.image_count_policy,
+            self.color_space_preference,
+            self.present_mode_preference
+        ) {
+            Err(e) if Surface::is_lost(&e) => {
+                warn!("Surface lost while creating swapchain, recreating surface.");
+
+                self.replace_surface()?;
+
+                Swapchain::new(
+                    size,
+                    &self.instance,
+                    &self.device,
+                    &self.surface,
+                    self.image_count_policy,
+                    self.color_space_preference,
+                    self.present_mode_preference
+                )?
+            },
+            result => result?
+        };
+
+        // The render pass and its pipelines are built for the format.
+        if self.swapchain.format().format != previous_format {
+            self.recreate_render_pass()?;
+        }
+
+        // Create the multisampled color image.
+        self.color_image = Self::new_color_image(&self.device, &self.swapchain, &self.render_pass)?;
 
         // Create the frame buffers wrapper.
-        self.frame_buffers = FrameBuffers::new(&self.device, &self.swapchain, &self.render_pass)?;
+        self.frame_buffers = Self::new_frame_buffers(
+            &self.device,
+            &self.swapchain,
+            &self.render_pass,
+            &self.color_image
+        )?;
+
+        // Point the compute present renderer at the new swapchain images.
+        // A new format may not support compute writes, so drop it if not.
+        if let Some(compute_present_renderer) = &mut self.compute_present_renderer {
+            match ComputePresentRenderer::is_supported(&self.device, &self.swapchain) {
+                true => compute_present_renderer.recreate(&self.device, &self.swapchain)?,
+                false => {
+                    warn!("The new swapchain doesn't support compute present.");
+
+                    compute_present_renderer.destroy(&self.device);
+
+                    self.compute_present_renderer = None;
+                    self.render_mode = RenderMode::Triangle;
+                }
+            }
+        }
+
+        // A scaled target follows the swapchain's size.
+        if let Some(render_scale) = self.render_scale {
+            self.replace_offscreen_target(Some(Self::scaled_extent(
+                &self.swapchain.extent(),
+                render_scale
+            )))?;
+        }
 
         Ok(())
     }
 
-    /// Compute the frames in flight.
-    unsafe fn frames_in_flight(device: &Device, surface: &Surface) -> Result<u32> {
-        let capabilities = surface.capabilities(&device.physical_device())?;
+    /// Rebuild the render pass for the swapchain's format, along with the
+    /// pipelines made for it and the offscreen target, which shares the
+    /// format. The device must be idle.
+    unsafe fn recreate_render_pass(&mut self) -> Result<()> {
+        info!(
+            "Swapchain format changed to {:?}, rebuilding the render pass.",
+            self.swapchain.format()
+        );
+
+        // Create the new render pass with the same sample count.
+        let mut render_pass =
+            RenderPass::new(&self.device, &self.swapchain, self.render_pass.samples())?;
 
-        Ok(match capabilities.max_image_count {
-            0 => max(FRAMES_IN_FLIGHT, capabilities.min_image_count),
-            _ => FRAMES_IN_FLIGHT.clamp(capabilities.min_image_count, capabilities.max_image_count)
-        })
+        render_pass.set_clear_values(Self::clear_values(&render_pass, self.clear_color))?;
+
+        // Swap in the new render pass. The old one is destroyed
+        // the next time the device waits to idle.
+        self.render_pass = render_pass;
+
+        // Rebuild the pipelines for the new render pass.
+        self.triangle_renderer
+            .reload_pipeline(&self.device, &self.pipeline_cache, &self.render_pass)?;
+
+        for renderable in &mut self.renderables {
+            renderable.recreate_pipelines(&self.device, &self.pipeline_cache, &self.render_pass)?;
+        }
+
+        if let Some(text_renderer) = &mut self.text_renderer {
+            text_renderer.recreate_pipelines(
+                &self.device,
+                &self.pipeline_cache,
+                &self.render_pass
+            )?;
+        }
+
+        // A fixed internal resolution keeps its extent. A scaled
+        // target is replaced once the swapchain is complete.
+        if self.render_scale.is_none() {
+            self.replace_offscreen_target(self.internal_resolution())?;
+        }
+
+        Ok(())
+    }
+
+    /// Create the multisampled color image if the render pass is multisampled.
+    unsafe fn new_color_image(
+        device: &Device,
+        swapchain: &Swapchain,
+        render_pass: &RenderPass
+    ) -> Result<Option<AttachmentImage>> {
+        // There's nothing to resolve without multisampling.
+        if render_pass.samples() == vk::SampleCountFlags::TYPE_1 {
+            return Ok(None);
+        }
+
+        let color_image = AttachmentImage::new(
+            device,
+            &ImageSettings {
+                format:            swapchain.format().format,
+                usage:             vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                tiling:            vk::ImageTiling::OPTIMAL,
+                samples:           render_pass.samples(),
+                mip_levels:        1,
+                kind:              ImageKind::Flat,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+            },
+            &swapchain.extent(),
+            vk::ImageAspectFlags::COLOR
+        )?;
+
+        // Name the image for debugging.
+        device.set_object_name(*color_image, "MSAA color image");
+
+        Ok(Some(color_image))
+    }
+
+    /// Create the frame buffers. The multisampled color image, if
+    /// any, comes first and is resolved to the swapchain image.
+    unsafe fn new_frame_buffers(
+        device: &Device,
+        swapchain: &Swapchain,
+        render_pass: &RenderPass,
+        color_image: &Option<AttachmentImage>
+    ) -> Result<FrameBuffers> {
+        FrameBuffers::new(
+            device,
+            swapchain,
+            render_pass,
+            |_, view| match color_image {
+                Some(color_image) => vec![*color_image.view(), view],
+                None => vec![view]
+            }
+        )
+    }
+
+    /// The clear values of a render pass whose attachments are all color.
+    fn clear_values(render_pass: &RenderPass, clear_color: [f32; 4]) -> Vec<vk::ClearValue> {
+        let clear_value = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: clear_color
+            }
+        };
+
+        vec![clear_value; render_pass.attachment_count() as usize]
     }
 }
 
 impl Drop for Renderer {
     fn drop(&mut self) {
         unsafe {
-            // Wait for the device to finish. We must do this or
-            // we may be in the middle of rendering on the GPU.
-            self.device
-                .device_wait_idle()
-                .unwrap();
+            // Wait for the device to finish. We must do this or we may
+            // be in the middle of rendering on the GPU. If the device
+            // was lost, there's nothing left to wait for.
+            if let Err(e) = self.device.wait_idle() {
+                error!("Failed to wait for the device to idle: {}", e);
+            }
 
             // Destroy the per-frame data.
             self.per_frame_data
                 .iter_mut()
                 .for_each(|data| data.destroy(&self.device));
 
+            // Destroy the threaded recorder.
+            if let Some(threaded_recorder) = &mut self.threaded_recorder {
+                threaded_recorder.destroy(&self.device);
+            }
+
+            // Destroy the timeline semaphore.
+            if let Some(timeline_semaphore) = &self.timeline_semaphore {
+                timeline_semaphore.destroy(&self.device);
+            }
+
+            // Destroy the timestamp query pool.
+            if let Some(query_pool) = &mut self.query_pool {
+                query_pool.destroy(&self.device);
+            }
+
+            // Destroy the compute present renderer.
+            if let Some(compute_present_renderer) = &mut self.compute_present_renderer {
+                compute_present_renderer.destroy(&self.device);
+            }
+
+            // Destroy the renderables.
+            self.renderables
+                .iter_mut()
+                .for_each(|renderable| renderable.destroy(&self.device));
+
+            // Destroy the text renderer.
+            if let Some(text_renderer) = &mut self.text_renderer {
+                text_renderer.destroy(&self.device);
+            }
+
             // Destroy the triangle renderer.
             self.triangle_renderer
                 .destroy(&self.device);
 
+            // Destroy the offscreen target.
+            if let Some(offscreen_target) = &mut self.offscreen_target {
+                offscreen_target.destroy(&self.device);
+            }
+
             // Destroy the frame buffers.
             self.frame_buffers
                 .destroy(&self.device);
 
-            // Destroy the render pass.
-            self.render_pass
+            // Save and destroy the pipeline cache.
+            self.pipeline_cache
                 .destroy(&self.device);
 
             // Destroy the swapchain.
             self.swapchain.destroy(&self.device);
 
-            // Destroy the device.
-            self.device.destroy();
-
-            // Destroy the surface.
-            self.surface.destroy();
-
-            // Destroy the debugging data.
-            if let Some(debugging) = &mut self.debugging {
-                debugging.destroy();
-            }
-
-            // Destroy the instance.
-            self.instance.destroy();
+            // The color image and render pass are destroyed by the device
+            // when it's dropped, then the surface, debugging data, and
+            // instance are destroyed as they're dropped.
         }
     }
 }
@@ -0,0 +1,196 @@
+use crate::{
+    ClipSpace, ColorSpacePreference, DeviceRequirements, DeviceSelector, ImageCountPolicy,
+    PresentModePreference, Renderer, ValidationSettings
+};
+use anyhow::Result;
+use ash::vk;
+use std::{path::PathBuf, sync::Arc};
+use winit::window::Window;
+
+/// The default maximum number of frames in flight.
+const FRAMES_IN_FLIGHT: u32 = 2;
+
+/// The default requested number of MSAA samples. This
+/// is clamped to what the device supports.
+const MSAA_SAMPLES: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4;
+
+/// Collects the options of a renderer. Everything but the assets path
+/// has a default that matches what the renderer has always done, so
+/// only the options that differ need to be set.
+#[derive(Clone, Debug)]
+pub struct RendererBuilder {
+    /// The path to the assets directory.
+    pub assets_path: PathBuf,
+
+    /// How to choose the physical device.
+    pub device_selector: DeviceSelector,
+
+    /// The extensions and features the device must or may support.
+    pub requirements: DeviceRequirements,
+
+    /// The validation settings.
+    pub validation: ValidationSettings,
+
+    /// The number of frames that may be in flight at once. This is
+    /// independent of the number of swapchain images.
+    pub frames_in_flight: u32,
+
+    /// The number of samples for multisampling, clamped
+    /// to what the device supports.
+    pub samples: vk::SampleCountFlags,
+
+    /// Which kind of swapchain format to prefer.
+    pub color_space_preference: ColorSpacePreference,
+
+    /// Which present mode to prefer.
+    pub present_mode_preference: PresentModePreference,
+
+    /// How many images to request for the swapchain.
+    pub image_count_policy: ImageCountPolicy,
+
+    /// The color the frame is cleared to.
+    pub clear_color: [f32; 4],
+
+    /// Which way the y axis points in clip space.
+    pub clip_space: ClipSpace,
+
+    /// The render scale, if the scene is rendered at a multiple of
+    /// the window's resolution. See Renderer::set_render_scale.
+    pub render_scale: Option<f32>,
+
+    /// Where the pipeline cache is stored, or none for
+    /// PipelineCache::default_path.
+    pub pipeline_cache_path: Option<PathBuf>,
+
+    /// Whether to create a text renderer for Renderer::draw_text.
+    /// It needs the font atlas and text shaders in the assets.
+    pub text: bool
+}
+
+impl RendererBuilder {
+    /// The default options for the given assets path.
+    pub fn new(assets_path: PathBuf) -> Self {
+        Self {
+            assets_path,
+            device_selector: DeviceSelector::default(),
+            requirements: DeviceRequirements::default(),
+            validation: ValidationSettings::default(),
+            frames_in_flight: FRAMES_IN_FLIGHT,
+            samples: MSAA_SAMPLES,
+            color_space_preference: ColorSpacePreference::default(),
+            present_mode_preference: PresentModePreference::default(),
+            image_count_policy: ImageCountPolicy::default(),
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            clip_space: ClipSpace::default(),
+            render_scale: None,
+            pipeline_cache_path: None,
+            text: false
+        }
+    }
+
+    /// Set how to choose the physical device.
+    pub fn device_selector(mut self, device_selector: DeviceSelector) -> Self {
+        self.device_selector = device_selector;
+
+        self
+    }
+
+    /// Set the extensions and features the device must or may support.
+    /// Start from DeviceRequirements::default to keep what the renderer
+    /// enables on its own.
+    pub fn requirements(mut self, requirements: DeviceRequirements) -> Self {
+        self.requirements = requirements;
+
+        self
+    }
+
+    /// Set the validation settings.
+    pub fn validation(mut self, validation: ValidationSettings) -> Self {
+        self.validation = validation;
+
+        self
+    }
+
+    /// Set the number of frames that may be in flight at once.
+    pub fn frames_in_flight(mut self, frames_in_flight: u32) -> Self {
+        self.frames_in_flight = frames_in_flight;
+
+        self
+    }
+
+    /// Set the number of samples for multisampling.
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+
+        self
+    }
+
+    /// Set which kind of swapchain format to prefer.
+    pub fn color_space_preference(mut self, color_space_preference: ColorSpacePreference) -> Self {
+        self.color_space_preference = color_space_preference;
+
+        self
+    }
+
+    /// Set which present mode to prefer.
+    pub fn present_mode_preference(
+        mut self,
+        present_mode_preference: PresentModePreference
+    ) -> Self {
+        self.present_mode_preference = present_mode_preference;
+
+        self
+    }
+
+    /// Set how many images to request for the swapchain.
+    pub fn image_count_policy(mut self, image_count_policy: ImageCountPolicy) -> Self {
+        self.image_count_policy = image_count_policy;
+
+        self
+    }
+
+    /// Set the color the frame is cleared to.
+    pub fn clear_color(mut self, clear_color: [f32; 4]) -> Self {
+        self.clear_color = clear_color;
+
+        self
+    }
+
+    /// Set which way the y axis points in clip space.
+    pub fn clip_space(mut self, clip_space: ClipSpace) -> Self {
+        self.clip_space = clip_space;
+
+        self
+    }
+
+    /// Set the render scale.
+    pub fn render_scale(mut self, render_scale: Option<f32>) -> Self {
+        self.render_scale = render_scale;
+
+        self
+    }
+
+    /// Set where the pipeline cache is stored.
+    pub fn pipeline_cache_path(mut self, pipeline_cache_path: Option<PathBuf>) -> Self {
+        self.pipeline_cache_path = pipeline_cache_path;
+
+        self
+    }
+
+    /// Set whether to create a text renderer for Renderer::draw_text.
+    pub fn text(mut self, text: bool) -> Self {
+        self.text = text;
+
+        self
+    }
+
+    /// Build the renderer for the given window.
+    ///
+    /// # Safety
+    ///
+    /// The window must outlive the renderer, as its surface refers to the
+    /// window.
+    pub unsafe fn build(&self, window: Arc<Window>) -> Result<Renderer> {
+        Renderer::new(window, self)
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    sync::atomic::{AtomicU32, Ordering}
+};
+
+/// The kinds of resources a tracker counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceKind {
+    /// A buffer.
+    Buffer,
+
+    /// An image.
+    Image
+}
+
+/// The number of live resources of each kind.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceCounts {
+    /// The number of live buffers.
+    pub buffers: u32,
+
+    /// The number of live images.
+    pub images: u32,
+
+    /// The number of live memory allocations. Sub-allocations made
+    /// with gpu-allocator aren't counted, it reports its own leaks.
+    pub allocations: u32
+}
+
+impl ResourceCounts {
+    /// Returns whether there are no live resources.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl Display for ResourceCounts {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} buffers, {} images, {} allocations",
+            self.buffers, self.images, self.allocations
+        )
+    }
+}
+
+/// Counts the buffers and images that have been created but not yet
+/// destroyed, which catches resources that were never destroyed. Memory
+/// allocations are already counted by the device for its allocation limit.
+#[derive(Debug, Default)]
+pub struct ResourceTracker {
+    /// The number of live buffers.
+    buffers: AtomicU32,
+
+    /// The number of live images.
+    images: AtomicU32
+}
+
+impl ResourceTracker {
+    /// Count a resource after it's created.
+    pub fn add(&self, kind: ResourceKind) {
+        self.counter(kind)
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Stop counting a resource after it's destroyed.
+    pub fn remove(&self, kind: ResourceKind) {
+        // Don't wrap around if a resource is removed twice.
+        let _ = self
+            .counter(kind)
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                count.checked_sub(1)
+            });
+    }
+
+    /// Returns the number of live resources of each kind, along
+    /// with the given number of live memory allocations.
+    pub fn counts(&self, allocations: u32) -> ResourceCounts {
+        ResourceCounts {
+            buffers: self.buffers.load(Ordering::Relaxed),
+            images: self.images.load(Ordering::Relaxed),
+            allocations
+        }
+    }
+
+    /// The counter for the given kind of resource.
+    fn counter(&self, kind: ResourceKind) -> &AtomicU32 {
+        match kind {
+            ResourceKind::Buffer => &self.buffers,
+            ResourceKind::Image => &self.images
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{DeferredDestroyQueue, DeferredResource, Device};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use std::{mem::take, ops::Deref, sync::Arc};
+
+/// The sampler settings. The defaults are linear filtering, repeat
+/// addressing, as much anisotropy as is supported, and every mip level.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerSettings {
+    /// The magnification filter.
+    pub mag_filter: vk::Filter,
+
+    /// The minification filter.
+    pub min_filter: vk::Filter,
+
+    /// How mip levels are blended.
+    pub mipmap_mode: vk::SamplerMipmapMode,
+
+    /// The addressing mode for u coordinates outside [0, 1].
+    pub address_mode_u: vk::SamplerAddressMode,
+
+    /// The addressing mode for v coordinates outside [0, 1].
+    pub address_mode_v: vk::SamplerAddressMode,
+
+    /// The addressing mode for w coordinates outside [0, 1].
+    pub address_mode_w: vk::SamplerAddressMode,
+
+    /// The maximum anisotropy, or none to disable anisotropic filtering.
+    /// This is clamped to what the device supports.
+    pub max_anisotropy: Option<f32>,
+
+    /// The bias added to the computed mip level. Negative values pick
+    /// sharper levels and positive values blurrier ones. This is
+    /// clamped to what the device supports.
+    pub mip_lod_bias: f32,
+
+    /// The lowest mip level that may be sampled.
+    pub min_lod: f32,
+
+    /// The highest mip level that may be sampled, or vk::LOD_CLAMP_NONE
+    /// for no limit. Zero only ever samples the first level.
+    pub max_lod: f32,
+
+    /// The color used by the clamp to border addressing mode.
+    pub border_color: vk::BorderColor,
+
+    /// The comparison applied to depth samples, or none to return the
+    /// samples as they are. Shadow maps compare against the fragment depth.
+    pub compare_op: Option<vk::CompareOp>
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            mag_filter:     vk::Filter::LINEAR,
+            min_filter:     vk::Filter::LINEAR,
+            mipmap_mode:    vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: Some(f32::MAX),
+            mip_lod_bias:   0.0,
+            min_lod:        0.0,
+            max_lod:        vk::LOD_CLAMP_NONE,
+            border_color:   vk::BorderColor::INT_OPAQUE_BLACK,
+            compare_op:     None
+        }
+    }
+}
+
+impl SamplerSettings {
+    /// The settings for sampling cubemaps. This is the default with
+    /// clamp to edge addressing, so there are no seams between faces.
+    pub fn cube() -> Self {
+        Self {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..Default::default()
+        }
+    }
+
+    /// The settings with the mip levels limited to those between
+    /// min_lod and max_lod, e.g. to skip the most detailed levels.
+    pub fn with_lod(self, min_lod: f32, max_lod: f32) -> Self {
+        Self {
+            min_lod,
+            max_lod,
+            ..self
+        }
+    }
+
+    /// The settings with the given bias added to the computed mip level.
+    pub fn with_mip_lod_bias(self, mip_lod_bias: f32) -> Self {
+        Self {
+            mip_lod_bias,
+            ..self
+        }
+    }
+
+    /// The settings for sampling shadow maps. Samples are compared
+    /// against the reference depth and filtered, giving soft edges.
+    /// Everything outside the map is clamped to the far plane so it's lit.
+    pub fn shadow() -> Self {
+        Self {
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            max_anisotropy: None,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            compare_op: Some(vk::CompareOp::LESS_OR_EQUAL),
+            ..Default::default()
+        }
+    }
+}
+
+/// Wraps a Vulkan sampler. Dropping the sampler without destroying
+/// it defers its destruction to the device's next wait for idle.
+pub struct Sampler {
+    /// The sampler.
+    sampler: vk::Sampler,
+
+    /// Where the sampler goes if it's dropped without being destroyed.
+    deferred_destroy_queue: Arc<DeferredDestroyQueue>
+}
+
+impl Sampler {
+    /// Create a new sampler.
+    ///
+    /// # Safety
+    ///
+    /// The sampler must be dropped or destroyed before the device is.
+    pub unsafe fn new(device: &Device, settings: &SamplerSettings) -> Result<Self> {
+        if settings.min_lod < 0.0 || settings.min_lod > settings.max_lod {
+            return Err(anyhow!(
+                "The sampler lod range {} to {} is invalid.",
+                settings.min_lod,
+                settings.max_lod
+            ));
+        }
+
+        // Clamp the lod bias to what the device supports.
+        let max_lod_bias = device
+            .properties()
+            .limits
+            .max_sampler_lod_bias;
+        let mip_lod_bias = settings
+            .mip_lod_bias
+            .clamp(-max_lod_bias, max_lod_bias);
+
+        // Anisotropic filtering is only used if the device feature is enabled.
+        let max_anisotropy = match device.anisotropy_enabled() {
+            false => None,
+            true => settings
+                .max_anisotropy
+                .map(|max_anisotropy| {
+                    max_anisotropy.clamp(
+                        1.0,
+                        device
+                            .properties()
+                            .limits
+                            .max_sampler_anisotropy
+                    )
+                })
+        };
+
+        // Create the sampler.
+        let sampler = device.create_sampler(
+            &vk::SamplerCreateInfo::default()
+                .mag_filter(settings.mag_filter)
+                .min_filter(settings.min_filter)
+                .mipmap_mode(settings.mipmap_mode)
+                .address_mode_u(settings.address_mode_u)
+                .address_mode_v(settings.address_mode_v)
+                .address_mode_w(settings.address_mode_w)
+                .anisotropy_enable(max_anisotropy.is_some())
+                .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+                .mip_lod_bias(mip_lod_bias)
+                .min_lod(settings.min_lod)
+                .max_lod(settings.max_lod)
+                .border_color(settings.border_color)
+                .compare_enable(settings.compare_op.is_some())
+                .compare_op(
+                    settings
+                        .compare_op
+                        .unwrap_or(vk::CompareOp::NEVER)
+                ),
+            None
+        )?;
+
+        Ok(Self {
+            sampler,
+            deferred_destroy_queue: device
+                .deferred_destroy_queue()
+                .clone()
+        })
+    }
+
+    /// Destroy the sampler right away.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the sampler.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        if let Some(resource) = self.take_resource() {
+            resource.destroy(device);
+        }
+    }
+
+    /// Take the handle out of the sampler, leaving it destroyed.
+    /// Returns None if the sampler was already destroyed.
+    fn take_resource(&mut self) -> Option<DeferredResource> {
+        if self.sampler == vk::Sampler::null() {
+            return None;
+        }
+
+        Some(DeferredResource::Sampler(take(&mut self.sampler)))
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        if let Some(resource) = self.take_resource() {
+            self.deferred_destroy_queue
+                .push(resource);
+        }
+    }
+}
+
+impl Deref for Sampler {
+    type Target = vk::Sampler;
+
+    fn deref(&self) -> &Self::Target {
+        &self.sampler
+    }
+}
@@ -0,0 +1,76 @@
+use anyhow::Result;
+use notify::{recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::HashSet,
+    fs::canonicalize,
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver}
+};
+use tracing::warn;
+
+/// Watches shader files for changes so that their
+/// pipelines can be rebuilt while the app is running.
+pub struct ShaderWatcher {
+    /// The file watcher. Events stop when this is dropped.
+    _watcher: RecommendedWatcher,
+
+    /// The file events.
+    events: Receiver<notify::Result<Event>>,
+
+    /// The watched files.
+    paths: HashSet<PathBuf>
+}
+
+impl ShaderWatcher {
+    /// Create a new watcher for the given shader files.
+    pub fn new(paths: &[PathBuf]) -> Result<Self> {
+        // Create the watcher.
+        let (sender, events) = channel();
+        let mut watcher = recommended_watcher(sender)?;
+
+        // Event paths are absolute, so ours must be too.
+        let paths = paths
+            .iter()
+            .map(canonicalize)
+            .collect::<Result<HashSet<_>, _>>()?;
+
+        // Watch the parent directories rather than the files. Many
+        // editors save by replacing the file, which would otherwise
+        // stop the watch.
+        let directories = paths
+            .iter()
+            .filter_map(|path| path.parent())
+            .collect::<HashSet<_>>();
+
+        for directory in directories {
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            paths
+        })
+    }
+
+    /// Whether any of the watched files changed since the last call.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+
+        // Drain all pending events.
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) => {
+                    changed |= matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                        && event
+                            .paths
+                            .iter()
+                            .any(|path| self.paths.contains(path));
+                },
+                Err(e) => warn!("Shader watcher error: {}", e)
+            }
+        }
+
+        changed
+    }
+}
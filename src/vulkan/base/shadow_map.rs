@@ -0,0 +1,209 @@
+use crate::{
+    AttachmentImage, BlendMode, ClipSpace, DepthBias, DepthStencilSettings, Device, FrameBuffers,
+    ImageKind, ImageSettings, PipelineSettings, RenderPass, Sampler, SamplerSettings, ShaderSource,
+    VertexDescriptions
+};
+use anyhow::Result;
+use ash::vk;
+
+/// A depth image that the scene is rendered to from a light's point of
+/// view. The main pass samples it with a comparison sampler to find out
+/// which fragments the light can't see. The render pass leaves the image
+/// ready to be sampled, so there's nothing to transition in between.
+pub struct ShadowMap {
+    /// The resolution of the shadow map.
+    extent: vk::Extent2D,
+
+    /// The depth-only render pass.
+    render_pass: RenderPass,
+
+    /// The depth image.
+    depth_image: AttachmentImage,
+
+    /// The frame buffers wrapper. There's only one.
+    frame_buffers: FrameBuffers,
+
+    /// The comparison sampler for reading the shadow map.
+    sampler: Sampler
+}
+
+impl ShadowMap {
+    /// The layout the depth image is in whenever it's sampled.
+    pub const SAMPLED_LAYOUT: vk::ImageLayout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+    /// Create a new square shadow map of the given size.
+    ///
+    /// # Safety
+    ///
+    /// The shadow map must be destroyed before the device is.
+    pub unsafe fn new(device: &Device, size: u32) -> Result<Self> {
+        let extent = vk::Extent2D {
+            width:  size,
+            height: size
+        };
+
+        // The depth format. It must be possible to sample it.
+        let format = device.sampled_depth_format()?;
+
+        // Create the render pass wrapper.
+        let render_pass = RenderPass::new_depth_only(device, format, Self::SAMPLED_LAYOUT)?;
+
+        // Create the depth image.
+        let depth_image = AttachmentImage::new(
+            device,
+            &ImageSettings {
+                format,
+                usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                tiling: vk::ImageTiling::OPTIMAL,
+                samples: vk::SampleCountFlags::TYPE_1,
+                mip_levels: 1,
+                kind: ImageKind::Flat,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+            },
+            &extent,
+            vk::ImageAspectFlags::DEPTH
+        )?;
+
+        // Create the frame buffers wrapper.
+        let frame_buffers = FrameBuffers::new_from_views(
+            device,
+            &[*depth_image.view()],
+            &extent,
+            &render_pass,
+            |_, view| vec![view]
+        )?;
+
+        // Create the sampler.
+        let sampler = Sampler::new(device, &SamplerSettings::shadow())?;
+
+        // Name the objects for debugging.
+        device.set_object_name(*depth_image, "Shadow map depth image");
+        device.set_object_name(*sampler, "Shadow map sampler");
+
+        Ok(Self {
+            extent,
+            render_pass,
+            depth_image,
+            frame_buffers,
+            sampler
+        })
+    }
+
+    /// Returns the resolution of the shadow map.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    /// Returns the depth-only render pass.
+    pub fn render_pass(&self) -> &RenderPass {
+        &self.render_pass
+    }
+
+    /// Returns the view of the depth image, for sampling it in the main pass.
+    pub fn view(&self) -> &vk::ImageView {
+        self.depth_image.view()
+    }
+
+    /// Returns the comparison sampler for reading the shadow map.
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    /// The settings of a depth-only pipeline that renders to the shadow map.
+    /// There's no frag shader, the vert shader only has to output positions
+    /// in the light's clip space. Depth bias is on to avoid shadow acne.
+    pub fn pipeline_settings(
+        &self,
+        vert_shader: ShaderSource,
+        vertex_descriptions: VertexDescriptions,
+        descriptor_set_layouts: Option<Vec<vk::DescriptorSetLayout>>,
+        push_constant_ranges: Vec<vk::PushConstantRange>
+    ) -> PipelineSettings {
+        PipelineSettings {
+            subpass: 0,
+            vert_shader,
+            frag_shader: None,
+            vert_entry: None,
+            frag_entry: None,
+            vert_specialization: vec![],
+            frag_specialization: vec![],
+            vertex_descriptions: Some(vertex_descriptions),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            blend: BlendMode::None,
+            samples: vk::SampleCountFlags::TYPE_1,
+            depth_stencil: Some(DepthStencilSettings::default()),
+            depth_bias: Some(DepthBias::default()),
+            dynamic_depth_bias: false,
+            descriptor_set_layouts,
+            push_constant_ranges
+        }
+    }
+
+    /// Begin the render pass and cover the shadow map with the viewport
+    /// and scissor. Record the shadow casters, then call end.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording outside of a render pass, and end
+    /// must be called before it's used for anything else.
+    pub unsafe fn begin(&self, device: &Device, command_buffer: &vk::CommandBuffer) {
+        // Begin the render pass.
+        self.render_pass.begin(
+            device,
+            &self.extent,
+            &self.frame_buffers,
+            command_buffer,
+            0,
+            vk::SubpassContents::INLINE
+        );
+
+        // Set the viewport state. Like the main pass, clip space y points
+        // up, so the light's projection is built the same way as the camera's.
+        // When sampling, v is then 0.5 - 0.5 * y in the light's clip space.
+        device.cmd_set_viewport(
+            *command_buffer,
+            0,
+            &[ClipSpace::default().viewport(&self.extent)]
+        );
+
+        // Set the scissor state.
+        device.cmd_set_scissor(*command_buffer, 0, &[self.extent.into()]);
+
+        // The line width is dynamic state, even though we only fill.
+        device.set_line_width(command_buffer, 1.0);
+    }
+
+    /// End the render pass. The shadow map is then ready to be sampled.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside the shadow map's render
+    /// pass, begun with begin.
+    pub unsafe fn end(&self, device: &Device, command_buffer: &vk::CommandBuffer) {
+        self.render_pass
+            .end(device, command_buffer);
+    }
+
+    /// Destroy the shadow map.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the shadow map.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the sampler.
+        self.sampler.destroy(device);
+
+        // Destroy the frame buffers.
+        self.frame_buffers.destroy(device);
+
+        // Destroy the depth image.
+        self.depth_image.destroy(device);
+
+        // Destroy the render pass.
+        self.render_pass.destroy(device);
+    }
+}
@@ -1,12 +1,14 @@
 use crate::Instance;
-use anyhow::Result;
+use anyhow::{Error, Result};
 use ash::{vk, Entry};
 use ash_window::create_surface;
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
-use std::{ops::Deref, sync::Arc};
+use std::{mem::take, ops::Deref, sync::Arc};
 use winit::window::Window;
 
-/// Wraps a Vulkan surface.
+/// Wraps a Vulkan surface. The surface is destroyed when it's
+/// dropped, if it wasn't already, so it must be dropped after
+/// its swapchain and before the instance.
 pub struct Surface {
     /// The surface functions.
     functions: ash::khr::surface::Instance,
@@ -79,10 +81,28 @@ impl Surface {
             .unwrap_or(false)
     }
 
-    /// Destroy the surface.
+    /// Whether an error is caused by the surface being lost. The
+    /// surface must be recreated before it can be used again.
+    pub fn is_lost(error: &Error) -> bool {
+        error.downcast_ref::<vk::Result>() == Some(&vk::Result::ERROR_SURFACE_LOST_KHR)
+    }
+
+    /// Destroy the surface. Does nothing if it was already destroyed.
     pub unsafe fn destroy(&mut self) {
+        if self.surface == vk::SurfaceKHR::null() {
+            return;
+        }
+
         self.functions
-            .destroy_surface(self.surface, None);
+            .destroy_surface(take(&mut self.surface), None);
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        unsafe {
+            self.destroy();
+        }
     }
 }
 
@@ -1,8 +1,208 @@
-use crate::{Device, Instance, Surface};
+use crate::{Device, Instance, PresentStatus, Surface};
 use anyhow::{anyhow, Result};
 use ash::vk::{self};
+use tracing::{debug, warn};
 use winit::dpi::PhysicalSize;
 
+/// Our preferred sRGB surface formats, in order of preference.
+const SRGB_FORMATS: [vk::SurfaceFormatKHR; 2] = [
+    vk::SurfaceFormatKHR {
+        format:      vk::Format::B8G8R8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+    },
+    vk::SurfaceFormatKHR {
+        format:      vk::Format::R8G8B8A8_SRGB,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+    }
+];
+
+/// Our preferred UNORM surface formats, in order of preference.
+const UNORM_FORMATS: [vk::SurfaceFormatKHR; 2] = [
+    vk::SurfaceFormatKHR {
+        format:      vk::Format::B8G8R8A8_UNORM,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+    },
+    vk::SurfaceFormatKHR {
+        format:      vk::Format::R8G8B8A8_UNORM,
+        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+    }
+];
+
+/// Our preferred linear extended sRGB (scRGB) surface formats, in order of preference.
+const HDR_LINEAR_FORMATS: [vk::SurfaceFormatKHR; 1] = [vk::SurfaceFormatKHR {
+    format:      vk::Format::R16G16B16A16_SFLOAT,
+    color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+}];
+
+/// Our preferred HDR10 surface formats, in order of preference.
+const HDR10_FORMATS: [vk::SurfaceFormatKHR; 2] = [
+    vk::SurfaceFormatKHR {
+        format:      vk::Format::A2B10G10R10_UNORM_PACK32,
+        color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT
+    },
+    vk::SurfaceFormatKHR {
+        format:      vk::Format::R16G16B16A16_SFLOAT,
+        color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT
+    }
+];
+
+/// Which kind of swapchain format to prefer. For SDR, the display
+/// expects sRGB encoded colors, the difference is who encodes them.
+/// HDR needs the swapchain color space instance extension and a
+/// display that supports it, and falls back to sRGB otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorSpacePreference {
+    /// An sRGB format. The hardware encodes on write, so
+    /// shaders output linear colors. This is the default.
+    #[default]
+    Srgb,
+
+    /// A UNORM format. Nothing is encoded for you, so shaders must
+    /// apply gamma themselves before output, e.g. with linearToSrgb
+    /// from assets/shaders/gamma.glsl. This suits code ported from
+    /// APIs where that's the norm, and UNORM formats more often
+    /// support storage usage for writing with compute shaders.
+    Unorm,
+
+    /// A 16-bit float format in the linear extended sRGB color space.
+    /// Shaders output linear colors, where 1.0 is SDR white and
+    /// anything brighter goes above it.
+    HdrLinear,
+
+    /// A 10-bit or 16-bit format in the HDR10 color space. Shaders
+    /// must output Rec. 2020 colors encoded with the PQ curve.
+    Hdr10
+}
+
+impl ColorSpacePreference {
+    /// The surface formats to look for, in order of preference.
+    pub fn formats(&self) -> &'static [vk::SurfaceFormatKHR] {
+        match self {
+            Self::Srgb => &SRGB_FORMATS,
+            Self::Unorm => &UNORM_FORMATS,
+            Self::HdrLinear => &HDR_LINEAR_FORMATS,
+            Self::Hdr10 => &HDR10_FORMATS
+        }
+    }
+
+    /// Returns true if shaders must encode their output themselves,
+    /// to sRGB for a UNORM format or with the PQ curve for HDR10.
+    pub fn manual_gamma(&self) -> bool {
+        matches!(self, Self::Unorm | Self::Hdr10)
+    }
+
+    /// Returns true if this prefers an HDR color space.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, Self::HdrLinear | Self::Hdr10)
+    }
+
+    /// The preference to fall back to if none of the formats are
+    /// available. HDR falls back to SDR sRGB.
+    pub fn fallback(&self) -> Option<Self> {
+        match self.is_hdr() {
+            true => Some(Self::Srgb),
+            false => None
+        }
+    }
+}
+
+/// Which present mode the swapchain prefers. FIFO is always
+/// supported, so every preference falls back to it eventually.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentModePreference {
+    /// Wait for vertical blank, capping the frame rate to the display's
+    /// refresh rate without tearing. This is the default.
+    #[default]
+    Fifo,
+
+    /// Replace the queued image with the newest one, for low latency
+    /// without tearing. The GPU renders frames that are never shown.
+    Mailbox,
+
+    /// Present right away, which may tear. Useful for benchmarking.
+    Immediate
+}
+
+impl PresentModePreference {
+    /// Every preference, e.g. for listing them in a settings menu.
+    pub const ALL: [Self; 3] = [Self::Fifo, Self::Mailbox, Self::Immediate];
+
+    /// Returns true if the preferred present mode is among the
+    /// given ones, so the swapchain won't have to fall back.
+    pub fn is_supported(&self, present_modes: &[vk::PresentModeKHR]) -> bool {
+        present_modes.contains(&self.present_modes()[0])
+    }
+
+    /// The present modes to look for, in order of preference.
+    pub fn present_modes(&self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            Self::Fifo => &[vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE],
+            Self::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            Self::Immediate => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO
+            ]
+        }
+    }
+}
+
+/// The image count used by the Maximum policy when
+/// the surface doesn't limit the number of images.
+const UNBOUNDED_IMAGE_COUNT: u32 = 8;
+
+/// How many images to request for the swapchain. More images
+/// smooth out frame pacing at the cost of added latency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ImageCountPolicy {
+    /// The minimum the surface supports.
+    Minimum,
+
+    /// One more than the minimum. This lets the application render
+    /// a new image while the others are queued for presentation,
+    /// which makes it a good default, especially for MAILBOX.
+    #[default]
+    MinimumPlusOne,
+
+    /// The maximum the surface supports.
+    Maximum,
+
+    /// An exact count, clamped to the range the surface supports.
+    Exact(u32)
+}
+
+impl ImageCountPolicy {
+    /// Compute the image count for the given surface capabilities.
+    pub fn image_count(&self, capabilities: &vk::SurfaceCapabilitiesKHR) -> u32 {
+        let min = capabilities.min_image_count;
+
+        // A max image count of zero means there is no limit.
+        let max = match capabilities.max_image_count {
+            0 => min.max(UNBOUNDED_IMAGE_COUNT),
+            max => max
+        };
+
+        match self {
+            Self::Minimum => min,
+            Self::MinimumPlusOne => (min + 1).min(max),
+            Self::Maximum => max,
+            Self::Exact(count) => (*count).clamp(min, max)
+        }
+    }
+}
+
+/// A swapchain image that was acquired for rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AcquiredImage {
+    /// The index of the image.
+    pub index: u32,
+
+    /// Whether the swapchain is suboptimal. The image can still be
+    /// rendered to and presented, but the swapchain should be
+    /// recreated after this frame.
+    pub suboptimal: bool
+}
+
 /// Wraps a Vulkan swapchain.
 pub struct Swapchain {
     /// The swapchain functions.
@@ -11,14 +211,28 @@ pub struct Swapchain {
     /// The swapchain.
     swapchain: vk::SwapchainKHR,
 
+    // The swapchain images.
+    images: Vec<vk::Image>,
+
     // The swapchain image views.
     views: Vec<vk::ImageView>,
 
+    // The render done semaphores, one per image. Presentation waits on
+    // them, and an image's semaphore can only be signaled again once
+    // the image is acquired again, which guarantees the wait is over.
+    render_done_semaphores: Vec<vk::Semaphore>,
+
     // The surface format.
     format: vk::SurfaceFormatKHR,
 
+    // The present mode.
+    present_mode: vk::PresentModeKHR,
+
     // The current extent.
-    extent: vk::Extent2D
+    extent: vk::Extent2D,
+
+    // The image usage flags.
+    usage: vk::ImageUsageFlags
 }
 
 impl Swapchain {
@@ -28,107 +242,108 @@ impl Swapchain {
         instance: &Instance,
         device: &Device,
         surface: &Surface,
-        frames_in_flight: u32
+        image_count_policy: ImageCountPolicy,
+        color_space_preference: ColorSpacePreference,
+        present_mode_preference: PresentModePreference
     ) -> Result<Self> {
         let functions = ash::khr::swapchain::Device::new(&instance, &device);
-        let (swapchain, views, format, extent) =
-            Self::make(device, surface, &functions, size, frames_in_flight)?;
 
-        Ok(Self {
+        Self::make(
+            instance,
+            device,
+            surface,
             functions,
-            swapchain,
-            views,
-            format,
-            extent
-        })
+            size,
+            image_count_policy,
+            color_space_preference,
+            present_mode_preference
+        )
     }
 
-    /// Acquire the next image in the swapchain. Returns the index of the acquired image.
-    /// If the returned index is None, it means we need to recreate the swapchain first.
-    pub unsafe fn acquire(&self, semaphore: &vk::Semaphore) -> Result<Option<u32>> {
+    /// Acquire the next image in the swapchain. If None is returned, the
+    /// swapchain is out of date and must be recreated before acquiring.
+    pub unsafe fn acquire(&self, semaphore: &vk::Semaphore) -> Result<Option<AcquiredImage>> {
         match self.functions.acquire_next_image(
             self.swapchain,
             std::u64::MAX,
             *semaphore,
             vk::Fence::null()
         ) {
-            Ok((index, suboptimal)) => match suboptimal {
-                true => Ok(None),
-                false => Ok(Some(index))
-            },
+            Ok((index, suboptimal)) => Ok(Some(AcquiredImage { index, suboptimal })),
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(None),
             Err(e) => Err(e.into())
         }
     }
 
-    /// Present the current image. Returns true if the swapchain should be recreated.
+    /// Present the current image. Returns whether the
+    /// swapchain is suboptimal or out of date.
     pub unsafe fn present(
         &self,
         device: &Device,
         semaphore: &vk::Semaphore,
         present_index: u32
-    ) -> Result<bool> {
-        match self.functions.queue_present(
-            *device.queue(),
+    ) -> Result<PresentStatus> {
+        device.present_queue().present(
+            &self.functions,
             &vk::PresentInfoKHR::default()
                 .wait_semaphores(&[*semaphore])
                 .swapchains(&[self.swapchain])
                 .image_indices(&[present_index])
-        ) {
-            Ok(suboptimal) => Ok(suboptimal),
-            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
-            Err(e) => Err(e.into())
-        }
+        )
     }
 
     /// Create a new swapchain.
     unsafe fn make(
+        instance: &Instance,
         device: &Device,
         surface: &Surface,
-        functions: &ash::khr::swapchain::Device,
+        functions: ash::khr::swapchain::Device,
         size: &PhysicalSize<u32>,
-        frames_in_flight: u32
-    ) -> Result<(
-        vk::SwapchainKHR,
-        Vec<vk::ImageView>,
-        vk::SurfaceFormatKHR,
-        vk::Extent2D
-    )> {
+        image_count_policy: ImageCountPolicy,
+        color_space_preference: ColorSpacePreference,
+        present_mode_preference: PresentModePreference
+    ) -> Result<Self> {
         // Get the available surface formats.
         let available_formats = surface.formats(&device.physical_device())?;
 
-        // TODO: Add this to device selection!
+        // HDR color spaces need the instance extension. Without
+        // it, we go straight to the fallback.
+        let color_space_preference = match color_space_preference.fallback() {
+            Some(fallback) if !instance.swapchain_colorspace_enabled() => {
+                warn!(
+                    "{:?} needs the swapchain color space extension, using {:?}.",
+                    color_space_preference, fallback
+                );
 
-        // Our preferred formats.
-        let preferred_formats = [
-            vk::SurfaceFormatKHR {
-                format:      vk::Format::B8G8R8A8_SRGB,
-                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
+                fallback
             },
-            vk::SurfaceFormatKHR {
-                format:      vk::Format::R8G8B8A8_SRGB,
-                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR
-            }
-        ];
+            _ => color_space_preference
+        };
 
-        // TODO: Select the first one in the list if
-        //  none of our preferences are available.
-
-        // One of our formats must be supported.
-        let format = preferred_formats
-            .into_iter()
-            .find(|x| available_formats.contains(x))
+        // Select the format.
+        let format = Self::select_format(&available_formats, color_space_preference)
             .ok_or_else(|| anyhow!("No suitable swapchain format found."))?;
 
+        // Warn if we couldn't get a preferred format. The fallback may
+        // encode differently than the shaders expect.
+        if !color_space_preference
+            .formats()
+            .contains(&format)
+        {
+            warn!(
+                "No preferred {:?} swapchain format available, using {:?}.",
+                color_space_preference, format
+            );
+        }
+
         // Get the available present modes.
         let available_present_modes = surface.present_modes(&device.physical_device())?;
 
-        // Our preferred present modes.
-        let preferred_present_modes = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
-
-        // On of our present modes must be supported.
-        let present_mode = preferred_present_modes
-            .into_iter()
+        // One of our preferred present modes must be supported.
+        let present_mode = present_mode_preference
+            .present_modes()
+            .iter()
+            .copied()
             .find(|x| available_present_modes.contains(x))
             .ok_or_else(|| anyhow!("No suitable swapchain present mode found."))?;
 
@@ -138,22 +353,69 @@ impl Swapchain {
         // Compute our extent.
         let extent = Self::compute_extent(size, &capabilities)?;
 
+        // Compute the image count.
+        let image_count = image_count_policy.image_count(&capabilities);
+
+        // Get the format properties.
+        let format_properties = instance
+            .get_physical_device_format_properties(*device.physical_device(), format.format);
+
+        // Allow compute shaders to write to the swapchain images if
+        // both the surface and the format support storage usage.
+        let storage_support = capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::STORAGE)
+            && format_properties
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::STORAGE_IMAGE);
+
+        // Compute the image usage.
+        let mut usage = match storage_support {
+            true => vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::STORAGE,
+            false => vk::ImageUsageFlags::COLOR_ATTACHMENT
+        };
+
+        // Allow blitting to the swapchain images, e.g. from an offscreen
+        // target, if the surface supports it. Nearly all of them do.
+        if capabilities
+            .supported_usage_flags
+            .contains(vk::ImageUsageFlags::TRANSFER_DST)
+        {
+            usage |= vk::ImageUsageFlags::TRANSFER_DST;
+        }
+
+        // If graphics and presentation run on different queue families,
+        // share the images between them rather than transferring ownership.
+        let queue_family_indices = [
+            device.queue_family_index(),
+            device.present_queue_family_index()
+        ];
+
+        let sharing_mode = match device.has_dedicated_present_queue() {
+            true => vk::SharingMode::CONCURRENT,
+            false => vk::SharingMode::EXCLUSIVE
+        };
+
         // Create the swapchain info.
-        let swapchain_info = vk::SwapchainCreateInfoKHR::default()
+        let mut swapchain_info = vk::SwapchainCreateInfoKHR::default()
             .surface(**surface)
-            .min_image_count(frames_in_flight)
+            .min_image_count(image_count)
             .image_format(format.format)
             .image_color_space(format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .image_usage(usage)
+            .image_sharing_mode(sharing_mode)
             .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
             .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
             .present_mode(present_mode)
             .clipped(true)
             .old_swapchain(vk::SwapchainKHR::null());
 
+        if sharing_mode == vk::SharingMode::CONCURRENT {
+            swapchain_info = swapchain_info.queue_family_indices(&queue_family_indices);
+        }
+
         // Create the swapchain.
         let swapchain = functions.create_swapchain(&swapchain_info, None)?;
 
@@ -188,7 +450,87 @@ impl Swapchain {
             })
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok((swapchain, views, format, extent))
+        // Name the images and views for debugging.
+        for (i, (image, view)) in images
+            .iter()
+            .zip(&views)
+            .enumerate()
+        {
+            device.set_object_name(*image, &format!("Swapchain image {}", i));
+            device.set_object_name(*view, &format!("Swapchain image view {}", i));
+        }
+
+        // Create the render done semaphores.
+        let render_done_semaphores = Self::new_render_done_semaphores(device, images.len())?;
+
+        // Log what we got. The format and present mode silently fall back
+        // and the driver may create more images than requested.
+        debug!(
+            "Created swapchain: {}x{}, {:?}, {:?}, {:?}, {} images ({} requested).",
+            extent.width,
+            extent.height,
+            format.format,
+            format.color_space,
+            present_mode,
+            images.len(),
+            image_count
+        );
+
+        Ok(Self {
+            functions,
+            swapchain,
+            images,
+            views,
+            render_done_semaphores,
+            format,
+            present_mode,
+            extent,
+            usage
+        })
+    }
+
+    /// Select a surface format, preferring the formats of the given
+    /// preference and falling back to the first available one.
+    /// Returns None if there are no formats at all.
+    pub fn select_format(
+        available_formats: &[vk::SurfaceFormatKHR],
+        color_space_preference: ColorSpacePreference
+    ) -> Option<vk::SurfaceFormatKHR> {
+        // A single undefined format means the surface has no preference,
+        // though only in the sRGB color space, so HDR is out of the question.
+        if let [vk::SurfaceFormatKHR {
+            format: vk::Format::UNDEFINED,
+            ..
+        }] = available_formats
+        {
+            let color_space_preference = color_space_preference
+                .fallback()
+                .unwrap_or(color_space_preference);
+
+            return Some(color_space_preference.formats()[0]);
+        }
+
+        // Find the first available format of the preference.
+        let find = |color_space_preference: ColorSpacePreference| {
+            color_space_preference
+                .formats()
+                .iter()
+                .copied()
+                .find(|x| available_formats.contains(x))
+        };
+
+        find(color_space_preference)
+            .or_else(|| {
+                color_space_preference
+                    .fallback()
+                    .and_then(find)
+            })
+            .or_else(|| available_formats.first().copied())
+    }
+
+    /// The images.
+    pub fn images(&self) -> &Vec<vk::Image> {
+        &self.images
     }
 
     /// The image views.
@@ -196,21 +538,101 @@ impl Swapchain {
         &self.views
     }
 
+    /// The semaphore that rendering to the image at the
+    /// given index signals and its presentation waits on.
+    pub fn render_done_semaphore(&self, present_index: u32) -> vk::Semaphore {
+        self.render_done_semaphores[present_index as usize]
+    }
+
+    /// Replace the render done semaphores. A failed frame can leave one
+    /// signaled with nothing waiting on it, so call this when recovering.
+    /// The device must be idle.
+    ///
+    /// # Safety
+    ///
+    /// The device must be idle, so no submission still waits on or signals the
+    /// old semaphores.
+    pub unsafe fn reset_render_done_semaphores(&mut self, device: &Device) -> Result<()> {
+        self.destroy_render_done_semaphores(device);
+
+        self.render_done_semaphores = Self::new_render_done_semaphores(device, self.images.len())?;
+
+        Ok(())
+    }
+
+    /// Create a render done semaphore for each image.
+    unsafe fn new_render_done_semaphores(
+        device: &Device,
+        count: usize
+    ) -> Result<Vec<vk::Semaphore>> {
+        (0..count)
+            .map(|i| {
+                let semaphore = device.create_semaphore(&Default::default(), None)?;
+
+                device.set_object_name(semaphore, &format!("Swapchain image {} render done", i));
+
+                Ok(semaphore)
+            })
+            .collect()
+    }
+
+    /// Destroy the render done semaphores.
+    unsafe fn destroy_render_done_semaphores(&self, device: &Device) {
+        for semaphore in &self.render_done_semaphores {
+            device.destroy_semaphore(*semaphore, None);
+        }
+    }
+
     /// The current format.
     pub fn format(&self) -> vk::SurfaceFormatKHR {
         self.format
     }
 
+    /// Whether the format is in an HDR color space. This can be false
+    /// even when HDR was preferred, if the display doesn't support it.
+    pub fn is_hdr(&self) -> bool {
+        self.format.color_space != vk::ColorSpaceKHR::SRGB_NONLINEAR
+    }
+
+    /// The present mode.
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    /// The number of images, which may be more than the image count policy asked for.
+    pub fn image_count(&self) -> u32 {
+        self.images.len() as u32
+    }
+
     /// The current extent.
     pub fn extent(&self) -> vk::Extent2D {
         self.extent
     }
 
+    /// The image usage flags.
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        self.usage
+    }
+
     /// Compute the extent of the swapchain.
     unsafe fn compute_extent(
         size: &PhysicalSize<u32>,
         capabilities: &vk::SurfaceCapabilitiesKHR
     ) -> Result<vk::Extent2D> {
+        // The window can outgrow what the surface supports, e.g. when it
+        // goes fullscreen on a large monitor. It's clamped below.
+        if size.width > capabilities.max_image_extent.width
+            || size.height > capabilities.max_image_extent.height
+        {
+            warn!(
+                "Window size {}x{} exceeds the maximum swapchain extent {}x{}, clamping.",
+                size.width,
+                size.height,
+                capabilities.max_image_extent.width,
+                capabilities.max_image_extent.height
+            );
+        }
+
         // If the current extent is set to the int max for both width and height,
         // then compute the extent based on the window size. Otherwise, use the
         // current extent that is provided by the surface.
@@ -235,6 +657,9 @@ impl Swapchain {
 
     /// Destroy the swapchain.
     pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the render done semaphores.
+        self.destroy_render_done_semaphores(device);
+
         // Destroy the image views.
         for view in &self.views {
             device.destroy_image_view(*view, None);
@@ -245,3 +670,81 @@ impl Swapchain {
             .destroy_swapchain(self.swapchain, None);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Surface capabilities with the given image count range.
+    fn capabilities(min_image_count: u32, max_image_count: u32) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            min_image_count,
+            max_image_count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn minimum_is_the_surface_minimum() {
+        assert_eq!(
+            ImageCountPolicy::Minimum.image_count(&capabilities(2, 3)),
+            2
+        );
+        assert_eq!(
+            ImageCountPolicy::Minimum.image_count(&capabilities(3, 0)),
+            3
+        );
+    }
+
+    #[test]
+    fn minimum_plus_one_is_capped_by_the_maximum() {
+        assert_eq!(
+            ImageCountPolicy::MinimumPlusOne.image_count(&capabilities(2, 8)),
+            3
+        );
+        assert_eq!(
+            ImageCountPolicy::MinimumPlusOne.image_count(&capabilities(3, 3)),
+            3
+        );
+        assert_eq!(
+            ImageCountPolicy::MinimumPlusOne.image_count(&capabilities(3, 0)),
+            4
+        );
+    }
+
+    #[test]
+    fn maximum_is_bounded_when_the_surface_is_unbounded() {
+        assert_eq!(
+            ImageCountPolicy::Maximum.image_count(&capabilities(2, 5)),
+            5
+        );
+        assert_eq!(
+            ImageCountPolicy::Maximum.image_count(&capabilities(2, 0)),
+            UNBOUNDED_IMAGE_COUNT
+        );
+        assert_eq!(
+            ImageCountPolicy::Maximum.image_count(&capabilities(10, 0)),
+            10
+        );
+    }
+
+    #[test]
+    fn exact_is_clamped_to_the_surface_range() {
+        assert_eq!(
+            ImageCountPolicy::Exact(4).image_count(&capabilities(2, 8)),
+            4
+        );
+        assert_eq!(
+            ImageCountPolicy::Exact(1).image_count(&capabilities(2, 8)),
+            2
+        );
+        assert_eq!(
+            ImageCountPolicy::Exact(9).image_count(&capabilities(2, 8)),
+            8
+        );
+        assert_eq!(
+            ImageCountPolicy::Exact(20).image_count(&capabilities(2, 0)),
+            UNBOUNDED_IMAGE_COUNT
+        );
+    }
+}
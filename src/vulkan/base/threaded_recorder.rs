@@ -0,0 +1,171 @@
+use crate::{CommandPool, Device};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use std::thread;
+
+/// Records secondary command buffers for a render pass on several
+/// threads at once. Command pools can't be used from more than one
+/// thread, so every thread gets its own pool for every frame in flight.
+/// The primary command buffer then runs them with cmd_execute_commands.
+pub struct ThreadedRecorder {
+    /// The command pools, indexed by frame and then by thread.
+    command_pools: Vec<Vec<CommandPool>>,
+
+    /// The secondary command buffers, indexed by frame and then by thread.
+    command_buffers: Vec<Vec<vk::CommandBuffer>>
+}
+
+impl ThreadedRecorder {
+    /// Create a new recorder with the given number of threads.
+    ///
+    /// # Safety
+    ///
+    /// The recorder must be destroyed before the device is.
+    pub unsafe fn new(device: &Device, thread_count: usize, frames_in_flight: u32) -> Result<Self> {
+        if thread_count == 0 {
+            return Err(anyhow!("A threaded recorder needs at least one thread."));
+        }
+
+        let mut command_pools = Vec::new();
+        let mut command_buffers = Vec::new();
+
+        for frame in 0..frames_in_flight {
+            let mut frame_command_pools = Vec::new();
+            let mut frame_command_buffers = Vec::new();
+
+            for thread in 0..thread_count {
+                // Create the command pool.
+                let command_pool = CommandPool::new(
+                    device,
+                    device.queue_family_index(),
+                    vk::CommandPoolCreateFlags::TRANSIENT
+                )?;
+
+                // Create the secondary command buffer.
+                let command_buffer = command_pool.new_command_buffer(device, false)?;
+
+                // Name the command buffer for debugging.
+                device.set_object_name(
+                    command_buffer,
+                    &format!("Frame {} thread {} command buffer", frame, thread)
+                );
+
+                frame_command_pools.push(command_pool);
+                frame_command_buffers.push(command_buffer);
+            }
+
+            command_pools.push(frame_command_pools);
+            command_buffers.push(frame_command_buffers);
+        }
+
+        Ok(Self {
+            command_pools,
+            command_buffers
+        })
+    }
+
+    /// Returns the number of threads.
+    pub fn thread_count(&self) -> usize {
+        self.command_buffers[0].len()
+    }
+
+    /// Record a secondary command buffer per thread for the given subpass.
+    /// The closure is called on every thread with the thread's index and
+    /// command buffer. Dynamic state isn't inherited from the primary
+    /// command buffer, so the closure must set the viewport and scissor.
+    /// The render pass must have been begun with secondary command buffer
+    /// contents. Returns the command buffers to execute, in thread order.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the command buffers recorded for the per-frame
+    /// index last time. The render pass and frame buffer must be the ones the
+    /// primary command buffer executes them in.
+    pub unsafe fn record<F>(
+        &mut self,
+        device: &Device,
+        per_frame_index: usize,
+        render_pass: &vk::RenderPass,
+        subpass: u32,
+        framebuffer: &vk::Framebuffer,
+        f: F
+    ) -> Result<&[vk::CommandBuffer]>
+    where
+        F: Fn(usize, &vk::CommandBuffer) -> Result<()> + Sync
+    {
+        // Reset the command pools. The caller guarantees that the
+        // GPU is done with this frame's command buffers.
+        for command_pool in &self.command_pools[per_frame_index] {
+            command_pool.reset(device, false)?;
+        }
+
+        let command_buffers = &self.command_buffers[per_frame_index];
+
+        // Record every command buffer on its own thread.
+        let results = thread::scope(|scope| {
+            let handles = command_buffers
+                .iter()
+                .enumerate()
+                .map(|(thread, command_buffer)| {
+                    let f = &f;
+
+                    scope.spawn(move || {
+                        // The render pass the command buffer continues.
+                        let inheritance_info = vk::CommandBufferInheritanceInfo::default()
+                            .render_pass(*render_pass)
+                            .subpass(subpass)
+                            .framebuffer(*framebuffer);
+
+                        // Begin the command buffer.
+                        device.begin_command_buffer(
+                            *command_buffer,
+                            &vk::CommandBufferBeginInfo::default()
+                                .flags(
+                                    vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE
+                                        | vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                                )
+                                .inheritance_info(&inheritance_info)
+                        )?;
+
+                        // Record the commands.
+                        f(thread, command_buffer)?;
+
+                        // End the command buffer.
+                        device.end_command_buffer(*command_buffer)?;
+
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Err(anyhow!("A recording thread panicked.")))
+                })
+                .collect::<Vec<Result<()>>>()
+        });
+
+        // Fail if any thread failed.
+        results
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(command_buffers)
+    }
+
+    /// Destroy the recorder.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with every command buffer the recorder recorded.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the command pools. This frees the command buffers.
+        self.command_pools
+            .iter_mut()
+            .flatten()
+            .for_each(|command_pool| command_pool.destroy(device));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::Device;
+use anyhow::Result;
+use ash::vk;
+use std::{ops::Deref, slice::from_ref};
+
+/// Wraps a Vulkan timeline semaphore. Unlike a binary semaphore, this
+/// holds a counter that only ever increases. Each submission signals
+/// the next value, and the host can wait for any value to be reached.
+pub struct TimelineSemaphore {
+    /// The semaphore.
+    semaphore: vk::Semaphore,
+
+    /// The last value a submission was asked to signal.
+    value: u64
+}
+
+impl TimelineSemaphore {
+    /// Create a new timeline semaphore. The counter starts at zero.
+    ///
+    /// # Safety
+    ///
+    /// Timeline semaphores must be enabled on the device, and the semaphore
+    /// must be destroyed before the device is.
+    pub unsafe fn new(device: &Device) -> Result<Self> {
+        // Create the semaphore.
+        let semaphore = device.create_semaphore(
+            &vk::SemaphoreCreateInfo::default().push_next(
+                &mut vk::SemaphoreTypeCreateInfo::default()
+                    .semaphore_type(vk::SemaphoreType::TIMELINE)
+                    .initial_value(0)
+            ),
+            None
+        )?;
+
+        Ok(Self {
+            semaphore,
+            value: 0
+        })
+    }
+
+    /// Returns the last value a submission was asked to signal.
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+
+    /// Returns the value the next submission should signal.
+    pub fn next_value(&self) -> u64 {
+        self.value + 1
+    }
+
+    /// Record that a submission signaling the next value was made.
+    /// Only call this once the submission succeeded, or waits on
+    /// later values will never finish.
+    pub fn advance(&mut self) {
+        self.value += 1;
+    }
+
+    /// Wait indefinitely for the counter to reach the given value.
+    ///
+    /// # Safety
+    ///
+    /// Something must eventually signal the value, or this never returns.
+    pub unsafe fn wait(&self, device: &Device, value: u64) -> Result<()> {
+        // The counter starts at zero, so there's nothing to wait for.
+        if value == 0 {
+            return Ok(());
+        }
+
+        device.wait_semaphores(
+            &vk::SemaphoreWaitInfo::default()
+                .semaphores(from_ref(&self.semaphore))
+                .values(from_ref(&value)),
+            u64::MAX
+        )?;
+
+        Ok(())
+    }
+
+    /// Destroy the semaphore.
+    ///
+    /// # Safety
+    ///
+    /// No submission may still wait on or signal the semaphore.
+    pub unsafe fn destroy(&self, device: &Device) {
+        device.destroy_semaphore(self.semaphore, None);
+    }
+}
+
+impl Deref for TimelineSemaphore {
+    type Target = vk::Semaphore;
+
+    fn deref(&self) -> &Self::Target {
+        &self.semaphore
+    }
+}
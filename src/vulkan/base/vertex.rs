@@ -0,0 +1,173 @@
+use ash::vk;
+use glam::{Mat4, Vec2, Vec3};
+use std::mem::{offset_of, size_of};
+
+/// A vertex type that can describe its layout to a pipeline.
+pub trait Vertex: Copy {
+    /// Get the binding description.
+    fn bindings() -> vk::VertexInputBindingDescription;
+
+    /// Get the attribute descriptions.
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription>;
+}
+
+/// A vertex with a 2D position and a color.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct PosColorVertex {
+    pub position: Vec2,
+    pub color:    Vec3
+}
+
+impl Vertex for PosColorVertex {
+    fn bindings() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding:    0,
+            stride:     size_of::<PosColorVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 0,
+                format:   vk::Format::R32G32_SFLOAT,
+                offset:   offset_of!(PosColorVertex, position) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 1,
+                format:   vk::Format::R32G32B32_SFLOAT,
+                offset:   offset_of!(PosColorVertex, color) as u32
+            },
+        ]
+    }
+}
+
+/// A vertex with a 2D position, a color, and texture coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct PosColorUvVertex {
+    pub position: Vec2,
+    pub color:    Vec3,
+    pub uv:       Vec2
+}
+
+impl Vertex for PosColorUvVertex {
+    fn bindings() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding:    0,
+            stride:     size_of::<PosColorUvVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 0,
+                format:   vk::Format::R32G32_SFLOAT,
+                offset:   offset_of!(PosColorUvVertex, position) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 1,
+                format:   vk::Format::R32G32B32_SFLOAT,
+                offset:   offset_of!(PosColorUvVertex, color) as u32
+            },
+            vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: 2,
+                format:   vk::Format::R32G32_SFLOAT,
+                offset:   offset_of!(PosColorUvVertex, uv) as u32
+            },
+        ]
+    }
+}
+
+/// A vertex with only a 3D position. Together with ColorVertex in
+/// a second binding, this keeps positions in their own buffer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct PositionVertex {
+    pub position: Vec3
+}
+
+impl Vertex for PositionVertex {
+    fn bindings() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding:    0,
+            stride:     size_of::<PositionVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![vk::VertexInputAttributeDescription {
+            binding:  0,
+            location: 0,
+            format:   vk::Format::R32G32B32_SFLOAT,
+            offset:   offset_of!(PositionVertex, position) as u32
+        }]
+    }
+}
+
+/// A vertex with only a color, for a binding next to PositionVertex.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct ColorVertex {
+    pub color: Vec3
+}
+
+impl Vertex for ColorVertex {
+    fn bindings() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding:    0,
+            stride:     size_of::<ColorVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        vec![vk::VertexInputAttributeDescription {
+            binding:  0,
+            location: 0,
+            format:   vk::Format::R32G32B32_SFLOAT,
+            offset:   offset_of!(ColorVertex, color) as u32
+        }]
+    }
+}
+
+/// Per-instance data with a model matrix. A mat4 takes up four
+/// attribute locations, one per column, so it's described as
+/// four vec4 attributes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct InstanceTransform {
+    pub model: Mat4
+}
+
+impl Vertex for InstanceTransform {
+    fn bindings() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding:    0,
+            stride:     size_of::<InstanceTransform>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE
+        }
+    }
+
+    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
+        (0..4)
+            .map(|column| vk::VertexInputAttributeDescription {
+                binding:  0,
+                location: column,
+                format:   vk::Format::R32G32B32A32_SFLOAT,
+                offset:   (offset_of!(InstanceTransform, model)
+                    + column as usize * size_of::<[f32; 4]>()) as u32
+            })
+            .collect()
+    }
+}
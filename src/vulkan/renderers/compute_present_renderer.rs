@@ -0,0 +1,304 @@
+use crate::{
+    ComputePipeline, ComputePipelineSettings, DescriptorBinding, DescriptorResource,
+    DescriptorSetLayout, Device, Pipeline, PipelineCache, ShaderSource, Swapchain
+};
+use anyhow::{anyhow, Result};
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use std::{mem::size_of, path::Path, time::Instant};
+
+/// The compute shader workgroup size.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Our push constants.
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+struct PushConstants {
+    time: f32
+}
+
+unsafe impl Zeroable for PushConstants {}
+unsafe impl Pod for PushConstants {}
+
+/// Renders directly to the swapchain images with a compute shader.
+/// No render pass or graphics pipeline is involved. The swapchain
+/// images are bound as storage images, so this requires swapchain
+/// storage usage and storage image writes without a format.
+pub struct ComputePresentRenderer {
+    /// The descriptor set layout.
+    descriptor_set_layout: DescriptorSetLayout,
+
+    /// The descriptor pool.
+    descriptor_pool: vk::DescriptorPool,
+
+    /// The descriptor sets, one per swapchain image.
+    descriptor_sets: Vec<vk::DescriptorSet>,
+
+    /// The pipeline.
+    pipeline: ComputePipeline,
+
+    /// The starting time.
+    start_time: Instant
+}
+
+impl ComputePresentRenderer {
+    /// Create a new renderer that writes to the swapchain images with a compute
+    /// shader.
+    ///
+    /// # Safety
+    ///
+    /// The renderer must be destroyed before the device is, and recreated
+    /// whenever the swapchain is.
+    pub unsafe fn new(
+        assets_path: &Path,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        swapchain: &Swapchain
+    ) -> Result<Self> {
+        // We can only write to the swapchain images if they support it.
+        if !Self::is_supported(device, swapchain) {
+            return Err(anyhow!("The swapchain does not support compute writes."));
+        }
+
+        // The shaders this renderer uses.
+        let comp_shader = ShaderSource::File(Pipeline::shader_path(
+            &assets_path.join("shaders/compute_present.comp")
+        ));
+
+        // Create the descriptor set layout.
+        let descriptor_set_layout = DescriptorSetLayout::new(
+            device,
+            &[DescriptorBinding {
+                binding:         0,
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                stage_flags:     vk::ShaderStageFlags::COMPUTE,
+                count:           1
+            }]
+        )?;
+
+        // Create the pipeline.
+        let pipeline = ComputePipeline::new(
+            device,
+            pipeline_cache,
+            &ComputePipelineSettings {
+                comp_shader,
+                comp_entry: None,
+                comp_specialization: vec![],
+                descriptor_set_layouts: Some(vec![*descriptor_set_layout]),
+                push_constant_ranges: vec![vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::COMPUTE,
+                    offset:      0,
+                    size:        size_of::<PushConstants>() as u32
+                }]
+            }
+        )?;
+
+        // Create the descriptor pool and sets.
+        let (descriptor_pool, descriptor_sets) =
+            Self::make_descriptor_sets(device, swapchain, &descriptor_set_layout)?;
+
+        Ok(Self {
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            pipeline,
+            start_time: Instant::now()
+        })
+    }
+
+    /// Whether compute shaders can write to the swapchain images. The
+    /// graphics queue, which records the frame, must support compute.
+    pub fn is_supported(device: &Device, swapchain: &Swapchain) -> bool {
+        device
+            .queue_family_properties()
+            .queue_flags
+            .contains(vk::QueueFlags::COMPUTE)
+            && swapchain
+                .usage()
+                .contains(vk::ImageUsageFlags::STORAGE)
+            && device
+                .features()
+                .shader_storage_image_write_without_format
+                != 0
+    }
+
+    /// Call when the swapchain has been recreated.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the old swapchain images, e.g. the device is
+    /// idle.
+    pub unsafe fn recreate(&mut self, device: &Device, swapchain: &Swapchain) -> Result<()> {
+        // The new swapchain must still support compute writes.
+        if !Self::is_supported(device, swapchain) {
+            return Err(anyhow!("The swapchain does not support compute writes."));
+        }
+
+        // Destroy the descriptor pool, which frees the descriptor sets.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        // Recreate the descriptor pool and sets.
+        (self.descriptor_pool, self.descriptor_sets) =
+            Self::make_descriptor_sets(device, swapchain, &self.descriptor_set_layout)?;
+
+        Ok(())
+    }
+
+    /// Create a descriptor set for each swapchain image.
+    unsafe fn make_descriptor_sets(
+        device: &Device,
+        swapchain: &Swapchain,
+        descriptor_set_layout: &DescriptorSetLayout
+    ) -> Result<(vk::DescriptorPool, Vec<vk::DescriptorSet>)> {
+        // The number of swapchain images.
+        let count = swapchain.views().len() as u32;
+
+        // Create the descriptor pool.
+        let descriptor_pool = descriptor_set_layout.new_pool(device, count)?;
+
+        // Create a descriptor set pointing at each swapchain image.
+        let descriptor_sets = swapchain
+            .views()
+            .iter()
+            .map(|view| {
+                descriptor_set_layout.allocate(
+                    device,
+                    &descriptor_pool,
+                    &[(
+                        0,
+                        DescriptorResource::image(
+                            *view,
+                            vk::Sampler::null(),
+                            vk::ImageLayout::GENERAL
+                        )
+                    )]
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((descriptor_pool, descriptor_sets))
+    }
+
+    /// Draw to the swapchain image.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording outside of a render pass, and the
+    /// swapchain image must have been acquired.
+    pub unsafe fn draw(
+        &mut self,
+        device: &Device,
+        swapchain: &Swapchain,
+        command_buffer: &vk::CommandBuffer,
+        present_index: u32
+    ) -> Result<()> {
+        // Get the extent.
+        let extent = swapchain.extent();
+
+        // Get the swapchain image.
+        let image = swapchain.images()[present_index as usize];
+
+        // The subresource range of the swapchain image.
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask:      vk::ImageAspectFlags::COLOR,
+            base_mip_level:   0,
+            level_count:      1,
+            base_array_layer: 0,
+            layer_count:      1
+        };
+
+        // Prepare the image for shader writes. The source stage
+        // matches the stage that waits on the acquire semaphore.
+        device.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)]
+        );
+
+        // Bind the pipeline.
+        self.pipeline
+            .bind(device, command_buffer);
+
+        // Bind the descriptor set.
+        self.pipeline.bind_descriptor_sets(
+            device,
+            command_buffer,
+            0,
+            &[self.descriptor_sets[present_index as usize]]
+        );
+
+        // Push the constants.
+        let push_constants = PushConstants {
+            time: self
+                .start_time
+                .elapsed()
+                .as_secs_f32()
+        };
+
+        self.pipeline
+            .push_constants(device, command_buffer, 0, &push_constants);
+
+        // Dispatch enough workgroups to cover the image.
+        self.pipeline.dispatch(
+            device,
+            command_buffer,
+            extent
+                .width
+                .div_ceil(WORKGROUP_SIZE),
+            extent
+                .height
+                .div_ceil(WORKGROUP_SIZE),
+            1
+        );
+
+        // Prepare the image for presentation.
+        device.cmd_pipeline_barrier(
+            *command_buffer,
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[vk::ImageMemoryBarrier::default()
+                .old_layout(vk::ImageLayout::GENERAL)
+                .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
+                .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .dst_access_mask(vk::AccessFlags::empty())
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(subresource_range)]
+        );
+
+        Ok(())
+    }
+
+    /// Destroy the renderer.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the renderer.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the pipeline.
+        self.pipeline.destroy(device);
+
+        // Destroy the descriptor pool.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        // Destroy the descriptor set layout.
+        self.descriptor_set_layout
+            .destroy(device);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{
+    BlendMode, ColorVertex, Device, MappedBuffer, MemoryCoherence, Pipeline, PipelineCache,
+    PipelineSettings, PositionVertex, RenderPass, Renderable, ShaderSource, VertexDescriptions
+};
+use anyhow::Result;
+use ash::vk;
+use glam::{Mat4, Vec3};
+use std::{mem::size_of, path::Path};
+
+/// The number of lines the vertex buffers start out with room for.
+const INITIAL_LINE_CAPACITY: usize = 256;
+
+/// The position and color buffers of a frame, which are bound
+/// to separate bindings, and the number of vertices they hold.
+struct LineBuffers {
+    /// The positions, at binding 0.
+    positions: MappedBuffer<PositionVertex>,
+
+    /// The colors, at binding 1.
+    colors: MappedBuffer<ColorVertex>,
+
+    /// The number of vertices the buffers hold.
+    capacity: usize
+}
+
+impl LineBuffers {
+    /// Create new buffers with room for the given number of vertices.
+    unsafe fn new(device: &Device, capacity: usize) -> Result<Self> {
+        // Create the position buffer.
+        let positions = MappedBuffer::new(
+            device,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryCoherence::default(),
+            &vec![PositionVertex::default(); capacity]
+        )?;
+
+        // Create the color buffer.
+        let colors = MappedBuffer::new(
+            device,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryCoherence::default(),
+            &vec![ColorVertex::default(); capacity]
+        )?;
+
+        device.set_object_name(*positions, "Line position buffer");
+        device.set_object_name(*colors, "Line color buffer");
+
+        Ok(Self {
+            positions,
+            colors,
+            capacity
+        })
+    }
+
+    /// Destroy the buffers.
+    unsafe fn destroy(&mut self, device: &Device) {
+        self.positions.destroy(device);
+        self.colors.destroy(device);
+    }
+}
+
+/// Draws 3D line segments, e.g. for debugging. Lines are added with
+/// add_line every frame, uploaded when the renderer draws, and then
+/// cleared. The positions and colors are separate vertex streams in
+/// their own bindings. Each frame in flight has its own buffers,
+/// which grow when a frame has more lines than they have room for.
+pub struct LineRenderer {
+    /// The line positions added since the last draw, two per line.
+    positions: Vec<PositionVertex>,
+
+    /// The line colors added since the last draw, two per line.
+    colors: Vec<ColorVertex>,
+
+    /// The vertex buffers, one set per frame in flight.
+    vertex_buffers: Vec<LineBuffers>,
+
+    /// The view projection matrix the lines are drawn with.
+    view_proj: Mat4,
+
+    /// The width of the lines in pixels. Widths other than 1.0
+    /// need the wide lines feature and are clamped otherwise.
+    line_width: f32,
+
+    /// The pipeline settings, kept for rebuilding the pipeline.
+    pipeline_settings: PipelineSettings,
+
+    /// The pipeline.
+    pipeline: Pipeline
+}
+
+impl LineRenderer {
+    /// Create a new line renderer that draws in the given render pass.
+    ///
+    /// # Safety
+    ///
+    /// The renderer must be destroyed before the device is.
+    pub unsafe fn new(
+        assets_path: &Path,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass,
+        frames_in_flight: u32
+    ) -> Result<Self> {
+        // Create the vertex buffers.
+        let vertex_buffers = (0..frames_in_flight)
+            .map(|_| LineBuffers::new(device, INITIAL_LINE_CAPACITY * 2))
+            .collect::<Result<Vec<_>>>()?;
+
+        // The positions and colors are in separate bindings.
+        let vertex_descriptions =
+            VertexDescriptions::for_vertex::<PositionVertex>().with_vertices::<ColorVertex>();
+
+        // The pipeline settings. Lines have no front or back, so nothing is culled.
+        let pipeline_settings = PipelineSettings {
+            subpass:                0,
+            vert_shader:            ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/line.vert")
+            )),
+            frag_shader:            Some(ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/line.frag")
+            ))),
+            vert_entry:             None,
+            frag_entry:             None,
+            vert_specialization:    vec![],
+            frag_specialization:    vec![],
+            vertex_descriptions:    Some(vertex_descriptions),
+            topology:               vk::PrimitiveTopology::LINE_LIST,
+            primitive_restart:      false,
+            polygon_mode:           vk::PolygonMode::FILL,
+            cull_mode:              vk::CullModeFlags::NONE,
+            front_face:             vk::FrontFace::COUNTER_CLOCKWISE,
+            blend:                  BlendMode::None,
+            samples:                render_pass.samples(),
+            depth_stencil:          None,
+            depth_bias:             None,
+            dynamic_depth_bias:     false,
+            descriptor_set_layouts: None,
+            push_constant_ranges:   vec![vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset:      0,
+                size:        size_of::<Mat4>() as u32
+            }]
+        };
+
+        // Create the pipeline.
+        let pipeline = Pipeline::new(device, pipeline_cache, render_pass, &pipeline_settings)?;
+
+        Ok(Self {
+            positions: Vec::with_capacity(INITIAL_LINE_CAPACITY * 2),
+            colors: Vec::with_capacity(INITIAL_LINE_CAPACITY * 2),
+            vertex_buffers,
+            view_proj: Mat4::IDENTITY,
+            line_width: 1.0,
+            pipeline_settings,
+            pipeline
+        })
+    }
+
+    /// Add a line from a to b for the next draw.
+    pub fn add_line(&mut self, a: Vec3, b: Vec3, color: Vec3) {
+        self.positions.extend([
+            PositionVertex { position: a },
+            PositionVertex { position: b }
+        ]);
+        self.colors
+            .extend([ColorVertex { color }; 2]);
+    }
+
+    /// Returns the number of lines waiting to be drawn.
+    pub fn line_count(&self) -> usize {
+        self.positions.len() / 2
+    }
+
+    /// Set the view projection matrix the lines are drawn with.
+    pub fn set_view_proj(&mut self, view_proj: Mat4) {
+        self.view_proj = view_proj;
+    }
+
+    /// Set the width of the lines in pixels.
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.line_width = line_width;
+    }
+
+    /// Upload the lines and draw them, then clear them for the next
+    /// frame. Call this inside the render pass the renderer was made for.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside the render pass the renderer
+    /// was made for. The GPU must be done with the previous use of the per-
+    /// frame index.
+    pub unsafe fn draw(
+        &mut self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) -> Result<()> {
+        if self.positions.is_empty() {
+            return Ok(());
+        }
+
+        // The number of vertices to draw.
+        let vertex_count = self.positions.len();
+
+        // Grow the frame's buffers if the lines don't fit. The frame's
+        // previous submission has finished, so the old ones can go.
+        let vertex_buffers = &mut self.vertex_buffers[per_frame_index];
+
+        if vertex_count > vertex_buffers.capacity {
+            let new_buffers = LineBuffers::new(device, vertex_count.next_power_of_two())?;
+
+            vertex_buffers.destroy(device);
+            *vertex_buffers = new_buffers;
+        }
+
+        // Upload the lines.
+        vertex_buffers
+            .positions
+            .overwrite_range(0, &self.positions)?;
+        vertex_buffers
+            .colors
+            .overwrite_range(0, &self.colors)?;
+        vertex_buffers
+            .positions
+            .flush(device)?;
+        vertex_buffers
+            .colors
+            .flush(device)?;
+
+        // Bind the pipeline.
+        device.cmd_bind_pipeline(
+            *command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline
+        );
+
+        // Set the line width.
+        device.set_line_width(command_buffer, self.line_width);
+
+        // Push the view projection matrix.
+        self.pipeline.push_constants(
+            device,
+            command_buffer,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            &self.view_proj.to_cols_array()
+        );
+
+        // Bind the position and color buffers.
+        device.cmd_bind_vertex_buffers(
+            *command_buffer,
+            0,
+            &[*vertex_buffers.positions, *vertex_buffers.colors],
+            &[0, 0]
+        );
+
+        // Draw the lines.
+        device.cmd_draw(*command_buffer, vertex_count as u32, 1, 0, 0);
+
+        // Clear the lines for the next frame.
+        self.positions.clear();
+        self.colors.clear();
+
+        Ok(())
+    }
+
+    /// Destroy the renderer.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the renderer.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the pipeline.
+        self.pipeline.destroy(device);
+
+        // Destroy the vertex buffers.
+        self.vertex_buffers
+            .iter_mut()
+            .for_each(|vertex_buffers| vertex_buffers.destroy(device));
+    }
+}
+
+impl Renderable for LineRenderer {
+    unsafe fn draw(
+        &mut self,
+        device: &Device,
+        _extent: &vk::Extent2D,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) -> Result<()> {
+        LineRenderer::draw(self, device, command_buffer, per_frame_index)
+    }
+
+    unsafe fn recreate_pipelines(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass
+    ) -> Result<()> {
+        self.pipeline
+            .recreate(device, pipeline_cache, render_pass, &self.pipeline_settings)
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        LineRenderer::destroy(self, device);
+    }
+}
@@ -1,3 +1,11 @@
+mod compute_present_renderer;
+mod line_renderer;
+mod post_process_pass;
+mod text_renderer;
 mod triangle_renderer;
 
+pub use compute_present_renderer::*;
+pub use line_renderer::*;
+pub use post_process_pass::*;
+pub use text_renderer::*;
 pub use triangle_renderer::*;
@@ -0,0 +1,293 @@
+use crate::{
+    BlendMode, ClipSpace, DescriptorBinding, DescriptorResource, DescriptorSetLayout, Device,
+    Pipeline, PipelineCache, PipelineSettings, RenderPass, Renderable, Sampler, SamplerSettings,
+    ShaderSource, SpecValue
+};
+use anyhow::Result;
+use ash::vk;
+use std::path::Path;
+
+/// The post-process pass settings.
+pub struct PostProcessSettings {
+    /// The frag shader. The shader gets the texture coordinates at location
+    /// 0, the input image at binding 0, and its sampler at binding 1.
+    pub frag_shader: ShaderSource,
+
+    /// The entry point of the frag shader, or none for main.
+    pub frag_entry: Option<String>,
+
+    /// The specialization constants of the frag shader, keyed by constant id.
+    pub frag_specialization: Vec<(u32, SpecValue)>,
+
+    /// The sampler settings for the input image.
+    pub sampler: SamplerSettings
+}
+
+impl PostProcessSettings {
+    /// The settings for the given frag shader. The input is sampled
+    /// with linear filtering and clamped to its edges.
+    pub fn new(frag_shader: ShaderSource) -> Self {
+        Self {
+            frag_shader,
+            frag_entry: None,
+            frag_specialization: vec![],
+            sampler: SamplerSettings {
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                max_anisotropy: None,
+                ..Default::default()
+            }
+        }
+    }
+}
+
+/// Draws a single fullscreen triangle that samples an input image with
+/// a fragment shader, e.g. for tonemapping or color grading. There's no
+/// vertex buffer, the vertex shader derives the corners from the vertex
+/// index. The output is whatever render pass the pass is drawn in, which
+/// must be compatible with the one it was created for.
+pub struct PostProcessPass {
+    /// The descriptor set layout.
+    descriptor_set_layout: DescriptorSetLayout,
+
+    /// The descriptor pool.
+    descriptor_pool: vk::DescriptorPool,
+
+    /// The descriptor set pointing at the input.
+    descriptor_set: vk::DescriptorSet,
+
+    /// The input sampler.
+    sampler: Sampler,
+
+    /// The pipeline settings, kept for rebuilding the pipeline.
+    pipeline_settings: PipelineSettings,
+
+    /// The pipeline.
+    pipeline: Pipeline
+}
+
+impl PostProcessPass {
+    /// Create a new post-process pass that renders to the given render
+    /// pass and reads the given input view. The input must be in the
+    /// given layout whenever the pass is drawn.
+    ///
+    /// # Safety
+    ///
+    /// The input view must outlive the pass, which must be destroyed before the
+    /// device is.
+    pub unsafe fn new(
+        assets_path: &Path,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass,
+        input_view: vk::ImageView,
+        input_layout: vk::ImageLayout,
+        settings: &PostProcessSettings
+    ) -> Result<Self> {
+        // Create the descriptor set layout.
+        let descriptor_set_layout = DescriptorSetLayout::new(
+            device,
+            &[
+                DescriptorBinding {
+                    binding:         0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                    count:           1
+                },
+                DescriptorBinding {
+                    binding:         1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                    count:           1
+                }
+            ]
+        )?;
+
+        // The pipeline settings. The vertex shader draws a fullscreen triangle.
+        let pipeline_settings = PipelineSettings {
+            subpass:                0,
+            vert_shader:            ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/fullscreen.vert")
+            )),
+            frag_shader:            Some(settings.frag_shader.clone()),
+            vert_entry:             None,
+            frag_entry:             settings.frag_entry.clone(),
+            vert_specialization:    vec![],
+            frag_specialization:    settings.frag_specialization.clone(),
+            vertex_descriptions:    None,
+            topology:               vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart:      false,
+            polygon_mode:           vk::PolygonMode::FILL,
+            cull_mode:              vk::CullModeFlags::NONE,
+            front_face:             vk::FrontFace::COUNTER_CLOCKWISE,
+            blend:                  BlendMode::None,
+            samples:                render_pass.samples(),
+            depth_stencil:          None,
+            depth_bias:             None,
+            dynamic_depth_bias:     false,
+            descriptor_set_layouts: Some(vec![*descriptor_set_layout]),
+            push_constant_ranges:   vec![]
+        };
+
+        // Create the pipeline.
+        let pipeline = Pipeline::new(device, pipeline_cache, render_pass, &pipeline_settings)?;
+
+        // Create the sampler.
+        let sampler = Sampler::new(device, &settings.sampler)?;
+
+        // Create the descriptor pool and set.
+        let descriptor_pool = descriptor_set_layout.new_pool(device, 1)?;
+        let descriptor_set = descriptor_set_layout.allocate(
+            device,
+            &descriptor_pool,
+            &Self::descriptor_resources(input_view, input_layout, *sampler)
+        )?;
+
+        // Name the objects for debugging.
+        device.set_object_name(*sampler, "Post-process sampler");
+        device.set_object_name(descriptor_set, "Post-process descriptor set");
+
+        Ok(Self {
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            sampler,
+            pipeline_settings,
+            pipeline
+        })
+    }
+
+    /// Point the pass at a new input, e.g. after the image it read was
+    /// recreated. The pass must not be in use by the GPU.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the pass, and the input must be in the given
+    /// layout whenever the pass is drawn.
+    pub unsafe fn set_input(
+        &self,
+        device: &Device,
+        input_view: vk::ImageView,
+        input_layout: vk::ImageLayout
+    ) -> Result<()> {
+        self.descriptor_set_layout.write(
+            device,
+            &self.descriptor_set,
+            &Self::descriptor_resources(input_view, input_layout, *self.sampler)
+        )
+    }
+
+    /// The descriptor resources for the given input.
+    fn descriptor_resources(
+        input_view: vk::ImageView,
+        input_layout: vk::ImageLayout,
+        sampler: vk::Sampler
+    ) -> [(u32, DescriptorResource); 2] {
+        [
+            (
+                0,
+                DescriptorResource::image(input_view, vk::Sampler::null(), input_layout)
+            ),
+            (
+                1,
+                DescriptorResource::image(
+                    vk::ImageView::null(),
+                    sampler,
+                    vk::ImageLayout::UNDEFINED
+                )
+            )
+        ]
+    }
+
+    /// Draw the fullscreen triangle. Call this inside the output render
+    /// pass, with the scissor covering the output. The triangle's corners
+    /// are in Vulkan's clip space, so the viewport is set to cover the
+    /// output in that convention, replacing a flipped one if there is one.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside a render pass compatible
+    /// with the one the pass was made for, and the input must be in the layout
+    /// it was given.
+    pub unsafe fn draw(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        extent: &vk::Extent2D
+    ) {
+        // Set the viewport state.
+        device.cmd_set_viewport(*command_buffer, 0, &[ClipSpace::YDown.viewport(extent)]);
+
+        // Bind the pipeline.
+        device.cmd_bind_pipeline(
+            *command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline
+        );
+
+        // The line width is dynamic state, even though we only fill.
+        device.set_line_width(command_buffer, 1.0);
+
+        // Bind the descriptor set.
+        device.cmd_bind_descriptor_sets(
+            *command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline.pipeline_layout(),
+            0,
+            &[self.descriptor_set],
+            &[]
+        );
+
+        // Draw the three vertices of the triangle.
+        device.cmd_draw(*command_buffer, 3, 1, 0, 0);
+    }
+
+    /// Destroy the pass.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the pass.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the pipeline.
+        self.pipeline.destroy(device);
+
+        // Destroy the sampler.
+        self.sampler.destroy(device);
+
+        // Destroy the descriptor pool.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        // Destroy the descriptor set layout.
+        self.descriptor_set_layout
+            .destroy(device);
+    }
+}
+
+impl Renderable for PostProcessPass {
+    unsafe fn draw(
+        &mut self,
+        device: &Device,
+        extent: &vk::Extent2D,
+        command_buffer: &vk::CommandBuffer,
+        _per_frame_index: usize
+    ) -> Result<()> {
+        PostProcessPass::draw(self, device, command_buffer, extent);
+
+        Ok(())
+    }
+
+    unsafe fn recreate_pipelines(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass
+    ) -> Result<()> {
+        self.pipeline
+            .recreate(device, pipeline_cache, render_pass, &self.pipeline_settings)
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        PostProcessPass::destroy(self, device);
+    }
+}
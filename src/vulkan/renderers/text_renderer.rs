@@ -0,0 +1,487 @@
+use crate::{
+    BlendMode, ClipSpace, DescriptorBinding, DescriptorResource, DescriptorSetLayout, Device,
+    ImageKind, ImageSettings, ImmutableImage, MappedBuffer, MemoryCoherence, Pipeline,
+    PipelineCache, PipelineSettings, PosColorUvVertex, RenderPass, Renderable, Sampler,
+    SamplerSettings, ShaderSource, VertexDescriptions
+};
+use anyhow::Result;
+use ash::vk;
+use glam::{Mat4, Vec2, Vec3};
+use std::{mem::size_of, path::Path};
+
+/// The number of glyphs the vertex buffers start out with room for.
+const INITIAL_GLYPH_CAPACITY: usize = 256;
+
+/// The number of vertices in a glyph's quad, two triangles.
+const VERTICES_PER_GLYPH: usize = 6;
+
+/// The first character in the font atlas.
+const FIRST_CHAR: u32 = 32;
+
+/// The last character in the font atlas. Its cell is a box,
+/// which is drawn in place of characters the atlas lacks.
+const LAST_CHAR: u32 = 127;
+
+/// The number of columns of cells in the font atlas.
+const ATLAS_COLUMNS: u32 = 16;
+
+/// The number of rows of cells in the font atlas.
+const ATLAS_ROWS: u32 = 6;
+
+/// The width of a glyph relative to its height. The
+/// font is monospaced, so this is also the advance.
+const GLYPH_ASPECT: f32 = 17.0 / 32.0;
+
+/// The vertex buffer of a frame and the number of vertices it holds.
+struct TextBuffer {
+    /// The glyph vertices.
+    vertices: MappedBuffer<PosColorUvVertex>,
+
+    /// The number of vertices the buffer holds.
+    capacity: usize
+}
+
+impl TextBuffer {
+    /// Create a new buffer with room for the given number of vertices.
+    unsafe fn new(device: &Device, capacity: usize) -> Result<Self> {
+        let vertices = MappedBuffer::new(
+            device,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            MemoryCoherence::default(),
+            &vec![PosColorUvVertex::default(); capacity]
+        )?;
+
+        device.set_object_name(*vertices, "Text vertex buffer");
+
+        Ok(Self { vertices, capacity })
+    }
+
+    /// Destroy the buffer.
+    unsafe fn destroy(&mut self, device: &Device) {
+        self.vertices.destroy(device);
+    }
+}
+
+/// Draws screen-space text, e.g. for debug overlays, from a monospaced
+/// font atlas. Text is added with draw_text every frame, uploaded when
+/// the renderer draws, and then cleared. Positions and sizes are in
+/// logical pixels and are multiplied by the scale factor, so text is
+/// the same physical size on every monitor. Each frame in flight has
+/// its own vertex buffer, which grows when a frame has more glyphs
+/// than it has room for.
+pub struct TextRenderer {
+    /// The glyph vertices added since the last draw.
+    vertices: Vec<PosColorUvVertex>,
+
+    /// The vertex buffers, one per frame in flight.
+    vertex_buffers: Vec<TextBuffer>,
+
+    /// The window's scale factor.
+    scale_factor: f32,
+
+    /// The font atlas.
+    font_image: ImmutableImage,
+
+    /// The font atlas sampler.
+    sampler: Sampler,
+
+    /// The descriptor set layout.
+    descriptor_set_layout: DescriptorSetLayout,
+
+    /// The descriptor pool.
+    descriptor_pool: vk::DescriptorPool,
+
+    /// The descriptor set pointing at the font atlas.
+    descriptor_set: vk::DescriptorSet,
+
+    /// The pipeline settings, kept for rebuilding the pipeline.
+    pipeline_settings: PipelineSettings,
+
+    /// The pipeline.
+    pipeline: Pipeline
+}
+
+impl TextRenderer {
+    /// Create a new text renderer that draws in the given render pass.
+    ///
+    /// # Safety
+    ///
+    /// The renderer must be destroyed before the device is.
+    pub unsafe fn new(
+        assets_path: &Path,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass,
+        frames_in_flight: u32
+    ) -> Result<Self> {
+        // Create the vertex buffers.
+        let vertex_buffers = (0..frames_in_flight)
+            .map(|_| TextBuffer::new(device, INITIAL_GLYPH_CAPACITY * VERTICES_PER_GLYPH))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Load the font atlas. The glyph coverage is in its alpha,
+        // which isn't gamma encoded, so the format is linear.
+        let font_image = ImmutableImage::new_from_file(
+            device,
+            &ImageSettings {
+                format:            vk::Format::R8G8B8A8_UNORM,
+                usage:             vk::ImageUsageFlags::SAMPLED,
+                tiling:            vk::ImageTiling::OPTIMAL,
+                samples:           vk::SampleCountFlags::TYPE_1,
+                mip_levels:        1,
+                kind:              ImageKind::Flat,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
+            },
+            &assets_path.join("textures/font.png"),
+            false
+        )?;
+
+        // Create the sampler. Glyphs at the edge of the atlas
+        // mustn't pick up texels from the opposite edge.
+        let sampler = Sampler::new(
+            device,
+            &SamplerSettings {
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                max_anisotropy: None,
+                ..Default::default()
+            }
+        )?;
+
+        // Create the descriptor set layout.
+        let descriptor_set_layout = DescriptorSetLayout::new(
+            device,
+            &[
+                DescriptorBinding {
+                    binding:         0,
+                    descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                    stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                    count:           1
+                },
+                DescriptorBinding {
+                    binding:         1,
+                    descriptor_type: vk::DescriptorType::SAMPLER,
+                    stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                    count:           1
+                }
+            ]
+        )?;
+
+        // Create the descriptor pool and set.
+        let descriptor_pool = descriptor_set_layout.new_pool(device, 1)?;
+        let descriptor_set = descriptor_set_layout.allocate(
+            device,
+            &descriptor_pool,
+            &[
+                (
+                    0,
+                    DescriptorResource::image(
+                        *font_image.view(),
+                        vk::Sampler::null(),
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                    )
+                ),
+                (
+                    1,
+                    DescriptorResource::image(
+                        vk::ImageView::null(),
+                        *sampler,
+                        vk::ImageLayout::UNDEFINED
+                    )
+                )
+            ]
+        )?;
+
+        // The pipeline settings. The glyphs are blended by
+        // their coverage and have no front or back to cull.
+        let pipeline_settings = PipelineSettings {
+            subpass:                0,
+            vert_shader:            ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/text.vert")
+            )),
+            frag_shader:            Some(ShaderSource::File(Pipeline::shader_path(
+                &assets_path.join("shaders/text.frag")
+            ))),
+            vert_entry:             None,
+            frag_entry:             None,
+            vert_specialization:    vec![],
+            frag_specialization:    vec![],
+            vertex_descriptions:    Some(VertexDescriptions::for_vertex::<PosColorUvVertex>()),
+            topology:               vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart:      false,
+            polygon_mode:           vk::PolygonMode::FILL,
+            cull_mode:              vk::CullModeFlags::NONE,
+            front_face:             vk::FrontFace::COUNTER_CLOCKWISE,
+            blend:                  BlendMode::AlphaBlend,
+            samples:                render_pass.samples(),
+            depth_stencil:          None,
+            depth_bias:             None,
+            dynamic_depth_bias:     false,
+            descriptor_set_layouts: Some(vec![*descriptor_set_layout]),
+            push_constant_ranges:   vec![vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset:      0,
+                size:        size_of::<Mat4>() as u32
+            }]
+        };
+
+        // Create the pipeline.
+        let pipeline = Pipeline::new(device, pipeline_cache, render_pass, &pipeline_settings)?;
+
+        // Name the objects for debugging.
+        device.set_object_name(*font_image, "Font atlas");
+        device.set_object_name(*sampler, "Font sampler");
+        device.set_object_name(descriptor_set, "Text descriptor set");
+
+        Ok(Self {
+            vertices: Vec::with_capacity(INITIAL_GLYPH_CAPACITY * VERTICES_PER_GLYPH),
+            vertex_buffers,
+            scale_factor: 1.0,
+            font_image,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_settings,
+            pipeline
+        })
+    }
+
+    /// Returns the scale factor text is drawn with.
+    pub fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    /// Set the scale factor text is drawn with, i.e. the window's.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// Add text for the next draw. The position is the top left corner
+    /// of the first line and the size is the height of a line, both in
+    /// logical pixels. Lines are split at newlines.
+    pub fn draw_text(&mut self, text: &str, position: Vec2, size: f32, color: Vec3) {
+        // The size of a glyph in physical pixels.
+        let glyph_size = Vec2::new(size * GLYPH_ASPECT, size) * self.scale_factor;
+
+        // The top left corner of the next glyph.
+        let origin = position * self.scale_factor;
+        let mut corner = origin;
+
+        // The size of a cell in the atlas in texture coordinates.
+        let cell_size = Vec2::new(1.0 / ATLAS_COLUMNS as f32, 1.0 / ATLAS_ROWS as f32);
+
+        for c in text.chars() {
+            if c == '\n' {
+                corner = Vec2::new(origin.x, corner.y + glyph_size.y);
+
+                continue;
+            }
+
+            // Find the glyph's cell, using the box for characters the atlas lacks.
+            let cell = match c as u32 {
+                c @ FIRST_CHAR..=LAST_CHAR => c - FIRST_CHAR,
+                _ => LAST_CHAR - FIRST_CHAR
+            };
+
+            // The corners of the glyph on screen and in the atlas.
+            let uv =
+                Vec2::new((cell % ATLAS_COLUMNS) as f32, (cell / ATLAS_COLUMNS) as f32) * cell_size;
+            let min = corner;
+            let max = corner + glyph_size;
+
+            let vertex = |x: f32, y: f32, u: f32, v: f32| PosColorUvVertex {
+                position: Vec2::new(x, y),
+                color,
+                uv: Vec2::new(u, v)
+            };
+
+            // Add the glyph's two triangles.
+            let (u0, v0) = (uv.x, uv.y);
+            let (u1, v1) = (uv.x + cell_size.x, uv.y + cell_size.y);
+
+            self.vertices.extend([
+                vertex(min.x, min.y, u0, v0),
+                vertex(min.x, max.y, u0, v1),
+                vertex(max.x, max.y, u1, v1),
+                vertex(min.x, min.y, u0, v0),
+                vertex(max.x, max.y, u1, v1),
+                vertex(max.x, min.y, u1, v0)
+            ]);
+
+            corner.x += glyph_size.x;
+        }
+    }
+
+    /// Clear the text added since the last draw without drawing it.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    /// Returns the number of glyphs waiting to be drawn.
+    pub fn glyph_count(&self) -> usize {
+        self.vertices.len() / VERTICES_PER_GLYPH
+    }
+
+    /// Upload the text and draw it, then clear it for the next frame.
+    /// Call this inside the render pass the renderer was made for.
+    /// The screen extent is the size of the window in physical pixels,
+    /// which text is laid out in. The render target may be smaller,
+    /// e.g. with an internal resolution, and the text scales to fit.
+    /// The viewport is set to cover the render extent in Vulkan's clip
+    /// space, replacing a flipped one if there is one.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside the render pass the renderer
+    /// was made for. The GPU must be done with the previous use of the per-
+    /// frame index.
+    pub unsafe fn draw(
+        &mut self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        extent: &vk::Extent2D,
+        screen_extent: &vk::Extent2D,
+        per_frame_index: usize
+    ) -> Result<()> {
+        if self.vertices.is_empty() {
+            return Ok(());
+        }
+
+        // The number of vertices to draw.
+        let vertex_count = self.vertices.len();
+
+        // Grow the frame's buffer if the text doesn't fit. The frame's
+        // previous submission has finished, so the old one can go.
+        let vertex_buffer = &mut self.vertex_buffers[per_frame_index];
+
+        if vertex_count > vertex_buffer.capacity {
+            let new_buffer = TextBuffer::new(device, vertex_count.next_power_of_two())?;
+
+            vertex_buffer.destroy(device);
+            *vertex_buffer = new_buffer;
+        }
+
+        // Upload the text.
+        vertex_buffer
+            .vertices
+            .overwrite_range(0, &self.vertices)?;
+        vertex_buffer
+            .vertices
+            .flush(device)?;
+
+        // Set the viewport state. The projection below expects y down.
+        device.cmd_set_viewport(*command_buffer, 0, &[ClipSpace::YDown.viewport(extent)]);
+
+        // Bind the pipeline.
+        device.cmd_bind_pipeline(
+            *command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline
+        );
+
+        // The line width is dynamic state, even though we only fill.
+        device.set_line_width(command_buffer, 1.0);
+
+        // Push the projection, which maps physical pixels to clip space.
+        let proj = Mat4::orthographic_rh(
+            0.0,
+            screen_extent.width as f32,
+            0.0,
+            screen_extent.height as f32,
+            -1.0,
+            1.0
+        );
+
+        self.pipeline.push_constants(
+            device,
+            command_buffer,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            &proj.to_cols_array()
+        );
+
+        // Bind the descriptor set.
+        device.cmd_bind_descriptor_sets(
+            *command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline.pipeline_layout(),
+            0,
+            &[self.descriptor_set],
+            &[]
+        );
+
+        // Bind the vertex buffer.
+        device.cmd_bind_vertex_buffers(*command_buffer, 0, &[*vertex_buffer.vertices], &[0]);
+
+        // Draw the glyphs.
+        device.cmd_draw(*command_buffer, vertex_count as u32, 1, 0, 0);
+
+        // Clear the text for the next frame.
+        self.vertices.clear();
+
+        Ok(())
+    }
+
+    /// Destroy the renderer.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with the renderer.
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        // Destroy the pipeline.
+        self.pipeline.destroy(device);
+
+        // Destroy the descriptor pool.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+
+        // Destroy the descriptor set layout.
+        self.descriptor_set_layout
+            .destroy(device);
+
+        // Destroy the sampler.
+        self.sampler.destroy(device);
+
+        // Destroy the font atlas.
+        self.font_image.destroy(device);
+
+        // Destroy the vertex buffers.
+        self.vertex_buffers
+            .iter_mut()
+            .for_each(|vertex_buffer| vertex_buffer.destroy(device));
+    }
+}
+
+impl Renderable for TextRenderer {
+    unsafe fn draw(
+        &mut self,
+        device: &Device,
+        extent: &vk::Extent2D,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) -> Result<()> {
+        TextRenderer::draw(
+            self,
+            device,
+            command_buffer,
+            extent,
+            extent,
+            per_frame_index
+        )
+    }
+
+    unsafe fn recreate_pipelines(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass
+    ) -> Result<()> {
+        self.pipeline
+            .recreate(device, pipeline_cache, render_pass, &self.pipeline_settings)
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        TextRenderer::destroy(self, device);
+    }
+}
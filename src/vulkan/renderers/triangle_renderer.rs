@@ -1,78 +1,38 @@
 use crate::{
-    Device, ImageSettings, ImmutableBuffer, ImmutableImage, MappedBuffer, Pipeline,
-    PipelineSettings, RenderPass, Swapchain, VertexDescriptions
+    BlendMode, ClipSpace, DescriptorBinding, DescriptorResource, DescriptorSetLayout, Device,
+    ImageKind, ImageSettings, ImmutableBuffer, ImmutableImage, InstanceTransform, MemoryCoherence,
+    Pipeline, PipelineCache, PipelineSettings, PosColorUvVertex, RegionBuffer, RenderPass,
+    Renderable, Sampler, SamplerSettings, ShaderSource, VertexDescriptions
 };
 use anyhow::Result;
 use ash::vk::{self};
+use bytemuck::{Pod, Zeroable};
 use glam::{Mat4, Vec3};
 use std::{
-    mem::{offset_of, size_of},
-    path::PathBuf,
+    mem::{replace, size_of},
+    ops::Range,
+    path::{Path, PathBuf},
     time::Instant
 };
 
-/// Our vertex type.
-#[derive(Clone, Copy)]
-#[repr(C)]
-struct Vertex {
-    position: glam::Vec2,
-    color:    glam::Vec3,
-    uv:       glam::Vec2
-}
-
-impl Vertex {
-    /// Get the binding description.
-    fn bindings() -> vk::VertexInputBindingDescription {
-        vk::VertexInputBindingDescription {
-            binding:    0,
-            stride:     size_of::<Vertex>() as u32,
-            input_rate: vk::VertexInputRate::VERTEX
-        }
-    }
-
-    /// Get the attribute descriptions.
-    fn attributes() -> Vec<vk::VertexInputAttributeDescription> {
-        vec![
-            vk::VertexInputAttributeDescription {
-                binding:  0,
-                location: 0,
-                format:   vk::Format::R32G32_SFLOAT,
-                offset:   offset_of!(Vertex, position) as u32
-            },
-            vk::VertexInputAttributeDescription {
-                binding:  0,
-                location: 1,
-                format:   vk::Format::R32G32B32_SFLOAT,
-                offset:   offset_of!(Vertex, color) as u32
-            },
-            vk::VertexInputAttributeDescription {
-                binding:  0,
-                location: 2,
-                format:   vk::Format::R32G32_SFLOAT,
-                offset:   offset_of!(Vertex, uv) as u32
-            },
-        ]
-    }
-}
-
 /// The vertices of our triangle.
-const VERTICES: [Vertex; 4] = [
-    Vertex {
+const VERTICES: [PosColorUvVertex; 4] = [
+    PosColorUvVertex {
         position: glam::Vec2::new(-0.5, -0.5),
         color:    glam::Vec3::new(1.0, 0.0, 0.0),
         uv:       glam::Vec2::new(1.0, 0.0)
     },
-    Vertex {
+    PosColorUvVertex {
         position: glam::Vec2::new(0.5, -0.5),
         color:    glam::Vec3::new(0.0, 1.0, 0.0),
         uv:       glam::Vec2::new(0.0, 0.0)
     },
-    Vertex {
+    PosColorUvVertex {
         position: glam::Vec2::new(0.5, 0.5),
         color:    glam::Vec3::new(0.0, 0.0, 1.0),
         uv:       glam::Vec2::new(0.0, 1.0)
     },
-    Vertex {
+    PosColorUvVertex {
         position: glam::Vec2::new(-0.5, 0.5),
         color:    glam::Vec3::new(1.0, 1.0, 1.0),
         uv:       glam::Vec2::new(1.0, 1.0)
@@ -86,77 +46,129 @@ const INDICES: [u16; 6] = [0, 1, 2, 2, 3, 0];
 #[derive(Clone, Copy, Default)]
 #[repr(C)]
 struct UniformData {
-    model: glam::Mat4,
-    view:  glam::Mat4,
-    proj:  glam::Mat4
+    view: glam::Mat4,
+    proj: glam::Mat4
+}
+
+unsafe impl Zeroable for UniformData {}
+unsafe impl Pod for UniformData {}
+
+/// Our per-object uniform data. Each object gets its own
+/// aligned slot in a dynamic uniform buffer.
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+struct ObjectData {
+    model: glam::Mat4
+}
+
+unsafe impl Zeroable for ObjectData {}
+unsafe impl Pod for ObjectData {}
+
+/// The number of objects we draw.
+const OBJECT_COUNT: usize = 3;
+
+/// The distance between objects along the x axis.
+const OBJECT_SPACING: f32 = 1.25;
+
+/// The number of instances of each object.
+const INSTANCE_COUNT: usize = 2;
+
+/// The distance between instances along the y axis.
+const INSTANCE_SPACING: f32 = 1.25;
+
+/// Our push constants.
+#[derive(Clone, Copy, Default)]
+#[repr(C)]
+struct PushConstants {
+    model: glam::Mat4
 }
 
-/// Per-frame data.
+unsafe impl Zeroable for PushConstants {}
+unsafe impl Pod for PushConstants {}
+
+/// Per-frame data. The uniforms of each frame live in their own region
+/// of the renderer's uniform buffer, followed by the per-object uniforms.
 struct PerFrameData {
-    /// The uniform buffer.
-    uniforms: MappedBuffer<UniformData>,
+    /// The offset of the uniform data in the uniform buffer.
+    uniforms_offset: vk::DeviceSize,
+
+    /// The offset of the first object's uniform data in the uniform
+    /// buffer. Each object is stored at a multiple of the object stride.
+    objects_offset: vk::DeviceSize,
 
-    /// The descriptor set.
-    descriptor_set: vk::DescriptorSet
+    /// The descriptor set, or none if descriptors are pushed.
+    descriptor_set: Option<vk::DescriptorSet>
 }
 
 impl PerFrameData {
     pub unsafe fn new(
         device: &Device,
-        descriptor_pool: &vk::DescriptorPool,
-        descriptor_set_layout: &vk::DescriptorSetLayout,
+        descriptor_pool: Option<&vk::DescriptorPool>,
+        descriptor_set_layout: &DescriptorSetLayout,
         image: &ImmutableImage,
-        sampler: &vk::Sampler
+        sampler: &vk::Sampler,
+        uniforms: &RegionBuffer,
+        region: usize
     ) -> Result<Self> {
-        // Create the uniform buffer.
-        let uniforms = MappedBuffer::new(
-            device,
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
-            &[UniformData::default()]
-        )?;
+        // The per-object uniforms follow the uniform data.
+        let uniforms_offset = uniforms.region_offset(region);
+        let objects_offset = uniforms_offset + TriangleRenderer::objects_offset(device);
+
+        let mut this = Self {
+            uniforms_offset,
+            objects_offset,
+            descriptor_set: None
+        };
 
-        // Create the descriptor set.
-        let descriptor_set = device.allocate_descriptor_sets(
-            &vk::DescriptorSetAllocateInfo::default()
-                .descriptor_pool(*descriptor_pool)
-                .set_layouts(&[*descriptor_set_layout])
-        )?[0];
-
-        // Update the descriptor set.
-        device.update_descriptor_sets(
-            &[
-                vk::WriteDescriptorSet::default()
-                    .dst_set(descriptor_set)
-                    .dst_binding(0)
-                    .dst_array_element(0)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                    .buffer_info(&[vk::DescriptorBufferInfo::default()
-                        .buffer(*uniforms)
-                        .offset(0)
-                        .range(size_of::<UniformData>() as vk::DeviceSize)]),
-                vk::WriteDescriptorSet::default()
-                    .dst_set(descriptor_set)
-                    .dst_binding(1)
-                    .dst_array_element(0)
-                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                    .image_info(&[vk::DescriptorImageInfo::default()
-                        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                        .image_view(*image.view())
-                        .sampler(*sampler)])
-            ],
-            &[]
-        );
+        // Create the descriptor set, unless descriptors are pushed. It
+        // points at the first object, the others are reached with
+        // dynamic offsets.
+        if let Some(descriptor_pool) = descriptor_pool {
+            this.descriptor_set = Some(descriptor_set_layout.allocate(
+                device,
+                descriptor_pool,
+                &this.descriptor_resources(uniforms, image, sampler, 0)
+            )?);
+        }
 
-        Ok(Self {
-            uniforms,
-            descriptor_set
-        })
+        Ok(this)
     }
 
-    /// Destroy the per-frame data.
-    pub unsafe fn destroy(&mut self, device: &Device) {
-        // Destroy the uniform buffer.
-        self.uniforms.destroy(device);
+    /// The resources the shaders read, with the per-object uniforms
+    /// starting at the given offset from the first object.
+    fn descriptor_resources(
+        &self,
+        uniforms: &vk::Buffer,
+        image: &ImmutableImage,
+        sampler: &vk::Sampler,
+        object_offset: vk::DeviceSize
+    ) -> [(u32, DescriptorResource); 3] {
+        [
+            (
+                0,
+                DescriptorResource::buffer(
+                    *uniforms,
+                    self.uniforms_offset,
+                    size_of::<UniformData>() as vk::DeviceSize
+                )
+            ),
+            (
+                1,
+                DescriptorResource::image(
+                    *image.view(),
+                    *sampler,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                )
+            ),
+            (
+                2,
+                DescriptorResource::buffer(
+                    *uniforms,
+                    self.objects_offset + object_offset,
+                    size_of::<ObjectData>() as vk::DeviceSize
+                )
+            )
+        ]
     }
 }
 
@@ -166,7 +178,7 @@ pub struct TriangleRenderer {
     image: ImmutableImage,
 
     /// The image sampler.
-    sampler: vk::Sampler,
+    sampler: Sampler,
 
     /// The vertex buffer.
     vertices: ImmutableBuffer,
@@ -174,11 +186,20 @@ pub struct TriangleRenderer {
     /// The index buffer.
     indices: ImmutableBuffer,
 
+    /// The per-instance transforms.
+    instances: ImmutableBuffer,
+
+    /// The indirect draw command for each object.
+    draw_commands: ImmutableBuffer,
+
     // The descriptor set layout.
-    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_set_layout: DescriptorSetLayout,
+
+    // The descriptor pool, or none if descriptors are pushed.
+    descriptor_pool: Option<vk::DescriptorPool>,
 
-    // The descriptor pool.
-    descriptor_pool: vk::DescriptorPool,
+    /// The uniform buffer, with a region for every frame in flight.
+    uniforms: RegionBuffer,
 
     /// The per-frame data.
     per_frame_data: Vec<PerFrameData>,
@@ -186,9 +207,24 @@ pub struct TriangleRenderer {
     /// The per-frame index.
     per_frame_index: usize,
 
+    /// The stride of an object in the uniform buffer.
+    object_stride: vk::DeviceSize,
+
+    /// The pipeline settings, kept so the pipeline can be rebuilt.
+    pipeline_settings: PipelineSettings,
+
     /// The pipeline.
     pipeline: Pipeline,
 
+    /// The push constants of the current frame.
+    push_constants: PushConstants,
+
+    /// The width of lines when drawing wireframes.
+    line_width: f32,
+
+    /// Which way the y axis points in clip space.
+    clip_space: ClipSpace,
+
     /// The starting time.
     start_time: std::time::Instant
 }
@@ -197,49 +233,33 @@ impl TriangleRenderer {
     pub unsafe fn new(
         assets_path: &PathBuf,
         device: &Device,
+        pipeline_cache: &PipelineCache,
         render_pass: &RenderPass,
         frames_in_flight: u32
     ) -> Result<Self> {
-        // Get the physical device properties.
-        let properties = device.properties();
-
         // The paths this renderer uses.
-        let vert_shader_path = assets_path.join("shaders/shader.vert.spv");
-        let frag_shader_path = assets_path.join("shaders/shader.frag.spv");
+        let vert_shader_path = Pipeline::shader_path(&assets_path.join("shaders/shader.vert"));
+        let frag_shader_path = Pipeline::shader_path(&assets_path.join("shaders/shader.frag"));
         let image_path = assets_path.join("textures/meme.jpg");
 
         // Load the image from disk.
         let image = ImmutableImage::new_from_file(
             device,
             &ImageSettings {
-                format:  vk::Format::R8G8B8A8_SRGB,
-                usage:   vk::ImageUsageFlags::SAMPLED,
-                samples: vk::SampleCountFlags::TYPE_1
+                format:            vk::Format::R8G8B8A8_SRGB,
+                usage:             vk::ImageUsageFlags::SAMPLED,
+                tiling:            vk::ImageTiling::OPTIMAL,
+                samples:           vk::SampleCountFlags::TYPE_1,
+                mip_levels:        1,
+                kind:              ImageKind::Flat,
+                memory_properties: vk::MemoryPropertyFlags::DEVICE_LOCAL
             },
-            &image_path
+            &image_path,
+            true
         )?;
 
         // Create the sampler.
-        let sampler = device.create_sampler(
-            &vk::SamplerCreateInfo::default()
-                .min_filter(vk::Filter::LINEAR)
-                .mag_filter(vk::Filter::LINEAR)
-                .address_mode_u(vk::SamplerAddressMode::REPEAT)
-                .address_mode_v(vk::SamplerAddressMode::REPEAT)
-                .address_mode_w(vk::SamplerAddressMode::REPEAT)
-                .anisotropy_enable(true)
-                .max_anisotropy(
-                    properties
-                        .limits
-                        .max_sampler_anisotropy
-                )
-                .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
-                .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
-                .mip_lod_bias(0.0)
-                .min_lod(0.0)
-                .max_lod(0.0),
-            None
-        )?;
+        let sampler = Sampler::new(device, &SamplerSettings::default())?;
 
         // Create the vertex buffer.
         let vertices =
@@ -248,87 +268,173 @@ impl TriangleRenderer {
         // Create the index buffer.
         let indices = ImmutableBuffer::new(device, vk::BufferUsageFlags::INDEX_BUFFER, &INDICES)?;
 
-        // Create the descriptor set layout.
-        let descriptor_set_layout = device.create_descriptor_set_layout(
-            &vk::DescriptorSetLayoutCreateInfo::default().bindings(&[
-                vk::DescriptorSetLayoutBinding::default()
-                    .binding(0)
-                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                    .descriptor_count(1)
-                    .stage_flags(vk::ShaderStageFlags::VERTEX),
-                vk::DescriptorSetLayoutBinding::default()
-                    .binding(1)
-                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                    .descriptor_count(1)
-                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
-            ]),
-            None
+        // Create the per-instance transforms. The
+        // instances are laid out in a column along y.
+        let transforms = (0..INSTANCE_COUNT)
+            .map(|index| {
+                let y = (index as f32 - (INSTANCE_COUNT - 1) as f32 / 2.0) * INSTANCE_SPACING;
+
+                InstanceTransform {
+                    model: Mat4::from_translation(Vec3::new(0.0, y, 0.0))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Create the instance buffer.
+        let instances =
+            ImmutableBuffer::new(device, vk::BufferUsageFlags::VERTEX_BUFFER, &transforms)?;
+
+        // Create the indirect draw command. Every object
+        // draws all of the instances of the quad.
+        let draw_commands = ImmutableBuffer::new_indirect(
+            device,
+            &[vk::DrawIndexedIndirectCommand {
+                index_count:    INDICES.len() as u32,
+                instance_count: INSTANCE_COUNT as u32,
+                first_index:    0,
+                vertex_offset:  0,
+                first_instance: 0
+            }]
         )?;
 
-        // Create the vertex descriptions.
-        let vertex_descriptions = VertexDescriptions {
-            bindings:   vec![Vertex::bindings()],
-            attributes: Vertex::attributes()
+        // Name the resources for debugging.
+        device.set_object_name(*image, "Triangle texture");
+        device.set_object_name(*sampler, "Triangle sampler");
+        device.set_object_name(*vertices, "Triangle vertex buffer");
+        device.set_object_name(*indices, "Triangle index buffer");
+        device.set_object_name(*instances, "Triangle instance buffer");
+        device.set_object_name(*draw_commands, "Triangle draw commands");
+
+        // Push descriptors can't hold dynamic uniform buffers, so when
+        // pushing, each object's uniforms are pushed at their offset.
+        let push_descriptors = device.push_descriptors_enabled();
+
+        let object_descriptor_type = match push_descriptors {
+            true => vk::DescriptorType::UNIFORM_BUFFER,
+            false => vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+        };
+
+        // The descriptor set bindings.
+        let bindings = [
+            DescriptorBinding {
+                binding:         0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                stage_flags:     vk::ShaderStageFlags::VERTEX,
+                count:           1
+            },
+            DescriptorBinding {
+                binding:         1,
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                stage_flags:     vk::ShaderStageFlags::FRAGMENT,
+                count:           1
+            },
+            DescriptorBinding {
+                binding:         2,
+                descriptor_type: object_descriptor_type,
+                stage_flags:     vk::ShaderStageFlags::VERTEX,
+                count:           1
+            }
+        ];
+
+        // Create the descriptor set layout.
+        let descriptor_set_layout = match push_descriptors {
+            true => DescriptorSetLayout::new_push(device, &bindings)?,
+            false => DescriptorSetLayout::new(device, &bindings)?
         };
 
+        // Create the vertex descriptions.
+        let vertex_descriptions = VertexDescriptions::for_vertex::<PosColorUvVertex>()
+            .with_instances::<InstanceTransform>();
+
         // Create the descriptor set layouts.
-        let descriptor_set_layouts = vec![descriptor_set_layout];
-
-        // Create the descriptor pool.
-        let descriptor_pool = device.create_descriptor_pool(
-            &vk::DescriptorPoolCreateInfo::default()
-                .pool_sizes(&[
-                    vk::DescriptorPoolSize::default()
-                        .ty(vk::DescriptorType::UNIFORM_BUFFER)
-                        .descriptor_count(frames_in_flight),
-                    vk::DescriptorPoolSize::default()
-                        .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                        .descriptor_count(frames_in_flight)
-                ])
-                .max_sets(frames_in_flight),
-            None
+        let descriptor_set_layouts = vec![*descriptor_set_layout];
+
+        // Create the descriptor pool, with a set for every frame in flight.
+        // Pushed descriptors don't need one.
+        let descriptor_pool = match push_descriptors {
+            true => None,
+            false => Some(descriptor_set_layout.new_pool(device, frames_in_flight)?)
+        };
+
+        // Each object's uniforms must start at an aligned offset.
+        let object_stride = device.uniform_buffer_stride(size_of::<ObjectData>() as vk::DeviceSize);
+
+        // Create the uniform buffer. Each frame gets a region holding
+        // its uniform data followed by the per-object uniforms.
+        let uniforms = RegionBuffer::new(
+            device,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            MemoryCoherence::default(),
+            Self::objects_offset(device) + object_stride * OBJECT_COUNT as vk::DeviceSize,
+            frames_in_flight as usize
         )?;
 
+        device.set_object_name(*uniforms, "Triangle uniform buffer");
+
         // Create the per-frame data.
-        let per_frame_data = (0..frames_in_flight)
-            .map(|_| {
+        let per_frame_data = (0..frames_in_flight as usize)
+            .map(|region| {
                 PerFrameData::new(
                     &device,
-                    &descriptor_pool,
+                    descriptor_pool.as_ref(),
                     &descriptor_set_layout,
                     &image,
-                    &sampler
+                    &sampler,
+                    &uniforms,
+                    region
                 )
             })
             .collect::<Result<Vec<_>>>()?;
 
+        // The pipeline settings.
+        let pipeline_settings = PipelineSettings {
+            subpass:                0,
+            vert_shader:            ShaderSource::File(vert_shader_path),
+            frag_shader:            Some(ShaderSource::File(frag_shader_path)),
+            vert_entry:             None,
+            frag_entry:             None,
+            vert_specialization:    vec![],
+            frag_specialization:    vec![],
+            vertex_descriptions:    Some(vertex_descriptions),
+            topology:               vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart:      false,
+            polygon_mode:           vk::PolygonMode::FILL,
+            cull_mode:              vk::CullModeFlags::BACK,
+            front_face:             vk::FrontFace::COUNTER_CLOCKWISE,
+            blend:                  BlendMode::None,
+            samples:                render_pass.samples(),
+            depth_stencil:          None,
+            depth_bias:             None,
+            dynamic_depth_bias:     false,
+            descriptor_set_layouts: Some(descriptor_set_layouts),
+            push_constant_ranges:   vec![vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset:      0,
+                size:        size_of::<PushConstants>() as u32
+            }]
+        };
+
         // Create the pipeline.
-        let pipeline = Pipeline::new(
-            device,
-            render_pass,
-            &PipelineSettings {
-                subpass: 0,
-                vert_shader_path,
-                frag_shader_path,
-                vertex_descriptions: Some(vertex_descriptions),
-                topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-                polygon_mode: vk::PolygonMode::FILL,
-                cull_mode: vk::CullModeFlags::BACK,
-                front_face: vk::FrontFace::COUNTER_CLOCKWISE,
-                descriptor_set_layouts: Some(descriptor_set_layouts)
-            }
-        )?;
+        let pipeline = Pipeline::new(device, pipeline_cache, render_pass, &pipeline_settings)?;
 
         Ok(Self {
             image,
             sampler,
             vertices,
             indices,
+            instances,
+            draw_commands,
             descriptor_set_layout,
             descriptor_pool,
+            uniforms,
             per_frame_data,
             per_frame_index: 0,
+            object_stride,
+            pipeline_settings,
             pipeline,
+            push_constants: PushConstants::default(),
+            line_width: 1.0,
+            clip_space: ClipSpace::default(),
             start_time: Instant::now()
         })
     }
@@ -337,33 +443,70 @@ impl TriangleRenderer {
     pub unsafe fn draw(
         &mut self,
         device: &Device,
-        swapchain: &Swapchain,
+        extent: &vk::Extent2D,
         command_buffer: &vk::CommandBuffer,
         _per_frame_index: usize
     ) -> Result<()> {
-        // Get the extent.
-        let extent = swapchain.extent();
+        // Update the uniforms.
+        self.update(device, extent)?;
+
+        // Record the draws of every object.
+        self.record(device, command_buffer, 0..OBJECT_COUNT)?;
 
+        Ok(())
+    }
+
+    /// Update the uniforms and push constants for this frame. Call
+    /// this once before recording, which may then happen in parallel.
+    ///
+    /// # Safety
+    ///
+    /// The GPU must be done with this frame's region of the uniform buffer,
+    /// e.g. the frame's fence has been waited on.
+    pub unsafe fn update(&mut self, device: &Device, extent: &vk::Extent2D) -> Result<()> {
         // Get our uniform data.
-        let uniform_data = self.get_uniform_data(&extent);
+        let uniform_data = self.get_uniform_data(extent);
 
-        // Get the per-frame data.
-        let per_frame_data = &mut self.per_frame_data[self.per_frame_index];
-        let uniforms = &mut per_frame_data.uniforms;
-        let descriptor_set = &per_frame_data.descriptor_set;
+        // Get our push constants.
+        self.push_constants = self.get_push_constants();
 
-        // Update the uniform buffer.
-        uniforms.overwrite(&[uniform_data])?;
+        // Update this frame's region of the uniform buffer.
+        let region = self.per_frame_index;
 
-        // Bind the descriptor set.
-        device.cmd_bind_descriptor_sets(
-            *command_buffer,
-            vk::PipelineBindPoint::GRAPHICS,
-            *self.pipeline.pipeline_layout(),
-            0,
-            &[*descriptor_set],
-            &[]
-        );
+        self.uniforms
+            .write(region, 0, &uniform_data)?;
+
+        // Update the per-object uniform data.
+        let objects_offset = Self::objects_offset(device);
+
+        for index in 0..OBJECT_COUNT {
+            self.uniforms.write(
+                region,
+                objects_offset + index as vk::DeviceSize * self.object_stride,
+                &Self::get_object_data(index)
+            )?;
+        }
+
+        self.uniforms.flush(device)?;
+
+        Ok(())
+    }
+
+    /// Record the draws of a range of objects. This only reads
+    /// the renderer, so several ranges can be recorded at once.
+    ///
+    /// # Safety
+    ///
+    /// The command buffer must be recording inside the main render pass, and
+    /// update must have been called for this frame.
+    pub unsafe fn record(
+        &self,
+        device: &Device,
+        command_buffer: &vk::CommandBuffer,
+        objects: Range<usize>
+    ) -> Result<()> {
+        // Get the per-frame data.
+        let per_frame_data = &self.per_frame_data[self.per_frame_index];
 
         // First, bind the pipeline.
         device.cmd_bind_pipeline(
@@ -372,29 +515,147 @@ impl TriangleRenderer {
             *self.pipeline
         );
 
-        // Bind the vertex buffer.
-        device.cmd_bind_vertex_buffers(*command_buffer, 0, &[*self.vertices], &[0]);
+        // Set the line width. This only matters for wireframes.
+        device.set_line_width(command_buffer, self.line_width);
+
+        // Push the model matrix.
+        self.pipeline.push_constants(
+            device,
+            command_buffer,
+            vk::ShaderStageFlags::VERTEX,
+            0,
+            &self.push_constants
+        );
+
+        // Bind the vertex and instance buffers.
+        device.cmd_bind_vertex_buffers(
+            *command_buffer,
+            0,
+            &[*self.vertices, *self.instances],
+            &[0, 0]
+        );
 
         // Bind the index buffer.
         device.cmd_bind_index_buffer(*command_buffer, *self.indices, 0, vk::IndexType::UINT16);
 
-        // Issue the draw command.
-        device.cmd_draw_indexed(*command_buffer, INDICES.len() as u32, 1, 0, 0, 0);
+        for index in objects {
+            // The offset of this object's uniforms.
+            let object_offset = index as vk::DeviceSize * self.object_stride;
+
+            match &per_frame_data.descriptor_set {
+                // Bind the descriptor set, pointing at this object's uniforms.
+                Some(descriptor_set) => device.cmd_bind_descriptor_sets(
+                    *command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    *self.pipeline.pipeline_layout(),
+                    0,
+                    &[*descriptor_set],
+                    &[object_offset as u32]
+                ),
+                // Push the descriptors, pointing at this object's uniforms.
+                None => self.descriptor_set_layout.push(
+                    device,
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline.pipeline_layout(),
+                    0,
+                    &per_frame_data.descriptor_resources(
+                        &self.uniforms,
+                        &self.image,
+                        &self.sampler,
+                        object_offset
+                    )
+                )?
+            }
+
+            // Issue the draw command, drawing every instance.
+            device.draw_indexed_indirect(
+                command_buffer,
+                &self.draw_commands,
+                0,
+                1,
+                size_of::<vk::DrawIndexedIndirectCommand>() as u32
+            );
+        }
 
         Ok(())
     }
 
-    /// Update the uniform data.
-    unsafe fn get_uniform_data(&self, extent: &vk::Extent2D) -> UniformData {
-        // Get the elapsed time in seconds.
-        let elapsed = self
-            .start_time
-            .elapsed()
-            .as_secs_f32();
+    /// Returns true if the triangles are drawn as wireframes.
+    pub fn wireframe(&self) -> bool {
+        self.pipeline_settings.polygon_mode == vk::PolygonMode::LINE
+    }
 
-        // Compute the model matrix.
-        let model = Mat4::from_rotation_z(90.0_f32.to_radians() * elapsed);
+    /// Draw the triangles as wireframes or filled. The pipeline is only
+    /// rebuilt if the polygon mode changes. On failure the current
+    /// pipeline and polygon mode are kept.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be the one the renderer draws in. This waits for
+    /// the device to go idle, so no other thread may be submitting work
+    /// meanwhile.
+    pub unsafe fn set_wireframe(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass,
+        wireframe: bool
+    ) -> Result<()> {
+        let polygon_mode = match wireframe {
+            true => vk::PolygonMode::LINE,
+            false => vk::PolygonMode::FILL
+        };
+
+        // Nothing to do if the polygon mode is unchanged.
+        if polygon_mode == self.pipeline_settings.polygon_mode {
+            return Ok(());
+        }
+
+        // Rebuild the pipeline with the new polygon mode.
+        let previous_polygon_mode = replace(&mut self.pipeline_settings.polygon_mode, polygon_mode);
+
+        if let Err(e) =
+            self.pipeline
+                .recreate(device, pipeline_cache, render_pass, &self.pipeline_settings)
+        {
+            self.pipeline_settings.polygon_mode = previous_polygon_mode;
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the width of lines when drawing wireframes.
+    pub fn line_width(&self) -> f32 {
+        self.line_width
+    }
+
+    /// Set the width of lines when drawing wireframes. The line width is
+    /// dynamic state, so this takes effect without rebuilding the pipeline.
+    pub fn set_line_width(&mut self, line_width: f32) {
+        self.line_width = line_width;
+    }
+
+    /// Returns which way the y axis points in clip space.
+    pub fn clip_space(&self) -> ClipSpace {
+        self.clip_space
+    }
+
+    /// Set which way the y axis points in clip space. This must match
+    /// the viewport the triangles are drawn with, see ClipSpace::viewport.
+    pub fn set_clip_space(&mut self, clip_space: ClipSpace) {
+        self.clip_space = clip_space;
+    }
 
+    /// Returns the number of objects drawn each frame.
+    pub fn object_count(&self) -> usize {
+        OBJECT_COUNT
+    }
+
+    /// Update the uniform data.
+    unsafe fn get_uniform_data(&self, extent: &vk::Extent2D) -> UniformData {
         // Compute the view matrix.
         let view = Mat4::look_at_rh(
             Vec3::new(2.0, 2.0, 2.0),
@@ -410,10 +671,78 @@ impl TriangleRenderer {
             10.0
         );
 
-        // Invert the y axis.
-        proj.y_axis.y *= -1.0;
+        // Invert the y axis, unless the viewport already does.
+        if self.clip_space == ClipSpace::YDown {
+            proj.y_axis.y *= -1.0;
+        }
+
+        UniformData { view, proj }
+    }
+
+    /// The offset of the per-object uniforms within a region of the
+    /// uniform buffer. They follow the uniform data, at an aligned offset.
+    fn objects_offset(device: &Device) -> vk::DeviceSize {
+        device.uniform_buffer_stride(size_of::<UniformData>() as vk::DeviceSize)
+    }
+
+    /// Get the uniform data of an object. The
+    /// objects are laid out in a row along x.
+    fn get_object_data(index: usize) -> ObjectData {
+        // Center the row on the origin.
+        let x = (index as f32 - (OBJECT_COUNT - 1) as f32 / 2.0) * OBJECT_SPACING;
+
+        // Compute the model matrix.
+        let model = Mat4::from_translation(Vec3::new(x, 0.0, 0.0));
+
+        ObjectData { model }
+    }
+
+    /// Get the push constants.
+    fn get_push_constants(&self) -> PushConstants {
+        // Get the elapsed time in seconds.
+        let elapsed = self
+            .start_time
+            .elapsed()
+            .as_secs_f32();
+
+        // Compute the model matrix.
+        let model = Mat4::from_rotation_z(90.0_f32.to_radians() * elapsed);
+
+        PushConstants { model }
+    }
+
+    /// The shader files the pipeline is built from. Shaders
+    /// that aren't files are left out.
+    pub fn shader_paths(&self) -> Vec<PathBuf> {
+        [
+            Some(&self.pipeline_settings.vert_shader),
+            self.pipeline_settings
+                .frag_shader
+                .as_ref()
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|shader| shader.path())
+        .map(Path::to_path_buf)
+        .collect()
+    }
 
-        UniformData { model, view, proj }
+    /// Rebuild the pipeline, e.g. after its shaders changed. On
+    /// failure the current pipeline is kept.
+    ///
+    /// # Safety
+    ///
+    /// The render pass must be the one the renderer draws in. This waits for
+    /// the device to go idle, so no other thread may be submitting work
+    /// meanwhile.
+    pub unsafe fn reload_pipeline(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass
+    ) -> Result<()> {
+        self.pipeline
+            .recreate(device, pipeline_cache, render_pass, &self.pipeline_settings)
     }
 
     /// Destroy the renderer.
@@ -421,16 +750,23 @@ impl TriangleRenderer {
         // Destroy the pipeline.
         self.pipeline.destroy(device);
 
-        // Destroy the per-frame data.
-        self.per_frame_data
-            .iter_mut()
-            .for_each(|data| data.destroy(device));
+        // Destroy the uniform buffer.
+        self.uniforms.destroy(device);
 
         // Destroy the descriptor pool.
-        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        if let Some(descriptor_pool) = self.descriptor_pool {
+            device.destroy_descriptor_pool(descriptor_pool, None);
+        }
 
         // Destroy the descriptor set layout.
-        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        self.descriptor_set_layout
+            .destroy(device);
+
+        // Destroy the indirect draw commands.
+        self.draw_commands.destroy(device);
+
+        // Destroy the instance buffer.
+        self.instances.destroy(device);
 
         // Destroy the index buffer.
         self.indices.destroy(device);
@@ -439,9 +775,34 @@ impl TriangleRenderer {
         self.vertices.destroy(device);
 
         // Destroy the sampler.
-        device.destroy_sampler(self.sampler, None);
+        self.sampler.destroy(device);
 
         // Destroy the image.
         self.image.destroy(device);
     }
 }
+
+impl Renderable for TriangleRenderer {
+    unsafe fn draw(
+        &mut self,
+        device: &Device,
+        extent: &vk::Extent2D,
+        command_buffer: &vk::CommandBuffer,
+        per_frame_index: usize
+    ) -> Result<()> {
+        TriangleRenderer::draw(self, device, extent, command_buffer, per_frame_index)
+    }
+
+    unsafe fn recreate_pipelines(
+        &mut self,
+        device: &Device,
+        pipeline_cache: &PipelineCache,
+        render_pass: &RenderPass
+    ) -> Result<()> {
+        self.reload_pipeline(device, pipeline_cache, render_pass)
+    }
+
+    unsafe fn destroy(&mut self, device: &Device) {
+        TriangleRenderer::destroy(self, device);
+    }
+}
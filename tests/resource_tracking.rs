@@ -0,0 +1,82 @@
+use anyhow::Result;
+use ash::vk;
+use vulkan::{
+    assets_path, DeviceRequirements, DeviceSelector, HeadlessRenderer, ValidationSettings,
+    VulkanError
+};
+
+/// The size of the rendered image.
+const EXTENT: vk::Extent2D = vk::Extent2D {
+    width:  64,
+    height: 64
+};
+
+/// The number of frames to render.
+const FRAME_COUNT: usize = 3;
+
+/// Rendering and reading back frames must destroy every buffer,
+/// image, and allocation it creates along the way.
+#[test]
+fn rendering_destroys_its_resources() -> Result<()> {
+    // Create the headless renderer. Machines without a Vulkan device
+    // can't run this, so we skip it. Any other error is a failure.
+    let renderer = unsafe {
+        HeadlessRenderer::new(
+            assets_path()?,
+            EXTENT,
+            &DeviceSelector::Auto,
+            &DeviceRequirements::default(),
+            &ValidationSettings {
+                collect_messages: true,
+                ..Default::default()
+            }
+        )
+    };
+
+    let mut renderer = match renderer {
+        Ok(renderer) => renderer,
+        Err(e) if VulkanError::is_no_suitable_device(&e) => {
+            eprintln!("Skipping, no Vulkan device is available: {}", e);
+
+            return Ok(());
+        },
+        Err(e) => return Err(e)
+    };
+
+    // Resources are only tracked in debug builds.
+    let Some(live_before) = renderer
+        .device()
+        .live_resource_counts()
+    else {
+        return Ok(());
+    };
+
+    // Render some frames and read them back.
+    for _ in 0..FRAME_COUNT {
+        unsafe {
+            renderer.draw()?;
+            renderer.screenshot()?;
+        }
+    }
+
+    // Wait for the device, which destroys anything that was dropped.
+    unsafe {
+        renderer.device().wait_idle()?;
+    }
+
+    assert_eq!(
+        renderer
+            .device()
+            .live_resource_counts(),
+        Some(live_before)
+    );
+
+    // The validation layer must not have reported any errors.
+    if let Some(debugging) = renderer.debugging() {
+        let errors = debugging.errors_since();
+
+        assert!(errors.is_empty(), "Validation errors: {:?}", errors);
+    }
+
+    Ok(())
+}